@@ -5,6 +5,8 @@ use core::fmt::Write;
 use core::mem;
 use core::ptr::{read_volatile, write_volatile};
 
+use kernel::ErrorCode;
+
 /// This is used in the syscall handler. When set to 1 this means the
 /// svc_handler was called. Marked `pub` because it is used in the cortex-m*
 /// specific handler.
@@ -344,4 +346,23 @@ impl kernel::syscall::UserspaceKernelBoundary for SysCall {
             },
         ));
     }
+
+    fn store_context(
+        &self,
+        state: &CortexMStoredState,
+        out: &mut [u8],
+    ) -> Result<usize, ErrorCode> {
+        // R4-R11, then YPC, PSR and PSP. R0-R3, R12, LR and PC are stacked by
+        // the hardware on the process stack.
+        let special = [state.yield_pc, state.psr, state.psp];
+        let words = state.regs.iter().chain(special.iter());
+        let size = (state.regs.len() + 3) * mem::size_of::<u32>();
+        if out.len() < size {
+            return Err(ErrorCode::SIZE);
+        }
+        for (word, bytes) in words.zip(out.chunks_exact_mut(mem::size_of::<u32>())) {
+            bytes.copy_from_slice(&(*word as u32).to_le_bytes());
+        }
+        Ok(size)
+    }
 }
@@ -0,0 +1,277 @@
+//! Interface for processes running on the host.
+//!
+//! A host process is a `NativeApp`: a function that gets an `AppContext`,
+//! through which it makes system calls like a process on a microcontroller
+//! would. Buffers shared with the kernel must be in process memory, which the
+//! process can grow with `AppContext::sbrk()`.
+//!
+//! Upcalls are `UpcallFn`s, which run on the thread of the process when it
+//! yields. As on hardware, a process that returns from its main function is
+//! terminated.
+//!
+//! The workspace builds with `panic = "abort"`, so a process that panics stops
+//! the whole host. Use `AppContext::fault()` to fault a process instead.
+
+use core::cell::Cell;
+use core::mem;
+use std::sync::Arc;
+use std::thread;
+
+use kernel::syscall::SyscallClass;
+use kernel::ErrorCode;
+
+use crate::syscall::{Mailbox, Resume, Trap, MAX_APP_NAME_LEN};
+
+/// A function that receives upcalls. The first three arguments are set by the
+/// capsule, the fourth is the application data passed to `subscribe()`.
+pub type UpcallFn = fn(&AppContext, usize, usize, usize, usize);
+
+/// A program that can run as a host process.
+#[derive(Clone, Copy)]
+pub struct NativeApp {
+    /// The name the entry point of the process refers to the app by.
+    pub name: &'static str,
+    /// The function to run when the process starts.
+    pub main: fn(&AppContext),
+}
+
+/// The process side of the system call interface.
+pub struct AppContext {
+    mailbox: Arc<Mailbox>,
+    flash_start: usize,
+    memory_start: usize,
+    memory_len: usize,
+    app_break: Cell<usize>,
+}
+
+impl AppContext {
+    pub(crate) fn new(mailbox: Arc<Mailbox>, args: [usize; 4]) -> AppContext {
+        AppContext {
+            mailbox,
+            flash_start: args[0],
+            memory_start: args[1],
+            memory_len: args[2],
+            app_break: Cell::new(args[3]),
+        }
+    }
+
+    /// Run `main` as the process, and exit when it returns.
+    pub(crate) fn run(self, main: fn(&AppContext)) {
+        main(&self);
+        self.exit(0);
+    }
+
+    /// Make a system call with the raw register arguments, and return the
+    /// raw register values the kernel returned. If the process yields and an
+    /// upcall is run, the registers are all zero.
+    pub fn syscall(&self, class: SyscallClass, args: [usize; 4]) -> [usize; 4] {
+        match self.mailbox.trap(Trap::Syscall {
+            class: class as u8,
+            args,
+        }) {
+            Resume::Return(regs) => regs,
+            Resume::Call { pc, args } => {
+                // The kernel only calls functions that were passed to
+                // `subscribe()` as an `UpcallFn`.
+                let upcall: UpcallFn = unsafe { mem::transmute(pc) };
+                upcall(self, args[0], args[1], args[2], args[3]);
+                [0; 4]
+            }
+            Resume::Stop => Self::stop(),
+        }
+    }
+
+    /// Block until an upcall was run.
+    pub fn yield_wait(&self) {
+        self.syscall(SyscallClass::Yield, [1, 0, 0, 0]);
+    }
+
+    /// Set the function to call for upcall `subscribe_num` of driver
+    /// `driver_num`, or remove it if `upcall` is `None`.
+    pub fn subscribe(
+        &self,
+        driver_num: usize,
+        subscribe_num: usize,
+        upcall: Option<UpcallFn>,
+        appdata: usize,
+    ) -> Result<(), ErrorCode> {
+        let upcall_ptr = upcall.map_or(0, |upcall| upcall as usize);
+        decode_result(self.syscall(
+            SyscallClass::Subscribe,
+            [driver_num, subscribe_num, upcall_ptr, appdata],
+        ))
+        .map(|_| ())
+    }
+
+    /// Call command `command_num` of driver `driver_num`. Returns the values
+    /// returned on success.
+    pub fn command(
+        &self,
+        driver_num: usize,
+        command_num: usize,
+        arg0: usize,
+        arg1: usize,
+    ) -> Result<[usize; 3], ErrorCode> {
+        decode_result(self.syscall(SyscallClass::Command, [driver_num, command_num, arg0, arg1]))
+    }
+
+    /// Share `buffer`, which must be in process memory, with a driver for
+    /// reading. Pass an empty buffer to stop sharing.
+    pub fn allow_readonly(
+        &self,
+        driver_num: usize,
+        allow_num: usize,
+        buffer: &[u8],
+    ) -> Result<(), ErrorCode> {
+        decode_result(self.syscall(
+            SyscallClass::ReadOnlyAllow,
+            [
+                driver_num,
+                allow_num,
+                buffer.as_ptr() as usize,
+                buffer.len(),
+            ],
+        ))
+        .map(|_| ())
+    }
+
+    /// Share `buffer`, which must be in process memory, with a driver for
+    /// reading and writing. Pass an empty buffer to stop sharing.
+    pub fn allow_readwrite(
+        &self,
+        driver_num: usize,
+        allow_num: usize,
+        buffer: &mut [u8],
+    ) -> Result<(), ErrorCode> {
+        decode_result(self.syscall(
+            SyscallClass::ReadWriteAllow,
+            [
+                driver_num,
+                allow_num,
+                buffer.as_mut_ptr() as usize,
+                buffer.len(),
+            ],
+        ))
+        .map(|_| ())
+    }
+
+    /// Grow the process memory the process can access by `increment` bytes,
+    /// and return the new memory.
+    pub fn sbrk(&self, increment: usize) -> Result<&'static mut [u8], ErrorCode> {
+        // The kernel returns the old break as a 32 bit value, which is not
+        // enough for a host pointer, so keep track of it here.
+        decode_result(self.syscall(SyscallClass::Memop, [1, increment, 0, 0]))?;
+        let start = self.app_break.get();
+        self.app_break.set(start + increment);
+        // The kernel gave this memory to the process, and it is only handed
+        // out once.
+        Ok(unsafe { core::slice::from_raw_parts_mut(start as *mut u8, increment) })
+    }
+
+    /// Make memop `op` with argument `arg`, and return the value the kernel
+    /// returned, if any.
+    pub fn memop(&self, op: usize, arg: usize) -> Result<usize, ErrorCode> {
+        decode_result(self.syscall(SyscallClass::Memop, [op, arg, 0, 0])).map(|values| values[0])
+    }
+
+    /// The start address of the process in flash.
+    pub fn flash_start(&self) -> usize {
+        self.flash_start
+    }
+
+    /// The start address and length of the memory of the process.
+    pub fn memory(&self) -> (usize, usize) {
+        (self.memory_start, self.memory_len)
+    }
+
+    /// Terminate the process.
+    pub fn exit(&self, completion_code: usize) -> ! {
+        self.syscall(SyscallClass::Exit, [0, completion_code, 0, 0]);
+        Self::stop()
+    }
+
+    /// Make the process fault.
+    pub fn fault(&self) -> ! {
+        self.mailbox.trap(Trap::Fault);
+        Self::stop()
+    }
+
+    /// The kernel never resumes a terminated or faulted process, and stops
+    /// the thread when it restarts the process, so block the thread forever.
+    fn stop() -> ! {
+        loop {
+            thread::park();
+        }
+    }
+}
+
+/// Decode the registers returned by a system call, as specified in TRD 104.
+fn decode_result(regs: [usize; 4]) -> Result<[usize; 3], ErrorCode> {
+    if regs[0] >= 128 {
+        return Ok([regs[1], regs[2], regs[3]]);
+    }
+    Err(match regs[1] {
+        2 => ErrorCode::BUSY,
+        3 => ErrorCode::ALREADY,
+        4 => ErrorCode::OFF,
+        5 => ErrorCode::RESERVE,
+        6 => ErrorCode::INVAL,
+        7 => ErrorCode::SIZE,
+        8 => ErrorCode::CANCEL,
+        9 => ErrorCode::NOMEM,
+        10 => ErrorCode::NOSUPPORT,
+        11 => ErrorCode::NODEVICE,
+        12 => ErrorCode::UNINSTALLED,
+        13 => ErrorCode::NOACK,
+        _ => ErrorCode::FAIL,
+    })
+}
+
+/// Create a TBF image for a host process that runs `app`, with a package name
+/// of the name of the app. The entry point of the process holds the name of
+/// the app, which is how the kernel finds the function to run.
+pub fn tbf_image(app: &NativeApp, minimum_ram_size: u32) -> Vec<u8> {
+    const TBF_VERSION: u16 = 2;
+    const TBF_BASE_SIZE: usize = 16;
+    const TBF_MAIN: u16 = 1;
+    const TBF_PACKAGE_NAME: u16 = 3;
+    const TBF_FLAG_ENABLED: u32 = 1;
+
+    let align4 = |len: usize| (len + 3) & !3;
+    let name = app.name.as_bytes();
+    assert!(name.len() < MAX_APP_NAME_LEN, "app name too long");
+
+    let header_size = TBF_BASE_SIZE + (4 + 12) + (4 + align4(name.len()));
+    let total_size = header_size + align4(name.len() + 1);
+
+    let mut image = Vec::with_capacity(total_size);
+    image.extend_from_slice(&TBF_VERSION.to_le_bytes());
+    image.extend_from_slice(&(header_size as u16).to_le_bytes());
+    image.extend_from_slice(&(total_size as u32).to_le_bytes());
+    image.extend_from_slice(&TBF_FLAG_ENABLED.to_le_bytes());
+    image.extend_from_slice(&0u32.to_le_bytes()); // Checksum, set below.
+
+    // Main TLV: the entry point is right after the header, and there is no
+    // protected region.
+    image.extend_from_slice(&TBF_MAIN.to_le_bytes());
+    image.extend_from_slice(&12u16.to_le_bytes());
+    image.extend_from_slice(&0u32.to_le_bytes());
+    image.extend_from_slice(&0u32.to_le_bytes());
+    image.extend_from_slice(&minimum_ram_size.to_le_bytes());
+
+    image.extend_from_slice(&TBF_PACKAGE_NAME.to_le_bytes());
+    image.extend_from_slice(&(name.len() as u16).to_le_bytes());
+    image.extend_from_slice(name);
+    image.resize(header_size, 0);
+
+    let checksum = image
+        .chunks(4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .fold(0, |checksum, word| checksum ^ word);
+    image[12..16].copy_from_slice(&checksum.to_le_bytes());
+
+    // The entry point: the name of the app.
+    image.extend_from_slice(name);
+    image.resize(total_size, 0);
+    image
+}
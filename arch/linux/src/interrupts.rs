@@ -0,0 +1,138 @@
+//! Interrupt controller for the host.
+//!
+//! Host threads, such as a thread reading stdin, cannot call into the kernel
+//! directly, as the kernel is not thread safe. Instead they raise an interrupt
+//! here, and the chip dispatches it to the peripheral on the kernel thread in
+//! `Chip::service_pending_interrupts()`, the same way hardware interrupts are
+//! handled.
+//!
+//! Interrupts can also be raised at a point in time, which is how alarms and
+//! the scheduler timer are implemented without a thread per timer.
+
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::Instant;
+
+/// The number of interrupt lines.
+pub const NUM_INTERRUPTS: usize = 32;
+
+/// The interrupt raised when the scheduler timer expires. Chips must not use
+/// it for peripherals.
+pub const SCHEDULER_TIMER_IRQ: u32 = 31;
+
+struct State {
+    /// Bitmask of the pending interrupts.
+    pending: u32,
+    /// When to raise each interrupt, if a timer is set for it.
+    timers: [Option<Instant>; NUM_INTERRUPTS],
+    /// Incremented on every event, so a thread that checked for events before
+    /// waiting does not miss one that happened in between.
+    events: u64,
+}
+
+impl State {
+    /// Raise the interrupts whose timers expired.
+    fn expire_timers(&mut self) {
+        let now = Instant::now();
+        for (irq, timer) in self.timers.iter_mut().enumerate() {
+            if timer.map_or(false, |deadline| deadline <= now) {
+                *timer = None;
+                self.pending |= 1 << irq;
+            }
+        }
+    }
+}
+
+pub struct InterruptController {
+    state: Mutex<State>,
+    event: Condvar,
+}
+
+impl InterruptController {
+    pub fn new() -> InterruptController {
+        InterruptController {
+            state: Mutex::new(State {
+                pending: 0,
+                timers: [None; NUM_INTERRUPTS],
+                events: 0,
+            }),
+            event: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<State> {
+        // A thread panicking while holding the lock aborts the host, so the
+        // lock cannot be poisoned.
+        self.state.lock().unwrap()
+    }
+
+    /// Mark an interrupt as pending. Can be called from any thread.
+    pub fn raise(&self, irq: u32) {
+        let mut state = self.lock();
+        state.pending |= 1 << irq;
+        state.events += 1;
+        self.event.notify_all();
+    }
+
+    /// Raise an interrupt at `deadline`, or cancel the timer of the interrupt
+    /// if `deadline` is `None`. Either way, the interrupt is no longer pending.
+    pub fn set_timer(&self, irq: u32, deadline: Option<Instant>) {
+        let mut state = self.lock();
+        state.pending &= !(1 << irq);
+        state.timers[irq as usize] = deadline;
+        state.events += 1;
+        self.event.notify_all();
+    }
+
+    /// Wake up the kernel thread if it is waiting in `wait_for_event()`,
+    /// without raising an interrupt.
+    pub fn notify(&self) {
+        let mut state = self.lock();
+        state.events += 1;
+        self.event.notify_all();
+    }
+
+    /// Whether any interrupt is pending.
+    pub fn has_pending(&self) -> bool {
+        let mut state = self.lock();
+        state.expire_timers();
+        state.pending != 0
+    }
+
+    /// Return and clear the lowest pending interrupt.
+    pub fn next_pending(&self) -> Option<u32> {
+        let mut state = self.lock();
+        state.expire_timers();
+        if state.pending == 0 {
+            return None;
+        }
+        let irq = state.pending.trailing_zeros();
+        state.pending &= !(1 << irq);
+        Some(irq)
+    }
+
+    /// A counter of the events so far, to pass to `wait_for_event()`.
+    pub fn events(&self) -> u64 {
+        self.lock().events
+    }
+
+    /// Block until an interrupt is pending, or an event happened after
+    /// `events()` returned `seen`.
+    pub fn wait_for_event(&self, seen: u64) {
+        let mut state = self.lock();
+        loop {
+            state.expire_timers();
+            if state.pending != 0 || state.events != seen {
+                return;
+            }
+
+            let next_timer = state.timers.iter().filter_map(|timer| *timer).min();
+            state = match next_timer {
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    self.event.wait_timeout(state, timeout).unwrap().0
+                }
+                None => self.event.wait(state).unwrap(),
+            };
+        }
+    }
+}
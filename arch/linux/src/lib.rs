@@ -0,0 +1,31 @@
+//! Support for running the Tock kernel as a Linux process.
+//!
+//! This crate lets the unmodified kernel, capsules and scheduler run on a
+//! development machine or in CI, where no boards are attached. It provides
+//! what the other arch crates provide for real hardware:
+//!
+//! - `interrupts`: an interrupt controller that host threads (for example a
+//!   thread reading stdin) and timers raise interrupts on, and that the kernel
+//!   waits on when it sleeps.
+//! - `scheduler_timer`: a `SchedulerTimer` backed by the host clock.
+//! - `syscall`: a `UserspaceKernelBoundary` that runs processes as host
+//!   threads.
+//! - `app`: the interface host processes use to make system calls, and a
+//!   helper to create TBF images for them.
+//!
+//! Processes cannot run Cortex-M or RISC-V machine code on the host. Instead,
+//! a process is a Rust function compiled into the board (a `NativeApp`), which
+//! runs in its own thread and makes system calls through an `AppContext`. The
+//! kernel still loads processes from TBF images in flash: the entry point of a
+//! host process holds the name of the `NativeApp` to run. The kernel thread and
+//! the process threads never run at the same time (see `syscall`).
+//!
+//! The MPU is not emulated: the `Chip` should use `()` as its MPU.
+
+#![crate_name = "linux"]
+#![crate_type = "rlib"]
+
+pub mod app;
+pub mod interrupts;
+pub mod scheduler_timer;
+pub mod syscall;
@@ -0,0 +1,70 @@
+//! Scheduler timer backed by the host clock.
+
+use core::cell::Cell;
+use std::time::{Duration, Instant};
+
+use crate::interrupts::{InterruptController, SCHEDULER_TIMER_IRQ};
+
+/// Raises `SCHEDULER_TIMER_IRQ` when the timeslice of a process expires. Host
+/// processes cannot be stopped, so the kernel only finds out at the next
+/// system call of the process.
+pub struct SchedulerTimer {
+    interrupts: &'static InterruptController,
+    /// When the current timeslice ends.
+    deadline: Cell<Option<Instant>>,
+    armed: Cell<bool>,
+}
+
+impl SchedulerTimer {
+    pub fn new(interrupts: &'static InterruptController) -> SchedulerTimer {
+        SchedulerTimer {
+            interrupts,
+            deadline: Cell::new(None),
+            armed: Cell::new(false),
+        }
+    }
+
+    fn update_interrupt(&self) {
+        let deadline = if self.armed.get() {
+            self.deadline.get()
+        } else {
+            None
+        };
+        self.interrupts.set_timer(SCHEDULER_TIMER_IRQ, deadline);
+    }
+}
+
+impl kernel::SchedulerTimer for SchedulerTimer {
+    fn start(&self, us: u32) {
+        self.deadline
+            .set(Some(Instant::now() + Duration::from_micros(us as u64)));
+        self.update_interrupt();
+    }
+
+    fn reset(&self) {
+        self.deadline.set(None);
+        self.armed.set(false);
+        self.update_interrupt();
+    }
+
+    fn arm(&self) {
+        self.armed.set(true);
+        self.update_interrupt();
+    }
+
+    fn disarm(&self) {
+        self.armed.set(false);
+        self.update_interrupt();
+    }
+
+    fn get_remaining_us(&self) -> Option<u32> {
+        self.deadline.get().and_then(|deadline| {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::from_micros(0) {
+                None
+            } else {
+                Some(remaining.as_micros() as u32)
+            }
+        })
+    }
+}
@@ -0,0 +1,315 @@
+//! Kernel-userland system call interface for host processes.
+//!
+//! Each process runs in its own host thread, started the first time the kernel
+//! switches to the process. A context switch hands control from the kernel
+//! thread to the process thread through a `Mailbox`: the kernel posts how the
+//! process should resume (return from a system call, or call a function), and
+//! waits until the process posts its next system call.
+//!
+//! The scheduler timer and other interrupts cannot stop a host thread, so the
+//! kernel handles them once the running process makes its next system call.
+//! A process thread therefore never runs at the same time as the kernel, which
+//! may write the buffers the process allowed, and a process that never makes
+//! a system call stops the kernel.
+//!
+//! When a process is terminated, its thread stays blocked in the system call
+//! it was in. When it is restarted, its old thread is parked for good, and a
+//! new thread is started when the process runs again.
+
+use core::fmt::Write;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use kernel::procs::FunctionCall;
+use kernel::syscall::{ContextSwitchReason, Syscall, SyscallReturn};
+use kernel::ErrorCode;
+
+use crate::app::{AppContext, NativeApp};
+use crate::interrupts::InterruptController;
+
+/// The longest name of a `NativeApp` the kernel looks for at the entry point
+/// of a process.
+pub const MAX_APP_NAME_LEN: usize = 64;
+
+/// How a process thread continues after a context switch.
+#[derive(Clone, Copy)]
+pub(crate) enum Resume {
+    /// Return from the system call with these register values.
+    Return([usize; 4]),
+    /// Call the function at `pc` with these arguments.
+    Call { pc: usize, args: [usize; 4] },
+    /// The process was restarted, stop the thread.
+    Stop,
+}
+
+/// Why a process thread stopped running.
+#[derive(Clone, Copy)]
+pub(crate) enum Trap {
+    /// The process called a system call of this class with these arguments.
+    Syscall { class: u8, args: [usize; 4] },
+    /// The process faulted.
+    Fault,
+}
+
+#[derive(Default)]
+struct MailboxState {
+    resume: Option<Resume>,
+    trap: Option<Trap>,
+}
+
+/// Hands control between the kernel thread and a process thread.
+pub(crate) struct Mailbox {
+    state: Mutex<MailboxState>,
+    resumed: Condvar,
+    interrupts: &'static InterruptController,
+}
+
+impl Mailbox {
+    fn new(interrupts: &'static InterruptController) -> Mailbox {
+        Mailbox {
+            state: Mutex::new(MailboxState::default()),
+            resumed: Condvar::new(),
+            interrupts,
+        }
+    }
+
+    /// Called by the process thread to stop running. Blocks until the kernel
+    /// resumes the process.
+    pub(crate) fn trap(&self, trap: Trap) -> Resume {
+        let mut state = self.state.lock().unwrap();
+        state.trap = Some(trap);
+        self.interrupts.notify();
+        loop {
+            if let Some(resume) = state.resume.take() {
+                return resume;
+            }
+            state = self.resumed.wait(state).unwrap();
+        }
+    }
+
+    fn resume(&self, resume: Resume) {
+        self.state.lock().unwrap().resume = Some(resume);
+        self.resumed.notify_one();
+    }
+
+    fn take_trap(&self) -> Option<Trap> {
+        self.state.lock().unwrap().trap.take()
+    }
+}
+
+/// The state of a host process.
+#[derive(Default)]
+pub struct LinuxStoredState {
+    /// The argument registers, holding the arguments of the last system call
+    /// and then its return value.
+    regs: [usize; 4],
+    /// The function the process was last told to call.
+    pc: usize,
+    /// The function to call when the process next resumes.
+    function_call: Option<(usize, [usize; 4])>,
+    /// The class of the last system call.
+    syscall_class: u8,
+    /// The thread of the process, once it has started.
+    mailbox: Option<Arc<Mailbox>>,
+}
+
+pub struct SysCall {
+    interrupts: &'static InterruptController,
+    /// The functions that can be run as processes.
+    apps: &'static [NativeApp],
+}
+
+impl SysCall {
+    pub fn new(interrupts: &'static InterruptController, apps: &'static [NativeApp]) -> SysCall {
+        SysCall { interrupts, apps }
+    }
+
+    /// Find the app whose name is stored at `entry`.
+    unsafe fn find_app(&self, entry: usize) -> Option<&'static NativeApp> {
+        if entry == 0 {
+            return None;
+        }
+        let name_len = (0..MAX_APP_NAME_LEN).find(|&i| *(entry as *const u8).add(i) == 0)?;
+        let name = core::slice::from_raw_parts(entry as *const u8, name_len);
+        let name = core::str::from_utf8(name).ok()?;
+        self.apps.iter().find(|app| app.name == name)
+    }
+
+    /// Start the thread of a process, running the app at `entry`.
+    unsafe fn start_process(
+        &self,
+        entry: usize,
+        args: [usize; 4],
+    ) -> Result<Arc<Mailbox>, ErrorCode> {
+        let app = self.find_app(entry).ok_or(ErrorCode::INVAL)?;
+        let mailbox = Arc::new(Mailbox::new(self.interrupts));
+        let context = AppContext::new(mailbox.clone(), args);
+        thread::Builder::new()
+            .name(app.name.to_string())
+            .spawn(move || context.run(app.main))
+            .map_err(|_| ErrorCode::NOMEM)?;
+        Ok(mailbox)
+    }
+}
+
+impl kernel::syscall::UserspaceKernelBoundary for SysCall {
+    type StoredState = LinuxStoredState;
+
+    fn initial_process_app_brk_size(&self) -> usize {
+        // Host processes have their stacks in host memory, and state is not
+        // stored in process memory.
+        0
+    }
+
+    unsafe fn initialize_process(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        state: &mut Self::StoredState,
+    ) -> Result<(), ()> {
+        // Park the thread of the previous run of the process, if any. It is
+        // waiting in a system call, as process threads only run during
+        // `switch_to_process()`.
+        if let Some(mailbox) = state.mailbox.take() {
+            mailbox.resume(Resume::Stop);
+        }
+        *state = LinuxStoredState::default();
+        Ok(())
+    }
+
+    unsafe fn set_syscall_return_value(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        state: &mut Self::StoredState,
+        return_value: SyscallReturn,
+    ) -> Result<(), ()> {
+        let mut regs = [0u32; 4];
+        let (r0, rest) = regs.split_at_mut(1);
+        let (r1, rest) = rest.split_at_mut(1);
+        let (r2, r3) = rest.split_at_mut(1);
+        return_value.encode_syscall_return(&mut r0[0], &mut r1[0], &mut r2[0], &mut r3[0]);
+        for (reg, value) in state.regs.iter_mut().zip(regs.iter()) {
+            *reg = *value as usize;
+        }
+        Ok(())
+    }
+
+    unsafe fn set_process_function(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        state: &mut Self::StoredState,
+        callback: FunctionCall,
+    ) -> Result<(), ()> {
+        state.function_call = Some((
+            callback.pc,
+            [
+                callback.argument0,
+                callback.argument1,
+                callback.argument2,
+                callback.argument3,
+            ],
+        ));
+        Ok(())
+    }
+
+    unsafe fn switch_to_process(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        state: &mut Self::StoredState,
+    ) -> (ContextSwitchReason, Option<*const u8>) {
+        match (state.mailbox.as_ref(), state.function_call.take()) {
+            (None, Some((pc, args))) => match self.start_process(pc, args) {
+                Ok(mailbox) => {
+                    state.pc = pc;
+                    state.mailbox = Some(mailbox);
+                }
+                Err(_) => return (ContextSwitchReason::Fault, None),
+            },
+            (None, None) => return (ContextSwitchReason::Fault, None),
+            (Some(mailbox), Some((pc, args))) => {
+                state.pc = pc;
+                mailbox.resume(Resume::Call { pc, args });
+            }
+            (Some(mailbox), None) => mailbox.resume(Resume::Return(state.regs)),
+        }
+
+        // The process runs until it makes a system call. Interrupts that
+        // arrive in the meantime are handled afterwards.
+        let mailbox = match state.mailbox.as_ref() {
+            Some(mailbox) => mailbox,
+            None => return (ContextSwitchReason::Fault, None),
+        };
+        loop {
+            let seen = self.interrupts.events();
+            if let Some(trap) = mailbox.take_trap() {
+                return match trap {
+                    Trap::Syscall { class, args } => {
+                        state.regs = args;
+                        state.syscall_class = class;
+                        match Syscall::from_register_arguments(
+                            class, args[0], args[1], args[2], args[3],
+                        ) {
+                            Some(syscall) => (ContextSwitchReason::SyscallFired { syscall }, None),
+                            None => (ContextSwitchReason::Fault, None),
+                        }
+                    }
+                    Trap::Fault => (ContextSwitchReason::Fault, None),
+                };
+            }
+            self.interrupts.wait_for_event(seen);
+        }
+    }
+
+    unsafe fn print_context(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        state: &Self::StoredState,
+        writer: &mut dyn Write,
+    ) {
+        let _ = writer.write_fmt(format_args!(
+            "\
+             \r\n R0 : {:#018X}    R2 : {:#018X}\
+             \r\n R1 : {:#018X}    R3 : {:#018X}\
+             \r\n PC : {:#018X}    Last syscall class: {}\
+             \r\n Thread: {}\
+             \r\n",
+            state.regs[0],
+            state.regs[2],
+            state.regs[1],
+            state.regs[3],
+            state.pc,
+            state.syscall_class,
+            if state.mailbox.is_some() {
+                "stopped in system call"
+            } else {
+                "not started"
+            },
+        ));
+    }
+
+    fn store_context(&self, state: &Self::StoredState, out: &mut [u8]) -> Result<usize, ErrorCode> {
+        // R0-R3, then PC. Host registers are 64 bits wide, so each is stored
+        // as two little endian 32 bit words, the low word first.
+        let registers = [
+            state.regs[0],
+            state.regs[1],
+            state.regs[2],
+            state.regs[3],
+            state.pc,
+        ];
+        let size = registers.len() * 8;
+        if out.len() < size {
+            return Err(ErrorCode::SIZE);
+        }
+        for (register, bytes) in registers.iter().zip(out.chunks_exact_mut(8)) {
+            let register = *register as u64;
+            bytes[..4].copy_from_slice(&(register as u32).to_le_bytes());
+            bytes[4..].copy_from_slice(&((register >> 32) as u32).to_le_bytes());
+        }
+        Ok(size)
+    }
+}
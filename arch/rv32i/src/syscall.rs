@@ -1,10 +1,12 @@
 //! Kernel-userland system call interface for RISC-V architecture.
 
 use core::fmt::Write;
+use core::mem;
 
 use crate::csr::mcause;
 use kernel;
 use kernel::syscall::ContextSwitchReason;
+use kernel::ErrorCode;
 
 /// This holds all of the state that the kernel must keep for the process when
 /// the process is not executing.
@@ -507,4 +509,22 @@ impl kernel::syscall::UserspaceKernelBoundary for SysCall {
             state.mtval,
         ));
     }
+
+    fn store_context(
+        &self,
+        state: &Riscv32iStoredState,
+        out: &mut [u8],
+    ) -> Result<usize, ErrorCode> {
+        // X1-X31, then PC, mcause and mtval.
+        let special = [state.pc, state.mcause, state.mtval];
+        let words = state.regs.iter().chain(special.iter());
+        let size = (state.regs.len() + 3) * mem::size_of::<u32>();
+        if out.len() < size {
+            return Err(ErrorCode::SIZE);
+        }
+        for (word, bytes) in words.zip(out.chunks_exact_mut(mem::size_of::<u32>())) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+        Ok(size)
+    }
 }
@@ -47,7 +47,8 @@ const FAULT_RESPONSE: kernel::procs::PanicFaultPolicy = kernel::procs::PanicFaul
 // Number of concurrent processes this platform supports.
 const NUM_PROCS: usize = 4;
 
-static mut PROCESSES: [Option<&'static dyn kernel::procs::Process>; NUM_PROCS] = [None; NUM_PROCS];
+static mut PROCESSES: [kernel::procs::ProcessSlot; NUM_PROCS] =
+    [kernel::procs::EMPTY_PROCESS_SLOT; NUM_PROCS];
 
 /// Dummy buffer that causes the linker to reserve enough space for the stack.
 #[no_mangle]
@@ -490,7 +491,7 @@ pub unsafe fn main() {
 
     let chip = static_init!(
         nrf52832::chip::NRF52<Nrf52832DefaultPeripherals>,
-        nrf52832::chip::NRF52::new(nrf52832_peripherals)
+        nrf52832::chip::NRF52::new(nrf52832_peripherals, &base_peripherals.clock)
     );
 
     nrf52832_peripherals.gpio_port[Pin::P0_31].make_output();
@@ -522,7 +523,6 @@ pub unsafe fn main() {
             &mut _sappmem as *mut u8,
             &_eappmem as *const u8 as usize - &_sappmem as *const u8 as usize,
         ),
-        &mut PROCESSES,
         &FAULT_RESPONSE,
         &process_management_capability,
     )
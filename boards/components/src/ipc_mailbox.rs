@@ -0,0 +1,58 @@
+//! Component for message-based IPC between processes.
+//!
+//! This provides one Component, `IpcMailboxComponent`, which gives every
+//! process a mailbox that the processes allowed by the board's rules can send
+//! messages to.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let ipc_mailbox =
+//!     components::ipc_mailbox::IpcMailboxComponent::new(board_kernel, &MAILBOX_RULES)
+//!         .finalize(());
+//! ```
+
+use capsules::ipc_mailbox::{IpcMailbox, MailboxRule};
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::static_init;
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+pub struct IpcMailboxComponent {
+    board_kernel: &'static kernel::Kernel,
+    rules: &'static [MailboxRule],
+}
+
+impl IpcMailboxComponent {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        rules: &'static [MailboxRule],
+    ) -> IpcMailboxComponent {
+        IpcMailboxComponent {
+            board_kernel,
+            rules,
+        }
+    }
+}
+
+impl Component for IpcMailboxComponent {
+    type StaticInput = ();
+    type Output = &'static IpcMailbox<Capability>;
+
+    unsafe fn finalize(self, _s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        static_init!(
+            IpcMailbox<Capability>,
+            IpcMailbox::new(
+                self.board_kernel,
+                self.board_kernel.create_grant(&grant_cap),
+                self.rules,
+                Capability,
+            )
+        )
+    }
+}
@@ -0,0 +1,115 @@
+//! Components for the key-value store and its userspace driver.
+//!
+//! This provides two Components:
+//!
+//! - `KVStoreComponent` provides a `hil::kv_store` implementation that gives
+//!   every owner its own namespace, on top of a `hil::kv_system`
+//!   implementation such as TicKV.
+//! - `KVStoreDriverComponent` provides a system call interface to the
+//!   key-value store.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let kv_store = components::kv_store::KVStoreComponent::new(tickv).finalize(
+//!     components::kv_store_component_helper!(
+//!         capsules::tickv::TicKVStore<
+//!             'static,
+//!             capsules::virtual_flash::FlashUser<'static, lowrisc::flash_ctrl::FlashCtrl>,
+//!         >,
+//!         capsules::tickv::TicKVKeyType,
+//!     ),
+//! );
+//! let kv_driver =
+//!     components::kv_store::KVStoreDriverComponent::new(board_kernel, kv_store).finalize(());
+//! ```
+
+use capsules::kv_driver::KVStoreDriver;
+use capsules::kv_store::{KVStore, UNHASHED_KEY_LENGTH, VALUE_BUFFER_LENGTH};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::kv_store;
+use kernel::hil::kv_system::{KVSystem, KeyType};
+use kernel::{static_init, static_init_half};
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! kv_store_component_helper {
+    ($K:ty, $T:ty $(,)?) => {{
+        use capsules::kv_store::KVStore;
+        use core::mem::MaybeUninit;
+        static mut BUF1: MaybeUninit<$T> = MaybeUninit::uninit();
+        static mut BUF2: MaybeUninit<KVStore<'static, $K, $T>> = MaybeUninit::uninit();
+        (&mut BUF1, &mut BUF2)
+    };};
+}
+
+pub struct KVStoreComponent<K: 'static + KVSystem<'static, K = T>, T: 'static + KeyType + Default> {
+    kv_system: &'static K,
+}
+
+impl<K: 'static + KVSystem<'static, K = T>, T: 'static + KeyType + Default> KVStoreComponent<K, T> {
+    pub fn new(kv_system: &'static K) -> KVStoreComponent<K, T> {
+        KVStoreComponent { kv_system }
+    }
+}
+
+impl<K: 'static + KVSystem<'static, K = T>, T: 'static + KeyType + Default> Component
+    for KVStoreComponent<K, T>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<T>,
+        &'static mut MaybeUninit<KVStore<'static, K, T>>,
+    );
+    type Output = &'static KVStore<'static, K, T>;
+
+    unsafe fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let key = static_init_half!(static_buffer.0, T, T::default());
+        let unhashed_key = static_init!([u8; UNHASHED_KEY_LENGTH], [0; UNHASHED_KEY_LENGTH]);
+        let value = static_init!([u8; VALUE_BUFFER_LENGTH], [0; VALUE_BUFFER_LENGTH]);
+        let read_buffer = static_init!([u8; VALUE_BUFFER_LENGTH], [0; VALUE_BUFFER_LENGTH]);
+
+        let kv_store = static_init_half!(
+            static_buffer.1,
+            KVStore<'static, K, T>,
+            KVStore::new(self.kv_system, unhashed_key, key, value, read_buffer)
+        );
+        self.kv_system.set_client(kv_store);
+        kv_store
+    }
+}
+
+pub struct KVStoreDriverComponent {
+    board_kernel: &'static kernel::Kernel,
+    kv_store: &'static dyn kv_store::KVStore<'static>,
+}
+
+impl KVStoreDriverComponent {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        kv_store: &'static dyn kv_store::KVStore<'static>,
+    ) -> KVStoreDriverComponent {
+        KVStoreDriverComponent {
+            board_kernel,
+            kv_store,
+        }
+    }
+}
+
+impl Component for KVStoreDriverComponent {
+    type StaticInput = ();
+    type Output = &'static KVStoreDriver<'static>;
+
+    unsafe fn finalize(self, _s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let driver = static_init!(
+            KVStoreDriver<'static>,
+            KVStoreDriver::new(self.kv_store, self.board_kernel.create_grant(&grant_cap))
+        );
+        self.kv_store.set_client(driver);
+        driver
+    }
+}
@@ -13,7 +13,7 @@
 
 use core::mem::MaybeUninit;
 use kernel::component::Component;
-use kernel::procs::Process;
+use kernel::procs::ProcessSlot;
 use kernel::{static_init, static_init_half};
 use kernel::{CoopProcessNode, CooperativeSched};
 
@@ -30,11 +30,11 @@ macro_rules! coop_component_helper {
 }
 
 pub struct CooperativeComponent {
-    processes: &'static [Option<&'static dyn Process>],
+    processes: &'static [ProcessSlot],
 }
 
 impl CooperativeComponent {
-    pub fn new(processes: &'static [Option<&'static dyn Process>]) -> CooperativeComponent {
+    pub fn new(processes: &'static [ProcessSlot]) -> CooperativeComponent {
         CooperativeComponent { processes }
     }
 }
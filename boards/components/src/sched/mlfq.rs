@@ -10,7 +10,7 @@ use core::mem::MaybeUninit;
 use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use kernel::component::Component;
 use kernel::hil::time;
-use kernel::procs::Process;
+use kernel::procs::{PriorityPolicy, ProcessSlot};
 use kernel::static_init_half;
 use kernel::{MLFQProcessNode, MLFQSched};
 
@@ -31,17 +31,20 @@ macro_rules! mlfq_component_helper {
 
 pub struct MLFQComponent<A: 'static + time::Alarm<'static>> {
     alarm_mux: &'static MuxAlarm<'static, A>,
-    processes: &'static [Option<&'static dyn Process>],
+    processes: &'static [ProcessSlot],
+    policy: &'static dyn PriorityPolicy,
 }
 
 impl<A: 'static + time::Alarm<'static>> MLFQComponent<A> {
     pub fn new(
         alarm_mux: &'static MuxAlarm<'static, A>,
-        processes: &'static [Option<&'static dyn Process>],
+        processes: &'static [ProcessSlot],
+        policy: &'static dyn PriorityPolicy,
     ) -> MLFQComponent<A> {
         MLFQComponent {
             alarm_mux,
             processes,
+            policy,
         }
     }
 }
@@ -64,7 +67,7 @@ impl<A: 'static + time::Alarm<'static>> Component for MLFQComponent<A> {
         let scheduler = static_init_half!(
             sched_buf,
             MLFQSched<'static, VirtualMuxAlarm<'static, A>>,
-            MLFQSched::new(scheduler_alarm)
+            MLFQSched::new(scheduler_alarm, self.policy)
         );
         for (i, node) in proc_nodes.iter_mut().enumerate() {
             let init_node = static_init_half!(
@@ -5,21 +5,32 @@
 //! Usage
 //! -----
 //! ```rust
-//! let scheduler =
-//!     components::priority::PriorityComponent::new(board_kernel).finalize(());
+//! let scheduler = components::priority::PriorityComponent::new(
+//!     board_kernel,
+//!     &kernel::procs::EqualPriorityPolicy {},
+//! )
+//! .finalize(());
 //! ```
 
 use kernel::component::Component;
+use kernel::procs::PriorityPolicy;
 use kernel::static_init;
 use kernel::PrioritySched;
 
 pub struct PriorityComponent {
     board_kernel: &'static kernel::Kernel,
+    policy: &'static dyn PriorityPolicy,
 }
 
 impl PriorityComponent {
-    pub fn new(board_kernel: &'static kernel::Kernel) -> PriorityComponent {
-        PriorityComponent { board_kernel }
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        policy: &'static dyn PriorityPolicy,
+    ) -> PriorityComponent {
+        PriorityComponent {
+            board_kernel,
+            policy,
+        }
     }
 }
 
@@ -28,7 +39,10 @@ impl Component for PriorityComponent {
     type Output = &'static mut PrioritySched;
 
     unsafe fn finalize(self, _static_buffer: Self::StaticInput) -> Self::Output {
-        let scheduler = static_init!(PrioritySched, PrioritySched::new(self.board_kernel));
+        let scheduler = static_init!(
+            PrioritySched,
+            PrioritySched::new(self.board_kernel, self.policy)
+        );
         scheduler
     }
 }
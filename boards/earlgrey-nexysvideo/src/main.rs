@@ -46,7 +46,8 @@ const NUM_PROCS: usize = 4;
 //
 // Actual memory for holding the active process structures. Need an empty list
 // at least.
-static mut PROCESSES: [Option<&'static dyn kernel::procs::Process>; 4] = [None; NUM_PROCS];
+static mut PROCESSES: [kernel::procs::ProcessSlot; NUM_PROCS] =
+    [kernel::procs::EMPTY_PROCESS_SLOT; NUM_PROCS];
 
 // Test access to the peripherals
 #[cfg(test)]
@@ -143,7 +144,7 @@ unsafe fn setup() -> (
     let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&PROCESSES));
 
     let dynamic_deferred_call_clients =
-        static_init!([DynamicDeferredCallClientState; 2], Default::default());
+        static_init!([DynamicDeferredCallClientState; 3], Default::default());
     let dynamic_deferred_caller = static_init!(
         DynamicDeferredCall,
         DynamicDeferredCall::new(dynamic_deferred_call_clients)
@@ -316,6 +317,7 @@ unsafe fn setup() -> (
         0x40000,                                     // Region size
         flash_ctrl_read_buf,                         // Buffer used internally in TicKV
         page_buffer,                                 // Buffer used with the flash controller
+        dynamic_deferred_caller,                     // Used to complete hashing keys
     )
     .finalize(components::tickv_component_helper!(
         lowrisc::flash_ctrl::FlashCtrl
@@ -431,7 +433,6 @@ unsafe fn setup() -> (
             &mut _sappmem as *mut u8,
             &_eappmem as *const u8 as usize - &_sappmem as *const u8 as usize,
         ),
-        &mut PROCESSES,
         &FAULT_RESPONSE,
         &process_mgmt_cap,
     )
@@ -457,8 +458,11 @@ pub unsafe fn main() {
     {
         let (board_kernel, earlgrey_nexysvideo, chip, _peripherals) = setup();
 
-        let scheduler =
-            components::sched::priority::PriorityComponent::new(board_kernel).finalize(());
+        let scheduler = components::sched::priority::PriorityComponent::new(
+            board_kernel,
+            &kernel::procs::EqualPriorityPolicy {},
+        )
+        .finalize(());
         let main_loop_cap = create_capability!(capabilities::MainLoopCapability);
 
         board_kernel.kernel_loop(
@@ -482,8 +486,13 @@ fn test_runner(tests: &[&dyn Fn()]) {
         BOARD = Some(board_kernel);
         PLATFORM = Some(&earlgrey_nexysvideo);
         PERIPHERALS = Some(peripherals);
-        SCHEDULER =
-            Some(components::sched::priority::PriorityComponent::new(board_kernel).finalize(()));
+        SCHEDULER = Some(
+            components::sched::priority::PriorityComponent::new(
+                board_kernel,
+                &kernel::procs::EqualPriorityPolicy {},
+            )
+            .finalize(()),
+        );
         MAIN_CAP = Some(&create_capability!(capabilities::MainLoopCapability));
 
         chip.watchdog().setup();
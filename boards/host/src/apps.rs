@@ -0,0 +1,48 @@
+//! Apps that run as processes on the host.
+//!
+//! Each app is a `NativeApp`, which the flash file refers to by name.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use linux::app::{AppContext, NativeApp};
+
+/// The apps processes can run.
+pub static APPS: [NativeApp; 1] = [NativeApp {
+    name: "hello",
+    main: hello,
+}];
+
+/// Print a greeting to the console and exit.
+fn hello(context: &AppContext) {
+    if console_write(context, b"Hello from a host process!\n").is_err() {
+        context.fault();
+    }
+}
+
+/// Write `message` to the console, and wait until it was written.
+fn console_write(context: &AppContext, message: &[u8]) -> Result<(), kernel::ErrorCode> {
+    const WRITE: usize = 1;
+
+    fn write_done(_: &AppContext, _written: usize, _: usize, _: usize, done: usize) {
+        let done = unsafe { &*(done as *const AtomicBool) };
+        done.store(true, Ordering::Relaxed);
+    }
+
+    // Buffers shared with the kernel must be in process memory.
+    let buffer = context.sbrk(message.len())?;
+    buffer.copy_from_slice(message);
+
+    let done = AtomicBool::new(false);
+    context.allow_readonly(capsules::console::DRIVER_NUM, WRITE, buffer)?;
+    context.subscribe(
+        capsules::console::DRIVER_NUM,
+        WRITE,
+        Some(write_done),
+        &done as *const AtomicBool as usize,
+    )?;
+    context.command(capsules::console::DRIVER_NUM, WRITE, message.len(), 0)?;
+    while !done.load(Ordering::Relaxed) {
+        context.yield_wait();
+    }
+    Ok(())
+}
@@ -0,0 +1,263 @@
+//! Board file for running Tock as a Linux process.
+//!
+//! The board runs the kernel and capsules on a development machine, for
+//! example to test them in CI where no hardware is attached. The console and
+//! the process console use stdin and stdout, alarms use the host clock, and
+//! flash is a file.
+//!
+//! The first `APP_FLASH_SIZE` bytes of the flash file hold the TBF images of
+//! the processes, which run the native apps in `apps`. The flash after that
+//! is storage for the nonvolatile storage driver. If the flash file does not
+//! exist, it is created with all the apps in `apps`.
+//!
+//! ```shell
+//! $ cargo run -p host -- [FLASH_FILE]
+//! ```
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ptr;
+
+use capsules::virtual_alarm::VirtualMuxAlarm;
+use kernel::capabilities;
+use kernel::common::dynamic_deferred_call::{DynamicDeferredCall, DynamicDeferredCallClientState};
+use kernel::component::Component;
+use kernel::hil;
+use kernel::Platform;
+use kernel::{create_capability, debug, static_init};
+use linux::interrupts::InterruptController;
+use linux_host::chip::{LinuxHost, LinuxHostDefaultPeripherals};
+
+mod apps;
+
+/// The default flash file.
+const FLASH_FILE: &str = "host-flash.bin";
+/// The size of the flash holding the processes.
+const APP_FLASH_SIZE: usize = 0x10000;
+/// The size of the flash for the nonvolatile storage driver.
+const STORAGE_SIZE: usize = 0x8000;
+
+/// Memory each process gets.
+const APP_RAM_SIZE: u32 = 0x4000;
+
+const NUM_PROCS: usize = 4;
+
+static mut PROCESSES: [kernel::procs::ProcessSlot; NUM_PROCS] =
+    [kernel::procs::EMPTY_PROCESS_SLOT; NUM_PROCS];
+
+static mut APP_MEMORY: [u8; 0x10000] = [0; 0x10000];
+
+static mut CHIP: Option<&'static LinuxHost<LinuxHostDefaultPeripherals>> = None;
+
+// How should the kernel respond when a process faults.
+const FAULT_RESPONSE: kernel::procs::StopWithDebugFaultPolicy =
+    kernel::procs::StopWithDebugFaultPolicy {};
+
+struct ProcessManagementCapability;
+unsafe impl capabilities::ProcessManagementCapability for ProcessManagementCapability {}
+
+/// A structure representing this platform that holds references to all
+/// capsules for this platform.
+struct Host {
+    console: &'static capsules::console::Console<'static>,
+    alarm: &'static capsules::alarm::AlarmDriver<
+        'static,
+        VirtualMuxAlarm<'static, linux_host::alarm::Alarm<'static>>,
+    >,
+    nonvolatile_storage: &'static capsules::nonvolatile_storage_driver::NonvolatileStorage<'static>,
+}
+
+/// Mapping of integer syscalls to objects that implement syscalls.
+impl Platform for Host {
+    fn with_driver<F, R>(&self, driver_num: usize, f: F) -> R
+    where
+        F: FnOnce(Option<&dyn kernel::Driver>) -> R,
+    {
+        match driver_num {
+            capsules::console::DRIVER_NUM => f(Some(self.console)),
+            capsules::alarm::DRIVER_NUM => f(Some(self.alarm)),
+            capsules::nonvolatile_storage_driver::DRIVER_NUM => f(Some(self.nonvolatile_storage)),
+            _ => f(None),
+        }
+    }
+}
+
+/// Open the flash file, creating it with all the apps if it does not exist,
+/// and read the processes from it.
+fn open_flash(path: &str) -> std::io::Result<(File, &'static [u8])> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(path)?;
+
+    if file.metadata()?.len() == 0 {
+        let mut image = Vec::new();
+        for app in apps::APPS.iter() {
+            image.extend(linux::app::tbf_image(app, APP_RAM_SIZE));
+        }
+        if image.len() > APP_FLASH_SIZE {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "apps do not fit in flash",
+            ));
+        }
+        image.resize(APP_FLASH_SIZE, 0);
+        // Erased storage.
+        image.resize(APP_FLASH_SIZE + STORAGE_SIZE, 0xFF);
+        file.write_all(&image)?;
+    }
+    file.set_len((APP_FLASH_SIZE + STORAGE_SIZE) as u64)?;
+
+    let mut app_flash = vec![0; APP_FLASH_SIZE];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut app_flash)?;
+    Ok((file, Box::leak(app_flash.into_boxed_slice())))
+}
+
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| FLASH_FILE.to_string());
+    let (flash_file, app_flash) = match open_flash(&path) {
+        Ok(flash) => flash,
+        Err(e) => {
+            eprintln!("Cannot open flash file {}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+
+    unsafe { start(flash_file, app_flash) }
+}
+
+/// Set up the board and run the kernel.
+unsafe fn start(flash_file: File, app_flash: &'static [u8]) -> ! {
+    let interrupts = static_init!(InterruptController, InterruptController::new());
+    let peripherals = static_init!(
+        LinuxHostDefaultPeripherals,
+        LinuxHostDefaultPeripherals::new(
+            interrupts,
+            flash_file,
+            APP_FLASH_SIZE as u64,
+            STORAGE_SIZE / linux_host::flash::PAGE_SIZE,
+        )
+    );
+
+    // initialize capabilities
+    let process_mgmt_cap = create_capability!(capabilities::ProcessManagementCapability);
+    let main_loop_cap = create_capability!(capabilities::MainLoopCapability);
+
+    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&PROCESSES));
+
+    let dynamic_deferred_call_clients =
+        static_init!([DynamicDeferredCallClientState; 2], Default::default());
+    let dynamic_deferred_caller = static_init!(
+        DynamicDeferredCall,
+        DynamicDeferredCall::new(dynamic_deferred_call_clients)
+    );
+    DynamicDeferredCall::set_global_instance(dynamic_deferred_caller);
+
+    let chip = static_init!(
+        LinuxHost<LinuxHostDefaultPeripherals>,
+        LinuxHost::new(interrupts, &apps::APPS, peripherals)
+    );
+    CHIP = Some(chip);
+
+    // Create a shared UART channel for the consoles and for kernel debug.
+    let uart_mux = components::console::UartMuxComponent::new(
+        &peripherals.uart,
+        115200,
+        dynamic_deferred_caller,
+    )
+    .finalize(());
+
+    // Setup the console.
+    let console = components::console::ConsoleComponent::new(board_kernel, uart_mux).finalize(());
+    // Create the debugger object that handles calls to `debug!()`.
+    components::debug_writer::DebugWriterComponent::new(uart_mux).finalize(());
+
+    // Setup the process console. The kernel is not placed in memory by a
+    // linker script, so there are no kernel addresses to show.
+    let process_console_uart = static_init!(
+        capsules::virtual_uart::UartDevice,
+        capsules::virtual_uart::UartDevice::new(uart_mux, true)
+    );
+    process_console_uart.setup();
+    let process_console = static_init!(
+        capsules::process_console::ProcessConsole<'static, ProcessManagementCapability>,
+        capsules::process_console::ProcessConsole::new(
+            process_console_uart,
+            &mut capsules::process_console::WRITE_BUF,
+            &mut capsules::process_console::READ_BUF,
+            &mut capsules::process_console::QUEUE_BUF,
+            &mut capsules::process_console::COMMAND_BUF,
+            board_kernel,
+            capsules::process_console::KernelAddresses {
+                stack_start: ptr::null(),
+                stack_end: ptr::null(),
+                text_start: ptr::null(),
+                text_end: ptr::null(),
+                read_only_data_start: ptr::null(),
+                relocations_start: ptr::null(),
+                relocations_end: ptr::null(),
+                bss_start: ptr::null(),
+                bss_end: ptr::null(),
+            },
+            ProcessManagementCapability,
+        )
+    );
+    hil::uart::Transmit::set_transmit_client(process_console_uart, process_console);
+    hil::uart::Receive::set_receive_client(process_console_uart, process_console);
+
+    // Alarm
+    let mux_alarm = components::alarm::AlarmMuxComponent::new(&peripherals.alarm).finalize(
+        components::alarm_mux_component_helper!(linux_host::alarm::Alarm),
+    );
+    let alarm = components::alarm::AlarmDriverComponent::new(board_kernel, mux_alarm).finalize(
+        components::alarm_component_helper!(linux_host::alarm::Alarm),
+    );
+
+    let nonvolatile_storage = components::nonvolatile_storage::NonvolatileStorageComponent::new(
+        board_kernel,
+        &peripherals.flash,
+        0,
+        STORAGE_SIZE,
+        0,
+        0,
+    )
+    .finalize(components::nv_storage_component_helper!(
+        linux_host::flash::Flash
+    ));
+
+    let host = Host {
+        console,
+        alarm,
+        nonvolatile_storage,
+    };
+
+    let _ = process_console.start();
+    debug!("Host initialization complete. Entering main loop.");
+
+    kernel::procs::load_processes(
+        board_kernel,
+        chip,
+        app_flash,
+        &mut APP_MEMORY,
+        &FAULT_RESPONSE,
+        &process_mgmt_cap,
+    )
+    .unwrap_or_else(|err| {
+        debug!("Error loading processes!");
+        debug!("{:?}", err);
+    });
+
+    let scheduler = components::sched::round_robin::RoundRobinComponent::new(&PROCESSES)
+        .finalize(components::rr_component_helper!(NUM_PROCS));
+    board_kernel.kernel_loop(
+        &host,
+        chip,
+        None::<&kernel::ipc::IPC<NUM_PROCS>>,
+        scheduler,
+        &main_loop_cap,
+    );
+}
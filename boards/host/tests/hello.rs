@@ -0,0 +1,101 @@
+//! Run the host board and check the output of its processes and of the
+//! process console.
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Board {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+impl Board {
+    fn start(test: &str) -> Board {
+        let flash =
+            std::env::temp_dir().join(format!("tock-host-{}-{}.bin", test, std::process::id()));
+        let _ = std::fs::remove_file(&flash);
+
+        let mut child = Command::new(env!("CARGO_BIN_EXE_host"))
+            .arg(&flash)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("cannot start the host board");
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                match line {
+                    Ok(line) => {
+                        if sender.send(line).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+
+        Board {
+            child,
+            stdin,
+            lines,
+        }
+    }
+
+    /// Wait for a line of output containing `text`.
+    fn expect(&self, text: &str) -> String {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.lines.recv_timeout(remaining) {
+                Ok(line) if line.contains(text) => return line,
+                Ok(_) => {}
+                Err(_) => panic!("no output containing {:?}", text),
+            }
+        }
+    }
+
+    fn send(&mut self, input: &str) {
+        self.stdin.write_all(input.as_bytes()).unwrap();
+        self.stdin.flush().unwrap();
+    }
+}
+
+impl Drop for Board {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[test]
+fn process_prints_to_console() {
+    let board = Board::start("print");
+    board.expect("Hello from a host process!");
+}
+
+#[test]
+fn process_console_lists_processes() {
+    let mut board = Board::start("list");
+    board.expect("Hello from a host process!");
+    // The process exits shortly after it printed.
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        board.send("list\n");
+        let line = board.expect("hello");
+        if line.contains("Terminated") {
+            break;
+        }
+        assert!(Instant::now() < deadline, "unexpected state: {}", line);
+        thread::sleep(Duration::from_millis(100));
+    }
+}
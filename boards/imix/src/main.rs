@@ -93,7 +93,8 @@ const PAN_ID: u16 = 0xABCD;
 // how should the kernel respond when a process faults
 const FAULT_RESPONSE: kernel::procs::PanicFaultPolicy = kernel::procs::PanicFaultPolicy {};
 
-static mut PROCESSES: [Option<&'static dyn kernel::procs::Process>; NUM_PROCS] = [None; NUM_PROCS];
+static mut PROCESSES: [kernel::procs::ProcessSlot; NUM_PROCS] =
+    [kernel::procs::EMPTY_PROCESS_SLOT; NUM_PROCS];
 
 static mut CHIP: Option<&'static sam4l::chip::Sam4l<Sam4lDefaultPeripherals>> = None;
 
@@ -330,6 +331,14 @@ pub unsafe fn main() {
     let alarm = AlarmDriverComponent::new(board_kernel, mux_alarm)
         .finalize(components::alarm_component_helper!(sam4l::ast::Ast));
 
+    // Only enter deep sleep if the chip wakes up in time for the next alarm.
+    let power_manager = static_init!(
+        kernel::power::PowerManager<0>,
+        kernel::power::PowerManager::new()
+    );
+    power_manager.set_wakeup_deadline(mux_alarm);
+    board_kernel.set_sleep_policy(power_manager, &main_cap);
+
     // # I2C and I2C Sensors
     let mux_i2c = static_init!(
         MuxI2C<'static>,
@@ -632,7 +641,6 @@ pub unsafe fn main() {
             &mut _sappmem as *mut u8,
             &_eappmem as *const u8 as usize - &_sappmem as *const u8 as usize,
         ),
-        &mut PROCESSES,
         &FAULT_RESPONSE,
         &process_mgmt_cap,
     )
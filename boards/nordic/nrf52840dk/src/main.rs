@@ -141,7 +141,8 @@ const FAULT_RESPONSE: kernel::procs::PanicFaultPolicy = kernel::procs::PanicFaul
 // Number of concurrent processes this platform supports.
 const NUM_PROCS: usize = 8;
 
-static mut PROCESSES: [Option<&'static dyn kernel::procs::Process>; NUM_PROCS] = [None; NUM_PROCS];
+static mut PROCESSES: [kernel::procs::ProcessSlot; NUM_PROCS] =
+    [kernel::procs::EMPTY_PROCESS_SLOT; NUM_PROCS];
 
 static mut CHIP: Option<&'static nrf52840::chip::NRF52<Nrf52840DefaultPeripherals>> = None;
 
@@ -320,7 +321,7 @@ pub unsafe fn main() {
 
     let chip = static_init!(
         nrf52840::chip::NRF52<Nrf52840DefaultPeripherals>,
-        nrf52840::chip::NRF52::new(nrf52840_peripherals)
+        nrf52840::chip::NRF52::new(nrf52840_peripherals, &base_peripherals.clock)
     );
     CHIP = Some(chip);
 
@@ -413,6 +414,18 @@ pub unsafe fn main() {
         nrf52840::aes::AesECB<'static>
     ));
 
+    // Stop the HFXO while sleeping, unless a radio or USB is on or the next
+    // alarm fires before the HFXO could start again.
+    let power_manager = static_init!(
+        kernel::power::PowerManager<3>,
+        kernel::power::PowerManager::new()
+    );
+    power_manager.set_wakeup_deadline(mux_alarm);
+    let _ = power_manager.register(&base_peripherals.ble_radio);
+    let _ = power_manager.register(&base_peripherals.ieee802154_radio);
+    let _ = power_manager.register(&nrf52840_peripherals.usbd);
+    board_kernel.set_sleep_policy(power_manager, &main_loop_capability);
+
     let local_ip_ifaces = static_init!(
         [IPAddr; 3],
         [
@@ -626,7 +639,6 @@ pub unsafe fn main() {
             &mut _sappmem as *mut u8,
             &_eappmem as *const u8 as usize - &_sappmem as *const u8 as usize,
         ),
-        &mut PROCESSES,
         &FAULT_RESPONSE,
         &process_management_capability,
     )
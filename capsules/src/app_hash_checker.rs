@@ -0,0 +1,227 @@
+//! Credentials checker that verifies the SHA-256 hash of processes.
+//!
+//! A TBF can carry a `SHA256` credentials footer, which holds the SHA-256
+//! hash of the TBF header and the application binary. `AppHashChecker`
+//! computes the hash of every process as it is loaded and compares it with the
+//! footer, so that processes whose flash was corrupted or modified after they
+//! were built do not run. A hash only detects modifications; it does not tell
+//! who built the process, which requires a signature.
+//!
+//! The hash is computed in software while processes are loaded, which takes
+//! some time for large processes.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let checker = static_init!(
+//!     capsules::app_hash_checker::AppHashChecker,
+//!     capsules::app_hash_checker::AppHashChecker::new(true)
+//! );
+//! kernel::procs::load_and_check_processes(
+//!     board_kernel,
+//!     chip,
+//!     app_flash,
+//!     app_memory,
+//!     &FAULT_RESPONSE,
+//!     checker,
+//!     &process_management_capability,
+//! )
+//! ```
+
+use core::convert::TryInto;
+use kernel::procs::{AppCredentialsChecker, CheckResult};
+use kernel::procs::{TbfFooterV2Credentials, TbfFooterV2CredentialsType};
+
+/// Accepts processes with a matching `SHA256` footer, and rejects processes
+/// with a `SHA256` footer that does not match. Other credentials are passed.
+pub struct AppHashChecker {
+    require_hash: bool,
+}
+
+impl AppHashChecker {
+    /// If `require_hash` is true, processes without a matching `SHA256`
+    /// footer do not run.
+    pub const fn new(require_hash: bool) -> AppHashChecker {
+        AppHashChecker { require_hash }
+    }
+}
+
+impl AppCredentialsChecker for AppHashChecker {
+    fn require_credentials(&self) -> bool {
+        self.require_hash
+    }
+
+    fn check_credentials(
+        &self,
+        credentials: &TbfFooterV2Credentials,
+        binary: &'static [u8],
+    ) -> CheckResult {
+        match credentials.format() {
+            TbfFooterV2CredentialsType::SHA256 => {
+                if sha256(binary)[..] == credentials.data()[..] {
+                    CheckResult::Accept
+                } else {
+                    CheckResult::Reject
+                }
+            }
+            _ => CheckResult::Pass,
+        }
+    }
+}
+
+/// Round constants of SHA-256.
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Initial hash value of SHA-256.
+const H0: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// Add the 64 byte block `block` to the hash `state`.
+fn compress(state: &mut [u32; 8], block: &[u8]) {
+    let mut w = [0u32; 64];
+    for (i, word) in block.chunks_exact(4).enumerate() {
+        w[i] = u32::from_be_bytes(word.try_into().unwrap());
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(w[i - 7])
+            .wrapping_add(s1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for i in 0..64 {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let temp1 = h
+            .wrapping_add(s1)
+            .wrapping_add(ch)
+            .wrapping_add(K[i])
+            .wrapping_add(w[i]);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let temp2 = s0.wrapping_add(maj);
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(temp1);
+        d = c;
+        c = b;
+        b = a;
+        a = temp1.wrapping_add(temp2);
+    }
+    for (value, add) in state.iter_mut().zip([a, b, c, d, e, f, g, h].iter()) {
+        *value = value.wrapping_add(*add);
+    }
+}
+
+/// The SHA-256 hash of `data`.
+fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut state = H0;
+    let mut blocks = data.chunks_exact(64);
+    for block in &mut blocks {
+        compress(&mut state, block);
+    }
+
+    // Pad the rest with a 1 bit, zeros and the length in bits, which takes
+    // one or two more blocks.
+    let rest = blocks.remainder();
+    let mut last = [0u8; 128];
+    last[..rest.len()].copy_from_slice(rest);
+    last[rest.len()] = 0x80;
+    let last_len = if rest.len() < 56 { 64 } else { 128 };
+    let bits = (data.len() as u64).wrapping_mul(8);
+    last[last_len - 8..last_len].copy_from_slice(&bits.to_be_bytes());
+    for block in last[..last_len].chunks_exact(64) {
+        compress(&mut state, block);
+    }
+
+    let mut hash = [0u8; 32];
+    for (bytes, value) in hash.chunks_exact_mut(4).zip(state.iter()) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use core::convert::TryFrom;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    fn hex(hash: [u8; 32]) -> std::string::String {
+        hash.iter()
+            .map(|byte| std::format!("{:02x}", byte))
+            .collect()
+    }
+
+    #[test]
+    fn sha256_matches_test_vectors() {
+        assert_eq!(
+            hex(sha256(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        // Needs a second block for the length.
+        assert_eq!(
+            hex(sha256(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        assert_eq!(
+            hex(sha256(&[b'a'; 1000])),
+            "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3"
+        );
+    }
+
+    /// Credentials of `format` with `data`.
+    fn credentials(format: u32, data: &[u8]) -> TbfFooterV2Credentials {
+        let mut bytes = Vec::from(format.to_le_bytes());
+        bytes.extend_from_slice(data);
+        TbfFooterV2Credentials::try_from(&*Box::leak(bytes.into_boxed_slice())).unwrap()
+    }
+
+    #[test]
+    fn checker_compares_hash_of_binary() {
+        let binary: &'static [u8] = Box::leak(Box::new([0x5a; 300]));
+        let checker = AppHashChecker::new(true);
+        assert!(checker.require_credentials());
+        assert_eq!(
+            checker.check_credentials(&credentials(5, &sha256(binary)), binary),
+            CheckResult::Accept
+        );
+
+        let mut wrong = sha256(binary);
+        wrong[31] ^= 1;
+        assert_eq!(
+            checker.check_credentials(&credentials(5, &wrong), binary),
+            CheckResult::Reject
+        );
+
+        // A SHA-512 hash is not checked.
+        assert_eq!(
+            checker.check_credentials(&credentials(7, &[0; 64]), binary),
+            CheckResult::Pass
+        );
+    }
+}
@@ -0,0 +1,334 @@
+//! Load new applications while the kernel is running.
+//!
+//! This allows a userspace process (for example a service that receives
+//! updates over the network) to add a new application to the board without a
+//! reboot. The process writes a TBF into the free region of app flash, after
+//! the last app, and then asks the kernel to load it. The kernel validates the
+//! TBF header, allocates memory for the new process from the memory that was
+//! not used at boot, and starts it. As the new TBF is appended to the app
+//! linked list, it is also loaded normally on the next boot.
+//!
+//! A process that can load apps can run any code it likes as a new process,
+//! so the board lists the persistent `AppId`s of the applications that may
+//! use this driver. All other processes, including anonymous ones, get
+//! `NODEVICE`. This is only as strong as the board's `AppIdPolicy` and
+//! credentials checker: with `TbfHeaderAppIdPolicy`, only processes whose
+//! credentials the checker verified have a persistent `AppId`.
+//!
+//! Only one process can load an app at a time. A load starts with the setup
+//! command, which reserves the free region of flash for the calling process.
+//!
+//! The TBF is written at the start of the free region, so the previous app
+//! must be padded such that the MPU can protect the new app at that address.
+//!
+//! The TBF is written through `hil::nonvolatile_storage` rather than directly
+//! through `hil::flash`. The process writes the TBF in chunks of the size of
+//! its buffer, which in general do not start or end at a page boundary, and
+//! the last app may end in the middle of the page the new TBF starts in. A
+//! board wraps its flash in `NonvolatileToPages`, which does the
+//! read-modify-write of each page the chunks touch.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! # use kernel::static_init;
+//!
+//! const UPDATER: u32 = 0x5e45_0010;
+//!
+//! pub static mut APP_LOADER_BUFFER: [u8; 512] = [0; 512];
+//! let app_loader = static_init!(
+//!     capsules::app_loader::AppLoader<'static, ProcessMgmtCap>,
+//!     capsules::app_loader::AppLoader::new(
+//!         nv_to_page,
+//!         dynamic_process_loader,
+//!         board_kernel.create_grant(&grant_cap),
+//!         &[UPDATER],
+//!         &mut APP_LOADER_BUFFER,
+//!         ProcessMgmtCap,
+//!     )
+//! );
+//! hil::nonvolatile_storage::NonvolatileStorage::set_client(nv_to_page, app_loader);
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+use core::mem;
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::common::cells::{OptionalCell, TakeCell};
+use kernel::hil;
+use kernel::procs::{DynamicProcessLoading, ProcessLoadError};
+use kernel::ErrorCode;
+use kernel::{CommandReturn, Driver, Grant, ProcessId, Read, ReadOnlyAppSlice, Upcall};
+
+/// Syscall driver number.
+use crate::driver;
+pub const DRIVER_NUM: usize = driver::NUM::AppLoader as usize;
+
+#[derive(Default)]
+pub struct App {
+    callback: Upcall,
+    buffer: ReadOnlyAppSlice,
+}
+
+pub struct AppLoader<'a, C: ProcessManagementCapability> {
+    storage: &'a dyn hil::nonvolatile_storage::NonvolatileStorage<'static>,
+    loader: &'a dyn DynamicProcessLoading,
+    apps: Grant<App>,
+    /// The persistent `AppId`s of the applications that may load apps.
+    allowed: &'static [u32],
+    /// The process that reserved the free region of flash, if any.
+    current_app: OptionalCell<ProcessId>,
+    /// Address and length of the region of flash reserved for the new app.
+    region: Cell<(usize, usize)>,
+    /// Whether a write to flash is in progress.
+    busy: Cell<bool>,
+    buffer: TakeCell<'static, [u8]>,
+    capability: C,
+}
+
+impl<'a, C: ProcessManagementCapability> AppLoader<'a, C> {
+    pub fn new(
+        storage: &'a dyn hil::nonvolatile_storage::NonvolatileStorage<'static>,
+        loader: &'a dyn DynamicProcessLoading,
+        grant: Grant<App>,
+        allowed: &'static [u32],
+        buffer: &'static mut [u8],
+        capability: C,
+    ) -> AppLoader<'a, C> {
+        AppLoader {
+            storage,
+            loader,
+            apps: grant,
+            allowed,
+            current_app: OptionalCell::empty(),
+            region: Cell::new((0, 0)),
+            busy: Cell::new(false),
+            buffer: TakeCell::new(buffer),
+            capability,
+        }
+    }
+
+    /// Whether the application of `appid` may load apps.
+    fn is_allowed(&self, appid: ProcessId) -> bool {
+        appid
+            .get_app_id()
+            .id()
+            .map_or(false, |id| self.allowed.contains(&id))
+    }
+
+    /// Reserve the free region of flash for `appid`, if it is large enough
+    /// for a TBF of `size` bytes.
+    fn setup(&self, size: usize, appid: ProcessId) -> Result<(), ErrorCode> {
+        // The region stays reserved for another process only as long as that
+        // process exists.
+        let reserved = self.current_app.map_or(false, |app| {
+            *app != appid && self.apps.enter(*app, |_| ()).is_ok()
+        });
+        if reserved || self.busy.get() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        let (address, length) = self.loader.free_flash(&self.capability);
+        if size == 0 || size > length {
+            return Err(ErrorCode::SIZE);
+        }
+        self.region.set((address, size));
+        self.current_app.set(appid);
+        Ok(())
+    }
+
+    /// Write the allowed buffer to `offset` in the reserved region.
+    fn write(&self, offset: usize, length: usize, appid: ProcessId) -> Result<(), ErrorCode> {
+        if self.current_app.map_or(true, |app| *app != appid) {
+            return Err(ErrorCode::RESERVE);
+        }
+        if self.busy.get() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        let (address, size) = self.region.get();
+        if offset >= size || length > size - offset {
+            return Err(ErrorCode::INVAL);
+        }
+
+        self.apps
+            .enter(appid, |app| {
+                app.buffer.map_or(Err(ErrorCode::RESERVE), |app_buffer| {
+                    self.buffer.take().map_or(Err(ErrorCode::NOMEM), |buffer| {
+                        if length > cmp::min(buffer.len(), app_buffer.len()) {
+                            self.buffer.replace(buffer);
+                            return Err(ErrorCode::SIZE);
+                        }
+                        buffer[..length].copy_from_slice(&app_buffer[..length]);
+
+                        self.busy.set(true);
+                        self.storage
+                            .write(buffer, address + offset, length)
+                            .map_err(|e| {
+                                self.busy.set(false);
+                                e
+                            })
+                    })
+                })
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+
+    /// Load the TBF in the reserved region as a new process.
+    fn load(&self, appid: ProcessId) -> Result<(), ErrorCode> {
+        if self.current_app.map_or(true, |app| *app != appid) {
+            return Err(ErrorCode::RESERVE);
+        }
+        if self.busy.get() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        // The free region only moves when an app is loaded, but check anyway
+        // that the TBF is where the kernel will look for it.
+        let (address, size) = self.region.get();
+        let (free_address, _) = self.loader.free_flash(&self.capability);
+        if address != free_address {
+            return Err(ErrorCode::INVAL);
+        }
+
+        // Limit the TBF to the region the process reserved, so the kernel
+        // does not load flash that was not written.
+        let result = self.loader.load_new_process(size, &self.capability);
+        // Whether or not loading worked, the process must start again from
+        // setup as the free region has either moved or must be rewritten.
+        self.current_app.clear();
+        match result {
+            Ok(Some(_)) => Ok(()),
+            Ok(None) => Err(ErrorCode::CANCEL),
+            Err(ProcessLoadError::NotEnoughMemory) => Err(ErrorCode::NOMEM),
+            Err(ProcessLoadError::NotEnoughFlash) => Err(ErrorCode::SIZE),
+            Err(_) => Err(ErrorCode::INVAL),
+        }
+    }
+
+    /// Release the reserved region without loading anything.
+    fn abort(&self, appid: ProcessId) -> Result<(), ErrorCode> {
+        if self.current_app.map_or(true, |app| *app != appid) {
+            return Err(ErrorCode::RESERVE);
+        }
+        if self.busy.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.current_app.clear();
+        Ok(())
+    }
+}
+
+impl<C: ProcessManagementCapability> hil::nonvolatile_storage::NonvolatileStorageClient<'static>
+    for AppLoader<'_, C>
+{
+    fn read_done(&self, _buffer: &'static mut [u8], _length: usize) {}
+
+    fn write_done(&self, buffer: &'static mut [u8], length: usize) {
+        self.buffer.replace(buffer);
+        self.busy.set(false);
+
+        self.current_app.map(|appid| {
+            let _ = self.apps.enter(*appid, |app| {
+                app.callback.schedule(length, 0, 0);
+            });
+        });
+    }
+}
+
+impl<C: ProcessManagementCapability> Driver for AppLoader<'_, C> {
+    /// Setup buffer to write from.
+    ///
+    /// ### `allow_num`
+    ///
+    /// - `0`: Set the buffer holding the part of the TBF to write.
+    fn allow_readonly(
+        &self,
+        appid: ProcessId,
+        allow_num: usize,
+        mut slice: ReadOnlyAppSlice,
+    ) -> Result<ReadOnlyAppSlice, (ReadOnlyAppSlice, ErrorCode)> {
+        let res = match allow_num {
+            0 => self
+                .apps
+                .enter(appid, |app| {
+                    mem::swap(&mut app.buffer, &mut slice);
+                    Ok(())
+                })
+                .unwrap_or_else(|err| Err(err.into())),
+            _ => Err(ErrorCode::NOSUPPORT),
+        };
+
+        match res {
+            Ok(()) => Ok(slice),
+            Err(e) => Err((slice, e)),
+        }
+    }
+
+    /// Setup callbacks.
+    ///
+    /// ### `subscribe_num`
+    ///
+    /// - `0`: Set a write done callback. The first argument is the number of
+    ///   bytes written.
+    fn subscribe(
+        &self,
+        subscribe_num: usize,
+        mut callback: Upcall,
+        app_id: ProcessId,
+    ) -> Result<Upcall, (Upcall, ErrorCode)> {
+        let res = match subscribe_num {
+            0 => self
+                .apps
+                .enter(app_id, |app| {
+                    mem::swap(&mut app.callback, &mut callback);
+                    Ok(())
+                })
+                .unwrap_or_else(|err| Err(err.into())),
+            _ => Err(ErrorCode::NOSUPPORT),
+        };
+
+        match res {
+            Ok(()) => Ok(callback),
+            Err(e) => Err((callback, e)),
+        }
+    }
+
+    /// App loader control.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    ///
+    /// The following commands return `NODEVICE` unless the board allows the
+    /// application to load apps.
+    ///
+    /// - `1`: Reserve the free region of app flash for a TBF of `arg1` bytes.
+    /// - `2`: Write `arg2` bytes of the allowed buffer to offset `arg1` in the
+    ///   reserved region.
+    /// - `3`: Load the TBF written to the reserved region as a new process.
+    /// - `4`: Release the reserved region without loading a process.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        arg2: usize,
+        appid: ProcessId,
+    ) -> CommandReturn {
+        let res = match command_num {
+            0 /* This driver exists. */ => Ok(()),
+            _ if !self.is_allowed(appid) => Err(ErrorCode::NODEVICE),
+            1 /* Setup */ => self.setup(arg1, appid),
+            2 /* Write */ => self.write(arg1, arg2, appid),
+            3 /* Load */ => self.load(appid),
+            4 /* Abort */ => self.abort(appid),
+            _ /* Unknown command num */ => Err(ErrorCode::NOSUPPORT),
+        };
+
+        match res {
+            Ok(()) => CommandReturn::success(),
+            Err(e) => CommandReturn::failure(e),
+        }
+    }
+}
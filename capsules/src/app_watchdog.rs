@@ -0,0 +1,330 @@
+//! Software watchdog for processes.
+//!
+//! A process starts its watchdog with a heartbeat interval, and must then
+//! ping the watchdog at least once every interval. If a process misses its
+//! heartbeat, for example because it hangs waiting for an event that never
+//! comes, the kernel counts the expiration in the process's debug
+//! information and applies the `WatchdogAction` the board chose:
+//!
+//! - `Restart`: Restart the process.
+//! - `Stop`: Stop the process, so it can be inspected and resumed from the
+//!   process console. The watchdog of the process is stopped as well.
+//! - `Reboot`: Stop the process and stop tickling the hardware watchdog, so
+//!   that it resets the board. This needs a chip with a hardware watchdog
+//!   (its `Chip::WatchDog` is not `()`). On other chips the board is not
+//!   reset: the process stays stopped, the kernel prints a warning and keeps
+//!   running without sleeping. Boards without a hardware watchdog should use
+//!   `Restart` or `Stop` instead.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! # use kernel::static_init;
+//!
+//! let app_watchdog = components::app_watchdog::AppWatchdogComponent::new(
+//!     board_kernel,
+//!     mux_alarm,
+//!     capsules::app_watchdog::WatchdogAction::Restart,
+//! )
+//! .finalize(components::app_watchdog_component_helper!(sam4l::ast::Ast));
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! ### Command
+//!
+//! - `0`: Driver check.
+//! - `1`: Start the watchdog with a heartbeat interval of `data` milliseconds,
+//!   or change the interval of a running watchdog. The interval starts at the
+//!   call.
+//! - `2`: Ping the watchdog, which starts a new interval.
+//! - `3`: Stop the watchdog.
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::debug;
+use kernel::hil::time::{self, Alarm, Ticks};
+use kernel::{CommandReturn, Driver, ErrorCode, Grant, Kernel, ProcessId};
+
+/// Syscall driver number.
+use crate::driver;
+pub const DRIVER_NUM: usize = driver::NUM::AppWatchdog as usize;
+
+/// The completion code of a process the watchdog restarts.
+pub const WATCHDOG_COMPLETION_CODE: u32 = 0xDEAD_0001;
+
+/// What the kernel does when a process misses its heartbeat.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchdogAction {
+    Restart,
+    Stop,
+    /// Reset the board with the hardware watchdog. Only resets boards whose
+    /// chip has a hardware watchdog, see the module documentation.
+    Reboot,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Expiration<T: Ticks> {
+    Disabled,
+    Enabled { reference: T, dt: T },
+}
+
+impl<T: Ticks> Expiration<T> {
+    /// Ticks until the heartbeat is missed, or `None` if it already was.
+    fn remaining(&self, now: T) -> Option<T> {
+        match *self {
+            Expiration::Disabled => None,
+            Expiration::Enabled { reference, dt } => {
+                let end = reference.wrapping_add(dt);
+                if now.within_range(reference, end) {
+                    Some(end.wrapping_sub(now))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+pub struct App<T: Ticks> {
+    expiration: Expiration<T>,
+}
+
+impl<T: Ticks> Default for App<T> {
+    fn default() -> App<T> {
+        App {
+            expiration: Expiration::Disabled,
+        }
+    }
+}
+
+pub struct AppWatchdog<'a, A: Alarm<'a>, C: ProcessManagementCapability> {
+    kernel: &'static Kernel,
+    alarm: &'a A,
+    apps: Grant<App<A::Ticks>>,
+    action: WatchdogAction,
+    capability: C,
+}
+
+impl<'a, A: Alarm<'a>, C: ProcessManagementCapability> AppWatchdog<'a, A, C> {
+    pub fn new(
+        kernel: &'static Kernel,
+        alarm: &'a A,
+        grant: Grant<App<A::Ticks>>,
+        action: WatchdogAction,
+        capability: C,
+    ) -> AppWatchdog<'a, A, C> {
+        AppWatchdog {
+            kernel,
+            alarm,
+            apps: grant,
+            action,
+            capability,
+        }
+    }
+
+    /// Set the alarm for the earliest heartbeat, or disarm it if no watchdog
+    /// runs.
+    fn reset_active_alarm(&self) {
+        let now = self.alarm.now();
+        let mut earliest: Option<A::Ticks> = None;
+        for app in self.apps.iter() {
+            app.enter(|app| {
+                if let Expiration::Enabled { .. } = app.expiration {
+                    // A heartbeat that was already missed expires right away.
+                    let remaining = app
+                        .expiration
+                        .remaining(now)
+                        .unwrap_or_else(|| A::Ticks::from(0));
+                    earliest = Some(earliest.map_or(remaining, |e| e.min(remaining)));
+                }
+            });
+        }
+        match earliest {
+            Some(remaining) => self.alarm.set_alarm(now, remaining),
+            None => {
+                let _ = self.alarm.disarm();
+            }
+        }
+    }
+
+    /// Find a process that missed its heartbeat and stop its watchdog.
+    fn take_expired(&self) -> Option<ProcessId> {
+        let now = self.alarm.now();
+        let mut expired = None;
+        for app in self.apps.iter() {
+            let processid = app.processid();
+            app.enter(|app| {
+                if let Expiration::Enabled { .. } = app.expiration {
+                    if app.expiration.remaining(now).is_none() {
+                        app.expiration = Expiration::Disabled;
+                        expired = Some(processid);
+                    }
+                }
+            });
+            if expired.is_some() {
+                break;
+            }
+        }
+        expired
+    }
+
+    fn expired(&self, processid: ProcessId) {
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                if process.processid() != processid {
+                    return;
+                }
+                process.debug_watchdog_expired();
+                match self.action {
+                    WatchdogAction::Restart => process.try_restart(WATCHDOG_COMPLETION_CODE),
+                    WatchdogAction::Stop => process.stop(),
+                    WatchdogAction::Reboot => {
+                        debug!(
+                            "app_watchdog: {} missed its heartbeat, waiting for the hardware watchdog to reset the board",
+                            process.get_process_name()
+                        );
+                        process.stop();
+                        self.kernel.watchdog_reset(&self.capability);
+                    }
+                }
+            });
+    }
+}
+
+impl<'a, A: Alarm<'a>, C: ProcessManagementCapability> time::AlarmClient for AppWatchdog<'a, A, C> {
+    fn alarm(&self) {
+        // The action is applied outside of the grant, as restarting a process
+        // frees its grant.
+        while let Some(processid) = self.take_expired() {
+            self.expired(processid);
+        }
+        self.reset_active_alarm();
+    }
+}
+
+impl<'a, A: Alarm<'a>, C: ProcessManagementCapability> Driver for AppWatchdog<'a, A, C> {
+    /// Control the watchdog of the process.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Start the watchdog with a heartbeat interval of `data`
+    ///   milliseconds. Returns `INVAL` if the interval is 0.
+    /// - `2`: Ping the watchdog. Returns `OFF` if the watchdog is stopped.
+    /// - `3`: Stop the watchdog. Returns `ALREADY` if it is stopped.
+    fn command(
+        &self,
+        command_num: usize,
+        data: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        if command_num == 0 {
+            return CommandReturn::success();
+        }
+
+        let now = self.alarm.now();
+        let res = self
+            .apps
+            .enter(processid, |app| match command_num {
+                1 => {
+                    if data == 0 {
+                        return Err(ErrorCode::INVAL);
+                    }
+                    let dt = A::ticks_from_ms(data as u32);
+                    app.expiration = Expiration::Enabled { reference: now, dt };
+                    Ok(())
+                }
+                2 => match app.expiration {
+                    Expiration::Enabled { dt, .. } => {
+                        app.expiration = Expiration::Enabled { reference: now, dt };
+                        Ok(())
+                    }
+                    Expiration::Disabled => Err(ErrorCode::OFF),
+                },
+                3 => match app.expiration {
+                    Expiration::Enabled { .. } => {
+                        app.expiration = Expiration::Disabled;
+                        Ok(())
+                    }
+                    Expiration::Disabled => Err(ErrorCode::ALREADY),
+                },
+                _ => Err(ErrorCode::NOSUPPORT),
+            })
+            .unwrap_or_else(|err| Err(err.into()));
+
+        match res {
+            Ok(()) => {
+                self.reset_active_alarm();
+                CommandReturn::success()
+            }
+            Err(e) => CommandReturn::failure(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kernel::hil::time::{Ticks24, Ticks32};
+
+    #[test]
+    fn disabled_watchdog_never_has_time_left() {
+        let expiration: Expiration<Ticks32> = Expiration::Disabled;
+        assert_eq!(expiration.remaining(Ticks32::from(0)), None);
+    }
+
+    #[test]
+    fn remaining_counts_down_to_the_heartbeat() {
+        let expiration = Expiration::Enabled {
+            reference: Ticks32::from(1000),
+            dt: Ticks32::from(100),
+        };
+        assert_eq!(
+            expiration.remaining(Ticks32::from(1000)),
+            Some(Ticks32::from(100))
+        );
+        assert_eq!(
+            expiration.remaining(Ticks32::from(1099)),
+            Some(Ticks32::from(1))
+        );
+        assert_eq!(expiration.remaining(Ticks32::from(1100)), None);
+        assert_eq!(expiration.remaining(Ticks32::from(5000)), None);
+    }
+
+    #[test]
+    fn remaining_wraps_around_32_bit_ticks() {
+        let expiration = Expiration::Enabled {
+            reference: Ticks32::from(0xFFFF_FFF0),
+            dt: Ticks32::from(0x20),
+        };
+        assert_eq!(
+            expiration.remaining(Ticks32::from(0xFFFF_FFFF)),
+            Some(Ticks32::from(0x11))
+        );
+        assert_eq!(
+            expiration.remaining(Ticks32::from(0x0F)),
+            Some(Ticks32::from(0x1))
+        );
+        assert_eq!(expiration.remaining(Ticks32::from(0x10)), None);
+    }
+
+    #[test]
+    fn remaining_wraps_around_24_bit_ticks() {
+        let expiration = Expiration::Enabled {
+            reference: Ticks24::from(0x00FF_FFF0),
+            dt: Ticks24::from(0x20),
+        };
+        // The counter wrapped to zero, so only 0x15 ticks passed.
+        assert_eq!(
+            expiration.remaining(Ticks24::from(0x05)),
+            Some(Ticks24::from(0x0B))
+        );
+        assert_eq!(expiration.remaining(Ticks24::from(0x10)), None);
+        // Ticks before the reference are a missed heartbeat of an earlier
+        // period, not time left.
+        assert_eq!(expiration.remaining(Ticks24::from(0x00FF_FF00)), None);
+    }
+}
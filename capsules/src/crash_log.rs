@@ -0,0 +1,839 @@
+//! Saves a record of every process fault to a persistent log.
+//!
+//! When a process faults the kernel prints its state to the debug console,
+//! where it is lost if nobody is watching. `CrashLog` is a
+//! `ProcessFaultPolicy` that instead saves a crash record for the process in
+//! a persistent log (see `capsules::log`), and then lets another fault policy
+//! decide what happens to the process. The records can be read back after the
+//! board reboots, for example with the `crashes` command of the process
+//! console.
+//!
+//! A crash record contains:
+//!
+//! - the process name and its persistent `AppId`,
+//! - how often the process restarted, and its syscall and dropped upcall
+//!   counts,
+//! - the last syscall the process called,
+//! - the memory map of the process,
+//! - the registers the kernel saved for the process, as stored by
+//!   `UserspaceKernelBoundary::store_context()`, and
+//! - the top of the process stack.
+//!
+//! Only one record can be written at a time. If a process faults while the
+//! previous record is still being written, the fault is not recorded, and is
+//! counted instead. Records are only written after `action()` returns, so the
+//! wrapped fault policy should not panic the board.
+//!
+//! Record format
+//! -------------
+//!
+//! Each record is a single log entry of `RECORD_SIZE` bytes. All values are
+//! little endian.
+//!
+//! ```text
+//! Offset  Size  Field
+//!      0     2  Magic (0x5243)
+//!      2     1  Version (1)
+//!      3     1  Flags: bit 0 AppId is valid, bit 1 last syscall is valid
+//!      4     1  Length of the process name
+//!      5     1  Class of the last syscall
+//!      6     1  Length of the registers
+//!      7     1  Length of the stack excerpt
+//!      8    16  Process name, truncated
+//!     24     4  AppId
+//!     28     4  Restart count
+//!     32     4  Syscall count
+//!     36     4  Dropped upcall count
+//!     40    16  The four register arguments of the last syscall
+//!     56    40  Memory map: flash start, flash non-protected start, flash end,
+//!               memory start, memory end, app break, kernel memory break,
+//!               heap start, stack start, lowest stack pointer (zero if not
+//!               known)
+//!     96   136  Registers
+//!    232    64  Stack excerpt, from the stack pointer upwards
+//! ```
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let crash_log = static_init!(
+//!     capsules::crash_log::CrashLog<'static, Log>,
+//!     capsules::crash_log::CrashLog::new(
+//!         log,
+//!         &FAULT_RESPONSE,
+//!         &mut capsules::crash_log::BUFFER
+//!     )
+//! );
+//! log.set_read_client(crash_log);
+//! log.set_append_client(crash_log);
+//!
+//! kernel::procs::load_processes(
+//!     board_kernel,
+//!     chip,
+//!     app_flash,
+//!     app_memory,
+//!     crash_log,
+//!     &process_management_capability,
+//! )
+//! .unwrap();
+//!
+//! process_console.set_crash_records(crash_log);
+//! crash_log.set_client(process_console);
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+use core::str;
+
+use kernel::common::cells::{OptionalCell, TakeCell};
+use kernel::hil::log::{LogRead, LogReadClient, LogWrite, LogWriteClient};
+use kernel::procs::{FaultAction, Process, ProcessFaultPolicy};
+use kernel::syscall::{Syscall, SyscallClass};
+use kernel::ErrorCode;
+
+/// The size of a crash record.
+pub const RECORD_SIZE: usize = 296;
+
+/// Buffer to hold a crash record while it is written or read.
+pub static mut BUFFER: [u8; RECORD_SIZE] = [0; RECORD_SIZE];
+
+const RECORD_MAGIC: u16 = 0x5243;
+const RECORD_VERSION: u8 = 1;
+
+const FLAG_APP_ID: u8 = 1 << 0;
+const FLAG_LAST_SYSCALL: u8 = 1 << 1;
+
+const NAME_OFFSET: usize = 8;
+const NAME_SIZE: usize = 16;
+const COUNTERS_OFFSET: usize = 24;
+const LAST_SYSCALL_OFFSET: usize = 40;
+const MEMORY_MAP_OFFSET: usize = 56;
+const MEMORY_MAP_WORDS: usize = 10;
+const REGISTERS_OFFSET: usize = 96;
+/// The largest number of register bytes a crash record holds.
+pub const REGISTERS_SIZE: usize = 136;
+const STACK_OFFSET: usize = 232;
+/// The largest number of stack bytes a crash record holds.
+pub const STACK_SIZE: usize = 64;
+
+/// The memory map of a process when it faulted. Addresses that were not known
+/// are zero.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CrashMemoryMap {
+    pub flash_start: u32,
+    pub flash_non_protected_start: u32,
+    pub flash_end: u32,
+    pub mem_start: u32,
+    pub mem_end: u32,
+    pub app_break: u32,
+    pub kernel_memory_break: u32,
+    pub heap_start: u32,
+    pub stack_start: u32,
+    pub stack_bottom: u32,
+}
+
+/// The state of a process when it faulted.
+#[derive(Clone, Copy)]
+pub struct CrashRecord {
+    name: [u8; NAME_SIZE],
+    name_len: usize,
+    pub app_id: Option<u32>,
+    pub restart_count: u32,
+    pub syscall_count: u32,
+    pub dropped_upcall_count: u32,
+    pub last_syscall: Option<Syscall>,
+    pub memory_map: CrashMemoryMap,
+    registers: [u8; REGISTERS_SIZE],
+    registers_len: usize,
+    stack: [u8; STACK_SIZE],
+    stack_len: usize,
+}
+
+impl CrashRecord {
+    /// Capture the state of a faulted process.
+    pub fn from_process(process: &dyn Process) -> CrashRecord {
+        let process_name = process.get_process_name().as_bytes();
+        let mut name = [0; NAME_SIZE];
+        let name_len = cmp::min(process_name.len(), NAME_SIZE);
+        name[..name_len].copy_from_slice(&process_name[..name_len]);
+
+        let mut registers = [0; REGISTERS_SIZE];
+        let registers_len = process.get_stored_state(&mut registers).unwrap_or(0);
+        let mut stack = [0; STACK_SIZE];
+        let stack_len = process.debug_copy_stack(&mut stack);
+
+        CrashRecord {
+            name,
+            name_len,
+            app_id: process.get_app_id().id(),
+            restart_count: process.get_restart_count() as u32,
+            syscall_count: process.debug_syscall_count() as u32,
+            dropped_upcall_count: process.debug_dropped_upcall_count() as u32,
+            last_syscall: process.debug_last_syscall(),
+            memory_map: CrashMemoryMap {
+                flash_start: process.flash_start() as u32,
+                flash_non_protected_start: process.flash_non_protected_start() as u32,
+                flash_end: process.flash_end() as u32,
+                mem_start: process.mem_start() as u32,
+                mem_end: process.mem_end() as u32,
+                app_break: process.app_memory_break() as u32,
+                kernel_memory_break: process.kernel_memory_break() as u32,
+                heap_start: process.debug_heap_start().map_or(0, |p| p as u32),
+                stack_start: process.debug_stack_start().map_or(0, |p| p as u32),
+                stack_bottom: process.debug_stack_end().map_or(0, |p| p as u32),
+            },
+            registers,
+            registers_len,
+            stack,
+            stack_len,
+        }
+    }
+
+    /// The name of the process, truncated to 16 bytes.
+    pub fn process_name(&self) -> &str {
+        str::from_utf8(&self.name[..self.name_len]).unwrap_or("")
+    }
+
+    /// The registers the kernel saved for the process, in the format of the
+    /// architecture the record was made on.
+    pub fn registers(&self) -> &[u8] {
+        &self.registers[..self.registers_len]
+    }
+
+    /// The top of the process stack, starting at the stack pointer.
+    pub fn stack(&self) -> &[u8] {
+        &self.stack[..self.stack_len]
+    }
+
+    /// Serialize the record into `buffer`, which must be at least
+    /// `RECORD_SIZE` bytes long.
+    fn encode(&self, buffer: &mut [u8]) {
+        let buffer = &mut buffer[..RECORD_SIZE];
+        for byte in buffer.iter_mut() {
+            *byte = 0;
+        }
+
+        let mut flags = 0;
+        if self.app_id.is_some() {
+            flags |= FLAG_APP_ID;
+        }
+        let (syscall_class, syscall_args) =
+            self.last_syscall
+                .map_or((SyscallClass::Yield, [0; 4]), |syscall| {
+                    flags |= FLAG_LAST_SYSCALL;
+                    syscall.to_register_arguments()
+                });
+
+        buffer[0..2].copy_from_slice(&RECORD_MAGIC.to_le_bytes());
+        buffer[2] = RECORD_VERSION;
+        buffer[3] = flags;
+        buffer[4] = self.name_len as u8;
+        buffer[5] = syscall_class as u8;
+        buffer[6] = self.registers_len as u8;
+        buffer[7] = self.stack_len as u8;
+        buffer[NAME_OFFSET..NAME_OFFSET + NAME_SIZE].copy_from_slice(&self.name);
+
+        let counters = [
+            self.app_id.unwrap_or(0),
+            self.restart_count,
+            self.syscall_count,
+            self.dropped_upcall_count,
+        ];
+        put_words(&mut buffer[COUNTERS_OFFSET..], &counters);
+        put_words(
+            &mut buffer[LAST_SYSCALL_OFFSET..],
+            &[
+                syscall_args[0] as u32,
+                syscall_args[1] as u32,
+                syscall_args[2] as u32,
+                syscall_args[3] as u32,
+            ],
+        );
+
+        let map = &self.memory_map;
+        put_words(
+            &mut buffer[MEMORY_MAP_OFFSET..],
+            &[
+                map.flash_start,
+                map.flash_non_protected_start,
+                map.flash_end,
+                map.mem_start,
+                map.mem_end,
+                map.app_break,
+                map.kernel_memory_break,
+                map.heap_start,
+                map.stack_start,
+                map.stack_bottom,
+            ],
+        );
+
+        buffer[REGISTERS_OFFSET..REGISTERS_OFFSET + REGISTERS_SIZE]
+            .copy_from_slice(&self.registers);
+        buffer[STACK_OFFSET..STACK_OFFSET + STACK_SIZE].copy_from_slice(&self.stack);
+    }
+
+    /// Parse a record serialized by `encode()`.
+    fn decode(buffer: &[u8]) -> Option<CrashRecord> {
+        if buffer.len() < RECORD_SIZE
+            || get_word16(buffer, 0) != RECORD_MAGIC
+            || buffer[2] != RECORD_VERSION
+        {
+            return None;
+        }
+
+        let flags = buffer[3];
+        let name_len = cmp::min(buffer[4] as usize, NAME_SIZE);
+        let registers_len = cmp::min(buffer[6] as usize, REGISTERS_SIZE);
+        let stack_len = cmp::min(buffer[7] as usize, STACK_SIZE);
+
+        let mut name = [0; NAME_SIZE];
+        name.copy_from_slice(&buffer[NAME_OFFSET..NAME_OFFSET + NAME_SIZE]);
+        let mut registers = [0; REGISTERS_SIZE];
+        registers.copy_from_slice(&buffer[REGISTERS_OFFSET..REGISTERS_OFFSET + REGISTERS_SIZE]);
+        let mut stack = [0; STACK_SIZE];
+        stack.copy_from_slice(&buffer[STACK_OFFSET..STACK_OFFSET + STACK_SIZE]);
+
+        let last_syscall = if flags & FLAG_LAST_SYSCALL != 0 {
+            let arg = |i: usize| get_word(buffer, LAST_SYSCALL_OFFSET + 4 * i) as usize;
+            Syscall::from_register_arguments(buffer[5], arg(0), arg(1), arg(2), arg(3))
+        } else {
+            None
+        };
+
+        let mut map = [0; MEMORY_MAP_WORDS];
+        for (i, word) in map.iter_mut().enumerate() {
+            *word = get_word(buffer, MEMORY_MAP_OFFSET + 4 * i);
+        }
+
+        Some(CrashRecord {
+            name,
+            name_len,
+            app_id: if flags & FLAG_APP_ID != 0 {
+                Some(get_word(buffer, COUNTERS_OFFSET))
+            } else {
+                None
+            },
+            restart_count: get_word(buffer, COUNTERS_OFFSET + 4),
+            syscall_count: get_word(buffer, COUNTERS_OFFSET + 8),
+            dropped_upcall_count: get_word(buffer, COUNTERS_OFFSET + 12),
+            last_syscall,
+            memory_map: CrashMemoryMap {
+                flash_start: map[0],
+                flash_non_protected_start: map[1],
+                flash_end: map[2],
+                mem_start: map[3],
+                mem_end: map[4],
+                app_break: map[5],
+                kernel_memory_break: map[6],
+                heap_start: map[7],
+                stack_start: map[8],
+                stack_bottom: map[9],
+            },
+            registers,
+            registers_len,
+            stack,
+            stack_len,
+        })
+    }
+}
+
+fn put_words(buffer: &mut [u8], words: &[u32]) {
+    for (word, bytes) in words.iter().zip(buffer.chunks_exact_mut(4)) {
+        bytes.copy_from_slice(&word.to_le_bytes());
+    }
+}
+
+fn get_word(buffer: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&buffer[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn get_word16(buffer: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buffer[offset], buffer[offset + 1]])
+}
+
+/// Read the crash records saved in a `CrashLog`.
+pub trait CrashRecords<'a> {
+    /// Set the client that is called when a record was read.
+    fn set_client(&self, client: &'a dyn CrashRecordsClient);
+
+    /// Start reading the saved records from the oldest one. The client is
+    /// called when the first record was read.
+    fn read_first(&self) -> Result<(), ErrorCode>;
+
+    /// Read the record after the last one that was read. The client is called
+    /// when the record was read. Returns `FAIL` if there are no more records.
+    fn read_next(&self) -> Result<(), ErrorCode>;
+
+    /// The last record that was read, or `None` if it was not a valid crash
+    /// record.
+    fn current(&self) -> Option<CrashRecord>;
+
+    /// The number of faults that were not recorded because the log was busy.
+    fn dropped(&self) -> usize;
+}
+
+/// Receive callbacks from `CrashRecords`.
+pub trait CrashRecordsClient {
+    /// A record was read and is available with `current()`, or reading failed.
+    /// The error is `FAIL` if there are no more records.
+    fn record_read(&self, result: Result<(), ErrorCode>);
+}
+
+pub struct CrashLog<'a, L: LogRead<'a> + LogWrite<'a>> {
+    log: &'a L,
+    /// Decides what happens to a process after its fault was recorded.
+    fault_policy: &'a dyn ProcessFaultPolicy,
+    buffer: TakeCell<'static, [u8]>,
+    /// Whether `buffer` holds a record that was read from the log.
+    buffer_valid: Cell<bool>,
+    dropped: Cell<usize>,
+    client: OptionalCell<&'a dyn CrashRecordsClient>,
+}
+
+impl<'a, L: LogRead<'a> + LogWrite<'a>> CrashLog<'a, L> {
+    pub fn new(
+        log: &'a L,
+        fault_policy: &'a dyn ProcessFaultPolicy,
+        buffer: &'static mut [u8],
+    ) -> CrashLog<'a, L> {
+        CrashLog {
+            log,
+            fault_policy,
+            buffer: TakeCell::new(buffer),
+            buffer_valid: Cell::new(false),
+            dropped: Cell::new(0),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    fn save_record(&self, record: &CrashRecord) -> Result<(), ErrorCode> {
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        self.buffer_valid.set(false);
+        record.encode(buffer);
+        self.log
+            .append(buffer, RECORD_SIZE)
+            .map_err(|(error, buffer)| {
+                self.buffer.replace(buffer);
+                error
+            })
+    }
+
+    fn read_record(&self) -> Result<(), ErrorCode> {
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        self.buffer_valid.set(false);
+        self.log
+            .read(buffer, RECORD_SIZE)
+            .map_err(|(error, buffer)| {
+                self.buffer.replace(buffer);
+                error
+            })
+    }
+}
+
+impl<'a, L: LogRead<'a> + LogWrite<'a>> ProcessFaultPolicy for CrashLog<'a, L> {
+    fn action(&self, process: &dyn Process) -> FaultAction {
+        if self
+            .save_record(&CrashRecord::from_process(process))
+            .is_err()
+        {
+            self.dropped.set(self.dropped.get() + 1);
+        }
+        self.fault_policy.action(process)
+    }
+}
+
+impl<'a, L: LogRead<'a> + LogWrite<'a>> CrashRecords<'a> for CrashLog<'a, L> {
+    fn set_client(&self, client: &'a dyn CrashRecordsClient) {
+        self.client.set(client);
+    }
+
+    fn read_first(&self) -> Result<(), ErrorCode> {
+        if self.buffer.is_none() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.log.seek(self.log.log_start())
+    }
+
+    fn read_next(&self) -> Result<(), ErrorCode> {
+        self.read_record()
+    }
+
+    fn current(&self) -> Option<CrashRecord> {
+        if !self.buffer_valid.get() {
+            return None;
+        }
+        self.buffer
+            .map_or(None, |buffer| CrashRecord::decode(buffer))
+    }
+
+    fn dropped(&self) -> usize {
+        self.dropped.get()
+    }
+}
+
+impl<'a, L: LogRead<'a> + LogWrite<'a>> LogReadClient for CrashLog<'a, L> {
+    fn read_done(&self, buffer: &'static mut [u8], length: usize, error: Result<(), ErrorCode>) {
+        self.buffer_valid
+            .set(error.is_ok() && length == RECORD_SIZE);
+        self.buffer.replace(buffer);
+        self.client.map(|client| client.record_read(error));
+    }
+
+    fn seek_done(&self, error: Result<(), ErrorCode>) {
+        let result = error.and_then(|()| self.read_record());
+        if result.is_err() {
+            self.client.map(|client| client.record_read(result));
+        }
+    }
+}
+
+impl<'a, L: LogRead<'a> + LogWrite<'a>> LogWriteClient for CrashLog<'a, L> {
+    fn append_done(
+        &self,
+        buffer: &'static mut [u8],
+        _length: usize,
+        _records_lost: bool,
+        error: Result<(), ErrorCode>,
+    ) {
+        self.buffer.replace(buffer);
+        if error.is_err() {
+            self.dropped.set(self.dropped.get() + 1);
+            return;
+        }
+
+        // Make sure the record survives a reboot.
+        let _ = self.log.sync();
+    }
+
+    fn sync_done(&self, _error: Result<(), ErrorCode>) {}
+
+    fn erase_done(&self, _error: Result<(), ErrorCode>) {}
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use core::cell::RefCell;
+    use kernel::procs::StopFaultPolicy;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    fn leak<T>(value: T) -> &'static T {
+        Box::leak(Box::new(value))
+    }
+
+    #[derive(Clone, Copy)]
+    enum Operation {
+        Read(usize),
+        Seek(usize),
+        Append(usize),
+        Sync,
+    }
+
+    /// A log kept in memory. Operations finish when the test calls
+    /// `complete()`.
+    struct MockLog<'a> {
+        entries: RefCell<Vec<Vec<u8>>>,
+        read_index: Cell<usize>,
+        pending: Cell<Option<Operation>>,
+        buffer: TakeCell<'static, [u8]>,
+        read_client: OptionalCell<&'a dyn LogReadClient>,
+        append_client: OptionalCell<&'a dyn LogWriteClient>,
+    }
+
+    impl<'a> MockLog<'a> {
+        fn new() -> MockLog<'a> {
+            MockLog {
+                entries: RefCell::new(Vec::new()),
+                read_index: Cell::new(0),
+                pending: Cell::new(None),
+                buffer: TakeCell::empty(),
+                read_client: OptionalCell::empty(),
+                append_client: OptionalCell::empty(),
+            }
+        }
+
+        fn start(
+            &self,
+            operation: Operation,
+            buffer: &'static mut [u8],
+        ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+            if self.pending.get().is_some() {
+                return Err((ErrorCode::BUSY, buffer));
+            }
+            self.buffer.replace(buffer);
+            self.pending.set(Some(operation));
+            Ok(())
+        }
+
+        /// Finish the pending operation and call the client. Returns `false`
+        /// if there is no operation.
+        fn complete(&self) -> bool {
+            match self.pending.take() {
+                Some(Operation::Read(length)) => {
+                    let buffer = self.buffer.take().unwrap();
+                    let index = self.read_index.get();
+                    let entry = self.entries.borrow().get(index).cloned();
+                    let (length, result) = match entry {
+                        Some(entry) => {
+                            let length = cmp::min(cmp::min(length, entry.len()), buffer.len());
+                            buffer[..length].copy_from_slice(&entry[..length]);
+                            self.read_index.set(index + 1);
+                            (length, Ok(()))
+                        }
+                        None => (0, Err(ErrorCode::FAIL)),
+                    };
+                    self.read_client
+                        .map(move |client| client.read_done(buffer, length, result));
+                }
+                Some(Operation::Seek(entry)) => {
+                    self.read_index.set(entry);
+                    self.read_client.map(|client| client.seek_done(Ok(())));
+                }
+                Some(Operation::Append(length)) => {
+                    let buffer = self.buffer.take().unwrap();
+                    self.entries.borrow_mut().push(buffer[..length].to_vec());
+                    self.append_client
+                        .map(move |client| client.append_done(buffer, length, false, Ok(())));
+                }
+                Some(Operation::Sync) => {
+                    self.append_client.map(|client| client.sync_done(Ok(())));
+                }
+                None => return false,
+            }
+            true
+        }
+    }
+
+    impl<'a> LogRead<'a> for MockLog<'a> {
+        type EntryID = usize;
+
+        fn set_read_client(&'a self, read_client: &'a dyn LogReadClient) {
+            self.read_client.set(read_client);
+        }
+
+        fn read(
+            &self,
+            buffer: &'static mut [u8],
+            length: usize,
+        ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+            self.start(Operation::Read(length), buffer)
+        }
+
+        fn log_start(&self) -> usize {
+            0
+        }
+
+        fn log_end(&self) -> usize {
+            self.entries.borrow().len()
+        }
+
+        fn next_read_entry_id(&self) -> usize {
+            self.read_index.get()
+        }
+
+        fn seek(&self, entry: usize) -> Result<(), ErrorCode> {
+            if self.pending.get().is_some() {
+                return Err(ErrorCode::BUSY);
+            }
+            self.pending.set(Some(Operation::Seek(entry)));
+            Ok(())
+        }
+
+        fn get_size(&self) -> usize {
+            usize::MAX
+        }
+    }
+
+    impl<'a> LogWrite<'a> for MockLog<'a> {
+        fn set_append_client(&'a self, append_client: &'a dyn LogWriteClient) {
+            self.append_client.set(append_client);
+        }
+
+        fn append(
+            &self,
+            buffer: &'static mut [u8],
+            length: usize,
+        ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+            self.start(Operation::Append(length), buffer)
+        }
+
+        fn sync(&self) -> Result<(), ErrorCode> {
+            if self.pending.get().is_some() {
+                return Err(ErrorCode::BUSY);
+            }
+            self.pending.set(Some(Operation::Sync));
+            Ok(())
+        }
+
+        fn erase(&self) -> Result<(), ErrorCode> {
+            Err(ErrorCode::NOSUPPORT)
+        }
+    }
+
+    struct RecordsClient {
+        result: Cell<Option<Result<(), ErrorCode>>>,
+    }
+
+    impl CrashRecordsClient for RecordsClient {
+        fn record_read(&self, result: Result<(), ErrorCode>) {
+            self.result.set(Some(result));
+        }
+    }
+
+    fn run_log(log: &MockLog) {
+        while log.complete() {}
+    }
+
+    /// A record of a process stopped in a `command`, with the registers
+    /// stored the way `store_context()` stores them: R0-R3 and the PC as
+    /// little endian 32 bit words.
+    fn crashed_record() -> CrashRecord {
+        let mut name = [0; NAME_SIZE];
+        name[..7].copy_from_slice(b"blinker");
+        let mut registers = [0; REGISTERS_SIZE];
+        let context: [u32; 5] = [0x1, 0x2000_0400, 0xdead_beef, 0x3, 0x0004_0123];
+        for (word, bytes) in context.iter().zip(registers.chunks_exact_mut(4)) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+        let mut stack = [0; STACK_SIZE];
+        for (i, byte) in stack.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+
+        CrashRecord {
+            name,
+            name_len: 7,
+            app_id: Some(0x1234_5678),
+            restart_count: 2,
+            syscall_count: 41,
+            dropped_upcall_count: 1,
+            last_syscall: Some(Syscall::Command {
+                driver_number: 0x2,
+                subdriver_number: 1,
+                arg0: 3,
+                arg1: 0,
+            }),
+            memory_map: CrashMemoryMap {
+                flash_start: 0x4_0000,
+                flash_non_protected_start: 0x4_0040,
+                flash_end: 0x4_2000,
+                mem_start: 0x2000_0000,
+                mem_end: 0x2000_2000,
+                app_break: 0x2000_1000,
+                kernel_memory_break: 0x2000_1800,
+                heap_start: 0x2000_0800,
+                stack_start: 0x2000_0800,
+                stack_bottom: 0x2000_0400,
+            },
+            registers,
+            registers_len: context.len() * 4,
+            stack,
+            stack_len: 48,
+        }
+    }
+
+    #[test]
+    fn record_roundtrips_through_the_log() {
+        let log = leak(MockLog::new());
+        let crash_log = leak(CrashLog::new(
+            log,
+            leak(StopFaultPolicy {}),
+            Box::leak(Box::new([0; RECORD_SIZE])),
+        ));
+        log.set_read_client(crash_log);
+        log.set_append_client(crash_log);
+        let client = leak(RecordsClient {
+            result: Cell::new(None),
+        });
+        crash_log.set_client(client);
+
+        let stored = crashed_record();
+        assert_eq!(crash_log.save_record(&stored), Ok(()));
+        run_log(log);
+        assert_eq!(log.entries.borrow().len(), 1);
+        assert_eq!(crash_log.dropped(), 0);
+
+        assert_eq!(crash_log.read_first(), Ok(()));
+        run_log(log);
+        assert_eq!(client.result.take(), Some(Ok(())));
+        let read = crash_log.current().unwrap();
+
+        assert_eq!(read.process_name(), "blinker");
+        assert_eq!(read.app_id, Some(0x1234_5678));
+        assert_eq!(read.restart_count, 2);
+        assert_eq!(read.syscall_count, 41);
+        assert_eq!(read.dropped_upcall_count, 1);
+        match read.last_syscall {
+            Some(Syscall::Command {
+                driver_number,
+                subdriver_number,
+                arg0,
+                arg1,
+            }) => assert_eq!(
+                (driver_number, subdriver_number, arg0, arg1),
+                (0x2, 1, 3, 0)
+            ),
+            _ => panic!("last syscall was not read back"),
+        }
+        let map = read.memory_map;
+        assert_eq!(
+            [
+                map.flash_start,
+                map.flash_non_protected_start,
+                map.flash_end,
+                map.mem_start,
+                map.mem_end,
+                map.app_break,
+                map.kernel_memory_break,
+                map.heap_start,
+                map.stack_start,
+                map.stack_bottom,
+            ],
+            [
+                0x4_0000,
+                0x4_0040,
+                0x4_2000,
+                0x2000_0000,
+                0x2000_2000,
+                0x2000_1000,
+                0x2000_1800,
+                0x2000_0800,
+                0x2000_0800,
+                0x2000_0400,
+            ]
+        );
+        assert_eq!(read.registers(), stored.registers());
+        assert_eq!(read.registers().len(), 20);
+        assert_eq!(&read.registers()[8..12], &0xdead_beefu32.to_le_bytes());
+        assert_eq!(read.stack(), stored.stack());
+
+        // There is only one record.
+        assert_eq!(crash_log.read_next(), Ok(()));
+        run_log(log);
+        assert_eq!(client.result.take(), Some(Err(ErrorCode::FAIL)));
+        assert!(crash_log.current().is_none());
+    }
+
+    #[test]
+    fn record_without_app_id_or_syscall_roundtrips() {
+        let mut stored = crashed_record();
+        stored.app_id = None;
+        stored.last_syscall = None;
+        let mut buffer = [0; RECORD_SIZE];
+        stored.encode(&mut buffer);
+
+        let read = CrashRecord::decode(&buffer).unwrap();
+        assert_eq!(read.app_id, None);
+        assert!(read.last_syscall.is_none());
+        assert_eq!(read.registers(), stored.registers());
+
+        buffer[2] = RECORD_VERSION + 1;
+        assert!(CrashRecord::decode(&buffer).is_none());
+    }
+}
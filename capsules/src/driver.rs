@@ -19,6 +19,10 @@ pub enum NUM {
 
     // Kernel
     Ipc                   = 0x10000,
+    AppLoader             = 0x10001,
+    AppWatchdog           = 0x10002,
+    IpcMailbox            = 0x10003,
+    DriverRegistry        = 0x10004,
 
     // HW Buses
     Spi                   = 0x20001,
@@ -42,6 +46,7 @@ pub enum NUM {
     AppFlash              = 0x50000,
     NvmStorage            = 0x50001,
     SdCard                = 0x50002,
+    KVStore               = 0x50003,
 
     // Sensors
     Temperature           = 0x60000,
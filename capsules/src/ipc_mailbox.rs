@@ -0,0 +1,506 @@
+//! Message-based inter-process communication.
+//!
+//! Every process has a mailbox that holds up to `MAILBOX_SIZE` messages of up
+//! to `MAX_MESSAGE_SIZE` bytes. Sending a message copies it from the sender
+//! into the mailbox of the receiver, and receiving it copies it out into a
+//! buffer of the receiver, so processes never share memory. Each message
+//! carries the identifier of the process that sent it, which the receiver can
+//! use to reply.
+//!
+//! Processes can only send messages to processes the board allows them to.
+//! The board passes a list of `MailboxRule`s, each of which names an
+//! application by its persistent `AppId` and the applications that may send
+//! it messages. Anonymous processes can neither send nor receive messages. An
+//! application that is not named by any rule cannot receive messages. For a
+//! service to reply to its clients, the clients need rules too.
+//!
+//! The rules are only as strong as the board's `AppIdPolicy` and credentials
+//! checker, which decide which process gets which `AppId`. With
+//! `TbfHeaderAppIdPolicy`, a process gets the `AppId` in its TBF header only
+//! if the checker verified its credentials. If the checker accepts
+//! credentials that any application can create, such as a hash of the
+//! application, any application can claim any `AppId` and so pass any rule.
+//!
+//! The mailbox of a process is only allocated in its grant once the process
+//! uses this driver, for example by subscribing to messages. Until then,
+//! sending it a message fails, so a sender cannot make the kernel allocate
+//! memory in processes that do not want messages.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! # use kernel::static_init;
+//! # use capsules::ipc_mailbox::MailboxRule;
+//!
+//! const SENSOR_SERVICE: u32 = 0x5e45_0001;
+//! const LOGGER: u32 = 0x5e45_0002;
+//! const DISPLAY: u32 = 0x5e45_0003;
+//!
+//! static RULES: [MailboxRule; 2] = [
+//!     MailboxRule {
+//!         service: SENSOR_SERVICE,
+//!         clients: &[LOGGER, DISPLAY],
+//!     },
+//!     MailboxRule {
+//!         service: LOGGER,
+//!         clients: &[SENSOR_SERVICE],
+//!     },
+//! ];
+//!
+//! let ipc_mailbox = components::ipc_mailbox::IpcMailboxComponent::new(
+//!     board_kernel,
+//!     &RULES,
+//! )
+//! .finalize(());
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! ### Allow
+//!
+//! - Read-only `0`: The message to send.
+//! - Read-only `1`: The name of the process to find.
+//! - Read-write `0`: The buffer messages are received into.
+//!
+//! ### Subscribe
+//!
+//! - `0`: Called when a message arrives in the mailbox, with the identifier of
+//!   the sender, the length of the message and the number of messages in the
+//!   mailbox.
+//!
+//! ### Command
+//!
+//! - `0`: Driver check.
+//! - `1`: Find the process named by read-only buffer `1`. Returns its
+//!   identifier, or `NODEVICE` if there is no such process.
+//! - `2`: Send the first `data2` bytes of read-only buffer `0` to the process
+//!   with identifier `data`. Returns `INVAL` if there is no such process,
+//!   `NODEVICE` if this process may not send it messages, `OFF` if the
+//!   receiver has not used this driver yet, `SIZE` if the message is too long
+//!   and `BUSY` if its mailbox is full.
+//! - `3`: Receive the oldest message in the mailbox into read-write buffer
+//!   `0`. Returns the identifier of the sender and the length of the message,
+//!   `FAIL` if the mailbox is empty, or `SIZE` if the message does not fit, in
+//!   which case the message stays in the mailbox.
+
+use core::cell::Cell;
+use core::mem;
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::procs::Process;
+use kernel::{AppId, CommandReturn, Driver, ErrorCode, Grant, Kernel, ProcessId, Upcall};
+use kernel::{Read, ReadOnlyAppSlice, ReadWrite, ReadWriteAppSlice};
+
+/// Syscall driver number.
+use crate::driver;
+pub const DRIVER_NUM: usize = driver::NUM::IpcMailbox as usize;
+
+/// How many messages a mailbox holds.
+pub const MAILBOX_SIZE: usize = 4;
+
+/// The longest message that can be sent.
+pub const MAX_MESSAGE_SIZE: usize = 64;
+
+/// Allows the applications with the persistent `AppId`s in `clients` to send
+/// messages to the application with the persistent `AppId` `service`.
+pub struct MailboxRule {
+    pub service: u32,
+    pub clients: &'static [u32],
+}
+
+/// Whether `rules` allow the application `sender` to send messages to the
+/// application `receiver`.
+fn is_allowed(rules: &[MailboxRule], sender: AppId, receiver: AppId) -> bool {
+    match (sender, receiver) {
+        (AppId::Persistent(sender), AppId::Persistent(receiver)) => rules
+            .iter()
+            .any(|rule| rule.service == receiver && rule.clients.contains(&sender)),
+        _ => false,
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Message {
+    /// Identifier of the process that sent the message.
+    sender: usize,
+    len: usize,
+    data: [u8; MAX_MESSAGE_SIZE],
+}
+
+const EMPTY_MESSAGE: Message = Message {
+    sender: 0,
+    len: 0,
+    data: [0; MAX_MESSAGE_SIZE],
+};
+
+pub struct App {
+    received_callback: Upcall,
+    send_buffer: ReadOnlyAppSlice,
+    name_buffer: ReadOnlyAppSlice,
+    receive_buffer: ReadWriteAppSlice,
+    /// Ring buffer of the received messages, starting at `head`.
+    mailbox: [Message; MAILBOX_SIZE],
+    head: usize,
+    count: usize,
+}
+
+impl App {
+    /// Add `message` to the end of the mailbox, and return how many messages
+    /// are in the mailbox.
+    fn push(&mut self, message: Message) -> Result<usize, ErrorCode> {
+        if self.count == MAILBOX_SIZE {
+            return Err(ErrorCode::BUSY);
+        }
+        let tail = (self.head + self.count) % MAILBOX_SIZE;
+        self.mailbox[tail] = message;
+        self.count += 1;
+        Ok(self.count)
+    }
+
+    /// The oldest message in the mailbox.
+    fn front(&self) -> Option<&Message> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(&self.mailbox[self.head])
+        }
+    }
+
+    /// Remove the oldest message from the mailbox.
+    fn pop(&mut self) {
+        if self.count > 0 {
+            self.head = (self.head + 1) % MAILBOX_SIZE;
+            self.count -= 1;
+        }
+    }
+}
+
+impl Default for App {
+    fn default() -> App {
+        App {
+            received_callback: Upcall::default(),
+            send_buffer: ReadOnlyAppSlice::default(),
+            name_buffer: ReadOnlyAppSlice::default(),
+            receive_buffer: ReadWriteAppSlice::default(),
+            mailbox: [EMPTY_MESSAGE; MAILBOX_SIZE],
+            head: 0,
+            count: 0,
+        }
+    }
+}
+
+pub struct IpcMailbox<C: ProcessManagementCapability> {
+    kernel: &'static Kernel,
+    apps: Grant<App>,
+    rules: &'static [MailboxRule],
+    capability: C,
+}
+
+impl<C: ProcessManagementCapability> IpcMailbox<C> {
+    pub fn new(
+        kernel: &'static Kernel,
+        grant: Grant<App>,
+        rules: &'static [MailboxRule],
+        capability: C,
+    ) -> IpcMailbox<C> {
+        IpcMailbox {
+            kernel,
+            apps: grant,
+            rules,
+            capability,
+        }
+    }
+
+    /// Find the process `matches` returns `true` for.
+    fn find_process<F>(&self, matches: F) -> Option<ProcessId>
+    where
+        F: Fn(&dyn Process) -> bool,
+    {
+        let found = Cell::new(None);
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                if found.get().is_none() && matches(process) {
+                    found.set(Some(process.processid()));
+                }
+            });
+        found.get()
+    }
+
+    fn discover(&self, processid: ProcessId) -> Result<usize, ErrorCode> {
+        let found = self
+            .apps
+            .enter(processid, |app| {
+                app.name_buffer.map_or(None, |name| {
+                    self.find_process(|process| process.get_process_name().as_bytes() == name)
+                })
+            })
+            .map_err(ErrorCode::from)?;
+        found
+            .map(|processid| processid.id())
+            .ok_or(ErrorCode::NODEVICE)
+    }
+
+    fn send(&self, sender: ProcessId, receiver_id: usize, len: usize) -> Result<(), ErrorCode> {
+        if len > MAX_MESSAGE_SIZE {
+            return Err(ErrorCode::SIZE);
+        }
+        let receiver = self
+            .find_process(|process| process.processid().id() == receiver_id)
+            .ok_or(ErrorCode::INVAL)?;
+        if !is_allowed(self.rules, sender.get_app_id(), receiver.get_app_id()) {
+            return Err(ErrorCode::NODEVICE);
+        }
+
+        // Copy the message out of the sender before entering the grant of the
+        // receiver, which may be the same process.
+        let mut message = Message {
+            sender: sender.id(),
+            len,
+            data: [0; MAX_MESSAGE_SIZE],
+        };
+        self.apps
+            .enter(sender, |app| {
+                app.send_buffer.map_or(Err(ErrorCode::RESERVE), |buffer| {
+                    if len > buffer.len() {
+                        return Err(ErrorCode::SIZE);
+                    }
+                    message.data[..len].copy_from_slice(&buffer[..len]);
+                    Ok(())
+                })
+            })
+            .map_err(ErrorCode::from)??;
+
+        // Only deliver to a mailbox the receiver already allocated, rather
+        // than allocating one in its grant region.
+        self.apps
+            .iter()
+            .find(|app| app.processid() == receiver)
+            .map_or(Err(ErrorCode::OFF), |app| {
+                app.enter(|app| {
+                    let count = app.push(message)?;
+                    app.received_callback
+                        .schedule(message.sender, message.len, count);
+                    Ok(())
+                })
+            })
+    }
+
+    fn receive(&self, processid: ProcessId) -> Result<(usize, usize), ErrorCode> {
+        self.apps
+            .enter(processid, |app| {
+                let message = *app.front().ok_or(ErrorCode::FAIL)?;
+                app.receive_buffer
+                    .mut_map_or(Err(ErrorCode::RESERVE), |buffer| {
+                        if message.len > buffer.len() {
+                            return Err(ErrorCode::SIZE);
+                        }
+                        buffer[..message.len].copy_from_slice(&message.data[..message.len]);
+                        Ok(())
+                    })?;
+                app.pop();
+                Ok((message.sender, message.len))
+            })
+            .map_err(ErrorCode::from)?
+    }
+}
+
+impl<C: ProcessManagementCapability> Driver for IpcMailbox<C> {
+    /// Setup shared buffers.
+    ///
+    /// ### `allow_num`
+    ///
+    /// - `0`: The buffer messages are received into.
+    fn allow_readwrite(
+        &self,
+        processid: ProcessId,
+        allow_num: usize,
+        mut slice: ReadWriteAppSlice,
+    ) -> Result<ReadWriteAppSlice, (ReadWriteAppSlice, ErrorCode)> {
+        let res = match allow_num {
+            0 => self
+                .apps
+                .enter(processid, |app| {
+                    mem::swap(&mut app.receive_buffer, &mut slice);
+                })
+                .map_err(ErrorCode::from),
+            _ => Err(ErrorCode::NOSUPPORT),
+        };
+
+        if let Err(e) = res {
+            Err((slice, e))
+        } else {
+            Ok(slice)
+        }
+    }
+
+    /// Setup shared buffers.
+    ///
+    /// ### `allow_num`
+    ///
+    /// - `0`: The message to send.
+    /// - `1`: The name of the process to find.
+    fn allow_readonly(
+        &self,
+        processid: ProcessId,
+        allow_num: usize,
+        mut slice: ReadOnlyAppSlice,
+    ) -> Result<ReadOnlyAppSlice, (ReadOnlyAppSlice, ErrorCode)> {
+        let res = match allow_num {
+            0 => self
+                .apps
+                .enter(processid, |app| {
+                    mem::swap(&mut app.send_buffer, &mut slice);
+                })
+                .map_err(ErrorCode::from),
+            1 => self
+                .apps
+                .enter(processid, |app| {
+                    mem::swap(&mut app.name_buffer, &mut slice);
+                })
+                .map_err(ErrorCode::from),
+            _ => Err(ErrorCode::NOSUPPORT),
+        };
+
+        if let Err(e) = res {
+            Err((slice, e))
+        } else {
+            Ok(slice)
+        }
+    }
+
+    /// Setup callbacks.
+    ///
+    /// ### `subscribe_num`
+    ///
+    /// - `0`: A message arrived in the mailbox.
+    fn subscribe(
+        &self,
+        subscribe_num: usize,
+        mut callback: Upcall,
+        processid: ProcessId,
+    ) -> Result<Upcall, (Upcall, ErrorCode)> {
+        let res = match subscribe_num {
+            0 => self
+                .apps
+                .enter(processid, |app| {
+                    mem::swap(&mut app.received_callback, &mut callback);
+                })
+                .map_err(ErrorCode::from),
+            _ => Err(ErrorCode::NOSUPPORT),
+        };
+
+        if let Err(e) = res {
+            Err((callback, e))
+        } else {
+            Ok(callback)
+        }
+    }
+
+    /// Find processes, and send and receive messages.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Find the process named by read-only buffer `1`, and return its
+    ///   identifier.
+    /// - `2`: Send `data2` bytes to the process with identifier `data`.
+    /// - `3`: Receive the oldest message, and return the identifier of its
+    ///   sender and its length.
+    fn command(
+        &self,
+        command_num: usize,
+        data: usize,
+        data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => match self.discover(processid) {
+                Ok(id) => CommandReturn::success_u32(id as u32),
+                Err(e) => CommandReturn::failure(e),
+            },
+            2 => match self.send(processid, data, data2) {
+                Ok(()) => CommandReturn::success(),
+                Err(e) => CommandReturn::failure(e),
+            },
+            3 => match self.receive(processid) {
+                Ok((sender, len)) => CommandReturn::success_u32_u32(sender as u32, len as u32),
+                Err(e) => CommandReturn::failure(e),
+            },
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVICE: u32 = 0x5e45_0001;
+    const CLIENT: u32 = 0x5e45_0002;
+    const OTHER: u32 = 0x5e45_0003;
+
+    static RULES: [MailboxRule; 1] = [MailboxRule {
+        service: SERVICE,
+        clients: &[CLIENT],
+    }];
+
+    fn message(sender: usize) -> Message {
+        Message {
+            sender,
+            len: 1,
+            data: [sender as u8; MAX_MESSAGE_SIZE],
+        }
+    }
+
+    #[test]
+    fn rules_allow_listed_clients_only() {
+        let service = AppId::Persistent(SERVICE);
+        let client = AppId::Persistent(CLIENT);
+        assert!(is_allowed(&RULES, client, service));
+        // Rules are one way.
+        assert!(!is_allowed(&RULES, service, client));
+        assert!(!is_allowed(&RULES, AppId::Persistent(OTHER), service));
+    }
+
+    #[test]
+    fn anonymous_processes_cannot_send_or_receive() {
+        assert!(!is_allowed(
+            &RULES,
+            AppId::Anonymous,
+            AppId::Persistent(SERVICE)
+        ));
+        assert!(!is_allowed(
+            &RULES,
+            AppId::Persistent(CLIENT),
+            AppId::Anonymous
+        ));
+        assert!(!is_allowed(&RULES, AppId::Anonymous, AppId::Anonymous));
+    }
+
+    #[test]
+    fn mailbox_is_first_in_first_out() {
+        let mut app = App::default();
+        assert!(app.front().is_none());
+
+        for sender in 0..MAILBOX_SIZE {
+            assert_eq!(app.push(message(sender)), Ok(sender + 1));
+        }
+        assert_eq!(app.push(message(9)), Err(ErrorCode::BUSY));
+
+        assert_eq!(app.front().map(|message| message.sender), Some(0));
+        app.pop();
+        // Wrap around the end of the ring buffer.
+        assert_eq!(app.push(message(4)), Ok(MAILBOX_SIZE));
+        for sender in 1..=MAILBOX_SIZE {
+            let front = app.front().unwrap();
+            assert_eq!((front.sender, front.data[0]), (sender, sender as u8));
+            app.pop();
+        }
+        assert!(app.front().is_none());
+        app.pop();
+        assert_eq!(app.push(message(5)), Ok(1));
+    }
+}
@@ -0,0 +1,363 @@
+//! Key-value storage for applications.
+//!
+//! This allows applications to store values under keys of their choice, on
+//! top of a `hil::kv_store` implementation such as the `kv_store` capsule.
+//! Every application has its own namespace, which is selected by its
+//! persistent application identifier. Anonymous applications have no
+//! persistent identity and can't use this driver.
+//!
+//! The namespaces only keep applications from reading or changing the values
+//! of other applications if no application can claim the `AppId` of another.
+//! That is up to the board's `AppIdPolicy` and credentials checker. With
+//! `TbfHeaderAppIdPolicy`, the `AppId` comes from the TBF header of verified
+//! processes only, so the checker must only verify credentials that an
+//! application cannot create for itself, such as a signature. A checker that
+//! accepts hashes lets any application use the namespace of any `AppId`.
+//!
+//! Only one operation runs at a time. Operations of other applications are
+//! queued, and each application can have one operation in progress.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let kv_driver = components::kv_store::KVStoreDriverComponent::new(
+//!     board_kernel,
+//!     kv_store,
+//! )
+//! .finalize(());
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! ### Allow
+//!
+//! - Read-only `0`: The key.
+//! - Read-only `1`: The value to set.
+//! - Read-write `0`: The buffer values are read into.
+//!
+//! ### Subscribe
+//!
+//! - `0`: Called when an operation completes, with the status code of the
+//!   operation and the length of the value that was read. The value is
+//!   truncated if it does not fit in read-write buffer `0`.
+//!
+//! ### Command
+//!
+//! - `0`: Driver check.
+//! - `1`: Get the value of the key in read-only buffer `0`.
+//! - `2`: Set the key in read-only buffer `0` to the value in read-only buffer
+//!   `1`. The value can be replaced and deleted later if `data` is `0`, and
+//!   can only be read if `data` is `1`.
+//! - `3`: Delete the key in read-only buffer `0`.
+//!
+//! Commands return `NODEVICE` if the application is anonymous and `BUSY` if
+//! it already has an operation in progress. The upcall reports `NOSUPPORT` if
+//! the key does not exist, `NODEVICE` if the value is read only and `NOMEM`
+//! if there is no space left to store the value.
+
+use core::cmp;
+use core::mem;
+use kernel::common::cells::OptionalCell;
+use kernel::hil::kv_store::{self, Owner, Permissions};
+use kernel::{AppId, CommandReturn, Driver, ErrorCode, Grant, ProcessId, Upcall};
+use kernel::{Read, ReadOnlyAppSlice, ReadWrite, ReadWriteAppSlice};
+
+/// Syscall driver number.
+use crate::driver;
+pub const DRIVER_NUM: usize = driver::NUM::KVStore as usize;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum UserCommand {
+    Get,
+    Set(Permissions),
+    Delete,
+}
+
+#[derive(Default)]
+pub struct App {
+    callback: Upcall,
+    key: ReadOnlyAppSlice,
+    value: ReadOnlyAppSlice,
+    read_buffer: ReadWriteAppSlice,
+    pending_command: Option<UserCommand>,
+}
+
+pub struct KVStoreDriver<'a> {
+    kv_store: &'a dyn kv_store::KVStore<'a>,
+    apps: Grant<App>,
+    current_app: OptionalCell<ProcessId>,
+}
+
+/// The namespace of the application `processid` belongs to, as identified by
+/// the board's `AppIdPolicy`.
+fn owner(processid: ProcessId) -> Result<Owner, ErrorCode> {
+    app_owner(processid.get_app_id())
+}
+
+/// The namespace of an application with `app_id`.
+fn app_owner(app_id: AppId) -> Result<Owner, ErrorCode> {
+    match app_id {
+        AppId::Persistent(id) => Ok(Owner::App(id)),
+        AppId::Anonymous => Err(ErrorCode::NODEVICE),
+    }
+}
+
+/// The operation command `command_num` starts.
+fn user_command(command_num: usize, data: usize) -> Result<UserCommand, ErrorCode> {
+    match command_num {
+        1 => Ok(UserCommand::Get),
+        2 => match data {
+            0 => Ok(UserCommand::Set(Permissions::ReadWrite)),
+            1 => Ok(UserCommand::Set(Permissions::ReadOnly)),
+            _ => Err(ErrorCode::INVAL),
+        },
+        3 => Ok(UserCommand::Delete),
+        _ => Err(ErrorCode::NOSUPPORT),
+    }
+}
+
+impl<'a> KVStoreDriver<'a> {
+    pub fn new(kv_store: &'a dyn kv_store::KVStore<'a>, grant: Grant<App>) -> KVStoreDriver<'a> {
+        KVStoreDriver {
+            kv_store,
+            apps: grant,
+            current_app: OptionalCell::empty(),
+        }
+    }
+
+    /// Start `command` with the buffers `app` allowed.
+    fn start(&self, owner: Owner, app: &App, command: UserCommand) -> Result<(), ErrorCode> {
+        app.key
+            .map_or(Err(ErrorCode::RESERVE), |key| match command {
+                UserCommand::Get => self.kv_store.get(owner, key),
+                UserCommand::Set(permissions) => {
+                    app.value.map_or(Err(ErrorCode::RESERVE), |value| {
+                        self.kv_store.set(owner, key, value, permissions)
+                    })
+                }
+                UserCommand::Delete => self.kv_store.delete(owner, key),
+            })
+    }
+
+    // Check to see if we are doing something. If not, go ahead and do this
+    // command. If so, this is queued and will be run when the pending command
+    // completes.
+    fn enqueue_command(&self, processid: ProcessId, command: UserCommand) -> Result<(), ErrorCode> {
+        let owner = owner(processid)?;
+        self.apps
+            .enter(processid, |app| {
+                if app.pending_command.is_some() || self.current_app.contains(&processid) {
+                    return Err(ErrorCode::BUSY);
+                }
+
+                if self.current_app.is_none() {
+                    self.start(owner, app, command)?;
+                    self.current_app.set(processid);
+                } else {
+                    app.pending_command = Some(command);
+                }
+                Ok(())
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+
+    /// Notify the current application that its command finished, and start
+    /// the next queued command.
+    fn complete(&self, result: Result<(), ErrorCode>, value: &[u8]) {
+        self.current_app.take().map(|processid| {
+            let _ = self.apps.enter(processid, |app| {
+                app.read_buffer.mut_map_or((), |buffer| {
+                    let length = cmp::min(value.len(), buffer.len());
+                    buffer[..length].copy_from_slice(&value[..length]);
+                });
+                app.callback
+                    .schedule(kernel::into_statuscode(result), value.len(), 0);
+            });
+        });
+
+        for cntr in self.apps.iter() {
+            let processid = cntr.processid();
+            let started_command = cntr.enter(|app| {
+                app.pending_command.take().map_or(false, |command| {
+                    match owner(processid).and_then(|owner| self.start(owner, app, command)) {
+                        Ok(()) => {
+                            self.current_app.set(processid);
+                            true
+                        }
+                        Err(e) => {
+                            app.callback.schedule(kernel::into_statuscode(Err(e)), 0, 0);
+                            false
+                        }
+                    }
+                })
+            });
+            if started_command {
+                break;
+            }
+        }
+    }
+}
+
+impl kv_store::Client for KVStoreDriver<'_> {
+    fn get_complete(&self, result: Result<(), ErrorCode>, value: &[u8]) {
+        self.complete(result, value);
+    }
+
+    fn set_complete(&self, result: Result<(), ErrorCode>) {
+        self.complete(result, &[]);
+    }
+
+    fn delete_complete(&self, result: Result<(), ErrorCode>) {
+        self.complete(result, &[]);
+    }
+}
+
+impl Driver for KVStoreDriver<'_> {
+    /// Setup shared buffers.
+    ///
+    /// ### `allow_num`
+    ///
+    /// - `0`: The buffer values are read into.
+    fn allow_readwrite(
+        &self,
+        processid: ProcessId,
+        allow_num: usize,
+        mut slice: ReadWriteAppSlice,
+    ) -> Result<ReadWriteAppSlice, (ReadWriteAppSlice, ErrorCode)> {
+        let res = match allow_num {
+            0 => self
+                .apps
+                .enter(processid, |app| {
+                    mem::swap(&mut app.read_buffer, &mut slice);
+                })
+                .map_err(ErrorCode::from),
+            _ => Err(ErrorCode::NOSUPPORT),
+        };
+
+        if let Err(e) = res {
+            Err((slice, e))
+        } else {
+            Ok(slice)
+        }
+    }
+
+    /// Setup shared buffers.
+    ///
+    /// ### `allow_num`
+    ///
+    /// - `0`: The key.
+    /// - `1`: The value to set.
+    fn allow_readonly(
+        &self,
+        processid: ProcessId,
+        allow_num: usize,
+        mut slice: ReadOnlyAppSlice,
+    ) -> Result<ReadOnlyAppSlice, (ReadOnlyAppSlice, ErrorCode)> {
+        let res = match allow_num {
+            0 => self
+                .apps
+                .enter(processid, |app| {
+                    mem::swap(&mut app.key, &mut slice);
+                })
+                .map_err(ErrorCode::from),
+            1 => self
+                .apps
+                .enter(processid, |app| {
+                    mem::swap(&mut app.value, &mut slice);
+                })
+                .map_err(ErrorCode::from),
+            _ => Err(ErrorCode::NOSUPPORT),
+        };
+
+        if let Err(e) = res {
+            Err((slice, e))
+        } else {
+            Ok(slice)
+        }
+    }
+
+    /// Setup callbacks.
+    ///
+    /// ### `subscribe_num`
+    ///
+    /// - `0`: An operation completed.
+    fn subscribe(
+        &self,
+        subscribe_num: usize,
+        mut callback: Upcall,
+        processid: ProcessId,
+    ) -> Result<Upcall, (Upcall, ErrorCode)> {
+        let res = match subscribe_num {
+            0 => self
+                .apps
+                .enter(processid, |app| {
+                    mem::swap(&mut app.callback, &mut callback);
+                })
+                .map_err(ErrorCode::from),
+            _ => Err(ErrorCode::NOSUPPORT),
+        };
+
+        if let Err(e) = res {
+            Err((callback, e))
+        } else {
+            Ok(callback)
+        }
+    }
+
+    /// Get, set and delete values.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Get the value of a key.
+    /// - `2`: Set the value of a key, read only if `data` is `1`.
+    /// - `3`: Delete a key.
+    fn command(
+        &self,
+        command_num: usize,
+        data: usize,
+        _data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        if command_num == 0 {
+            return CommandReturn::success();
+        }
+
+        match user_command(command_num, data)
+            .and_then(|command| self.enqueue_command(processid, command))
+        {
+            Ok(()) => CommandReturn::success(),
+            Err(e) => CommandReturn::failure(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn persistent_apps_have_their_own_namespace() {
+        assert_eq!(app_owner(AppId::Persistent(7)), Ok(Owner::App(7)));
+        assert_eq!(app_owner(AppId::Persistent(8)), Ok(Owner::App(8)));
+        assert_eq!(app_owner(AppId::Anonymous), Err(ErrorCode::NODEVICE));
+    }
+
+    #[test]
+    fn commands_select_operation_and_permissions() {
+        assert_eq!(user_command(1, 0), Ok(UserCommand::Get));
+        assert_eq!(
+            user_command(2, 0),
+            Ok(UserCommand::Set(Permissions::ReadWrite))
+        );
+        assert_eq!(
+            user_command(2, 1),
+            Ok(UserCommand::Set(Permissions::ReadOnly))
+        );
+        assert_eq!(user_command(2, 2), Err(ErrorCode::INVAL));
+        assert_eq!(user_command(3, 0), Ok(UserCommand::Delete));
+        assert_eq!(user_command(4, 0), Err(ErrorCode::NOSUPPORT));
+    }
+}
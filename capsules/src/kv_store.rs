@@ -0,0 +1,883 @@
+//! Key-value store with a namespace for every owner.
+//!
+//! This capsule implements `hil::kv_store` on top of a `hil::kv_system`
+//! implementation, such as the TicKV capsule.
+//!
+//! +-----------------------+
+//! |                       |
+//! |  Capsule using K-V    |
+//! |                       |
+//! +-----------------------+
+//!
+//!    hil::kv_store
+//!
+//! +-----------------------+
+//! |                       |
+//! |  K-V in Tock          |
+//! |  (this file)          |
+//! |                       |
+//! +-----------------------+
+//!
+//!    hil::kv_system
+//!
+//! +-----------------------+
+//! |                       |
+//! |  K-V library          |
+//! |                       |
+//! +-----------------------+
+//!
+//!    hil::flash
+//!
+//! Keys are namespaced by hashing them together with their owner, so the same
+//! key refers to a different value for every owner. The data that is hashed
+//! is the kind and the identifier of the owner, the length of the key and the
+//! key, padded with zeros:
+//!
+//! ```text
+//! +------+----------+------------+-----+---------
+//! | kind | owner id | key length | key | padding
+//! +------+----------+------------+-----+---------
+//!    1B     4B LE        1B
+//! ```
+//!
+//! Each value is stored after a header with its owner and its permissions,
+//! which is checked before the value is returned, replaced or deleted. This
+//! also keeps owners apart if two of their namespaced keys hash to the same
+//! hashed key.
+//!
+//! ```text
+//! +---------+-------+--------------+----------+-------
+//! | version | flags | value length | owner id | value
+//! +---------+-------+--------------+----------+-------
+//!     1B       1B       2B LE         4B LE
+//! ```
+//!
+//! Existing values are replaced with `update_key()`, so a value keeps either
+//! its old or its new contents if the power is lost while it is being set.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let kv_store = components::kv_store::KVStoreComponent::new(tickv).finalize(
+//!     components::kv_store_component_helper!(
+//!         capsules::tickv::TicKVStore<
+//!             'static,
+//!             capsules::virtual_flash::FlashUser<'static, lowrisc::flash_ctrl::FlashCtrl>,
+//!         >,
+//!         capsules::tickv::TicKVKeyType,
+//!     ),
+//! );
+//! ```
+
+use core::cell::Cell;
+use kernel::common::cells::{OptionalCell, TakeCell};
+use kernel::hil::kv_store::{self, Owner, Permissions};
+use kernel::hil::kv_system::{self, KVSystem, KeyInfo, KeyType};
+use kernel::ErrorCode;
+
+/// The longest key that can be stored.
+pub const MAX_KEY_LENGTH: usize = 64;
+
+/// The longest value that can be stored.
+pub const MAX_VALUE_LENGTH: usize = 256;
+
+/// Length of the owner and the key length that are hashed with a key.
+const NAMESPACE_LENGTH: usize = 6;
+
+/// Length of the buffer keys are namespaced in.
+pub const UNHASHED_KEY_LENGTH: usize = NAMESPACE_LENGTH + MAX_KEY_LENGTH;
+
+const HEADER_VERSION: u8 = 0;
+const HEADER_LENGTH: usize = 8;
+
+/// Length of the buffers values are stored and read with.
+pub const VALUE_BUFFER_LENGTH: usize = HEADER_LENGTH + MAX_VALUE_LENGTH;
+
+/// Set if the owner is an application rather than the kernel.
+const FLAG_APP: u8 = 1 << 0;
+/// Set if the value can be replaced and deleted.
+const FLAG_WRITE: u8 = 1 << 1;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Operation {
+    None,
+    Get,
+    Set,
+    Delete,
+}
+
+/// The kind and the identifier of `owner`, as they are stored.
+fn owner_id(owner: Owner) -> (u8, u32) {
+    match owner {
+        Owner::Kernel => (0, 0),
+        Owner::App(id) => (FLAG_APP, id),
+    }
+}
+
+/// The header stored before a value.
+struct Header {
+    owner: Owner,
+    permissions: Permissions,
+    length: usize,
+}
+
+impl Header {
+    fn encode(&self, buf: &mut [u8]) {
+        let (kind, id) = owner_id(self.owner);
+        let write = match self.permissions {
+            Permissions::ReadWrite => FLAG_WRITE,
+            Permissions::ReadOnly => 0,
+        };
+
+        buf[0] = HEADER_VERSION;
+        buf[1] = kind | write;
+        buf[2..4].copy_from_slice(&(self.length as u16).to_le_bytes());
+        buf[4..8].copy_from_slice(&id.to_le_bytes());
+    }
+
+    /// Reads the header at the start of `buf`, which must also hold the
+    /// value.
+    fn decode(buf: &[u8]) -> Option<Header> {
+        if buf.len() < HEADER_LENGTH || buf[0] != HEADER_VERSION {
+            return None;
+        }
+
+        let flags = buf[1];
+        let length = u16::from_le_bytes([buf[2], buf[3]]) as usize;
+        let id = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]);
+        if HEADER_LENGTH + length > buf.len() {
+            return None;
+        }
+
+        Some(Header {
+            owner: if flags & FLAG_APP != 0 {
+                Owner::App(id)
+            } else {
+                Owner::Kernel
+            },
+            permissions: if flags & FLAG_WRITE != 0 {
+                Permissions::ReadWrite
+            } else {
+                Permissions::ReadOnly
+            },
+            length,
+        })
+    }
+}
+
+pub struct KVStore<'a, K: KVSystem<'a, K = T>, T: 'static + KeyType> {
+    kv_system: &'a K,
+    client: OptionalCell<&'a dyn kv_store::Client>,
+    operation: Cell<Operation>,
+    owner: Cell<Owner>,
+
+    unhashed_key: TakeCell<'static, [u8]>,
+    key: TakeCell<'static, T>,
+    /// The header and the value to set
+    value: TakeCell<'static, [u8]>,
+    value_length: Cell<usize>,
+    /// The header and the value that are stored
+    read_buffer: TakeCell<'static, [u8]>,
+}
+
+impl<'a, K: KVSystem<'a, K = T>, T: 'static + KeyType> KVStore<'a, K, T> {
+    pub fn new(
+        kv_system: &'a K,
+        unhashed_key: &'static mut [u8; UNHASHED_KEY_LENGTH],
+        key: &'static mut T,
+        value: &'static mut [u8; VALUE_BUFFER_LENGTH],
+        read_buffer: &'static mut [u8; VALUE_BUFFER_LENGTH],
+    ) -> KVStore<'a, K, T> {
+        KVStore {
+            kv_system,
+            client: OptionalCell::empty(),
+            operation: Cell::new(Operation::None),
+            owner: Cell::new(Owner::Kernel),
+            unhashed_key: TakeCell::new(unhashed_key),
+            key: TakeCell::new(key),
+            value: TakeCell::new(value),
+            value_length: Cell::new(0),
+            read_buffer: TakeCell::new(read_buffer),
+        }
+    }
+
+    /// Namespaces `key` with `owner` and starts hashing it.
+    fn start(&self, operation: Operation, owner: Owner, key: &[u8]) -> Result<(), ErrorCode> {
+        if self.operation.get() != Operation::None {
+            return Err(ErrorCode::BUSY);
+        }
+        if key.len() > MAX_KEY_LENGTH {
+            return Err(ErrorCode::SIZE);
+        }
+
+        let unhashed_key = self.unhashed_key.take().unwrap();
+        let (kind, id) = owner_id(owner);
+        unhashed_key[0] = kind;
+        unhashed_key[1..5].copy_from_slice(&id.to_le_bytes());
+        unhashed_key[5] = key.len() as u8;
+        let (key_data, padding) = unhashed_key[NAMESPACE_LENGTH..].split_at_mut(key.len());
+        key_data.copy_from_slice(key);
+        for b in padding.iter_mut() {
+            *b = 0;
+        }
+
+        match self
+            .kv_system
+            .generate_key(unhashed_key, self.key.take().unwrap())
+        {
+            Ok(()) => {
+                self.operation.set(operation);
+                self.owner.set(owner);
+                Ok(())
+            }
+            Err((unhashed_key, key, e)) => {
+                self.unhashed_key.replace(unhashed_key);
+                self.key.replace(key);
+                Err(e.err().unwrap_or(ErrorCode::FAIL))
+            }
+        }
+    }
+
+    /// Finishes the current operation and notifies the client.
+    fn complete(&self, result: Result<(), ErrorCode>) {
+        let operation = self.operation.replace(Operation::None);
+        self.client.map(|client| match operation {
+            Operation::Get => client.get_complete(result, &[]),
+            Operation::Set => client.set_complete(result),
+            Operation::Delete => client.delete_complete(result),
+            Operation::None => {}
+        });
+    }
+
+    /// Appends the new value, or replaces the `stored` one.
+    fn set_value(&self, key: &'static mut T, stored: Result<Header, ErrorCode>) {
+        let value = self.value.take().unwrap();
+        let length = self.value_length.get();
+
+        let ret = match stored {
+            // There is no value yet
+            Err(ErrorCode::NOSUPPORT) => self.kv_system.append_key(key, value, length),
+            Ok(header) if header.permissions == Permissions::ReadWrite => {
+                self.kv_system.update_key(key, value, length)
+            }
+            Ok(_) => Err((key, value, Err(ErrorCode::NODEVICE))),
+            Err(e) => Err((key, value, Err(e))),
+        };
+
+        if let Err((key, value, e)) = ret {
+            self.key.replace(key);
+            self.value.replace(value);
+            self.complete(Err(e.err().unwrap_or(ErrorCode::FAIL)));
+        }
+    }
+
+    /// Deletes the `stored` value.
+    fn delete_value(&self, key: &'static mut T, stored: Result<Header, ErrorCode>) {
+        let ret = match stored {
+            Ok(header) if header.permissions == Permissions::ReadWrite => {
+                self.kv_system.invalidate_key(key)
+            }
+            Ok(_) => Err((key, Err(ErrorCode::NODEVICE))),
+            Err(e) => Err((key, Err(e))),
+        };
+
+        if let Err((key, e)) = ret {
+            self.key.replace(key);
+            self.complete(Err(e.err().unwrap_or(ErrorCode::FAIL)));
+        }
+    }
+}
+
+impl<'a, K: KVSystem<'a, K = T>, T: 'static + KeyType> kv_store::KVStore<'a> for KVStore<'a, K, T> {
+    fn set_client(&self, client: &'a dyn kv_store::Client) {
+        self.client.set(client);
+    }
+
+    fn get(&self, owner: Owner, key: &[u8]) -> Result<(), ErrorCode> {
+        self.start(Operation::Get, owner, key)
+    }
+
+    fn set(
+        &self,
+        owner: Owner,
+        key: &[u8],
+        value: &[u8],
+        permissions: Permissions,
+    ) -> Result<(), ErrorCode> {
+        if self.operation.get() != Operation::None {
+            return Err(ErrorCode::BUSY);
+        }
+        if value.len() > MAX_VALUE_LENGTH {
+            return Err(ErrorCode::SIZE);
+        }
+
+        self.value.map(|buf| {
+            let header = Header {
+                owner,
+                permissions,
+                length: value.len(),
+            };
+            header.encode(buf);
+            buf[HEADER_LENGTH..HEADER_LENGTH + value.len()].copy_from_slice(value);
+        });
+        self.value_length.set(HEADER_LENGTH + value.len());
+
+        self.start(Operation::Set, owner, key)
+    }
+
+    fn delete(&self, owner: Owner, key: &[u8]) -> Result<(), ErrorCode> {
+        self.start(Operation::Delete, owner, key)
+    }
+}
+
+impl<'a, K: KVSystem<'a, K = T>, T: 'static + KeyType> kv_system::Client<T> for KVStore<'a, K, T> {
+    fn generate_key_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        unhashed_key: &'static mut [u8],
+        key_buf: &'static mut T,
+    ) {
+        self.unhashed_key.replace(unhashed_key);
+
+        if let Err(e) = result {
+            self.key.replace(key_buf);
+            self.complete(Err(e));
+            return;
+        }
+
+        // Every operation first reads the stored value to check its header
+        if let Err((key, read_buffer, e)) = self
+            .kv_system
+            .get_value(key_buf, self.read_buffer.take().unwrap())
+        {
+            self.key.replace(key);
+            self.read_buffer.replace(read_buffer);
+            self.complete(Err(e.err().unwrap_or(ErrorCode::FAIL)));
+        }
+    }
+
+    fn append_key_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: &'static mut T,
+        value: &'static mut [u8],
+    ) {
+        self.key.replace(key);
+        self.value.replace(value);
+        self.complete(result);
+    }
+
+    fn update_key_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: &'static mut T,
+        value: &'static mut [u8],
+    ) {
+        self.key.replace(key);
+        self.value.replace(value);
+        self.complete(result);
+    }
+
+    fn get_value_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: &'static mut T,
+        ret_buf: &'static mut [u8],
+    ) {
+        let owner = self.owner.get();
+        let stored = result
+            .and_then(|()| Header::decode(ret_buf).ok_or(ErrorCode::FAIL))
+            .and_then(|header| {
+                if header.owner == owner {
+                    Ok(header)
+                } else {
+                    Err(ErrorCode::NODEVICE)
+                }
+            });
+        self.read_buffer.replace(ret_buf);
+
+        match self.operation.get() {
+            Operation::Get => {
+                self.key.replace(key);
+                match stored {
+                    Ok(header) => {
+                        self.operation.set(Operation::None);
+                        self.read_buffer.map(|buf| {
+                            self.client.map(|client| {
+                                client.get_complete(
+                                    Ok(()),
+                                    &buf[HEADER_LENGTH..HEADER_LENGTH + header.length],
+                                )
+                            });
+                        });
+                    }
+                    Err(e) => self.complete(Err(e)),
+                }
+            }
+            Operation::Set => self.set_value(key, stored),
+            Operation::Delete => self.delete_value(key, stored),
+            Operation::None => {
+                self.key.replace(key);
+            }
+        }
+    }
+
+    fn invalidate_key_complete(&self, result: Result<(), ErrorCode>, key: &'static mut T) {
+        self.key.replace(key);
+        self.complete(result);
+    }
+
+    fn garbage_collect_complete(&self, _result: Result<(), ErrorCode>) {}
+
+    fn next_key_complete(&self, _result: Result<KeyInfo, ErrorCode>, key: &'static mut T) {
+        self.key.replace(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::{Cell, RefCell};
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    use kernel::common::cells::{OptionalCell, TakeCell};
+    use kernel::hil::kv_store::{self, KVStore as _, Owner, Permissions};
+    use kernel::hil::kv_system::{self, KVSystem};
+    use kernel::ErrorCode;
+
+    use super::*;
+
+    type Key = [u8; 8];
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum Pending {
+        GenerateKey,
+        AppendKey(usize),
+        UpdateKey(usize),
+        GetValue,
+        InvalidateKey,
+    }
+
+    /// An in-memory `hil::kv_system` which completes an operation when the
+    /// test calls `complete()`.
+    struct MockKVSystem {
+        hash: fn(&[u8]) -> Key,
+        values: RefCell<Vec<(Key, Vec<u8>)>>,
+        client: OptionalCell<&'static dyn kv_system::Client<Key>>,
+        pending: Cell<Option<Pending>>,
+        unhashed_key: TakeCell<'static, [u8]>,
+        key: TakeCell<'static, Key>,
+        buffer: TakeCell<'static, [u8]>,
+    }
+
+    impl MockKVSystem {
+        fn new(hash: fn(&[u8]) -> Key) -> MockKVSystem {
+            MockKVSystem {
+                hash,
+                values: RefCell::new(Vec::new()),
+                client: OptionalCell::empty(),
+                pending: Cell::new(None),
+                unhashed_key: TakeCell::empty(),
+                key: TakeCell::empty(),
+                buffer: TakeCell::empty(),
+            }
+        }
+
+        fn position(&self, key: &Key) -> Option<usize> {
+            self.values.borrow().iter().position(|(k, _)| k == key)
+        }
+
+        /// Complete the pending operation. Returns `false` if there was none.
+        fn complete(&self) -> bool {
+            let pending = match self.pending.take() {
+                Some(pending) => pending,
+                None => return false,
+            };
+            let client = self.client.extract().unwrap();
+
+            match pending {
+                Pending::GenerateKey => {
+                    let unhashed_key = self.unhashed_key.take().unwrap();
+                    let key = self.key.take().unwrap();
+                    *key = (self.hash)(unhashed_key);
+                    client.generate_key_complete(Ok(()), unhashed_key, key);
+                }
+                Pending::AppendKey(length) | Pending::UpdateKey(length) => {
+                    let key = self.key.take().unwrap();
+                    let value = self.buffer.take().unwrap();
+                    let stored = value[..length].to_vec();
+                    let result = match (pending, self.position(key)) {
+                        (Pending::AppendKey(_), None) => {
+                            self.values.borrow_mut().push((*key, stored));
+                            Ok(())
+                        }
+                        (Pending::UpdateKey(_), Some(i)) => {
+                            self.values.borrow_mut()[i].1 = stored;
+                            Ok(())
+                        }
+                        _ => Err(ErrorCode::NOSUPPORT),
+                    };
+                    match pending {
+                        Pending::AppendKey(_) => client.append_key_complete(result, key, value),
+                        _ => client.update_key_complete(result, key, value),
+                    }
+                }
+                Pending::GetValue => {
+                    let key = self.key.take().unwrap();
+                    let ret_buf = self.buffer.take().unwrap();
+                    let result = self.position(key).map_or(Err(ErrorCode::NOSUPPORT), |i| {
+                        let values = self.values.borrow();
+                        let stored = &values[i].1;
+                        ret_buf[..stored.len()].copy_from_slice(stored);
+                        Ok(())
+                    });
+                    client.get_value_complete(result, key, ret_buf);
+                }
+                Pending::InvalidateKey => {
+                    let key = self.key.take().unwrap();
+                    let result = self.position(key).map_or(Err(ErrorCode::NOSUPPORT), |i| {
+                        self.values.borrow_mut().remove(i);
+                        Ok(())
+                    });
+                    client.invalidate_key_complete(result, key);
+                }
+            }
+            true
+        }
+    }
+
+    impl KVSystem<'static> for MockKVSystem {
+        type K = Key;
+
+        fn set_client(&self, client: &'static dyn kv_system::Client<Key>) {
+            self.client.set(client);
+        }
+
+        fn generate_key(
+            &self,
+            unhashed_key: &'static mut [u8],
+            key_buf: &'static mut Key,
+        ) -> Result<(), (&'static mut [u8], &'static mut Key, Result<(), ErrorCode>)> {
+            self.pending.set(Some(Pending::GenerateKey));
+            self.unhashed_key.replace(unhashed_key);
+            self.key.replace(key_buf);
+            Ok(())
+        }
+
+        fn append_key(
+            &self,
+            key: &'static mut Key,
+            value: &'static mut [u8],
+            length: usize,
+        ) -> Result<(), (&'static mut Key, &'static mut [u8], Result<(), ErrorCode>)> {
+            self.pending.set(Some(Pending::AppendKey(length)));
+            self.key.replace(key);
+            self.buffer.replace(value);
+            Ok(())
+        }
+
+        fn update_key(
+            &self,
+            key: &'static mut Key,
+            value: &'static mut [u8],
+            length: usize,
+        ) -> Result<(), (&'static mut Key, &'static mut [u8], Result<(), ErrorCode>)> {
+            self.pending.set(Some(Pending::UpdateKey(length)));
+            self.key.replace(key);
+            self.buffer.replace(value);
+            Ok(())
+        }
+
+        fn get_value(
+            &self,
+            key: &'static mut Key,
+            ret_buf: &'static mut [u8],
+        ) -> Result<(), (&'static mut Key, &'static mut [u8], Result<(), ErrorCode>)> {
+            self.pending.set(Some(Pending::GetValue));
+            self.key.replace(key);
+            self.buffer.replace(ret_buf);
+            Ok(())
+        }
+
+        fn invalidate_key(
+            &self,
+            key: &'static mut Key,
+        ) -> Result<(), (&'static mut Key, Result<(), ErrorCode>)> {
+            self.pending.set(Some(Pending::InvalidateKey));
+            self.key.replace(key);
+            Ok(())
+        }
+
+        fn garbage_collect(&self) -> Result<usize, Result<(), ErrorCode>> {
+            Err(Err(ErrorCode::NOSUPPORT))
+        }
+
+        fn next_key(
+            &self,
+            _start: usize,
+            key: &'static mut Key,
+        ) -> Result<(), (&'static mut Key, Result<(), ErrorCode>)> {
+            Err((key, Err(ErrorCode::NOSUPPORT)))
+        }
+    }
+
+    /// 64-bit FNV-1a, which is enough to tell test keys apart.
+    fn fnv1a(data: &[u8]) -> Key {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for b in data {
+            hash ^= *b as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        hash.to_le_bytes()
+    }
+
+    /// Hashes only the key, so the same key of all owners collides.
+    fn ignore_owner(data: &[u8]) -> Key {
+        fnv1a(&data[NAMESPACE_LENGTH..])
+    }
+
+    #[derive(Default)]
+    struct StoreClient {
+        result: Cell<Option<Result<(), ErrorCode>>>,
+        value: RefCell<Vec<u8>>,
+    }
+
+    impl kv_store::Client for StoreClient {
+        fn get_complete(&self, result: Result<(), ErrorCode>, value: &[u8]) {
+            self.result.set(Some(result));
+            *self.value.borrow_mut() = value.to_vec();
+        }
+
+        fn set_complete(&self, result: Result<(), ErrorCode>) {
+            self.result.set(Some(result));
+        }
+
+        fn delete_complete(&self, result: Result<(), ErrorCode>) {
+            self.result.set(Some(result));
+        }
+    }
+
+    struct Setup {
+        kv_system: &'static MockKVSystem,
+        store: &'static KVStore<'static, MockKVSystem, Key>,
+        client: &'static StoreClient,
+    }
+
+    impl Setup {
+        fn new(hash: fn(&[u8]) -> Key) -> Setup {
+            let kv_system: &'static MockKVSystem = Box::leak(Box::new(MockKVSystem::new(hash)));
+            let store: &'static KVStore<'static, MockKVSystem, Key> =
+                Box::leak(Box::new(KVStore::new(
+                    kv_system,
+                    Box::leak(Box::new([0; UNHASHED_KEY_LENGTH])),
+                    Box::leak(Box::new([0; 8])),
+                    Box::leak(Box::new([0; VALUE_BUFFER_LENGTH])),
+                    Box::leak(Box::new([0; VALUE_BUFFER_LENGTH])),
+                )));
+            kv_system.set_client(store);
+            let client: &'static StoreClient = Box::leak(Box::new(StoreClient::default()));
+            store.set_client(client);
+            Setup {
+                kv_system,
+                store,
+                client,
+            }
+        }
+
+        /// Complete the started operation and return its result.
+        fn finish(&self, started: Result<(), ErrorCode>) -> Result<(), ErrorCode> {
+            started?;
+            while self.kv_system.complete() {}
+            self.client
+                .result
+                .take()
+                .expect("operation did not complete")
+        }
+
+        fn get(&self, owner: Owner, key: &[u8]) -> Result<Vec<u8>, ErrorCode> {
+            self.finish(self.store.get(owner, key))
+                .map(|()| self.client.value.borrow().clone())
+        }
+
+        fn set(
+            &self,
+            owner: Owner,
+            key: &[u8],
+            value: &[u8],
+            permissions: Permissions,
+        ) -> Result<(), ErrorCode> {
+            self.finish(self.store.set(owner, key, value, permissions))
+        }
+
+        fn delete(&self, owner: Owner, key: &[u8]) -> Result<(), ErrorCode> {
+            self.finish(self.store.delete(owner, key))
+        }
+    }
+
+    #[test]
+    fn header_encodes_owner_permissions_and_length() {
+        let mut buf = [0; HEADER_LENGTH + 3];
+        Header {
+            owner: Owner::App(0x12345678),
+            permissions: Permissions::ReadOnly,
+            length: 3,
+        }
+        .encode(&mut buf);
+        assert_eq!(
+            buf[..HEADER_LENGTH],
+            [0, FLAG_APP, 3, 0, 0x78, 0x56, 0x34, 0x12]
+        );
+
+        let header = Header::decode(&buf).unwrap();
+        assert_eq!(header.owner, Owner::App(0x12345678));
+        assert_eq!(header.permissions, Permissions::ReadOnly);
+        assert_eq!(header.length, 3);
+
+        Header {
+            owner: Owner::Kernel,
+            permissions: Permissions::ReadWrite,
+            length: 0,
+        }
+        .encode(&mut buf);
+        assert_eq!(buf[..HEADER_LENGTH], [0, FLAG_WRITE, 0, 0, 0, 0, 0, 0]);
+        let header = Header::decode(&buf).unwrap();
+        assert_eq!(header.owner, Owner::Kernel);
+        assert_eq!(header.permissions, Permissions::ReadWrite);
+        assert_eq!(header.length, 0);
+    }
+
+    #[test]
+    fn header_decode_rejects_invalid_headers() {
+        let mut buf = [0; HEADER_LENGTH + 3];
+        Header {
+            owner: Owner::App(1),
+            permissions: Permissions::ReadWrite,
+            length: 3,
+        }
+        .encode(&mut buf);
+
+        // The value does not fit in the buffer
+        assert!(Header::decode(&buf[..HEADER_LENGTH + 2]).is_none());
+        // Too short for a header
+        assert!(Header::decode(&buf[..HEADER_LENGTH - 1]).is_none());
+
+        buf[0] = HEADER_VERSION + 1;
+        assert!(Header::decode(&buf).is_none());
+    }
+
+    #[test]
+    fn same_key_of_different_owners_is_separate() {
+        let kv = Setup::new(fnv1a);
+        assert_eq!(
+            kv.set(Owner::App(1), b"key", b"one", Permissions::ReadWrite),
+            Ok(())
+        );
+        assert_eq!(
+            kv.set(Owner::App(2), b"key", b"two", Permissions::ReadWrite),
+            Ok(())
+        );
+        assert_eq!(kv.get(Owner::Kernel, b"key"), Err(ErrorCode::NOSUPPORT));
+        assert_eq!(
+            kv.set(Owner::Kernel, b"key", b"kernel", Permissions::ReadWrite),
+            Ok(())
+        );
+        assert_eq!(kv.kv_system.values.borrow().len(), 3);
+
+        assert_eq!(kv.get(Owner::App(1), b"key"), Ok(b"one".to_vec()));
+        assert_eq!(kv.get(Owner::App(2), b"key"), Ok(b"two".to_vec()));
+        assert_eq!(kv.get(Owner::Kernel, b"key"), Ok(b"kernel".to_vec()));
+
+        assert_eq!(kv.delete(Owner::App(1), b"key"), Ok(()));
+        assert_eq!(kv.get(Owner::App(1), b"key"), Err(ErrorCode::NOSUPPORT));
+        assert_eq!(kv.get(Owner::App(2), b"key"), Ok(b"two".to_vec()));
+    }
+
+    #[test]
+    fn read_write_value_is_replaced() {
+        let kv = Setup::new(fnv1a);
+        assert_eq!(
+            kv.set(Owner::App(1), b"key", b"old", Permissions::ReadWrite),
+            Ok(())
+        );
+        assert_eq!(
+            kv.set(Owner::App(1), b"key", b"newer", Permissions::ReadWrite),
+            Ok(())
+        );
+        assert_eq!(kv.get(Owner::App(1), b"key"), Ok(b"newer".to_vec()));
+        assert_eq!(kv.kv_system.values.borrow().len(), 1);
+    }
+
+    #[test]
+    fn read_only_value_cannot_be_replaced_or_deleted() {
+        let kv = Setup::new(fnv1a);
+        assert_eq!(
+            kv.set(Owner::App(1), b"key", b"fixed", Permissions::ReadOnly),
+            Ok(())
+        );
+        assert_eq!(
+            kv.set(Owner::App(1), b"key", b"other", Permissions::ReadWrite),
+            Err(ErrorCode::NODEVICE)
+        );
+        assert_eq!(kv.delete(Owner::App(1), b"key"), Err(ErrorCode::NODEVICE));
+        assert_eq!(kv.get(Owner::App(1), b"key"), Ok(b"fixed".to_vec()));
+    }
+
+    #[test]
+    fn colliding_key_of_other_owner_is_rejected() {
+        let kv = Setup::new(ignore_owner);
+        assert_eq!(
+            kv.set(Owner::App(1), b"key", b"one", Permissions::ReadWrite),
+            Ok(())
+        );
+
+        assert_eq!(kv.get(Owner::App(2), b"key"), Err(ErrorCode::NODEVICE));
+        assert_eq!(
+            kv.set(Owner::App(2), b"key", b"two", Permissions::ReadWrite),
+            Err(ErrorCode::NODEVICE)
+        );
+        assert_eq!(kv.delete(Owner::App(2), b"key"), Err(ErrorCode::NODEVICE));
+        assert_eq!(kv.get(Owner::Kernel, b"key"), Err(ErrorCode::NODEVICE));
+
+        assert_eq!(kv.get(Owner::App(1), b"key"), Ok(b"one".to_vec()));
+    }
+
+    #[test]
+    fn oversized_keys_and_values_are_rejected() {
+        let kv = Setup::new(fnv1a);
+        let long = [0; MAX_VALUE_LENGTH + 1];
+        assert_eq!(
+            kv.store.get(Owner::App(1), &long[..MAX_KEY_LENGTH + 1]),
+            Err(ErrorCode::SIZE)
+        );
+        assert_eq!(
+            kv.store
+                .set(Owner::App(1), b"key", &long, Permissions::ReadWrite),
+            Err(ErrorCode::SIZE)
+        );
+        assert_eq!(
+            kv.set(
+                Owner::App(1),
+                &long[..MAX_KEY_LENGTH],
+                &long[..MAX_VALUE_LENGTH],
+                Permissions::ReadWrite
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn second_operation_is_busy() {
+        let kv = Setup::new(fnv1a);
+        assert_eq!(kv.store.get(Owner::App(1), b"key"), Ok(()));
+        assert_eq!(kv.store.delete(Owner::App(1), b"key"), Err(ErrorCode::BUSY));
+        assert_eq!(
+            kv.store
+                .set(Owner::App(1), b"key", b"v", Permissions::ReadWrite),
+            Err(ErrorCode::BUSY)
+        );
+        assert_eq!(kv.finish(Ok(())), Err(ErrorCode::NOSUPPORT));
+    }
+}
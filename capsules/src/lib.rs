@@ -3,6 +3,7 @@
 #![no_std]
 
 pub mod test;
+pub mod testing;
 
 #[macro_use]
 pub mod net;
@@ -15,11 +16,15 @@ pub mod analog_comparator;
 pub mod analog_sensor;
 pub mod apds9960;
 pub mod app_flash_driver;
+pub mod app_hash_checker;
+pub mod app_loader;
+pub mod app_watchdog;
 pub mod ble_advertising_driver;
 pub mod bus;
 pub mod button;
 pub mod buzzer_driver;
 pub mod console;
+pub mod crash_log;
 pub mod crc;
 pub mod ctap;
 pub mod dac;
@@ -37,7 +42,10 @@ pub mod humidity;
 pub mod i2c_master;
 pub mod i2c_master_slave_driver;
 pub mod ieee802154;
+pub mod ipc_mailbox;
 pub mod isl29035;
+pub mod kv_driver;
+pub mod kv_store;
 pub mod l3gd20;
 pub mod led;
 pub mod led_matrix;
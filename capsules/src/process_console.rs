@@ -5,13 +5,21 @@
 //! --------
 //!
 //! This module provides a simple text-based console to inspect and control
-//! which processes are running. The console has five commands:
+//! which processes are running. The console has these commands:
 //!  - 'help' prints the available commands and arguments
 //!  - 'status' prints the current system status
 //!  - 'list' lists the current processes with their IDs and running state
 //!  - 'stop n' stops the process with name n
 //!  - 'start n' starts the stopped process with name n
 //!  - 'fault n' forces the process with name n into a fault state
+//!  - 'remove n' terminates the process with name n and frees its memory
+//!  - 'reload n' loads the process with name n again from flash, removing it
+//!    first if it is loaded
+//!  - 'trace on n' starts recording the system calls of the process with name n
+//!  - 'trace off n' stops recording the system calls of the process with name n
+//!  - 'trace' prints and clears the recorded system calls
+//!  - 'crashes' prints the saved crash records of faulted processes
+//!  - 'drivers' lists the syscall drivers the board provides
 //!  - 'panic' causes the kernel to run the panic handler
 //!
 //! ### `list` Command Fields:
@@ -26,6 +34,11 @@
 //!   because the queue was full.
 //! - `Restarts`: How many times this process has crashed and been restarted by
 //!   the kernel.
+//! - `CPU (ms)`: How long the process has run, in milliseconds, including
+//!   previous runs before it restarted.
+//! - `Switches`: How many times the kernel has switched to the process.
+//! - `Wakeups`: How many times the process was woken up to run an upcall after
+//!   it yielded.
 //! - `State`: The state the process is in.
 //! - `Grants`: The number of grants that have been initialized for the process
 //!   out of the total number of grants defined by the kernel.
@@ -58,6 +71,36 @@
 //! pconsole.start();
 //! ```
 //!
+//! The `remove` and `reload` commands need a process loader that supports
+//! loading processes at runtime:
+//!
+//! ```rust
+//! pconsole.set_process_loader(dynamic_process_loader);
+//! ```
+//!
+//! The `trace` commands need a syscall trace buffer that the kernel passes
+//! system calls to (see `kernel::syscall_trace`):
+//!
+//! ```rust
+//! board_kernel.set_syscall_tracer(syscall_trace, &process_management_capability);
+//! pconsole.set_syscall_trace(syscall_trace);
+//! ```
+//!
+//! The `drivers` command needs the driver registry of the board (see
+//! `kernel::driver_registry`):
+//!
+//! ```rust
+//! pconsole.set_driver_registry(drivers);
+//! ```
+//!
+//! The `crashes` command needs a crash log that saves process faults (see
+//! `capsules::crash_log`):
+//!
+//! ```rust
+//! pconsole.set_crash_records(crash_log);
+//! crash_log.set_client(pconsole);
+//! ```
+//!
 //! Buffer use and output
 //! ---------------------
 //! `ProcessConsole` does not use its own write buffer for output:
@@ -91,9 +134,9 @@
 //! Initialization complete. Entering main loop
 //! Hello World!
 //! list
-//! PID    Name    Quanta  Syscalls  Dropped Upcalls  Restarts    State  Grants
-//! 00     blink        0       113                0         0  Yielded    1/12
-//! 01     c_hello      0         8                0         0  Yielded    3/12
+//! PID    Name    Quanta  Syscalls  Dropped Upcalls  Restarts  CPU (ms)  Switches  Wakeups    State  Grants
+//! 00     blink        0       113                0         0         2       114       56  Yielded    1/12
+//! 01     c_hello      0         8                0         0         0         9        0  Yielded    3/12
 //! ```
 //!
 //! To get a general view of the system, use the status command:
@@ -103,6 +146,10 @@
 //! Total processes: 2
 //! Active processes: 2
 //! Timeslice expirations: 0
+//! Process CPU time: 2 ms
+//! Context switches: 123
+//! Wakeups: 56
+//! Watchdog expirations: 0
 //! ```
 //!
 //! and you can control processes with the `start` and `stop` commands:
@@ -111,6 +158,54 @@
 //! stop blink
 //! Process blink stopped
 //! ```
+//!
+//! `process` prints the memory layout of a process, followed by the most
+//! stack and heap it has used since it started. The stack high-water mark is
+//! only known once the process told the kernel where its stack starts (memop
+//! `10`), and likewise for the heap (memop `11`):
+//!
+//! ```text
+//! process blink
+//! ...
+//!  Stack high-water mark: 1468 bytes
+//!  Heap high-water mark: 812 bytes
+//! ```
+//!
+//! To see which system calls a process makes, record them with `trace on` and
+//! print them with `trace`. Each line shows the time the system call was
+//! handled in ticks, the process identifier, the system call, and the value
+//! returned to the process:
+//!
+//! ```text
+//! trace on blink
+//! Tracing system calls of process blink.
+//! trace
+//! 2 system calls recorded, 0 overwritten.
+//!   18432010 00 Command { driver_number: 2, subdriver_number: 1, arg0: 0, arg1: 0 } -> Success
+//!   18432107 00 Yield { which: 1, address: 0x0 } -> -
+//! ```
+//!
+//! Faults saved by a crash log, including from before the board last reset,
+//! are printed with `crashes`, oldest first:
+//!
+//! ```text
+//! crashes
+//! Crash records (0 faults not recorded):
+//! Process blink, AppId 0x0000a1b2, 1 restarts, 113 syscalls, 0 dropped upcalls
+//!  Last syscall: Command { driver_number: 2, subdriver_number: 1, arg0: 0, arg1: 0 }
+//!  ...
+//! ```
+//!
+//! `drivers` lists the syscall drivers of the board, which helps to find out
+//! why a process gets `NODEVICE`:
+//!
+//! ```text
+//! drivers
+//! 3 drivers installed:
+//!   0x00000  alarm
+//!   0x00001  console
+//!   0x00002  led
+//! ```
 
 use core::cell::Cell;
 use core::cmp;
@@ -118,12 +213,17 @@ use core::fmt;
 use core::fmt::write;
 use core::str;
 use kernel::capabilities::ProcessManagementCapability;
-use kernel::common::cells::TakeCell;
+use kernel::common::cells::{OptionalCell, TakeCell};
 use kernel::ProcessId;
 
+use crate::crash_log::{CrashRecord, CrashRecords, CrashRecordsClient};
+
 use kernel::debug;
+use kernel::driver_registry::DriverRegistry;
 use kernel::hil::uart;
 use kernel::introspection::KernelInfo;
+use kernel::procs::{DynamicProcessLoading, State};
+use kernel::syscall_trace::SyscallTraceControl;
 use kernel::ErrorCode;
 use kernel::Kernel;
 
@@ -131,7 +231,7 @@ use kernel::Kernel;
 pub static mut WRITE_BUF: [u8; 500] = [0; 500];
 /// Buffer responses are initially held in until copied to the TX buffer and
 /// transmitted.
-pub static mut QUEUE_BUF: [u8; 300] = [0; 300];
+pub static mut QUEUE_BUF: [u8; 500] = [0; 500];
 /// Since reads are byte-by-byte, to properly echo what's typed,
 /// we can use a very small read buffer.
 pub static mut READ_BUF: [u8; 4] = [0; 4];
@@ -160,6 +260,16 @@ enum WriterState {
     ProcessStackUnused,
     ProcessFlash,
     ProcessProtected,
+    ProcessHighWaterMarks,
+    SyscallTraceStart,
+    SyscallTrace,
+    CrashRecordStart,
+    CrashRecordSummary,
+    CrashRecordMemory,
+    CrashRecordRegisters,
+    CrashRecordStack,
+    DriversStart,
+    Drivers,
 }
 
 impl Default for WriterState {
@@ -212,6 +322,30 @@ pub struct ProcessConsole<'a, C: ProcessManagementCapability> {
     /// Memory addresses of where the kernel is placed in memory on chip.
     kernel_addresses: KernelAddresses,
 
+    /// Used to remove and reload processes, if the board supports it.
+    process_loader: OptionalCell<&'a dyn DynamicProcessLoading>,
+
+    /// Used to control and print syscall tracing, if the board supports it.
+    syscall_trace: OptionalCell<&'a dyn SyscallTraceControl>,
+
+    /// Number of recorded system calls left to print for the `trace` command.
+    syscall_trace_remaining: Cell<usize>,
+
+    /// Used to print saved crash records, if the board supports it.
+    crash_records: OptionalCell<&'a dyn CrashRecords<'a>>,
+
+    /// The crash record being printed for the `crashes` command.
+    crash_record: OptionalCell<CrashRecord>,
+
+    /// Read the next crash record once the current one has been printed.
+    crash_records_continue: Cell<bool>,
+
+    /// Used to list the syscall drivers, if the board has a registry.
+    driver_registry: OptionalCell<&'a DriverRegistry>,
+
+    /// Index of the next driver to print for the `drivers` command.
+    driver_index: Cell<usize>,
+
     /// This capsule needs to use potentially dangerous APIs related to
     /// processes, and requires a capability to access those APIs.
     capability: C,
@@ -276,10 +410,148 @@ impl<'a, C: ProcessManagementCapability> ProcessConsole<'a, C> {
             execute: Cell::new(false),
             kernel: kernel,
             kernel_addresses: kernel_addresses,
+            process_loader: OptionalCell::empty(),
+            syscall_trace: OptionalCell::empty(),
+            syscall_trace_remaining: Cell::new(0),
+            crash_records: OptionalCell::empty(),
+            crash_record: OptionalCell::empty(),
+            crash_records_continue: Cell::new(false),
+            driver_registry: OptionalCell::empty(),
+            driver_index: Cell::new(0),
             capability: capability,
         }
     }
 
+    /// Enable the `remove` and `reload` commands.
+    pub fn set_process_loader(&self, process_loader: &'a dyn DynamicProcessLoading) {
+        self.process_loader.set(process_loader);
+    }
+
+    /// Enable the `trace` commands.
+    pub fn set_syscall_trace(&self, syscall_trace: &'a dyn SyscallTraceControl) {
+        self.syscall_trace.set(syscall_trace);
+    }
+
+    /// Enable the `crashes` command.
+    pub fn set_crash_records(&self, crash_records: &'a dyn CrashRecords<'a>) {
+        self.crash_records.set(crash_records);
+    }
+
+    /// Enable the `drivers` command.
+    pub fn set_driver_registry(&self, driver_registry: &'a DriverRegistry) {
+        self.driver_registry.set(driver_registry);
+    }
+
+    /// Start or stop recording the system calls of the process named `name`.
+    fn set_syscall_tracing(&self, name: &str, enabled: bool, console_writer: &mut ConsoleWriter) {
+        let syscall_trace = match self.syscall_trace.extract() {
+            Some(syscall_trace) => syscall_trace,
+            None => {
+                let _ = write(
+                    console_writer,
+                    format_args!("Syscall tracing is not supported.\n"),
+                );
+                return;
+            }
+        };
+
+        let result = Cell::new(Err(ErrorCode::INVAL));
+        self.kernel
+            .process_each_capability(&self.capability, |proc| {
+                if proc.get_process_name() == name {
+                    result.set(syscall_trace.set_tracing(
+                        proc.processid(),
+                        enabled,
+                        &self.capability,
+                    ));
+                }
+            });
+
+        let _ = match result.get() {
+            Ok(()) if enabled => write(
+                console_writer,
+                format_args!("Tracing system calls of process {}.\n", name),
+            ),
+            Ok(()) => write(
+                console_writer,
+                format_args!("Stopped tracing system calls of process {}.\n", name),
+            ),
+            Err(e) => write(
+                console_writer,
+                format_args!("Failed to trace process {}: {:?}\n", name, e),
+            ),
+        };
+    }
+
+    /// Terminate the process named `name` if it is still running and remove
+    /// it, freeing its memory. Returns `INVAL` if there is no such process.
+    fn remove_process(&self, name: &str) -> Result<(), ErrorCode> {
+        let process_loader = self.process_loader.extract().ok_or(ErrorCode::NOSUPPORT)?;
+
+        let process_id = Cell::new(None);
+        self.kernel
+            .process_each_capability(&self.capability, |proc| {
+                if proc.get_process_name() == name {
+                    match proc.get_state() {
+                        State::Terminated | State::Faulted | State::WaitingToRestart => {}
+                        _ => proc.terminate(0),
+                    }
+                    process_id.set(Some(proc.processid()));
+                }
+            });
+
+        process_id
+            .get()
+            .map_or(Err(ErrorCode::INVAL), |process_id| {
+                process_loader.remove_process(process_id, &self.capability)
+            })
+    }
+
+    /// Remove the process named `name` if it is loaded, and then load it
+    /// again from flash.
+    fn reload_process(&self, name: &str, console_writer: &mut ConsoleWriter) {
+        let process_loader = match self.process_loader.extract() {
+            Some(process_loader) => process_loader,
+            None => {
+                let _ = write(
+                    console_writer,
+                    format_args!("Process loading is not supported.\n"),
+                );
+                return;
+            }
+        };
+
+        match self.remove_process(name) {
+            Ok(()) | Err(ErrorCode::INVAL) => {}
+            Err(e) => {
+                let _ = write(
+                    console_writer,
+                    format_args!("Failed to remove process {}: {:?}\n", name, e),
+                );
+                return;
+            }
+        }
+
+        let result = process_loader
+            .find_process_flash(name, &self.capability)
+            .map(|flash_address| process_loader.load_process(flash_address, &self.capability));
+        let _ = match result {
+            Some(Ok(Some(_))) => write(console_writer, format_args!("Process {} loaded.\n", name)),
+            Some(Ok(None)) => write(
+                console_writer,
+                format_args!("Process {} was not loaded.\n", name),
+            ),
+            Some(Err(e)) => write(
+                console_writer,
+                format_args!("Failed to load process {}: {:?}\n", name, e),
+            ),
+            None => write(
+                console_writer,
+                format_args!("No process {} in flash.\n", name),
+            ),
+        };
+    }
+
     pub fn start(&self) -> Result<(), ErrorCode> {
         if self.running.get() == false {
             self.rx_buffer.take().map(|buffer| {
@@ -302,7 +574,7 @@ impl<'a, C: ProcessManagementCapability> ProcessConsole<'a, C> {
 
             let _ = self.write_bytes(b"Welcome to the process console.\n");
             let _ = self.write_bytes(
-                b"Valid commands are: help status list stop start fault process kernel\n",
+                b"Valid commands are: help status list stop start fault process kernel remove reload trace crashes drivers\n",
             );
         }
         Ok(())
@@ -326,11 +598,109 @@ impl<'a, C: ProcessManagementCapability> ProcessConsole<'a, C> {
             WriterState::ProcessStack => WriterState::ProcessStackUnused,
             WriterState::ProcessStackUnused => WriterState::ProcessFlash,
             WriterState::ProcessFlash => WriterState::ProcessProtected,
-            WriterState::ProcessProtected => WriterState::Empty,
+            WriterState::ProcessProtected => WriterState::ProcessHighWaterMarks,
+            WriterState::ProcessHighWaterMarks => WriterState::Empty,
+            WriterState::SyscallTraceStart | WriterState::SyscallTrace => {
+                let pending = self
+                    .syscall_trace
+                    .map_or(0, |syscall_trace| syscall_trace.len());
+                if self.syscall_trace_remaining.get() > 0 && pending > 0 {
+                    WriterState::SyscallTrace
+                } else {
+                    WriterState::Empty
+                }
+            }
+            WriterState::CrashRecordStart => WriterState::CrashRecordSummary,
+            WriterState::CrashRecordSummary => WriterState::CrashRecordMemory,
+            WriterState::CrashRecordMemory => WriterState::CrashRecordRegisters,
+            WriterState::CrashRecordRegisters => WriterState::CrashRecordStack,
+            WriterState::CrashRecordStack => WriterState::Empty,
+            WriterState::DriversStart | WriterState::Drivers => {
+                let count = self
+                    .driver_registry
+                    .map_or(0, |driver_registry| driver_registry.drivers().len());
+                if self.driver_index.get() < count {
+                    WriterState::Drivers
+                } else {
+                    WriterState::Empty
+                }
+            }
             WriterState::Empty => WriterState::Empty,
         }
     }
 
+    fn print_crash_record_summary(&self, record: &CrashRecord) {
+        let mut console_writer = ConsoleWriter::new();
+        let _ = write(
+            &mut console_writer,
+            format_args!("Process {}, AppId ", record.process_name()),
+        );
+        let _ = match record.app_id {
+            Some(app_id) => write(&mut console_writer, format_args!("{:#010x}", app_id)),
+            None => write(&mut console_writer, format_args!("none")),
+        };
+        let _ = write(
+            &mut console_writer,
+            format_args!(
+                ", {} restarts, {} syscalls, {} dropped upcalls\n",
+                record.restart_count, record.syscall_count, record.dropped_upcall_count
+            ),
+        );
+        let _ = match record.last_syscall {
+            Some(syscall) => write(
+                &mut console_writer,
+                format_args!(" Last syscall: {:?}\n", syscall),
+            ),
+            None => write(&mut console_writer, format_args!(" Last syscall: none\n")),
+        };
+        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+    }
+
+    fn print_crash_record_memory(&self, record: &CrashRecord) {
+        let map = &record.memory_map;
+        let mut console_writer = ConsoleWriter::new();
+        let _ = write(
+            &mut console_writer,
+            format_args!(
+                "\
+                 \r\n Flash   {:#010X}-{:#010X}, app code from {:#010X}\
+                 \r\n Memory  {:#010X}-{:#010X}\
+                 \r\n Breaks  app {:#010X}, kernel {:#010X}\
+                 \r\n Heap    {:#010X}, stack {:#010X}, stack low {:#010X}\r\n",
+                map.flash_start,
+                map.flash_end,
+                map.flash_non_protected_start,
+                map.mem_start,
+                map.mem_end,
+                map.app_break,
+                map.kernel_memory_break,
+                map.heap_start,
+                map.stack_start,
+                map.stack_bottom,
+            ),
+        );
+        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+    }
+
+    /// Print `bytes` as little endian 32 bit words, eight per line.
+    fn print_crash_record_words(&self, title: &str, bytes: &[u8]) {
+        let mut console_writer = ConsoleWriter::new();
+        let _ = write(&mut console_writer, format_args!(" {}:", title));
+        for (i, word) in bytes.chunks(4).enumerate() {
+            if i % 8 == 0 {
+                let _ = write(&mut console_writer, format_args!("\n  "));
+            }
+            let mut word_bytes = [0; 4];
+            word_bytes[..word.len()].copy_from_slice(word);
+            let _ = write(
+                &mut console_writer,
+                format_args!(" {:08x}", u32::from_le_bytes(word_bytes)),
+            );
+        }
+        let _ = write(&mut console_writer, format_args!("\n"));
+        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+    }
+
     // These `print_process_()` functions are split out from the main state
     // machine because of an incompatibility with rustfmt. Rustfmt cannot handle
     // long lines inside of match statements, so we use individual functions to
@@ -769,6 +1139,91 @@ impl<'a, C: ProcessManagementCapability> ProcessConsole<'a, C> {
                         });
                 }
             }
+            WriterState::ProcessHighWaterMarks => {
+                if let Some(proc_id) = process_id {
+                    let info: KernelInfo = KernelInfo::new(self.kernel);
+                    let stack = info.app_stack_high_water_mark(proc_id, &self.capability);
+                    let heap = info.app_heap_high_water_mark(proc_id, &self.capability);
+
+                    let mut console_writer = ConsoleWriter::new();
+                    let _ = match stack {
+                        Some(bytes) => write(
+                            &mut console_writer,
+                            format_args!(" Stack high-water mark: {} bytes\n", bytes),
+                        ),
+                        None => write(
+                            &mut console_writer,
+                            format_args!(" Stack high-water mark: unknown\n"),
+                        ),
+                    };
+                    let _ = match heap {
+                        Some(bytes) => write(
+                            &mut console_writer,
+                            format_args!(" Heap high-water mark: {} bytes\n", bytes),
+                        ),
+                        None => write(
+                            &mut console_writer,
+                            format_args!(" Heap high-water mark: unknown\n"),
+                        ),
+                    };
+                    let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                }
+            }
+            WriterState::SyscallTrace => {
+                let entry = self
+                    .syscall_trace
+                    .and_then(|syscall_trace| syscall_trace.pop_entry(&self.capability));
+                if let Some(entry) = entry {
+                    self.syscall_trace_remaining
+                        .set(self.syscall_trace_remaining.get() - 1);
+
+                    let mut console_writer = ConsoleWriter::new();
+                    let _ = write(
+                        &mut console_writer,
+                        format_args!(
+                            "{:10} {:02} {:?} -> ",
+                            entry.timestamp, entry.process_id, entry.syscall
+                        ),
+                    );
+                    let _ = match entry.result {
+                        Some(result) => write(&mut console_writer, format_args!("{:?}\n", result)),
+                        None => write(&mut console_writer, format_args!("-\n")),
+                    };
+                    let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                }
+            }
+            WriterState::Drivers => {
+                let index = self.driver_index.get();
+                self.driver_index.set(index + 1);
+                self.driver_registry.map(|driver_registry| {
+                    if let Some(entry) = driver_registry.drivers().get(index) {
+                        let mut console_writer = ConsoleWriter::new();
+                        let _ = write(
+                            &mut console_writer,
+                            format_args!("  {:#07x}  {}\n", entry.driver_num, entry.name),
+                        );
+                        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                    }
+                });
+            }
+            WriterState::CrashRecordSummary => {
+                self.crash_record
+                    .map(|record| self.print_crash_record_summary(record));
+            }
+            WriterState::CrashRecordMemory => {
+                self.crash_record
+                    .map(|record| self.print_crash_record_memory(record));
+            }
+            WriterState::CrashRecordRegisters => {
+                self.crash_record
+                    .map(|record| self.print_crash_record_words("Registers", record.registers()));
+            }
+            WriterState::CrashRecordStack => {
+                if let Some(record) = self.crash_record.take() {
+                    self.print_crash_record_words("Stack", record.stack());
+                    self.crash_records_continue.set(true);
+                }
+            }
             _ => {}
         }
     }
@@ -798,8 +1253,9 @@ impl<'a, C: ProcessManagementCapability> ProcessConsole<'a, C> {
                         if clean_str.starts_with("help") {
                             let _ = self.write_bytes(b"Welcome to the process console.\n");
                             let _ = self.write_bytes(b"Valid commands are: ");
-                            let _ = self
-                                .write_bytes(b"help status list stop start fault process kernel\n");
+                            let _ = self.write_bytes(
+                                b"help status list stop start fault process kernel remove reload trace crashes drivers\n",
+                            );
                         } else if clean_str.starts_with("start") {
                             let argument = clean_str.split_whitespace().nth(1);
                             argument.map(|name| {
@@ -860,10 +1316,127 @@ impl<'a, C: ProcessManagementCapability> ProcessConsole<'a, C> {
                                         }
                                     });
                             });
+                        } else if clean_str.starts_with("remove") {
+                            let argument = clean_str.split_whitespace().nth(1);
+                            argument.map(|name| {
+                                let mut console_writer = ConsoleWriter::new();
+                                let _ = match self.remove_process(name) {
+                                    Ok(()) => write(
+                                        &mut console_writer,
+                                        format_args!("Process {} removed.\n", name),
+                                    ),
+                                    Err(e) => write(
+                                        &mut console_writer,
+                                        format_args!(
+                                            "Failed to remove process {}: {:?}\n",
+                                            name, e
+                                        ),
+                                    ),
+                                };
+                                let _ =
+                                    self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                            });
+                        } else if clean_str.starts_with("reload") {
+                            let argument = clean_str.split_whitespace().nth(1);
+                            argument.map(|name| {
+                                let mut console_writer = ConsoleWriter::new();
+                                self.reload_process(name, &mut console_writer);
+                                let _ =
+                                    self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                            });
+                        } else if clean_str.starts_with("trace") {
+                            let mut arguments = clean_str.split_whitespace().skip(1);
+                            let mut console_writer = ConsoleWriter::new();
+                            match (arguments.next(), arguments.next()) {
+                                (Some("on"), Some(name)) => {
+                                    self.set_syscall_tracing(name, true, &mut console_writer)
+                                }
+                                (Some("off"), Some(name)) => {
+                                    self.set_syscall_tracing(name, false, &mut console_writer)
+                                }
+                                (None, _) => match self.syscall_trace.extract() {
+                                    Some(syscall_trace) => {
+                                        // Only print what was recorded before
+                                        // the command, as system calls made
+                                        // while printing are recorded too.
+                                        let recorded = syscall_trace.len();
+                                        let _ = write(
+                                            &mut console_writer,
+                                            format_args!(
+                                                "{} system calls recorded, {} overwritten.\n",
+                                                recorded,
+                                                syscall_trace.overwritten()
+                                            ),
+                                        );
+                                        self.syscall_trace_remaining.set(recorded);
+                                    }
+                                    None => {
+                                        let _ = write(
+                                            &mut console_writer,
+                                            format_args!("Syscall tracing is not supported.\n"),
+                                        );
+                                    }
+                                },
+                                _ => {
+                                    let _ = write(
+                                        &mut console_writer,
+                                        format_args!("Usage: trace [on|off <name>]\n"),
+                                    );
+                                }
+                            }
+                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                            if self.syscall_trace_remaining.get() > 0 {
+                                self.write_state(WriterState::SyscallTraceStart, None);
+                            }
+                        } else if clean_str.starts_with("drivers") {
+                            let mut console_writer = ConsoleWriter::new();
+                            let _ = match self.driver_registry.extract() {
+                                Some(driver_registry) => {
+                                    self.driver_index.set(0);
+                                    write(
+                                        &mut console_writer,
+                                        format_args!(
+                                            "{} drivers installed:\n",
+                                            driver_registry.drivers().len()
+                                        ),
+                                    )
+                                }
+                                None => write(
+                                    &mut console_writer,
+                                    format_args!("The driver registry is not supported.\n"),
+                                ),
+                            };
+                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                            if self.driver_registry.is_some() {
+                                self.write_state(WriterState::DriversStart, None);
+                            }
+                        } else if clean_str.starts_with("crashes") {
+                            let mut console_writer = ConsoleWriter::new();
+                            let _ = match self.crash_records.extract() {
+                                Some(crash_records) => match crash_records.read_first() {
+                                    Ok(()) => write(
+                                        &mut console_writer,
+                                        format_args!(
+                                            "Crash records ({} faults not recorded):\n",
+                                            crash_records.dropped()
+                                        ),
+                                    ),
+                                    Err(e) => write(
+                                        &mut console_writer,
+                                        format_args!("Failed to read crash records: {:?}\n", e),
+                                    ),
+                                },
+                                None => write(
+                                    &mut console_writer,
+                                    format_args!("Crash records are not supported.\n"),
+                                ),
+                            };
+                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
                         } else if clean_str.starts_with("list") {
                             let _ = self.write_bytes(b" PID    Name                Quanta  ");
                             let _ = self.write_bytes(b"Syscalls  Dropped Callbacks  ");
-                            let _ = self.write_bytes(b"Restarts    State  Grants\n");
+                            let _ = self.write_bytes(b"Restarts  CPU (ms)  Switches  Wakeups");
+                            let _ = self.write_bytes(b"    State  Grants\n");
                             self.kernel
                                 .process_each_capability(&self.capability, |proc| {
                                     let info: KernelInfo = KernelInfo::new(self.kernel);
@@ -876,13 +1449,16 @@ impl<'a, C: ProcessManagementCapability> ProcessConsole<'a, C> {
                                     let _ = write(
                                         &mut console_writer,
                                         format_args!(
-                                            "  {:?}\t{:<20}{:6}{:10}{:19}{:10}  {:?}{:5}/{}\n",
+                                            "  {:?}\t{:<20}{:6}{:10}{:19}{:10}{:10}{:10}{:9}  {:?}{:5}/{}\n",
                                             process_id,
                                             pname,
                                             proc.debug_timeslice_expiration_count(),
                                             proc.debug_syscall_count(),
                                             proc.debug_dropped_upcall_count(),
                                             proc.get_restart_count(),
+                                            proc.debug_cpu_time_us() / 1000,
+                                            proc.debug_context_switch_count(),
+                                            proc.debug_wakeup_count(),
                                             proc.get_state(),
                                             grants_used,
                                             grants_total
@@ -921,6 +1497,26 @@ impl<'a, C: ProcessManagementCapability> ProcessConsole<'a, C> {
                                 ),
                             );
                             let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                            console_writer.clear();
+                            let _ = write(
+                                &mut console_writer,
+                                format_args!(
+                                    "Process CPU time: {} ms\nContext switches: {}\nWakeups: {}\n",
+                                    info.cpu_time_us(&self.capability) / 1000,
+                                    info.context_switches(&self.capability),
+                                    info.wakeups(&self.capability)
+                                ),
+                            );
+                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                            console_writer.clear();
+                            let _ = write(
+                                &mut console_writer,
+                                format_args!(
+                                    "Watchdog expirations: {}\n",
+                                    info.watchdog_expirations(&self.capability)
+                                ),
+                            );
+                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
                         } else if clean_str.starts_with("process") {
                             let argument = clean_str.split_whitespace().nth(1);
                             argument.map(|name| {
@@ -953,8 +1549,9 @@ impl<'a, C: ProcessManagementCapability> ProcessConsole<'a, C> {
                             self.write_state(WriterState::KernelStart, None);
                         } else {
                             let _ = self.write_bytes(b"Valid commands are: ");
-                            let _ = self
-                                .write_bytes(b"help status list stop start fault process kernel\n");
+                            let _ = self.write_bytes(
+                                b"help status list stop start fault process kernel remove reload trace crashes drivers\n",
+                            );
                         }
                     }
                     Err(_e) => {
@@ -1040,6 +1637,9 @@ impl<'a, C: ProcessManagementCapability> uart::TransmitClient for ProcessConsole
         if self.writer_state.get() != WriterState::Empty
             && self.writer_state.get() != WriterState::KernelStart
             && self.writer_state.get() != WriterState::ProcessStart
+            && self.writer_state.get() != WriterState::SyscallTraceStart
+            && self.writer_state.get() != WriterState::CrashRecordStart
+            && self.writer_state.get() != WriterState::DriversStart
         {
             self.write_state(WriterState::Empty, None);
         }
@@ -1059,6 +1659,19 @@ impl<'a, C: ProcessManagementCapability> uart::TransmitClient for ProcessConsole
             self.write_state(WriterState::Empty, None);
         }
 
+        // Once a crash record has been printed, continue with the next one.
+        if self.writer_state.get() == WriterState::Empty && self.crash_records_continue.get() {
+            self.crash_records_continue.set(false);
+            let result = self
+                .crash_records
+                .map_or(Err(ErrorCode::NOSUPPORT), |crash_records| {
+                    crash_records.read_next()
+                });
+            if result.is_err() {
+                self.record_read(result);
+            }
+        }
+
         // Check if we just received and echoed a newline character, and
         // therefore need to process the received message.
         if self.execute.get() {
@@ -1067,6 +1680,36 @@ impl<'a, C: ProcessManagementCapability> uart::TransmitClient for ProcessConsole
         }
     }
 }
+impl<'a, C: ProcessManagementCapability> CrashRecordsClient for ProcessConsole<'a, C> {
+    fn record_read(&self, result: Result<(), ErrorCode>) {
+        let record = result.and_then(|()| {
+            self.crash_records
+                .and_then(|crash_records| crash_records.current())
+                .ok_or(ErrorCode::INVAL)
+        });
+        match record {
+            Ok(record) => {
+                self.crash_record.set(record);
+                self.write_state(WriterState::CrashRecordStart, None);
+            }
+            Err(e) => {
+                let mut console_writer = ConsoleWriter::new();
+                let _ = match e {
+                    ErrorCode::FAIL => write(
+                        &mut console_writer,
+                        format_args!("No more crash records.\n"),
+                    ),
+                    _ => write(
+                        &mut console_writer,
+                        format_args!("Failed to read crash record: {:?}\n", e),
+                    ),
+                };
+                let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+            }
+        }
+    }
+}
+
 impl<'a, C: ProcessManagementCapability> uart::ReceiveClient for ProcessConsole<'a, C> {
     fn received_buffer(
         &self,
@@ -38,13 +38,18 @@
 //! ```
 //! ---Starting TicKV Tests---
 //! Key: [18, 52, 86, 120, 154, 188, 222, 240] with value [16, 32, 48] was added
+//! Now updating the key
+//! Key: [18, 52, 86, 120, 154, 188, 222, 240] was updated to value [17, 33, 49]
 //! Now retriving the key
-//! Key: [18, 52, 86, 120, 154, 188, 222, 240] with value [16, 32, 48, 0] was retrived
+//! Key: [18, 52, 86, 120, 154, 188, 222, 240] with value [17, 33, 49, 0] was retrived
 //! Removed Key: [18, 52, 86, 120, 154, 188, 222, 240]
 //! Try to read removed key: [18, 52, 86, 120, 154, 188, 222, 240]
 //! Unable to find key: [18, 52, 86, 120, 154, 188, 222, 240]
 //! Let's start a garbage collection
 //! Finished garbage collection
+//! Listing the stored keys
+//! Found key: [68, 242, 118, 79, 255, 247, 201, 123] with a value of 0 bytes in region 68
+//! Listed all keys
 //! ---Finished TicKV Tests---
 //! ```
 
@@ -52,7 +57,7 @@ use core::cell::Cell;
 use core::marker::PhantomData;
 use kernel::common::cells::TakeCell;
 use kernel::debug;
-use kernel::hil::kv_system::{self, KVSystem, KeyType};
+use kernel::hil::kv_system::{self, KVSystem, KeyInfo, KeyType};
 use kernel::ErrorCode;
 
 #[derive(Clone, Copy, PartialEq)]
@@ -61,14 +66,15 @@ enum CurrentState {
     ExpectGetValueFail,
 }
 
-pub struct KVSystemTest<'a, S: KVSystem<'static>, T: KeyType> {
+pub struct KVSystemTest<'a, S: KVSystem<'static>, T: 'static + KeyType> {
     kv_system: &'a S,
     phantom: PhantomData<&'a T>,
     ret_buffer: TakeCell<'static, [u8]>,
+    key_buffer: TakeCell<'static, T>,
     state: Cell<CurrentState>,
 }
 
-impl<'a, S: KVSystem<'static>, T: KeyType> KVSystemTest<'a, S, T> {
+impl<'a, S: KVSystem<'static>, T: 'static + KeyType> KVSystemTest<'a, S, T> {
     pub fn new(kv_system: &'a S, static_buf: &'static mut [u8; 4]) -> KVSystemTest<'a, S, T> {
         debug!("---Starting TicKV Tests---");
 
@@ -76,19 +82,20 @@ impl<'a, S: KVSystem<'static>, T: KeyType> KVSystemTest<'a, S, T> {
             kv_system: kv_system,
             phantom: PhantomData,
             ret_buffer: TakeCell::new(static_buf),
+            key_buffer: TakeCell::empty(),
             state: Cell::new(CurrentState::Normal),
         }
     }
 }
 
-impl<'a, S: KVSystem<'static, K = T>, T: KeyType + core::fmt::Debug> kv_system::Client<T>
+impl<'a, S: KVSystem<'static, K = T>, T: 'static + KeyType + core::fmt::Debug> kv_system::Client<T>
     for KVSystemTest<'a, S, T>
 {
     fn generate_key_complete(
         &self,
         _result: Result<(), ErrorCode>,
-        _unhashed_key: &'static [u8],
-        _key_buf: &'static T,
+        _unhashed_key: &'static mut [u8],
+        _key_buf: &'static mut T,
     ) {
         unimplemented!()
     }
@@ -97,18 +104,40 @@ impl<'a, S: KVSystem<'static, K = T>, T: KeyType + core::fmt::Debug> kv_system::
         &self,
         result: Result<(), ErrorCode>,
         key: &'static mut T,
-        value: &'static [u8],
+        value: &'static mut [u8],
     ) {
         match result {
             Ok(()) => {
                 debug!("Key: {:?} with value {:?} was added", key, value);
+                debug!("Now updating the key");
+                for v in value.iter_mut() {
+                    *v += 1;
+                }
+                let length = value.len();
+                self.kv_system.update_key(key, value, length).unwrap();
+            }
+            Err(e) => {
+                panic!("Error adding key: {:?}", e);
+            }
+        }
+    }
+
+    fn update_key_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: &'static mut T,
+        value: &'static mut [u8],
+    ) {
+        match result {
+            Ok(()) => {
+                debug!("Key: {:?} was updated to value {:?}", key, value);
                 debug!("Now retriving the key");
                 self.kv_system
                     .get_value(key, self.ret_buffer.take().unwrap())
                     .unwrap();
             }
             Err(e) => {
-                panic!("Error adding key: {:?}", e);
+                panic!("Error updating key: {:?}", e);
             }
         }
     }
@@ -130,6 +159,7 @@ impl<'a, S: KVSystem<'static, K = T>, T: KeyType + core::fmt::Debug> kv_system::
                     // We expected this failure
                     debug!("Unable to find key: {:?}", key);
                     self.state.set(CurrentState::Normal);
+                    self.key_buffer.replace(key);
 
                     debug!("Let's start a garbage collection");
                     self.kv_system.garbage_collect().unwrap();
@@ -161,11 +191,33 @@ impl<'a, S: KVSystem<'static, K = T>, T: KeyType + core::fmt::Debug> kv_system::
         match result {
             Ok(()) => {
                 debug!("Finished garbage collection");
-                debug!("---Finished TicKV Tests---");
+                debug!("Listing the stored keys");
+                self.kv_system
+                    .next_key(0, self.key_buffer.take().unwrap())
+                    .unwrap();
             }
             Err(e) => {
                 panic!("Error running garbage collection: {:?}", e);
             }
         }
     }
+
+    fn next_key_complete(&self, result: Result<KeyInfo, ErrorCode>, key: &'static mut T) {
+        match result {
+            Ok(key_info) => {
+                debug!(
+                    "Found key: {:?} with a value of {} bytes in region {}",
+                    key, key_info.value_length, key_info.region
+                );
+                self.kv_system.next_key(key_info.next, key).unwrap();
+            }
+            Err(ErrorCode::NOSUPPORT) => {
+                debug!("Listed all keys");
+                debug!("---Finished TicKV Tests---");
+            }
+            Err(e) => {
+                panic!("Error listing keys: {:?}", e);
+            }
+        }
+    }
 }
@@ -0,0 +1,108 @@
+//! Mock alarm, whose time only moves when the test advances it.
+//!
+//! The alarm counts at 1 kHz, so one tick is one millisecond.
+
+use core::cell::Cell;
+
+use kernel::common::cells::OptionalCell;
+use kernel::hil::time::{self, Alarm, Ticks, Ticks32, Time};
+use kernel::ErrorCode;
+
+pub struct MockAlarm<'a> {
+    now: Cell<Ticks32>,
+    reference: Cell<Ticks32>,
+    dt: Cell<Ticks32>,
+    armed: Cell<bool>,
+    client: OptionalCell<&'a dyn time::AlarmClient>,
+}
+
+impl<'a> MockAlarm<'a> {
+    pub fn new() -> MockAlarm<'a> {
+        MockAlarm {
+            now: Cell::new(Ticks32::from(0)),
+            reference: Cell::new(Ticks32::from(0)),
+            dt: Cell::new(Ticks32::from(0)),
+            armed: Cell::new(false),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    /// Set the current time, without firing the alarm.
+    pub fn set_now(&self, now: u32) {
+        self.now.set(Ticks32::from(now));
+    }
+
+    /// Ticks until the alarm expires, which is 0 if it already expired.
+    fn remaining(&self) -> u32 {
+        let elapsed = self.now.get().wrapping_sub(self.reference.get());
+        self.dt.get().into_u32().saturating_sub(elapsed.into_u32())
+    }
+
+    /// Move time forward by `ticks`. Each time the alarm expires on the way,
+    /// time stops at the expiration and the client is called, so alarms the
+    /// client sets again also fire if they expire in time. Returns how many
+    /// times the alarm fired.
+    pub fn advance(&self, ticks: u32) -> usize {
+        let mut left = ticks;
+        let mut fired = 0;
+        while self.armed.get() {
+            let remaining = self.remaining();
+            if remaining > left {
+                break;
+            }
+            self.now
+                .set(self.now.get().wrapping_add(Ticks32::from(remaining)));
+            left -= remaining;
+            self.armed.set(false);
+            fired += 1;
+            self.client.map(|client| client.alarm());
+        }
+        self.now
+            .set(self.now.get().wrapping_add(Ticks32::from(left)));
+        fired
+    }
+
+    /// Move time forward to when the alarm expires, and fire it. Returns
+    /// `false` if the alarm is not armed.
+    pub fn fire(&self) -> bool {
+        self.armed.get() && self.advance(self.remaining()) > 0
+    }
+}
+
+impl<'a> Time for MockAlarm<'a> {
+    type Frequency = time::Freq1KHz;
+    type Ticks = Ticks32;
+
+    fn now(&self) -> Ticks32 {
+        self.now.get()
+    }
+}
+
+impl<'a> Alarm<'a> for MockAlarm<'a> {
+    fn set_alarm_client(&'a self, client: &'a dyn time::AlarmClient) {
+        self.client.set(client);
+    }
+
+    fn set_alarm(&self, reference: Ticks32, dt: Ticks32) {
+        self.reference.set(reference);
+        self.dt.set(dt);
+        self.armed.set(true);
+    }
+
+    fn get_alarm(&self) -> Ticks32 {
+        self.reference.get().wrapping_add(self.dt.get())
+    }
+
+    fn disarm(&self) -> Result<(), ErrorCode> {
+        self.armed.set(false);
+        Ok(())
+    }
+
+    fn is_armed(&self) -> bool {
+        self.armed.get()
+    }
+
+    fn minimum_dt(&self) -> Ticks32 {
+        Ticks32::from(1)
+    }
+}
@@ -0,0 +1,208 @@
+//! Mock flash of `PAGES` pages kept in memory.
+//!
+//! Operations change the flash when the test completes them, so a test can
+//! also check what happens when an operation never finishes.
+
+use core::cell::Cell;
+use core::ops::{Index, IndexMut};
+
+use kernel::common::cells::{MapCell, OptionalCell, TakeCell};
+use kernel::hil;
+use kernel::ErrorCode;
+
+use super::Queue;
+
+pub const PAGE_SIZE: usize = 512;
+
+/// How many operations the mock records.
+pub const FLASH_QUEUE_SIZE: usize = 32;
+
+pub struct MockPage(pub [u8; PAGE_SIZE]);
+
+impl Default for MockPage {
+    fn default() -> Self {
+        Self([0; PAGE_SIZE])
+    }
+}
+
+impl Index<usize> for MockPage {
+    type Output = u8;
+
+    fn index(&self, idx: usize) -> &u8 {
+        &self.0[idx]
+    }
+}
+
+impl IndexMut<usize> for MockPage {
+    fn index_mut(&mut self, idx: usize) -> &mut u8 {
+        &mut self.0[idx]
+    }
+}
+
+impl AsMut<[u8]> for MockPage {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+/// An operation the flash was asked to do, with its page number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlashOperation {
+    Read(usize),
+    Write(usize),
+    Erase(usize),
+}
+
+pub struct MockFlash<const PAGES: usize> {
+    pages: MapCell<[[u8; PAGE_SIZE]; PAGES]>,
+    client: OptionalCell<&'static dyn hil::flash::Client<MockFlash<PAGES>>>,
+    pending: Cell<Option<FlashOperation>>,
+    buffer: TakeCell<'static, MockPage>,
+    fail_next: Cell<bool>,
+    operations: Queue<FlashOperation, FLASH_QUEUE_SIZE>,
+}
+
+impl<const PAGES: usize> MockFlash<PAGES> {
+    /// Create a flash which is erased.
+    pub fn new() -> MockFlash<PAGES> {
+        MockFlash {
+            pages: MapCell::new([[0xFF; PAGE_SIZE]; PAGES]),
+            client: OptionalCell::empty(),
+            pending: Cell::new(None),
+            buffer: TakeCell::empty(),
+            fail_next: Cell::new(false),
+            operations: Queue::new(),
+        }
+    }
+
+    /// A copy of page `page_number`.
+    pub fn page(&self, page_number: usize) -> [u8; PAGE_SIZE] {
+        self.pages
+            .map_or([0xFF; PAGE_SIZE], |pages| pages[page_number])
+    }
+
+    /// Write `data` to the flash at `offset`, without an operation.
+    pub fn set_contents(&self, offset: usize, data: &[u8]) {
+        self.pages.map(|pages| {
+            for (i, byte) in data.iter().enumerate() {
+                let address = offset + i;
+                pages[address / PAGE_SIZE][address % PAGE_SIZE] = *byte;
+            }
+        });
+    }
+
+    /// Operations started so far, which the test removes from the queue as
+    /// it checks them.
+    pub fn operations(&self) -> &Queue<FlashOperation, FLASH_QUEUE_SIZE> {
+        &self.operations
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.pending.get().is_some()
+    }
+
+    /// Make the next completed operation fail, without changing the flash.
+    pub fn fail_next(&self) {
+        self.fail_next.set(true);
+    }
+
+    /// Do the current operation and call the client. Returns `false` if there
+    /// is no operation.
+    pub fn complete(&self) -> bool {
+        let operation = match self.pending.take() {
+            Some(operation) => operation,
+            None => return false,
+        };
+        let error = if self.fail_next.replace(false) {
+            hil::flash::Error::FlashError
+        } else {
+            hil::flash::Error::CommandComplete
+        };
+        let ok = error == hil::flash::Error::CommandComplete;
+
+        match operation {
+            FlashOperation::Read(page_number) => {
+                self.buffer.take().map(|buffer| {
+                    if ok {
+                        buffer.0 = self.page(page_number);
+                    }
+                    self.client
+                        .map(move |client| client.read_complete(buffer, error));
+                });
+            }
+            FlashOperation::Write(page_number) => {
+                self.buffer.take().map(|buffer| {
+                    if ok {
+                        self.pages.map(|pages| pages[page_number] = buffer.0);
+                    }
+                    self.client
+                        .map(move |client| client.write_complete(buffer, error));
+                });
+            }
+            FlashOperation::Erase(page_number) => {
+                if ok {
+                    self.pages
+                        .map(|pages| pages[page_number] = [0xFF; PAGE_SIZE]);
+                }
+                self.client.map(|client| client.erase_complete(error));
+            }
+        }
+        true
+    }
+
+    fn start(&self, operation: FlashOperation, page_number: usize) -> Result<(), ErrorCode> {
+        if page_number >= PAGES {
+            return Err(ErrorCode::INVAL);
+        }
+        if self.pending.get().is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.operations.push(operation);
+        self.pending.set(Some(operation));
+        Ok(())
+    }
+}
+
+impl<C: hil::flash::Client<Self>, const PAGES: usize> hil::flash::HasClient<'static, C>
+    for MockFlash<PAGES>
+{
+    fn set_client(&self, client: &'static C) {
+        self.client.set(client);
+    }
+}
+
+impl<const PAGES: usize> hil::flash::Flash for MockFlash<PAGES> {
+    type Page = MockPage;
+
+    fn read_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        match self.start(FlashOperation::Read(page_number), page_number) {
+            Ok(()) => {
+                self.buffer.replace(buf);
+                Ok(())
+            }
+            Err(e) => Err((e, buf)),
+        }
+    }
+
+    fn write_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        match self.start(FlashOperation::Write(page_number), page_number) {
+            Ok(()) => {
+                self.buffer.replace(buf);
+                Ok(())
+            }
+            Err(e) => Err((e, buf)),
+        }
+    }
+
+    fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode> {
+        self.start(FlashOperation::Erase(page_number), page_number)
+    }
+}
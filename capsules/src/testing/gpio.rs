@@ -0,0 +1,154 @@
+//! Mock GPIO pin, whose input level the test sets.
+
+use core::cell::Cell;
+
+use kernel::common::cells::OptionalCell;
+use kernel::hil::gpio;
+
+pub struct MockPin<'a> {
+    input: Cell<bool>,
+    output: Cell<bool>,
+    floating_state: Cell<gpio::FloatingState>,
+    level: Cell<bool>,
+    /// The edges interrupts are enabled for, as (rising, falling).
+    edges: Cell<Option<(bool, bool)>>,
+    /// How many times the pin was set, cleared or toggled.
+    writes: Cell<usize>,
+    client: OptionalCell<&'a dyn gpio::Client>,
+}
+
+impl<'a> MockPin<'a> {
+    pub fn new() -> MockPin<'a> {
+        MockPin {
+            input: Cell::new(false),
+            output: Cell::new(false),
+            floating_state: Cell::new(gpio::FloatingState::PullNone),
+            level: Cell::new(false),
+            edges: Cell::new(None),
+            writes: Cell::new(0),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    /// The level of the pin, either driven by the pin or set by the test.
+    pub fn level(&self) -> bool {
+        self.level.get()
+    }
+
+    /// How many times the pin was set, cleared or toggled.
+    pub fn writes(&self) -> usize {
+        self.writes.get()
+    }
+
+    /// Drive the pin to `level` from outside, calling the client if an
+    /// interrupt is enabled for the edge.
+    pub fn set_level(&self, level: bool) {
+        let previous = self.level.replace(level);
+        let fired = match self.edges.get() {
+            Some((rising, falling)) => {
+                (rising && !previous && level) || (falling && previous && !level)
+            }
+            None => false,
+        };
+        if fired {
+            self.client.map(|client| client.fired());
+        }
+    }
+
+    fn write(&self, level: bool) {
+        self.level.set(level);
+        self.writes.set(self.writes.get() + 1);
+    }
+}
+
+impl<'a> gpio::Configure for MockPin<'a> {
+    fn configuration(&self) -> gpio::Configuration {
+        match (self.input.get(), self.output.get()) {
+            (false, false) => gpio::Configuration::LowPower,
+            (true, false) => gpio::Configuration::Input,
+            (false, true) => gpio::Configuration::Output,
+            (true, true) => gpio::Configuration::InputOutput,
+        }
+    }
+
+    fn make_output(&self) -> gpio::Configuration {
+        self.output.set(true);
+        self.configuration()
+    }
+
+    fn disable_output(&self) -> gpio::Configuration {
+        self.output.set(false);
+        self.configuration()
+    }
+
+    fn make_input(&self) -> gpio::Configuration {
+        self.input.set(true);
+        self.configuration()
+    }
+
+    fn disable_input(&self) -> gpio::Configuration {
+        self.input.set(false);
+        self.configuration()
+    }
+
+    fn deactivate_to_low_power(&self) {
+        self.input.set(false);
+        self.output.set(false);
+    }
+
+    fn set_floating_state(&self, state: gpio::FloatingState) {
+        self.floating_state.set(state);
+    }
+
+    fn floating_state(&self) -> gpio::FloatingState {
+        self.floating_state.get()
+    }
+}
+
+impl<'a> gpio::Output for MockPin<'a> {
+    fn set(&self) {
+        self.write(true);
+    }
+
+    fn clear(&self) {
+        self.write(false);
+    }
+
+    fn toggle(&self) -> bool {
+        let level = !self.level.get();
+        self.write(level);
+        level
+    }
+}
+
+impl<'a> gpio::Input for MockPin<'a> {
+    fn read(&self) -> bool {
+        self.level.get()
+    }
+}
+
+impl<'a> gpio::Interrupt<'a> for MockPin<'a> {
+    fn set_client(&self, client: &'a dyn gpio::Client) {
+        self.client.set(client);
+    }
+
+    fn enable_interrupts(&self, mode: gpio::InterruptEdge) {
+        self.edges.set(Some(match mode {
+            gpio::InterruptEdge::RisingEdge => (true, false),
+            gpio::InterruptEdge::FallingEdge => (false, true),
+            gpio::InterruptEdge::EitherEdge => (true, true),
+        }));
+    }
+
+    fn disable_interrupts(&self) {
+        self.edges.set(None);
+    }
+
+    fn is_pending(&self) -> bool {
+        // The client is called right away.
+        false
+    }
+}
+
+impl<'a> gpio::Pin for MockPin<'a> {}
+impl<'a> gpio::InterruptPin<'a> for MockPin<'a> {}
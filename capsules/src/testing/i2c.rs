@@ -0,0 +1,173 @@
+//! Mock I2C master, which records transactions and answers them with
+//! scripted responses.
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::common::cells::{OptionalCell, TakeCell};
+use kernel::hil::i2c;
+
+use super::{Bytes, Queue};
+
+/// How many transactions and responses the mock keeps.
+pub const I2C_QUEUE_SIZE: usize = 16;
+
+/// A transaction the I2C master was asked to do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct I2CTransaction {
+    pub addr: u8,
+    /// The bytes written, which are empty for a read.
+    pub write: Bytes,
+    /// How many bytes are read, which is 0 for a write.
+    pub read_len: usize,
+}
+
+/// The scripted result of a transaction.
+#[derive(Clone, Copy, Debug)]
+pub struct I2CResponse {
+    /// The bytes read. If there are fewer than requested, the rest of the
+    /// read is zeros.
+    pub read: Bytes,
+    pub result: Result<(), i2c::Error>,
+}
+
+pub struct MockI2CMaster {
+    client: OptionalCell<&'static dyn i2c::I2CHwMasterClient>,
+    enabled: Cell<bool>,
+    buffer: TakeCell<'static, [u8]>,
+    read_len: Cell<usize>,
+    transactions: Queue<I2CTransaction, I2C_QUEUE_SIZE>,
+    responses: Queue<I2CResponse, I2C_QUEUE_SIZE>,
+}
+
+impl MockI2CMaster {
+    pub fn new() -> MockI2CMaster {
+        MockI2CMaster {
+            client: OptionalCell::empty(),
+            enabled: Cell::new(false),
+            buffer: TakeCell::empty(),
+            read_len: Cell::new(0),
+            transactions: Queue::new(),
+            responses: Queue::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.get()
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.buffer.is_some()
+    }
+
+    /// Transactions started so far, which the test removes from the queue as
+    /// it checks them.
+    pub fn transactions(&self) -> &Queue<I2CTransaction, I2C_QUEUE_SIZE> {
+        &self.transactions
+    }
+
+    /// Answer a future transaction successfully, with `read` as the data
+    /// read. Responses are used in the order they are added.
+    pub fn respond(&self, read: &[u8]) {
+        self.responses.push(I2CResponse {
+            read: Bytes::new(read),
+            result: Ok(()),
+        });
+    }
+
+    /// Fail a future transaction with `error`.
+    pub fn respond_error(&self, error: i2c::Error) {
+        self.responses.push(I2CResponse {
+            read: Bytes::empty(),
+            result: Err(error),
+        });
+    }
+
+    /// Finish the current transaction with the next scripted response, or
+    /// successfully with zeros read if there is none. Returns `false` if there
+    /// is no transaction.
+    pub fn complete(&self) -> bool {
+        match self.buffer.take() {
+            Some(buffer) => {
+                let response = self.responses.pop().unwrap_or(I2CResponse {
+                    read: Bytes::empty(),
+                    result: Ok(()),
+                });
+                let read_len = cmp::min(self.read_len.get(), buffer.len());
+                if response.result.is_ok() {
+                    let copied = response.read.copy_to(&mut buffer[..read_len]);
+                    for byte in buffer[copied..read_len].iter_mut() {
+                        *byte = 0;
+                    }
+                }
+                self.client
+                    .map(move |client| client.command_complete(buffer, response.result));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn start(
+        &self,
+        addr: u8,
+        buffer: &'static mut [u8],
+        write_len: usize,
+        read_len: usize,
+    ) -> Result<(), (i2c::Error, &'static mut [u8])> {
+        if self.buffer.is_some() {
+            return Err((i2c::Error::Busy, buffer));
+        }
+        let write_len = cmp::min(write_len, buffer.len());
+        self.transactions.push(I2CTransaction {
+            addr,
+            write: Bytes::new(&buffer[..write_len]),
+            read_len,
+        });
+        self.read_len.set(read_len);
+        self.buffer.replace(buffer);
+        Ok(())
+    }
+}
+
+impl i2c::I2CMaster for MockI2CMaster {
+    fn set_master_client(&self, master_client: &'static dyn i2c::I2CHwMasterClient) {
+        self.client.set(master_client);
+    }
+
+    fn enable(&self) {
+        self.enabled.set(true);
+    }
+
+    fn disable(&self) {
+        self.enabled.set(false);
+    }
+
+    fn write_read(
+        &self,
+        addr: u8,
+        data: &'static mut [u8],
+        write_len: u8,
+        read_len: u8,
+    ) -> Result<(), (i2c::Error, &'static mut [u8])> {
+        self.start(addr, data, write_len as usize, read_len as usize)
+    }
+
+    fn write(
+        &self,
+        addr: u8,
+        data: &'static mut [u8],
+        len: u8,
+    ) -> Result<(), (i2c::Error, &'static mut [u8])> {
+        self.start(addr, data, len as usize, 0)
+    }
+
+    fn read(
+        &self,
+        addr: u8,
+        buffer: &'static mut [u8],
+        len: u8,
+    ) -> Result<(), (i2c::Error, &'static mut [u8])> {
+        self.start(addr, buffer, 0, len as usize)
+    }
+}
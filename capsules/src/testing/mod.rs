@@ -0,0 +1,193 @@
+//! Mock HIL implementations for testing capsules with `cargo test`.
+//!
+//! Each mock implements one or more HIL traits, records the requests it
+//! receives, and answers them with responses scripted by the test. Mocks
+//! never call their clients on their own: the test calls a method such as
+//! `MockAlarm::advance()` or `MockUart::complete_transmit()`, which does what
+//! the interrupt handler of a real peripheral would do. This makes the order
+//! of all callbacks deterministic.
+//!
+//! Capsules which use dynamic deferred calls get a `DynamicDeferredCall`
+//! created by the test, whose calls the test runs with
+//! `run_deferred_calls()`, passing a `MainLoopCapability` as a board would.
+//! Since capsules forbid unsafe code, and so cannot create capabilities,
+//! these tests are integration tests in `capsules/tests`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use capsules::testing::alarm::MockAlarm;
+//! # use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+//! let alarm = leak(MockAlarm::new());
+//! let mux = leak(MuxAlarm::new(alarm));
+//! alarm.set_alarm_client(mux);
+//! let virtual_alarm = leak(VirtualMuxAlarm::new(mux));
+//! virtual_alarm.set_alarm_client(client);
+//!
+//! virtual_alarm.set_alarm(virtual_alarm.now(), 10.into());
+//! alarm.advance(10);
+//! assert!(client.fired.get());
+//! ```
+//!
+//! Recorded requests and scripted responses are kept in fixed size queues,
+//! so the mocks do not need an allocator and can also be used on a board.
+
+use core::cell::Cell;
+use core::fmt;
+
+use kernel::capabilities::MainLoopCapability;
+use kernel::common::cells::MapCell;
+use kernel::common::dynamic_deferred_call::DynamicDeferredCall;
+
+pub mod alarm;
+pub mod flash;
+pub mod gpio;
+pub mod i2c;
+pub mod rng;
+pub mod spi;
+pub mod uart;
+
+/// Run deferred calls of `deferred_caller` until none are pending, or until
+/// `max_rounds` rounds of calls ran. Returns whether calls are still
+/// pending, which means a client keeps scheduling itself.
+pub fn run_deferred_calls(
+    deferred_caller: &DynamicDeferredCall,
+    max_rounds: usize,
+    capability: &dyn MainLoopCapability,
+) -> bool {
+    for _ in 0..max_rounds {
+        if !deferred_caller.has_pending() {
+            return false;
+        }
+        deferred_caller.call(capability);
+    }
+    deferred_caller.has_pending()
+}
+
+/// A first in, first out queue of up to `N` items.
+///
+/// Mocks use queues both for the requests they record and for the responses
+/// they are scripted with.
+pub struct Queue<T: Copy, const N: usize> {
+    items: MapCell<[Option<T>; N]>,
+    head: Cell<usize>,
+    len: Cell<usize>,
+}
+
+impl<T: Copy, const N: usize> Queue<T, N> {
+    pub fn new() -> Queue<T, N> {
+        Queue {
+            items: MapCell::new([None; N]),
+            head: Cell::new(0),
+            len: Cell::new(0),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.get()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len.get() == 0
+    }
+
+    /// Add `item` at the end of the queue. Returns `false` if the queue is
+    /// full and `item` was dropped.
+    pub fn push(&self, item: T) -> bool {
+        let len = self.len.get();
+        if len == N {
+            return false;
+        }
+        let tail = (self.head.get() + len) % N;
+        self.items.map(|items| items[tail] = Some(item));
+        self.len.set(len + 1);
+        true
+    }
+
+    /// Remove the first item of the queue.
+    pub fn pop(&self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let head = self.head.get();
+        let item = self.items.map_or(None, |items| items[head].take());
+        self.head.set((head + 1) % N);
+        self.len.set(self.len.get() - 1);
+        item
+    }
+
+    /// The `index`th item from the start of the queue.
+    pub fn get(&self, index: usize) -> Option<T> {
+        if index >= self.len.get() {
+            return None;
+        }
+        let head = self.head.get();
+        self.items.map_or(None, |items| items[(head + index) % N])
+    }
+
+    /// Move items from the queue to `dest`, until `dest` is full or the queue
+    /// is empty. Returns how many items were moved.
+    pub fn pop_into(&self, dest: &mut [T]) -> usize {
+        let mut count = 0;
+        while count < dest.len() {
+            match self.pop() {
+                Some(item) => dest[count] = item,
+                None => break,
+            }
+            count += 1;
+        }
+        count
+    }
+
+    pub fn clear(&self) {
+        while self.pop().is_some() {}
+    }
+}
+
+/// The largest transfer that `Bytes` records.
+pub const MAX_BYTES: usize = 64;
+
+/// A copy of up to `MAX_BYTES` bytes of a transfer.
+///
+/// Longer transfers are truncated; the length of the whole transfer is part
+/// of the recorded request.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Bytes {
+    data: [u8; MAX_BYTES],
+    len: usize,
+}
+
+impl Bytes {
+    pub fn new(data: &[u8]) -> Bytes {
+        let len = core::cmp::min(data.len(), MAX_BYTES);
+        let mut bytes = Bytes {
+            data: [0; MAX_BYTES],
+            len,
+        };
+        bytes.data[..len].copy_from_slice(&data[..len]);
+        bytes
+    }
+
+    pub fn empty() -> Bytes {
+        Bytes::new(&[])
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+
+    /// Copy the bytes to the start of `dest`, as far as they fit. Returns how
+    /// many bytes were copied.
+    pub fn copy_to(&self, dest: &mut [u8]) -> usize {
+        let len = core::cmp::min(self.len, dest.len());
+        dest[..len].copy_from_slice(&self.data[..len]);
+        len
+    }
+}
+
+impl fmt::Debug for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.as_slice().fmt(f)
+    }
+}
@@ -0,0 +1,100 @@
+//! Mock random number generator, which returns scripted numbers followed by
+//! a fixed pseudo-random sequence.
+
+use core::cell::Cell;
+
+use kernel::common::cells::OptionalCell;
+use kernel::hil::rng;
+use kernel::ErrorCode;
+
+use super::Queue;
+
+/// How many scripted numbers the mock keeps.
+pub const RNG_QUEUE_SIZE: usize = 64;
+
+pub struct MockRng<'a> {
+    client: OptionalCell<&'a dyn rng::Client>,
+    requested: Cell<bool>,
+    numbers: Queue<u32, RNG_QUEUE_SIZE>,
+    /// State of the xorshift generator used once the scripted numbers run
+    /// out.
+    state: Cell<u32>,
+}
+
+impl<'a> MockRng<'a> {
+    pub fn new() -> MockRng<'a> {
+        MockRng {
+            client: OptionalCell::empty(),
+            requested: Cell::new(false),
+            numbers: Queue::new(),
+            state: Cell::new(0x1234_5678),
+        }
+    }
+
+    /// Return `numbers` before the pseudo-random sequence.
+    pub fn push_numbers(&self, numbers: &[u32]) {
+        for number in numbers {
+            self.numbers.push(*number);
+        }
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.get()
+    }
+
+    /// Call the client with random numbers, if it requested them. If the
+    /// client wants more, it stays requested. Returns `false` if no numbers
+    /// were requested.
+    pub fn complete(&self) -> bool {
+        if !self.requested.replace(false) {
+            return false;
+        }
+        let mut numbers = Numbers { rng: self };
+        let more = self.client.map_or(rng::Continue::Done, |client| {
+            client.randomness_available(&mut numbers, Ok(()))
+        });
+        if more == rng::Continue::More {
+            self.requested.set(true);
+        }
+        true
+    }
+
+    fn next(&self) -> u32 {
+        self.numbers.pop().unwrap_or_else(|| {
+            let mut x = self.state.get();
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            self.state.set(x);
+            x
+        })
+    }
+}
+
+struct Numbers<'a, 'b> {
+    rng: &'b MockRng<'a>,
+}
+
+impl Iterator for Numbers<'_, '_> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        Some(self.rng.next())
+    }
+}
+
+impl<'a> rng::Rng<'a> for MockRng<'a> {
+    fn get(&self) -> Result<(), ErrorCode> {
+        self.requested.set(true);
+        Ok(())
+    }
+
+    fn cancel(&self) -> Result<(), ErrorCode> {
+        self.requested.set(false);
+        Ok(())
+    }
+
+    fn set_client(&'a self, client: &'a dyn rng::Client) {
+        self.client.set(client);
+    }
+}
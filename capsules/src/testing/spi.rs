@@ -0,0 +1,157 @@
+//! Mock SPI master device, which records transfers and answers them with
+//! scripted data.
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::common::cells::{OptionalCell, TakeCell};
+use kernel::hil::spi::{self, ClockPhase, ClockPolarity};
+use kernel::ErrorCode;
+
+use super::{Bytes, Queue};
+
+/// How many transfers and responses the mock keeps.
+pub const SPI_QUEUE_SIZE: usize = 16;
+
+/// A transfer the SPI device was asked to do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpiTransfer {
+    /// The bytes written.
+    pub write: Bytes,
+    /// The length of the transfer.
+    pub len: usize,
+    /// Whether the transfer has a read buffer.
+    pub read: bool,
+}
+
+pub struct MockSpiMasterDevice {
+    client: OptionalCell<&'static dyn spi::SpiMasterClient>,
+    polarity: Cell<ClockPolarity>,
+    phase: Cell<ClockPhase>,
+    rate: Cell<u32>,
+    write_buffer: TakeCell<'static, [u8]>,
+    read_buffer: TakeCell<'static, [u8]>,
+    len: Cell<usize>,
+    transfers: Queue<SpiTransfer, SPI_QUEUE_SIZE>,
+    responses: Queue<Bytes, SPI_QUEUE_SIZE>,
+}
+
+impl MockSpiMasterDevice {
+    pub fn new() -> MockSpiMasterDevice {
+        MockSpiMasterDevice {
+            client: OptionalCell::empty(),
+            polarity: Cell::new(ClockPolarity::IdleLow),
+            phase: Cell::new(ClockPhase::SampleLeading),
+            rate: Cell::new(0),
+            write_buffer: TakeCell::empty(),
+            read_buffer: TakeCell::empty(),
+            len: Cell::new(0),
+            transfers: Queue::new(),
+            responses: Queue::new(),
+        }
+    }
+
+    /// Set the client, which the SPI mux does for virtual devices.
+    pub fn set_client(&self, client: &'static dyn spi::SpiMasterClient) {
+        self.client.set(client);
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.write_buffer.is_some()
+    }
+
+    /// Transfers started so far, which the test removes from the queue as it
+    /// checks them.
+    pub fn transfers(&self) -> &Queue<SpiTransfer, SPI_QUEUE_SIZE> {
+        &self.transfers
+    }
+
+    /// Read `data` in a future transfer. Responses are used in the order they
+    /// are added.
+    pub fn respond(&self, data: &[u8]) {
+        self.responses.push(Bytes::new(data));
+    }
+
+    /// Finish the current transfer, reading the next scripted response. If
+    /// there is none, or it is shorter than the transfer, the rest of the
+    /// read is `0xFF`, like an idle bus. Returns `false` if there is no
+    /// transfer.
+    pub fn complete(&self) -> bool {
+        match self.write_buffer.take() {
+            Some(write_buffer) => {
+                let len = self.len.get();
+                let response = self.responses.pop().unwrap_or_else(Bytes::empty);
+                let read_buffer = self.read_buffer.take().map(|buffer| {
+                    let copied = response.copy_to(&mut buffer[..len]);
+                    for byte in buffer[copied..len].iter_mut() {
+                        *byte = 0xFF;
+                    }
+                    buffer
+                });
+                self.client
+                    .map(move |client| client.read_write_done(write_buffer, read_buffer, len));
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl spi::SpiMasterDevice for MockSpiMasterDevice {
+    fn configure(&self, cpol: ClockPolarity, cpal: ClockPhase, rate: u32) {
+        self.polarity.set(cpol);
+        self.phase.set(cpal);
+        self.rate.set(rate);
+    }
+
+    fn read_write_bytes(
+        &self,
+        write_buffer: &'static mut [u8],
+        read_buffer: Option<&'static mut [u8]>,
+        len: usize,
+    ) -> Result<(), ErrorCode> {
+        if self.write_buffer.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        let mut len = cmp::min(len, write_buffer.len());
+        if let Some(buffer) = read_buffer.as_ref() {
+            len = cmp::min(len, buffer.len());
+        }
+        self.transfers.push(SpiTransfer {
+            write: Bytes::new(&write_buffer[..len]),
+            len,
+            read: read_buffer.is_some(),
+        });
+        self.len.set(len);
+        self.write_buffer.replace(write_buffer);
+        if let Some(buffer) = read_buffer {
+            self.read_buffer.replace(buffer);
+        }
+        Ok(())
+    }
+
+    fn set_polarity(&self, cpol: ClockPolarity) {
+        self.polarity.set(cpol);
+    }
+
+    fn set_phase(&self, cpal: ClockPhase) {
+        self.phase.set(cpal);
+    }
+
+    fn set_rate(&self, rate: u32) {
+        self.rate.set(rate);
+    }
+
+    fn get_polarity(&self) -> ClockPolarity {
+        self.polarity.get()
+    }
+
+    fn get_phase(&self) -> ClockPhase {
+        self.phase.get()
+    }
+
+    fn get_rate(&self) -> u32 {
+        self.rate.get()
+    }
+}
@@ -0,0 +1,205 @@
+//! Mock UART, which records transmitted bytes and receives bytes given by
+//! the test.
+
+use core::cell::Cell;
+
+use kernel::common::cells::{OptionalCell, TakeCell};
+use kernel::hil::uart;
+use kernel::ErrorCode;
+
+use super::Queue;
+
+/// How many transmitted bytes and not yet received bytes the mock keeps.
+pub const UART_QUEUE_SIZE: usize = 1024;
+
+pub struct MockUart<'a> {
+    tx_client: OptionalCell<&'a dyn uart::TransmitClient>,
+    rx_client: OptionalCell<&'a dyn uart::ReceiveClient>,
+    parameters: OptionalCell<uart::Parameters>,
+    tx_buffer: TakeCell<'static, [u8]>,
+    tx_len: Cell<usize>,
+    rx_buffer: TakeCell<'static, [u8]>,
+    rx_len: Cell<usize>,
+    rx_index: Cell<usize>,
+    rx_aborted: Cell<bool>,
+    transmitted: Queue<u8, UART_QUEUE_SIZE>,
+    input: Queue<u8, UART_QUEUE_SIZE>,
+}
+
+impl<'a> MockUart<'a> {
+    pub fn new() -> MockUart<'a> {
+        MockUart {
+            tx_client: OptionalCell::empty(),
+            rx_client: OptionalCell::empty(),
+            parameters: OptionalCell::empty(),
+            tx_buffer: TakeCell::empty(),
+            tx_len: Cell::new(0),
+            rx_buffer: TakeCell::empty(),
+            rx_len: Cell::new(0),
+            rx_index: Cell::new(0),
+            rx_aborted: Cell::new(false),
+            transmitted: Queue::new(),
+            input: Queue::new(),
+        }
+    }
+
+    /// The parameters of the last `configure()`.
+    pub fn parameters(&self) -> Option<uart::Parameters> {
+        self.parameters.extract()
+    }
+
+    /// Bytes transmitted so far, which the test removes from the queue as it
+    /// checks them.
+    pub fn transmitted(&self) -> &Queue<u8, UART_QUEUE_SIZE> {
+        &self.transmitted
+    }
+
+    pub fn is_transmitting(&self) -> bool {
+        self.tx_buffer.is_some()
+    }
+
+    pub fn is_receiving(&self) -> bool {
+        self.rx_buffer.is_some()
+    }
+
+    /// Finish the current transmission with `result`. Returns `false` if
+    /// nothing is being transmitted.
+    pub fn complete_transmit(&self, result: Result<(), ErrorCode>) -> bool {
+        match self.tx_buffer.take() {
+            Some(buffer) => {
+                let len = if result.is_ok() { self.tx_len.get() } else { 0 };
+                self.tx_client
+                    .map(move |client| client.transmitted_buffer(buffer, len, result));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Receive `data`, calling the receive client each time a receive buffer
+    /// is full. Data for which there is no receive buffer is kept until the
+    /// next call. An aborted receive also completes on the next call.
+    pub fn receive(&self, data: &[u8]) {
+        for byte in data {
+            self.input.push(*byte);
+        }
+
+        while let Some(buffer) = self.rx_buffer.take() {
+            let mut index = self.rx_index.get();
+            if !self.rx_aborted.get() {
+                while index < self.rx_len.get() {
+                    match self.input.pop() {
+                        Some(byte) => buffer[index] = byte,
+                        None => break,
+                    }
+                    index += 1;
+                }
+            }
+
+            if self.rx_aborted.replace(false) {
+                self.rx_client.map(move |client| {
+                    client.received_buffer(
+                        buffer,
+                        index,
+                        Err(ErrorCode::CANCEL),
+                        uart::Error::Aborted,
+                    )
+                });
+            } else if index == self.rx_len.get() {
+                self.rx_client.map(move |client| {
+                    client.received_buffer(buffer, index, Ok(()), uart::Error::None)
+                });
+            } else {
+                self.rx_index.set(index);
+                self.rx_buffer.replace(buffer);
+                break;
+            }
+        }
+    }
+}
+
+impl<'a> uart::Configure for MockUart<'a> {
+    fn configure(&self, params: uart::Parameters) -> Result<(), ErrorCode> {
+        self.parameters.set(params);
+        Ok(())
+    }
+}
+
+impl<'a> uart::Transmit<'a> for MockUart<'a> {
+    fn set_transmit_client(&self, client: &'a dyn uart::TransmitClient) {
+        self.tx_client.set(client);
+    }
+
+    fn transmit_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        tx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if tx_len == 0 || tx_len > tx_buffer.len() {
+            return Err((ErrorCode::SIZE, tx_buffer));
+        }
+        if self.tx_buffer.is_some() {
+            return Err((ErrorCode::BUSY, tx_buffer));
+        }
+
+        for byte in &tx_buffer[..tx_len] {
+            self.transmitted.push(*byte);
+        }
+        self.tx_len.set(tx_len);
+        self.tx_buffer.replace(tx_buffer);
+        Ok(())
+    }
+
+    fn transmit_word(&self, _word: u32) -> Result<(), ErrorCode> {
+        Err(ErrorCode::FAIL)
+    }
+
+    fn transmit_abort(&self) -> Result<(), ErrorCode> {
+        // The transmission still completes with `complete_transmit()`.
+        if self.tx_buffer.is_some() {
+            Err(ErrorCode::BUSY)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<'a> uart::Receive<'a> for MockUart<'a> {
+    fn set_receive_client(&self, client: &'a dyn uart::ReceiveClient) {
+        self.rx_client.set(client);
+    }
+
+    fn receive_buffer(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if rx_len == 0 || rx_len > rx_buffer.len() {
+            return Err((ErrorCode::SIZE, rx_buffer));
+        }
+        if self.rx_buffer.is_some() {
+            return Err((ErrorCode::BUSY, rx_buffer));
+        }
+
+        self.rx_len.set(rx_len);
+        self.rx_index.set(0);
+        self.rx_buffer.replace(rx_buffer);
+        Ok(())
+    }
+
+    fn receive_word(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::FAIL)
+    }
+
+    fn receive_abort(&self) -> Result<(), ErrorCode> {
+        if self.rx_buffer.is_some() {
+            self.rx_aborted.set(true);
+            Err(ErrorCode::BUSY)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<'a> uart::Uart<'a> for MockUart<'a> {}
+impl<'a> uart::UartData<'a> for MockUart<'a> {}
@@ -6,6 +6,11 @@
 //! This capsule interfaces with flash and exposes the Tock `hil::kv_system`
 //! interface to others.
 //!
+//! Keys are hashed with SipHash-2-4. The `generate_key` callback is delivered
+//! through a dynamic deferred call, so the board must register the store with
+//! a `DynamicDeferredCall` and pass the handle to
+//! `initialize_callback_handle()`.
+//!
 //! +-----------------------+
 //! |                       |
 //! |  Capsule using K-V    |
@@ -31,7 +36,11 @@
 //!    hil::flash
 
 use core::cell::Cell;
+use core::hash::Hasher;
 use kernel::common::cells::{OptionalCell, TakeCell};
+use kernel::common::dynamic_deferred_call::{
+    DeferredCallHandle, DynamicDeferredCall, DynamicDeferredCallClient,
+};
 use kernel::hil::flash::{self, Flash};
 use kernel::hil::kv_system::{self, KVSystem};
 use kernel::ErrorCode;
@@ -41,10 +50,16 @@ use tickv::{self, AsyncTicKV};
 enum Operation {
     None,
     Init,
+    /// Initialising, waiting for a write that finishes an interrupted update
+    InitWrite,
     GetKey,
     AppendKey,
+    UpdateKey,
+    /// Updating a key, waiting for its last write
+    UpdateKeyWrite,
     InvalidateKey,
     GarbageCollect,
+    NextKey,
 }
 
 pub struct TickFSFlastCtrl<'a, F: Flash + 'static> {
@@ -97,7 +112,7 @@ impl<'a, F: Flash> tickv::flash_controller::FlashController<512> for TickFSFlast
 
         if self
             .flash
-            .write_page((0x20040000 + address) / 512, data_buf)
+            .write_page(self.region_offset + address / 512, data_buf)
             .is_err()
         {
             return Err(tickv::error_codes::ErrorCode::WriteFail);
@@ -120,9 +135,19 @@ pub struct TicKVStore<'a, F: Flash + 'static> {
     operation: Cell<Operation>,
     next_operation: Cell<Operation>,
 
-    value_buffer: Cell<Option<&'static [u8]>>,
+    value_buffer: TakeCell<'static, [u8]>,
+    value_length: Cell<usize>,
     key_buffer: TakeCell<'static, [u8; 8]>,
     ret_buffer: TakeCell<'static, [u8]>,
+    next_key_start: Cell<usize>,
+
+    /// The buffers of a `generate_key` call, held until the deferred call
+    unhashed_key_buffer: TakeCell<'static, [u8]>,
+    hashed_key_buffer: TakeCell<'static, [u8; 8]>,
+    deferred_caller: &'a DynamicDeferredCall,
+    deferred_handle: OptionalCell<DeferredCallHandle>,
+    /// Set if `next_key` found a key without reading the flash
+    next_key_found: Cell<bool>,
 
     client: OptionalCell<&'a dyn kv_system::Client<TicKVKeyType>>,
 }
@@ -134,6 +159,7 @@ impl<'a, F: Flash> TicKVStore<'a, F> {
         flash_read_buffer: &'static mut F::Page,
         region_offset: usize,
         flash_size: usize,
+        deferred_caller: &'a DynamicDeferredCall,
     ) -> TicKVStore<'a, F> {
         let tickv = AsyncTicKV::<TickFSFlastCtrl<F>, 512>::new(
             TickFSFlastCtrl::new(flash, flash_read_buffer, region_offset),
@@ -145,13 +171,25 @@ impl<'a, F: Flash> TicKVStore<'a, F> {
             tickv,
             operation: Cell::new(Operation::None),
             next_operation: Cell::new(Operation::None),
-            value_buffer: Cell::new(None),
+            value_buffer: TakeCell::empty(),
+            value_length: Cell::new(0),
             key_buffer: TakeCell::empty(),
             ret_buffer: TakeCell::empty(),
+            next_key_start: Cell::new(0),
+            unhashed_key_buffer: TakeCell::empty(),
+            hashed_key_buffer: TakeCell::empty(),
+            deferred_caller,
+            deferred_handle: OptionalCell::empty(),
+            next_key_found: Cell::new(false),
             client: OptionalCell::empty(),
         }
     }
 
+    /// Initializes the handle for the deferred `generate_key` callbacks.
+    pub fn initialize_callback_handle(&self, handle: DeferredCallHandle) {
+        self.deferred_handle.replace(handle);
+    }
+
     pub fn initalise(&self) {
         let _ret = self.tickv.initalise(0x7bc9f7ff4f76f244);
         self.operation.set(Operation::Init);
@@ -160,11 +198,15 @@ impl<'a, F: Flash> TicKVStore<'a, F> {
     fn complete_init(&self) {
         self.operation.set(Operation::None);
         match self.next_operation.get() {
-            Operation::None | Operation::Init => {}
+            Operation::None
+            | Operation::Init
+            | Operation::InitWrite
+            | Operation::UpdateKeyWrite => {}
             Operation::AppendKey => {
                 match self.append_key(
                     self.key_buffer.take().unwrap(),
                     self.value_buffer.take().unwrap(),
+                    self.value_length.get(),
                 ) {
                     Err((key, value, error)) => {
                         self.client.map(move |cb| {
@@ -174,6 +216,20 @@ impl<'a, F: Flash> TicKVStore<'a, F> {
                     _ => {}
                 }
             }
+            Operation::UpdateKey => {
+                match self.update_key(
+                    self.key_buffer.take().unwrap(),
+                    self.value_buffer.take().unwrap(),
+                    self.value_length.get(),
+                ) {
+                    Err((key, value, error)) => {
+                        self.client.map(move |cb| {
+                            cb.update_key_complete(error, key, value);
+                        });
+                    }
+                    _ => {}
+                }
+            }
             Operation::GetKey => {
                 match self.get_value(
                     self.key_buffer.take().unwrap(),
@@ -205,9 +261,78 @@ impl<'a, F: Flash> TicKVStore<'a, F> {
                 }
                 _ => {}
             },
+            Operation::NextKey => {
+                match self.next_key(self.next_key_start.get(), self.key_buffer.take().unwrap()) {
+                    Err((key, error)) => {
+                        self.client.map(move |cb| {
+                            cb.next_key_complete(Err(error.err().unwrap_or(ErrorCode::FAIL)), key);
+                        });
+                    }
+                    _ => {}
+                }
+            }
         }
         self.next_operation.set(Operation::None);
     }
+
+    fn complete_next_key(&self, ret: Result<tickv::success_codes::SuccessCode, tickv::ErrorCode>) {
+        let result = match ret {
+            Ok(_) => match self.tickv.get_stored_key_info() {
+                Some(key_info) => {
+                    self.key_buffer
+                        .map(|key| *key = key_info.hashed_key.to_le_bytes());
+                    Ok(kv_system::KeyInfo {
+                        value_length: key_info.value_length,
+                        region: key_info.region,
+                        next: key_info.next,
+                    })
+                }
+                None => Err(ErrorCode::FAIL),
+            },
+            Err(tickv::error_codes::ErrorCode::ReadNotReady(_)) => return,
+            Err(tickv::error_codes::ErrorCode::KeyNotFound) => Err(ErrorCode::NOSUPPORT),
+            Err(_) => Err(ErrorCode::FAIL),
+        };
+
+        self.operation.set(Operation::None);
+        self.client.map(|cb| {
+            cb.next_key_complete(result, self.key_buffer.take().unwrap());
+        });
+    }
+
+    fn continue_update(&self, ret: Result<tickv::success_codes::SuccessCode, tickv::ErrorCode>) {
+        let result = match ret {
+            Ok(tickv::success_codes::SuccessCode::Queued) => {
+                self.operation.set(Operation::UpdateKeyWrite);
+                return;
+            }
+            Ok(_) => Ok(()),
+            Err(tickv::error_codes::ErrorCode::ReadNotReady(_))
+            | Err(tickv::error_codes::ErrorCode::WriteNotReady(_)) => return,
+            Err(e) => Err(tickv_error(e)),
+        };
+
+        self.operation.set(Operation::None);
+        self.client.map(|cb| {
+            cb.update_key_complete(
+                result,
+                self.key_buffer.take().unwrap(),
+                self.tickv.get_stored_value_buffer().unwrap(),
+            );
+        });
+    }
+}
+
+/// Maps a TicKV error to the `ErrorCode` documented by `hil::kv_system`.
+fn tickv_error(e: tickv::ErrorCode) -> ErrorCode {
+    match e {
+        tickv::error_codes::ErrorCode::KeyNotFound
+        | tickv::error_codes::ErrorCode::KeyAlreadyExists => ErrorCode::NOSUPPORT,
+        tickv::error_codes::ErrorCode::RegionFull | tickv::error_codes::ErrorCode::FlashFull => {
+            ErrorCode::NOMEM
+        }
+        _ => ErrorCode::FAIL,
+    }
 }
 
 impl<'a, F: Flash> flash::Client<F> for TicKVStore<'a, F> {
@@ -227,8 +352,9 @@ impl<'a, F: Flash> flash::Client<F> for TicKVStore<'a, F> {
         match self.operation.get() {
             Operation::Init => match ret {
                 Ok(tickv::success_codes::SuccessCode::Complete)
-                | Ok(tickv::success_codes::SuccessCode::Written) => {
-                    self.operation.set(Operation::None)
+                | Ok(tickv::success_codes::SuccessCode::Written) => self.complete_init(),
+                Err(tickv::error_codes::ErrorCode::WriteNotReady(_)) => {
+                    self.operation.set(Operation::InitWrite)
                 }
                 _ => {}
             },
@@ -244,12 +370,14 @@ impl<'a, F: Flash> flash::Client<F> for TicKVStore<'a, F> {
                         );
                     });
                 }
-                Err(tickv::error_codes::ErrorCode::EraseNotReady(_)) | Ok(_) => {}
-                _ => {
+                Err(tickv::error_codes::ErrorCode::ReadNotReady(_))
+                | Err(tickv::error_codes::ErrorCode::EraseNotReady(_))
+                | Ok(_) => {}
+                Err(e) => {
                     self.operation.set(Operation::None);
                     self.client.map(|cb| {
                         cb.get_value_complete(
-                            Err(ErrorCode::FAIL),
+                            Err(tickv_error(e)),
                             self.key_buffer.take().unwrap(),
                             self.ret_buffer.take().unwrap(),
                         );
@@ -261,14 +389,38 @@ impl<'a, F: Flash> flash::Client<F> for TicKVStore<'a, F> {
                 | Ok(tickv::success_codes::SuccessCode::Written) => {
                     self.operation.set(Operation::None);
                 }
-                _ => {}
+                Err(tickv::error_codes::ErrorCode::ReadNotReady(_))
+                | Err(tickv::error_codes::ErrorCode::WriteNotReady(_))
+                | Ok(_) => {}
+                Err(e) => {
+                    self.operation.set(Operation::None);
+                    self.client.map(|cb| {
+                        cb.append_key_complete(
+                            Err(tickv_error(e)),
+                            self.key_buffer.take().unwrap(),
+                            self.tickv.get_stored_value_buffer().unwrap(),
+                        );
+                    });
+                }
             },
+            Operation::UpdateKey => self.continue_update(ret),
             Operation::InvalidateKey => match ret {
                 Ok(tickv::success_codes::SuccessCode::Complete)
                 | Ok(tickv::success_codes::SuccessCode::Written) => {
                     self.operation.set(Operation::None);
                 }
-                _ => {}
+                Err(tickv::error_codes::ErrorCode::ReadNotReady(_))
+                | Err(tickv::error_codes::ErrorCode::WriteNotReady(_))
+                | Ok(_) => {}
+                Err(e) => {
+                    self.operation.set(Operation::None);
+                    self.client.map(|cb| {
+                        cb.invalidate_key_complete(
+                            Err(tickv_error(e)),
+                            self.key_buffer.take().unwrap(),
+                        );
+                    });
+                }
             },
             Operation::GarbageCollect => match ret {
                 Ok(tickv::success_codes::SuccessCode::Complete)
@@ -280,6 +432,7 @@ impl<'a, F: Flash> flash::Client<F> for TicKVStore<'a, F> {
                 }
                 _ => {}
             },
+            Operation::NextKey => self.complete_next_key(ret),
             _ => unreachable!(),
         }
     }
@@ -295,6 +448,19 @@ impl<'a, F: Flash> flash::Client<F> for TicKVStore<'a, F> {
             Operation::Init => {
                 self.complete_init();
             }
+            Operation::InitWrite => {
+                // Continue finishing the interrupted update
+                self.operation.set(Operation::Init);
+                let (ret, _buf_buffer) = self.tickv.continue_operation();
+
+                match ret {
+                    Ok(_) => self.complete_init(),
+                    Err(tickv::error_codes::ErrorCode::WriteNotReady(_)) => {
+                        self.operation.set(Operation::InitWrite)
+                    }
+                    _ => {}
+                }
+            }
             Operation::AppendKey => {
                 self.operation.set(Operation::None);
                 self.client.map(|cb| {
@@ -305,6 +471,21 @@ impl<'a, F: Flash> flash::Client<F> for TicKVStore<'a, F> {
                     );
                 });
             }
+            Operation::UpdateKey => {
+                // Continue with the next write of the update
+                let (ret, _buf_buffer) = self.tickv.continue_operation();
+                self.continue_update(ret);
+            }
+            Operation::UpdateKeyWrite => {
+                self.operation.set(Operation::None);
+                self.client.map(|cb| {
+                    cb.update_key_complete(
+                        Ok(()),
+                        self.key_buffer.take().unwrap(),
+                        self.tickv.get_stored_value_buffer().unwrap(),
+                    );
+                });
+            }
             Operation::InvalidateKey => {
                 self.operation.set(Operation::None);
                 self.client.map(|cb| {
@@ -354,8 +535,8 @@ impl<'a, F: Flash> KVSystem<'a> for TicKVStore<'a, F> {
 
     fn generate_key(
         &self,
-        _unhashed_key: &'static mut [u8],
-        _key_buf: &'static mut Self::K,
+        unhashed_key: &'static mut [u8],
+        key_buf: &'static mut Self::K,
     ) -> Result<
         (),
         (
@@ -364,39 +545,114 @@ impl<'a, F: Flash> KVSystem<'a> for TicKVStore<'a, F> {
             Result<(), ErrorCode>,
         ),
     > {
-        unimplemented!()
+        if self.unhashed_key_buffer.is_some() {
+            // A key is already being generated.
+            return Err((unhashed_key, key_buf, Err(ErrorCode::BUSY)));
+        }
+
+        match self.deferred_handle.extract() {
+            Some(handle) => {
+                // `core::hash::SipHasher` is deprecated in favour of the
+                // hasher in `std`, which isn't available to the kernel.
+                #[allow(deprecated)]
+                let mut sip = core::hash::SipHasher::new();
+                sip.write(unhashed_key);
+                *key_buf = sip.finish().to_le_bytes();
+
+                self.unhashed_key_buffer.replace(unhashed_key);
+                self.hashed_key_buffer.replace(key_buf);
+                self.deferred_caller.set(handle);
+                Ok(())
+            }
+            None => Err((unhashed_key, key_buf, Err(ErrorCode::FAIL))),
+        }
     }
 
     fn append_key(
         &self,
         key: &'static mut Self::K,
-        value: &'static [u8],
-    ) -> Result<(), (&'static mut Self::K, &'static [u8], Result<(), ErrorCode>)> {
+        value: &'static mut [u8],
+        length: usize,
+    ) -> Result<
+        (),
+        (
+            &'static mut Self::K,
+            &'static mut [u8],
+            Result<(), ErrorCode>,
+        ),
+    > {
         match self.operation.get() {
             Operation::None => {
                 self.operation.set(Operation::AppendKey);
 
-                match self.tickv.append_key(u64::from_le_bytes(*key), value) {
-                    Ok(_ret) => {
+                match self
+                    .tickv
+                    .append_key(u64::from_le_bytes(*key), value, length)
+                {
+                    Ok(_) | Err((None, _)) => {
                         self.key_buffer.replace(key);
                         Ok(())
                     }
-                    Err(e) => match e {
-                        tickv::error_codes::ErrorCode::ReadNotReady(_)
-                        | tickv::error_codes::ErrorCode::WriteNotReady(_) => {
-                            self.key_buffer.replace(key);
-                            Ok(())
-                        }
-                        _ => Err((key, value, Err(ErrorCode::FAIL))),
-                    },
+                    Err((Some(value), e)) => {
+                        self.operation.set(Operation::None);
+                        Err((key, value, Err(tickv_error(e))))
+                    }
                 }
             }
-            Operation::Init => {
+            Operation::Init | Operation::InitWrite => {
                 // The init process is still occuring.
                 // We can save this request and start it after init
                 self.next_operation.set(Operation::AppendKey);
                 self.key_buffer.replace(key);
-                self.value_buffer.replace(Some(value));
+                self.value_buffer.replace(value);
+                self.value_length.set(length);
+                Ok(())
+            }
+            _ => {
+                // An operation is already in process.
+                Err((key, value, Err(ErrorCode::BUSY)))
+            }
+        }
+    }
+
+    fn update_key(
+        &self,
+        key: &'static mut Self::K,
+        value: &'static mut [u8],
+        length: usize,
+    ) -> Result<
+        (),
+        (
+            &'static mut Self::K,
+            &'static mut [u8],
+            Result<(), ErrorCode>,
+        ),
+    > {
+        match self.operation.get() {
+            Operation::None => {
+                self.operation.set(Operation::UpdateKey);
+
+                match self
+                    .tickv
+                    .update_key(u64::from_le_bytes(*key), value, length)
+                {
+                    Ok(_) | Err((None, _)) => {
+                        self.key_buffer.replace(key);
+                        Ok(())
+                    }
+                    Err((Some(value), e)) => {
+                        self.operation.set(Operation::None);
+                        Err((key, value, Err(tickv_error(e))))
+                    }
+                }
+            }
+            Operation::Init | Operation::InitWrite => {
+                // The init process is still occuring.
+                // We can save this request and start it after init
+                self.next_operation.set(Operation::UpdateKey);
+                self.key_buffer.replace(key);
+                self.value_buffer.replace(value);
+                self.value_length.set(length);
                 Ok(())
             }
             _ => {
@@ -433,11 +689,14 @@ impl<'a, F: Flash> KVSystem<'a> for TicKVStore<'a, F> {
                             self.key_buffer.replace(key);
                             Ok(())
                         }
-                        _ => Err((key, buf.unwrap(), Err(ErrorCode::FAIL))),
+                        _ => {
+                            self.operation.set(Operation::None);
+                            Err((key, buf.unwrap(), Err(tickv_error(e))))
+                        }
                     },
                 }
             }
-            Operation::Init => {
+            Operation::Init | Operation::InitWrite => {
                 // The init process is still occuring.
                 // We can save this request and start it after init
                 self.next_operation.set(Operation::GetKey);
@@ -471,11 +730,14 @@ impl<'a, F: Flash> KVSystem<'a> for TicKVStore<'a, F> {
                             self.key_buffer.replace(key);
                             Ok(())
                         }
-                        _ => Err((key, Err(ErrorCode::FAIL))),
+                        _ => {
+                            self.operation.set(Operation::None);
+                            Err((key, Err(tickv_error(e))))
+                        }
                     },
                 }
             }
-            Operation::Init => {
+            Operation::Init | Operation::InitWrite => {
                 // The init process is still occuring.
                 // We can save this request and start it after init
                 self.next_operation.set(Operation::InvalidateKey);
@@ -503,7 +765,7 @@ impl<'a, F: Flash> KVSystem<'a> for TicKVStore<'a, F> {
                     },
                 }
             }
-            Operation::Init => {
+            Operation::Init | Operation::InitWrite => {
                 // The init process is still occuring.
                 // We can save this request and start it after init
                 self.next_operation.set(Operation::GarbageCollect);
@@ -515,4 +777,72 @@ impl<'a, F: Flash> KVSystem<'a> for TicKVStore<'a, F> {
             }
         }
     }
+
+    fn next_key(
+        &self,
+        start: usize,
+        key: &'static mut Self::K,
+    ) -> Result<(), (&'static mut Self::K, Result<(), ErrorCode>)> {
+        match self.operation.get() {
+            Operation::None => {
+                self.operation.set(Operation::NextKey);
+
+                match self.tickv.next_key(start) {
+                    Ok(_) => match self.deferred_handle.extract() {
+                        // The key was found without reading the flash, the
+                        // client is called from the deferred call
+                        Some(handle) => {
+                            self.key_buffer.replace(key);
+                            self.next_key_found.set(true);
+                            self.deferred_caller.set(handle);
+                            Ok(())
+                        }
+                        None => {
+                            self.operation.set(Operation::None);
+                            Err((key, Err(ErrorCode::FAIL)))
+                        }
+                    },
+                    Err(tickv::error_codes::ErrorCode::ReadNotReady(_)) => {
+                        self.key_buffer.replace(key);
+                        Ok(())
+                    }
+                    Err(tickv::error_codes::ErrorCode::KeyNotFound) => {
+                        self.operation.set(Operation::None);
+                        Err((key, Err(ErrorCode::NOSUPPORT)))
+                    }
+                    Err(_) => {
+                        self.operation.set(Operation::None);
+                        Err((key, Err(ErrorCode::FAIL)))
+                    }
+                }
+            }
+            Operation::Init | Operation::InitWrite => {
+                // The init process is still occuring.
+                // We can save this request and start it after init
+                self.next_operation.set(Operation::NextKey);
+                self.next_key_start.set(start);
+                self.key_buffer.replace(key);
+                Ok(())
+            }
+            _ => {
+                // An operation is already in process.
+                Err((key, Err(ErrorCode::BUSY)))
+            }
+        }
+    }
+}
+
+impl<'a, F: Flash> DynamicDeferredCallClient for TicKVStore<'a, F> {
+    fn call(&self, _handle: DeferredCallHandle) {
+        if let Some(unhashed_key) = self.unhashed_key_buffer.take() {
+            let key_buf = self.hashed_key_buffer.take().unwrap();
+            self.client.map(move |cb| {
+                cb.generate_key_complete(Ok(()), unhashed_key, key_buf);
+            });
+        }
+
+        if self.next_key_found.replace(false) {
+            self.complete_next_key(Ok(tickv::success_codes::SuccessCode::Complete));
+        }
+    }
 }
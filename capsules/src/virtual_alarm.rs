@@ -4,7 +4,8 @@
 use core::cell::Cell;
 use kernel::common::cells::OptionalCell;
 use kernel::common::{List, ListLink, ListNode};
-use kernel::hil::time::{self, Alarm, Ticks, Time};
+use kernel::hil::time::{self, Alarm, Frequency, Ticks, Time};
+use kernel::power::WakeupDeadline;
 use kernel::ErrorCode;
 
 /// An object to multiplex multiple "virtual" alarms over a single underlying alarm. A
@@ -184,6 +185,28 @@ impl<'a, A: Alarm<'a>> MuxAlarm<'a, A> {
     }
 }
 
+/// The next wakeup is when the soonest virtual alarm fires, so the kernel
+/// does not choose a sleep state that takes longer to wake up from.
+impl<'a, A: Alarm<'a>> WakeupDeadline for MuxAlarm<'a, A> {
+    fn us_until_wakeup(&self) -> Option<u32> {
+        self.next_tick_vals.get().map(|(reference, dt)| {
+            let now = self.alarm.now();
+            let end = reference.wrapping_add(dt);
+            let remaining = if now.within_range(reference, end) {
+                end.wrapping_sub(now).into_u32()
+            } else {
+                0
+            };
+            let us = remaining as u64 * 1_000_000 / A::Frequency::frequency() as u64;
+            if us > u32::MAX as u64 {
+                u32::MAX
+            } else {
+                us as u32
+            }
+        })
+    }
+}
+
 impl<'a, A: Alarm<'a>> time::AlarmClient for MuxAlarm<'a, A> {
     /// When the underlying alarm has fired, we have to multiplex this event back to the virtual
     /// alarms that should now fire.
@@ -0,0 +1,267 @@
+//! Tests of the app loader, which writes TBFs to a mock flash and loads them
+//! with a mock process loader.
+
+mod common;
+
+use std::boxed::Box;
+use std::cell::Cell;
+
+use capsules::app_loader::{AppLoader, DRIVER_NUM};
+use capsules::nonvolatile_to_pages::NonvolatileToPages;
+use capsules::testing::flash::{FlashOperation, MockFlash, MockPage, PAGE_SIZE};
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::hil::flash::HasClient;
+use kernel::hil::nonvolatile_storage::NonvolatileStorage;
+use kernel::procs::{DynamicProcessLoading, Process, ProcessLoadError};
+use kernel::{Driver, ErrorCode, ProcessId};
+
+use common::{allow_readonly, leak, leak_buffer, write_memory, Board, Capability};
+
+const UPDATER: u32 = 0x5e45_0010;
+const OTHER: u32 = 0x5e45_0011;
+
+const FLASH_PAGES: usize = 8;
+
+/// The free region of app flash, which starts in the middle of a page.
+const FREE_FLASH: usize = PAGE_SIZE + 128;
+const FREE_FLASH_LEN: usize = 2048;
+
+/// Process loader with a fixed free region of app flash, which records the
+/// loads instead of creating processes.
+struct MockLoader {
+    /// The `max_size` of each load.
+    loaded: Cell<Option<usize>>,
+    /// The process that loads create.
+    new_process: Cell<Option<ProcessId>>,
+}
+
+impl DynamicProcessLoading for MockLoader {
+    fn free_flash(&self, _capability: &dyn ProcessManagementCapability) -> (usize, usize) {
+        (FREE_FLASH, FREE_FLASH_LEN)
+    }
+
+    fn load_new_process(
+        &self,
+        max_size: usize,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> Result<Option<ProcessId>, ProcessLoadError> {
+        self.loaded.set(Some(max_size));
+        Ok(self.new_process.get())
+    }
+
+    fn load_process(
+        &self,
+        _flash_address: usize,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> Result<Option<ProcessId>, ProcessLoadError> {
+        Err(ProcessLoadError::NoValidTbf)
+    }
+
+    fn find_process_flash(
+        &self,
+        _name: &str,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> Option<usize> {
+        None
+    }
+
+    fn remove_process(
+        &self,
+        _process_id: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+}
+
+struct Setup {
+    flash: &'static MockFlash<FLASH_PAGES>,
+    loader: &'static MockLoader,
+    app_loader: &'static AppLoader<'static, Capability>,
+    updater: &'static dyn Process,
+    other: &'static dyn Process,
+    anonymous: &'static dyn Process,
+}
+
+impl Setup {
+    /// Create an app loader with a 64 byte buffer that allows `UPDATER` to
+    /// load apps.
+    fn new() -> Setup {
+        let board = Board::new();
+        let flash = leak(MockFlash::<FLASH_PAGES>::new());
+        let storage = leak(NonvolatileToPages::new(
+            flash,
+            Box::leak(Box::new(MockPage::default())),
+        ));
+        flash.set_client(storage);
+        let loader = leak(MockLoader {
+            loaded: Cell::new(None),
+            new_process: Cell::new(None),
+        });
+        let app_loader = leak(AppLoader::new(
+            storage,
+            loader,
+            board.kernel.create_grant(&Capability),
+            &[UPDATER],
+            leak_buffer(64),
+            Capability,
+        ));
+        storage.set_client(app_loader);
+
+        let processes = board.load(&[
+            ("updater", Some(UPDATER)),
+            ("other", Some(OTHER)),
+            ("anonymous", None),
+        ]);
+        loader.new_process.set(Some(processes[1].processid()));
+        Setup {
+            flash,
+            loader,
+            app_loader,
+            updater: processes[0],
+            other: processes[1],
+            anonymous: processes[2],
+        }
+    }
+
+    fn command(
+        &self,
+        process: &dyn Process,
+        command_num: usize,
+        arg1: usize,
+        arg2: usize,
+    ) -> Result<(), ErrorCode> {
+        let result = self
+            .app_loader
+            .command(command_num, arg1, arg2, process.processid());
+        result.get_failure().map_or(Ok(()), Err)
+    }
+
+    /// Copy `data` to the memory of the updater and allow it to the app
+    /// loader.
+    fn allow(&self, data: &[u8]) {
+        write_memory(self.updater, 0, data);
+        allow_readonly(self.app_loader, DRIVER_NUM, self.updater, 0, 0, data.len()).unwrap();
+    }
+
+    /// Complete all flash operations.
+    fn run_flash(&self) {
+        while self.flash.complete() {}
+    }
+
+    /// A copy of `len` bytes of the flash at `address`.
+    fn flash_contents(&self, address: usize, len: usize) -> Vec<u8> {
+        (address..address + len)
+            .map(|address| self.flash.page(address / PAGE_SIZE)[address % PAGE_SIZE])
+            .collect()
+    }
+}
+
+/// The bytes of a TBF of `len` bytes.
+fn tbf(len: usize) -> Vec<u8> {
+    (0..len).map(|i| i as u8).collect()
+}
+
+#[test]
+fn writes_and_loads_tbf() {
+    let setup = Setup::new();
+    let tbf = tbf(100);
+
+    // The end of the last app, in the page the free region starts in.
+    setup.flash.set_contents(FREE_FLASH - 4, &[0xa5; 4]);
+
+    assert_eq!(setup.command(setup.updater, 1, tbf.len(), 0), Ok(()));
+    setup.allow(&tbf[..64]);
+    assert_eq!(setup.command(setup.updater, 2, 0, 64), Ok(()));
+    setup.run_flash();
+    setup.allow(&tbf[64..]);
+    assert_eq!(setup.command(setup.updater, 2, 64, 36), Ok(()));
+    setup.run_flash();
+    assert_eq!(setup.flash_contents(FREE_FLASH, tbf.len()), tbf);
+    assert_eq!(setup.flash_contents(FREE_FLASH - 4, 4), [0xa5; 4]);
+
+    assert_eq!(setup.command(setup.updater, 3, 0, 0), Ok(()));
+    assert_eq!(setup.loader.loaded.get(), Some(tbf.len()));
+
+    // The next app starts with setup again.
+    assert_eq!(
+        setup.command(setup.updater, 2, 0, 64),
+        Err(ErrorCode::RESERVE)
+    );
+}
+
+#[test]
+fn rejects_writes_outside_region() {
+    let setup = Setup::new();
+    assert_eq!(
+        setup.command(setup.updater, 1, FREE_FLASH_LEN + 1, 0),
+        Err(ErrorCode::SIZE)
+    );
+    assert_eq!(setup.command(setup.updater, 1, 100, 0), Ok(()));
+    setup.allow(&tbf(64));
+
+    assert_eq!(
+        setup.command(setup.updater, 2, 90, 20),
+        Err(ErrorCode::INVAL)
+    );
+    assert_eq!(
+        setup.command(setup.updater, 2, 100, 1),
+        Err(ErrorCode::INVAL)
+    );
+    assert_eq!(
+        setup.command(setup.updater, 2, usize::MAX, 2),
+        Err(ErrorCode::INVAL)
+    );
+    assert!(setup.flash.operations().is_empty());
+
+    // Writes inside the region still work.
+    assert_eq!(setup.command(setup.updater, 2, 90, 10), Ok(()));
+    setup.run_flash();
+    assert_eq!(setup.flash_contents(FREE_FLASH + 90, 10), tbf(10));
+}
+
+#[test]
+fn is_busy_while_writing() {
+    let setup = Setup::new();
+    assert_eq!(setup.command(setup.updater, 1, 64, 0), Ok(()));
+    setup.allow(&tbf(64));
+    assert_eq!(setup.command(setup.updater, 2, 0, 32), Ok(()));
+
+    assert_eq!(
+        setup.command(setup.updater, 2, 32, 32),
+        Err(ErrorCode::BUSY)
+    );
+    assert_eq!(setup.command(setup.updater, 3, 0, 0), Err(ErrorCode::BUSY));
+    assert_eq!(setup.command(setup.updater, 4, 0, 0), Err(ErrorCode::BUSY));
+    assert_eq!(setup.loader.loaded.get(), None);
+
+    setup.run_flash();
+    assert_eq!(
+        setup.flash.operations().pop(),
+        Some(FlashOperation::Read(1))
+    );
+    assert_eq!(
+        setup.flash.operations().pop(),
+        Some(FlashOperation::Write(1))
+    );
+    assert_eq!(setup.command(setup.updater, 2, 32, 32), Ok(()));
+    setup.run_flash();
+    assert_eq!(setup.command(setup.updater, 3, 0, 0), Ok(()));
+}
+
+#[test]
+fn only_allowed_applications_load_apps() {
+    let setup = Setup::new();
+    for process in [setup.other, setup.anonymous].iter() {
+        assert_eq!(setup.command(*process, 0, 0, 0), Ok(()));
+        assert_eq!(setup.command(*process, 1, 64, 0), Err(ErrorCode::NODEVICE));
+    }
+
+    // The region is reserved for the process that set it up.
+    assert_eq!(setup.command(setup.updater, 1, 64, 0), Ok(()));
+    assert_eq!(
+        setup.command(setup.other, 3, 0, 0),
+        Err(ErrorCode::NODEVICE)
+    );
+    assert_eq!(setup.command(setup.updater, 4, 0, 0), Ok(()));
+}
@@ -0,0 +1,310 @@
+//! A board for tests of capsules that serve processes.
+//!
+//! The processes are loaded from TBFs built by the test, by the same kernel
+//! code a board uses, on a mock chip that never runs them. A test creates the
+//! grants of the capsules it tests, loads the processes, and then makes
+//! system calls by calling the capsule's `Driver` methods as the kernel
+//! would.
+
+// Each test uses only some of the helpers.
+#![allow(dead_code)]
+
+use std::boxed::Box;
+use std::cell::Cell;
+use std::fmt::Write;
+use std::vec;
+use std::vec::Vec;
+
+use kernel::capabilities::{MemoryAllocationCapability, ProcessManagementCapability};
+use kernel::procs::{
+    AllowSlot, AppCredentialsChecker, CheckResult, FunctionCall, Process, ProcessSlot,
+    StopFaultPolicy, TbfFooterV2Credentials, TbfHeaderAppIdPolicy,
+};
+use kernel::syscall::{ContextSwitchReason, SyscallClass, SyscallReturn, UserspaceKernelBoundary};
+use kernel::{Chip, Driver, ErrorCode, Kernel, Read};
+use tock_tbf::builder::{credentials_footer_size, write_credentials_footer, TbfHeaderBuilder};
+
+pub struct Capability;
+unsafe impl ProcessManagementCapability for Capability {}
+unsafe impl MemoryAllocationCapability for Capability {}
+
+/// How many processes the board can load.
+const NUM_PROCS: usize = 4;
+
+/// Memory of each process that is accessible to the process.
+const APP_MEMORY_SIZE: usize = 1024;
+
+/// Memory of each process, including its grants.
+const MINIMUM_RAM: u32 = 4096;
+
+/// Format of the credentials footer of processes with an `AppId`: SHA256.
+const CREDENTIALS_FORMAT: u32 = 5;
+
+pub fn leak<T>(value: T) -> &'static T {
+    Box::leak(Box::new(value))
+}
+
+pub fn leak_buffer(len: usize) -> &'static mut [u8] {
+    Box::leak(vec![0; len].into_boxed_slice())
+}
+
+pub struct MockUserspaceKernelBoundary {}
+
+impl UserspaceKernelBoundary for MockUserspaceKernelBoundary {
+    type StoredState = ();
+
+    fn initial_process_app_brk_size(&self) -> usize {
+        APP_MEMORY_SIZE
+    }
+
+    unsafe fn initialize_process(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        _state: &mut (),
+    ) -> Result<(), ()> {
+        Ok(())
+    }
+
+    unsafe fn set_syscall_return_value(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        _state: &mut (),
+        _return_value: SyscallReturn,
+    ) -> Result<(), ()> {
+        Ok(())
+    }
+
+    unsafe fn set_process_function(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        _state: &mut (),
+        _upcall: FunctionCall,
+    ) -> Result<(), ()> {
+        Ok(())
+    }
+
+    unsafe fn switch_to_process(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        _state: &mut (),
+    ) -> (ContextSwitchReason, Option<*const u8>) {
+        (ContextSwitchReason::Interrupted, None)
+    }
+
+    unsafe fn print_context(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        _state: &(),
+        _writer: &mut dyn Write,
+    ) {
+    }
+
+    fn store_context(&self, _state: &(), _out: &mut [u8]) -> Result<usize, ErrorCode> {
+        Ok(0)
+    }
+}
+
+pub struct MockChip {
+    userspace_kernel_boundary: MockUserspaceKernelBoundary,
+}
+
+impl Chip for MockChip {
+    type MPU = ();
+    type UserspaceKernelBoundary = MockUserspaceKernelBoundary;
+    type SchedulerTimer = ();
+    type WatchDog = ();
+
+    fn service_pending_interrupts(&self) {}
+
+    fn has_pending_interrupts(&self) -> bool {
+        false
+    }
+
+    fn mpu(&self) -> &() {
+        &()
+    }
+
+    fn scheduler_timer(&self) -> &() {
+        &()
+    }
+
+    fn watchdog(&self) -> &() {
+        &()
+    }
+
+    fn userspace_kernel_boundary(&self) -> &MockUserspaceKernelBoundary {
+        &self.userspace_kernel_boundary
+    }
+
+    fn sleep(&self) {}
+
+    unsafe fn atomic<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        f()
+    }
+
+    unsafe fn print_state(&self, _writer: &mut dyn Write) {}
+}
+
+/// Checker that accepts every credential, so processes with a credentials
+/// footer are verified and get the `AppId` in their header.
+struct AcceptingChecker {}
+
+impl AppCredentialsChecker for AcceptingChecker {
+    fn require_credentials(&self) -> bool {
+        false
+    }
+
+    fn check_credentials(&self, _: &TbfFooterV2Credentials, _: &'static [u8]) -> CheckResult {
+        CheckResult::Accept
+    }
+}
+
+/// A TBF named `name` with no code. With an `app_id` the TBF has an App ID
+/// TLV and a credentials footer, otherwise the process is anonymous.
+fn tbf(name: &str, app_id: Option<u32>) -> Vec<u8> {
+    let builder = TbfHeaderBuilder::new().enabled(true).package_name(name);
+    let builder = match app_id {
+        Some(app_id) => builder.app_id(app_id),
+        None => builder,
+    };
+    let binary_end = builder.program(0, 0, MINIMUM_RAM, 0, 0).header_size();
+    let builder = builder.program(0, 0, MINIMUM_RAM, binary_end as u32, 0);
+
+    let mut flash = vec![0; binary_end];
+    if app_id.is_some() {
+        flash.resize(binary_end + credentials_footer_size(32), 0);
+        write_credentials_footer(CREDENTIALS_FORMAT, &[0; 32], &mut flash[binary_end..]).unwrap();
+    }
+    let builder = builder.total_size(flash.len() as u32);
+    builder.write(&mut flash).unwrap();
+    flash
+}
+
+pub struct Board {
+    pub kernel: &'static Kernel,
+    processes: &'static [ProcessSlot],
+}
+
+impl Board {
+    pub fn new() -> Board {
+        let processes: &'static [ProcessSlot] = Box::leak(
+            (0..NUM_PROCS)
+                .map(|_| Cell::new(None))
+                .collect::<Vec<_>>()
+                .into_boxed_slice(),
+        );
+        Board {
+            kernel: leak(Kernel::new(processes)),
+            processes,
+        }
+    }
+
+    /// Load a process for each of `apps`, given by its name and its `AppId`,
+    /// if it has one. The kernel fixes the number of grants when it loads
+    /// the first process, so the test must create all grants before.
+    pub fn load(&self, apps: &[(&str, Option<u32>)]) -> Vec<&'static dyn Process> {
+        let mut flash: Vec<u8> = apps
+            .iter()
+            .flat_map(|(name, app_id)| tbf(name, *app_id))
+            .collect();
+        flash.resize(flash.len() + 64, 0xFF);
+
+        // Word-aligned memory, as an MPU would allocate.
+        let memory: &'static mut [u64] =
+            Box::leak(vec![0u64; NUM_PROCS * MINIMUM_RAM as usize / 4].into_boxed_slice());
+        let memory = unsafe {
+            core::slice::from_raw_parts_mut(memory.as_mut_ptr() as *mut u8, memory.len() * 8)
+        };
+        kernel::procs::load_and_check_processes(
+            self.kernel,
+            leak(MockChip {
+                userspace_kernel_boundary: MockUserspaceKernelBoundary {},
+            }),
+            Box::leak(flash.into_boxed_slice()),
+            memory,
+            &StopFaultPolicy {},
+            &AcceptingChecker {},
+            &TbfHeaderAppIdPolicy {},
+            &Capability,
+        )
+        .unwrap();
+
+        self.processes
+            .iter()
+            .filter_map(|slot| slot.get())
+            .collect()
+    }
+}
+
+/// Copy `data` to `offset` in the accessible memory of `process`.
+pub fn write_memory(process: &dyn Process, offset: usize, data: &[u8]) {
+    for (i, byte) in data.iter().enumerate() {
+        let address = process.mem_start().wrapping_add(offset + i) as *mut u8;
+        assert!(unsafe { process.set_byte(address, *byte) });
+    }
+}
+
+/// Copy `len` bytes at `offset` in the accessible memory of `process`.
+pub fn read_memory(process: &dyn Process, offset: usize, len: usize) -> Vec<u8> {
+    assert!(process.mem_start() as usize + offset + len <= process.app_memory_break() as usize);
+    unsafe { core::slice::from_raw_parts(process.mem_start().add(offset), len) }.to_vec()
+}
+
+/// Share `len` bytes at `offset` in the memory of `process` with `driver`
+/// through read-only allow `allow_num`, as the kernel does for the allow
+/// system call.
+pub fn allow_readonly(
+    driver: &dyn Driver,
+    driver_num: usize,
+    process: &dyn Process,
+    allow_num: usize,
+    offset: usize,
+    len: usize,
+) -> Result<(), ErrorCode> {
+    let slot = AllowSlot {
+        class: SyscallClass::ReadOnlyAllow,
+        driver_number: driver_num,
+        subdriver_number: allow_num,
+    };
+    let address = process.mem_start().wrapping_add(offset);
+    let slice = process.build_readonly_appslice(slot, address, len)?;
+    let (returned, result) = match driver.allow_readonly(process.processid(), allow_num, slice) {
+        Ok(previous) => ((previous.ptr(), previous.len()), Ok(())),
+        Err((slice, e)) => ((slice.ptr(), slice.len()), Err(e)),
+    };
+    process.allow_complete(slot, (address, len), returned, result.is_ok());
+    result
+}
+/// Share `len` bytes at `offset` in the memory of `process` with `driver`
+/// through read-write allow `allow_num`, as the kernel does for the allow
+/// system call.
+pub fn allow_readwrite(
+    driver: &dyn Driver,
+    driver_num: usize,
+    process: &dyn Process,
+    allow_num: usize,
+    offset: usize,
+    len: usize,
+) -> Result<(), ErrorCode> {
+    let slot = AllowSlot {
+        class: SyscallClass::ReadWriteAllow,
+        driver_number: driver_num,
+        subdriver_number: allow_num,
+    };
+    let address = process.mem_start().wrapping_add(offset) as *mut u8;
+    let slice = process.build_readwrite_appslice(slot, address, len)?;
+    let (returned, result) = match driver.allow_readwrite(process.processid(), allow_num, slice) {
+        Ok(previous) => ((previous.ptr(), previous.len()), Ok(())),
+        Err((slice, e)) => ((slice.ptr(), slice.len()), Err(e)),
+    };
+    process.allow_complete(slot, (address, len), returned, result.is_ok());
+    result
+}
@@ -0,0 +1,219 @@
+//! Tests of the mailbox, which send messages between processes through their
+//! grants.
+
+mod common;
+
+use capsules::ipc_mailbox::{IpcMailbox, MailboxRule, DRIVER_NUM, MAILBOX_SIZE, MAX_MESSAGE_SIZE};
+use kernel::procs::Process;
+use kernel::{CommandReturn, Driver, ErrorCode};
+
+use common::{allow_readonly, allow_readwrite, leak, read_memory, write_memory, Board, Capability};
+
+const SERVICE: u32 = 0x5e45_0001;
+const CLIENT: u32 = 0x5e45_0002;
+const OTHER: u32 = 0x5e45_0003;
+
+static RULES: [MailboxRule; 1] = [MailboxRule {
+    service: SERVICE,
+    clients: &[CLIENT],
+}];
+
+/// Where processes keep the message they send.
+const SEND_BUFFER: usize = 0;
+/// Where processes keep the name of the process to find.
+const NAME_BUFFER: usize = 128;
+/// Where processes receive messages.
+const RECEIVE_BUFFER: usize = 256;
+
+struct Setup {
+    mailbox: &'static IpcMailbox<Capability>,
+    service: &'static dyn Process,
+    client: &'static dyn Process,
+    other: &'static dyn Process,
+    anonymous: &'static dyn Process,
+}
+
+impl Setup {
+    fn new() -> Setup {
+        let board = Board::new();
+        let mailbox = leak(IpcMailbox::new(
+            board.kernel,
+            board.kernel.create_grant(&Capability),
+            &RULES,
+            Capability,
+        ));
+        let processes = board.load(&[
+            ("service", Some(SERVICE)),
+            ("client", Some(CLIENT)),
+            ("other", Some(OTHER)),
+            ("anonymous", None),
+        ]);
+        Setup {
+            mailbox,
+            service: processes[0],
+            client: processes[1],
+            other: processes[2],
+            anonymous: processes[3],
+        }
+    }
+
+    fn command(
+        &self,
+        process: &dyn Process,
+        command_num: usize,
+        data: usize,
+        data2: usize,
+    ) -> CommandReturn {
+        self.mailbox
+            .command(command_num, data, data2, process.processid())
+    }
+
+    /// Allow the receive buffer of `process`, which also allocates its
+    /// mailbox.
+    fn open_mailbox(&self, process: &dyn Process) {
+        allow_readwrite(
+            self.mailbox,
+            DRIVER_NUM,
+            process,
+            0,
+            RECEIVE_BUFFER,
+            MAX_MESSAGE_SIZE,
+        )
+        .unwrap();
+    }
+
+    /// Send `message` from `sender` to `receiver`.
+    fn send(
+        &self,
+        sender: &dyn Process,
+        receiver: &dyn Process,
+        message: &[u8],
+    ) -> Result<(), ErrorCode> {
+        write_memory(sender, SEND_BUFFER, message);
+        allow_readonly(
+            self.mailbox,
+            DRIVER_NUM,
+            sender,
+            0,
+            SEND_BUFFER,
+            message.len(),
+        )?;
+        let result = self.command(sender, 2, receiver.processid().id(), message.len());
+        result.get_failure().map_or(Ok(()), Err)
+    }
+
+    /// Receive the oldest message of `receiver`, and return its sender and
+    /// contents.
+    fn receive(&self, receiver: &dyn Process) -> Result<(usize, Vec<u8>), ErrorCode> {
+        let result = self.command(receiver, 3, 0, 0);
+        match result.get_success_u32_u32() {
+            Some((sender, len)) => Ok((
+                sender as usize,
+                read_memory(receiver, RECEIVE_BUFFER, len as usize),
+            )),
+            None => Err(result.get_failure().unwrap()),
+        }
+    }
+}
+
+#[test]
+fn sends_and_receives_messages() {
+    let setup = Setup::new();
+    setup.open_mailbox(setup.service);
+
+    assert_eq!(setup.send(setup.client, setup.service, b"hello"), Ok(()));
+    assert_eq!(setup.send(setup.client, setup.service, b"tock"), Ok(()));
+
+    let client_id = setup.client.processid().id();
+    assert_eq!(
+        setup.receive(setup.service),
+        Ok((client_id, b"hello".to_vec()))
+    );
+    assert_eq!(
+        setup.receive(setup.service),
+        Ok((client_id, b"tock".to_vec()))
+    );
+    assert_eq!(setup.receive(setup.service), Err(ErrorCode::FAIL));
+}
+
+#[test]
+fn rules_decide_who_can_send() {
+    let setup = Setup::new();
+    for process in [setup.service, setup.client, setup.other, setup.anonymous].iter() {
+        setup.open_mailbox(*process);
+    }
+
+    for sender in [setup.other, setup.anonymous].iter() {
+        assert_eq!(
+            setup.send(*sender, setup.service, b"hi"),
+            Err(ErrorCode::NODEVICE)
+        );
+    }
+    // Rules are one way.
+    assert_eq!(
+        setup.send(setup.service, setup.client, b"hi"),
+        Err(ErrorCode::NODEVICE)
+    );
+    assert_eq!(
+        setup.send(setup.client, setup.anonymous, b"hi"),
+        Err(ErrorCode::NODEVICE)
+    );
+    assert_eq!(setup.receive(setup.service), Err(ErrorCode::FAIL));
+}
+
+#[test]
+fn delivers_only_to_open_mailboxes_with_space() {
+    let setup = Setup::new();
+    assert_eq!(
+        setup.send(setup.client, setup.service, b"hi"),
+        Err(ErrorCode::OFF)
+    );
+
+    setup.open_mailbox(setup.service);
+    assert_eq!(
+        setup.send(setup.client, setup.service, &[0; MAX_MESSAGE_SIZE + 1]),
+        Err(ErrorCode::SIZE)
+    );
+    for _ in 0..MAILBOX_SIZE {
+        assert_eq!(setup.send(setup.client, setup.service, b"hi"), Ok(()));
+    }
+    assert_eq!(
+        setup.send(setup.client, setup.service, b"hi"),
+        Err(ErrorCode::BUSY)
+    );
+
+    // A message that does not fit the receive buffer stays in the mailbox.
+    // The shorter buffer replaces the one the mailbox holds.
+    allow_readwrite(
+        setup.mailbox,
+        DRIVER_NUM,
+        setup.service,
+        0,
+        RECEIVE_BUFFER,
+        1,
+    )
+    .unwrap();
+    assert_eq!(setup.receive(setup.service), Err(ErrorCode::SIZE));
+    setup.open_mailbox(setup.service);
+    assert_eq!(
+        setup.receive(setup.service),
+        Ok((setup.client.processid().id(), b"hi".to_vec()))
+    );
+}
+
+#[test]
+fn finds_processes_by_name() {
+    let setup = Setup::new();
+    write_memory(setup.client, NAME_BUFFER, b"service");
+    allow_readonly(setup.mailbox, DRIVER_NUM, setup.client, 1, NAME_BUFFER, 7).unwrap();
+    assert_eq!(
+        setup.command(setup.client, 1, 0, 0).get_success_u32(),
+        Some(setup.service.processid().id() as u32)
+    );
+
+    write_memory(setup.client, NAME_BUFFER, b"unknown");
+    assert_eq!(
+        setup.command(setup.client, 1, 0, 0).get_failure(),
+        Some(ErrorCode::NODEVICE)
+    );
+}
@@ -0,0 +1,426 @@
+//! Tests of the mocks, driving the virtualizers and other capsules built on
+//! the HILs they implement.
+
+use std::boxed::Box;
+use std::cell::Cell;
+
+use kernel::capabilities::MainLoopCapability;
+use kernel::common::cells::TakeCell;
+use kernel::common::dynamic_deferred_call::{DynamicDeferredCall, DynamicDeferredCallClientState};
+use kernel::hil::flash::HasClient;
+use kernel::hil::gpio::{self, Interrupt, Output};
+use kernel::hil::i2c::{self, I2CDevice, I2CMaster};
+use kernel::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
+use kernel::hil::rng::{self, Rng};
+use kernel::hil::spi::{self, SpiMasterDevice};
+use kernel::hil::time::{Alarm, AlarmClient, Ticks32, Time};
+use kernel::hil::uart::{self, Receive, Transmit};
+use kernel::{create_capability, ErrorCode};
+
+use capsules::nonvolatile_to_pages::NonvolatileToPages;
+use capsules::testing::alarm::MockAlarm;
+use capsules::testing::flash::{FlashOperation, MockFlash, MockPage};
+use capsules::testing::gpio::MockPin;
+use capsules::testing::i2c::{I2CTransaction, MockI2CMaster};
+use capsules::testing::rng::MockRng;
+use capsules::testing::spi::MockSpiMasterDevice;
+use capsules::testing::uart::MockUart;
+use capsules::testing::{run_deferred_calls, Bytes};
+use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules::virtual_i2c::MuxI2C;
+use capsules::virtual_uart::{MuxUart, UartDevice};
+
+fn leak<T>(value: T) -> &'static T {
+    Box::leak(Box::new(value))
+}
+
+fn leak_buffer(len: usize) -> &'static mut [u8] {
+    Box::leak(std::vec![0; len].into_boxed_slice())
+}
+
+fn deferred_caller() -> &'static DynamicDeferredCall {
+    let clients: &'static [DynamicDeferredCallClientState; 4] = leak(Default::default());
+    leak(DynamicDeferredCall::new(clients))
+}
+
+struct AlarmCounter {
+    fired: Cell<usize>,
+}
+
+impl AlarmClient for AlarmCounter {
+    fn alarm(&self) {
+        self.fired.set(self.fired.get() + 1);
+    }
+}
+
+#[test]
+fn virtual_alarms_fire_in_order() {
+    let alarm = leak(MockAlarm::new());
+    let mux = leak(MuxAlarm::new(alarm));
+    alarm.set_alarm_client(mux);
+
+    let first = leak(VirtualMuxAlarm::new(mux));
+    let second = leak(VirtualMuxAlarm::new(mux));
+    let first_client = leak(AlarmCounter {
+        fired: Cell::new(0),
+    });
+    let second_client = leak(AlarmCounter {
+        fired: Cell::new(0),
+    });
+    first.set_alarm_client(first_client);
+    second.set_alarm_client(second_client);
+
+    second.set_alarm(second.now(), Ticks32::from(20));
+    first.set_alarm(first.now(), Ticks32::from(10));
+
+    assert_eq!(alarm.advance(9), 0);
+    assert_eq!(first_client.fired.get(), 0);
+    alarm.advance(1);
+    assert_eq!(first_client.fired.get(), 1);
+    assert_eq!(second_client.fired.get(), 0);
+    assert!(alarm.fire());
+    assert_eq!(alarm.now(), Ticks32::from(20));
+    assert_eq!(second_client.fired.get(), 1);
+    assert!(!alarm.is_armed());
+}
+
+struct UartClient {
+    transmitted: Cell<Option<usize>>,
+    received: TakeCell<'static, [u8]>,
+    received_len: Cell<usize>,
+}
+
+impl uart::TransmitClient for UartClient {
+    fn transmitted_buffer(
+        &self,
+        _tx_buffer: &'static mut [u8],
+        tx_len: usize,
+        rval: Result<(), ErrorCode>,
+    ) {
+        assert_eq!(rval, Ok(()));
+        self.transmitted.set(Some(tx_len));
+    }
+}
+
+impl uart::ReceiveClient for UartClient {
+    fn received_buffer(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+        rval: Result<(), ErrorCode>,
+        _error: uart::Error,
+    ) {
+        assert_eq!(rval, Ok(()));
+        self.received_len.set(rx_len);
+        self.received.replace(rx_buffer);
+    }
+}
+
+#[test]
+fn virtual_uart_transmits_and_receives() {
+    let uart = leak(MockUart::new());
+    let deferred_caller = deferred_caller();
+    let main_loop_cap = create_capability!(MainLoopCapability);
+    let mux = leak(MuxUart::new(uart, leak_buffer(64), 115200, deferred_caller));
+    mux.initialize_callback_handle(deferred_caller.register(mux).unwrap());
+    uart.set_transmit_client(mux);
+    uart.set_receive_client(mux);
+    mux.initialize();
+    assert_eq!(uart.parameters().map(|p| p.baud_rate), Some(115200));
+
+    let device = leak(UartDevice::new(mux, true));
+    device.setup();
+    let client = leak(UartClient {
+        transmitted: Cell::new(None),
+        received: TakeCell::empty(),
+        received_len: Cell::new(0),
+    });
+    device.set_transmit_client(client);
+    device.set_receive_client(client);
+
+    let buffer = leak_buffer(16);
+    buffer[..5].copy_from_slice(b"hello");
+    assert!(device.transmit_buffer(buffer, 5).is_ok());
+    // The mux starts the transmission from a deferred call.
+    assert!(uart.transmitted().is_empty());
+    assert!(!run_deferred_calls(deferred_caller, 10, &main_loop_cap));
+    let mut transmitted = [0; 16];
+    let len = uart.transmitted().pop_into(&mut transmitted);
+    assert_eq!(&transmitted[..len], b"hello");
+    assert!(uart.complete_transmit(Ok(())));
+    assert_eq!(client.transmitted.get(), Some(5));
+
+    assert!(device.receive_buffer(leak_buffer(8), 3).is_ok());
+    assert!(!run_deferred_calls(deferred_caller, 10, &main_loop_cap));
+    uart.receive(b"ab");
+    assert!(client.received.is_none());
+    uart.receive(b"c");
+    assert_eq!(client.received_len.get(), 3);
+    client
+        .received
+        .map(|buffer| assert_eq!(&buffer[..3], b"abc"));
+}
+
+struct I2CClient {
+    result: Cell<Option<Result<(), i2c::Error>>>,
+    buffer: TakeCell<'static, [u8]>,
+}
+
+impl i2c::I2CClient for I2CClient {
+    fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), i2c::Error>) {
+        self.result.set(Some(status));
+        self.buffer.replace(buffer);
+    }
+}
+
+#[test]
+fn virtual_i2c_device_reads_scripted_data() {
+    let i2c = leak(MockI2CMaster::new());
+    let deferred_caller = deferred_caller();
+    let main_loop_cap = create_capability!(MainLoopCapability);
+    let mux = leak(MuxI2C::new(i2c, None, deferred_caller));
+    mux.initialize_callback_handle(deferred_caller.register(mux).unwrap());
+    i2c.set_master_client(mux);
+
+    let device = leak(capsules::virtual_i2c::I2CDevice::new(mux, 0x40));
+    let client = leak(I2CClient {
+        result: Cell::new(None),
+        buffer: TakeCell::empty(),
+    });
+    device.set_client(client);
+
+    i2c.respond(&[0x12, 0x34]);
+    i2c.respond_error(i2c::Error::DataNak);
+
+    let buffer = leak_buffer(4);
+    buffer[0] = 0xE3;
+    device.enable();
+    assert!(i2c.is_enabled());
+    assert!(device.write_read(buffer, 1, 2).is_ok());
+    assert!(!run_deferred_calls(deferred_caller, 10, &main_loop_cap));
+    assert_eq!(
+        i2c.transactions().pop(),
+        Some(I2CTransaction {
+            addr: 0x40,
+            write: Bytes::new(&[0xE3]),
+            read_len: 2,
+        })
+    );
+    assert!(i2c.complete());
+    assert_eq!(client.result.get(), Some(Ok(())));
+    let buffer = client.buffer.take().unwrap();
+    assert_eq!(&buffer[..2], &[0x12, 0x34]);
+
+    assert!(device.write(buffer, 1).is_ok());
+    assert!(!run_deferred_calls(deferred_caller, 10, &main_loop_cap));
+    assert!(i2c.complete());
+    assert_eq!(client.result.get(), Some(Err(i2c::Error::DataNak)));
+    assert!(!i2c.complete());
+}
+
+struct StorageClient {
+    read: Cell<Option<usize>>,
+    written: Cell<Option<usize>>,
+    buffer: TakeCell<'static, [u8]>,
+}
+
+impl NonvolatileStorageClient<'static> for StorageClient {
+    fn read_done(&self, buffer: &'static mut [u8], length: usize) {
+        self.read.set(Some(length));
+        self.buffer.replace(buffer);
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8], length: usize) {
+        self.written.set(Some(length));
+        self.buffer.replace(buffer);
+    }
+}
+
+/// Complete flash operations until the storage is done.
+fn run_flash<const PAGES: usize>(flash: &MockFlash<PAGES>) {
+    while flash.complete() {}
+}
+
+#[test]
+fn nonvolatile_to_pages_writes_across_pages() {
+    let flash = leak(MockFlash::<4>::new());
+    let storage = leak(NonvolatileToPages::new(
+        flash,
+        Box::leak(Box::new(MockPage::default())),
+    ));
+    flash.set_client(storage);
+    let client = leak(StorageClient {
+        read: Cell::new(None),
+        written: Cell::new(None),
+        buffer: TakeCell::empty(),
+    });
+    storage.set_client(client);
+
+    let buffer = leak_buffer(8);
+    buffer.copy_from_slice(b"tock-kv!");
+    assert!(storage.write(buffer, 508, 8).is_ok());
+    run_flash(flash);
+    assert_eq!(client.written.get(), Some(8));
+    assert_eq!(&flash.page(0)[508..], b"tock");
+    assert_eq!(&flash.page(1)[..4], b"-kv!");
+    assert_eq!(flash.operations().pop(), Some(FlashOperation::Read(0)));
+    assert_eq!(flash.operations().pop(), Some(FlashOperation::Write(0)));
+
+    let buffer = client.buffer.take().unwrap();
+    flash.operations().clear();
+    assert!(storage.read(buffer, 510, 4).is_ok());
+    run_flash(flash);
+    assert_eq!(client.read.get(), Some(4));
+    client
+        .buffer
+        .map(|buffer| assert_eq!(&buffer[..4], b"ck-k"));
+}
+
+#[test]
+fn flash_failure_keeps_contents() {
+    let flash = MockFlash::<2>::new();
+    flash.set_contents(0, &[0; 4]);
+    flash.fail_next();
+    assert!(kernel::hil::flash::Flash::erase_page(&flash, 0).is_ok());
+    assert_eq!(
+        kernel::hil::flash::Flash::erase_page(&flash, 1),
+        Err(ErrorCode::BUSY)
+    );
+    assert!(flash.complete());
+    assert_eq!(&flash.page(0)[..4], &[0; 4]);
+    assert_eq!(
+        kernel::hil::flash::Flash::erase_page(&flash, 2),
+        Err(ErrorCode::INVAL)
+    );
+}
+
+struct SpiClient {
+    read: TakeCell<'static, [u8]>,
+}
+
+impl spi::SpiMasterClient for SpiClient {
+    fn read_write_done(
+        &self,
+        _write_buffer: &'static mut [u8],
+        read_buffer: Option<&'static mut [u8]>,
+        len: usize,
+    ) {
+        assert_eq!(len, 3);
+        if let Some(buffer) = read_buffer {
+            self.read.replace(buffer);
+        }
+    }
+}
+
+#[test]
+fn spi_transfer_reads_scripted_data() {
+    let spi = leak(MockSpiMasterDevice::new());
+    let client = leak(SpiClient {
+        read: TakeCell::empty(),
+    });
+    spi.set_client(client);
+    spi.configure(
+        spi::ClockPolarity::IdleHigh,
+        spi::ClockPhase::SampleTrailing,
+        1_000_000,
+    );
+    assert_eq!(spi.get_rate(), 1_000_000);
+
+    spi.respond(&[0xAA]);
+    let write = leak_buffer(3);
+    write.copy_from_slice(&[1, 2, 3]);
+    assert!(spi.read_write_bytes(write, Some(leak_buffer(4)), 8).is_ok());
+    assert_eq!(
+        spi.read_write_bytes(leak_buffer(1), None, 1),
+        Err(ErrorCode::BUSY)
+    );
+    assert_eq!(
+        spi.transfers().pop().map(|t| t.write),
+        Some(Bytes::new(&[1, 2, 3]))
+    );
+    assert!(spi.complete());
+    client
+        .read
+        .map(|buffer| assert_eq!(&buffer[..3], &[0xAA, 0xFF, 0xFF]));
+}
+
+struct PinClient {
+    fired: Cell<usize>,
+}
+
+impl gpio::Client for PinClient {
+    fn fired(&self) {
+        self.fired.set(self.fired.get() + 1);
+    }
+}
+
+#[test]
+fn pin_interrupts_on_enabled_edge() {
+    let pin = leak(MockPin::new());
+    let client = leak(PinClient {
+        fired: Cell::new(0),
+    });
+    pin.set_client(client);
+
+    pin.set_level(true);
+    assert_eq!(client.fired.get(), 0);
+    pin.enable_interrupts(gpio::InterruptEdge::FallingEdge);
+    pin.set_level(true);
+    pin.set_level(false);
+    assert_eq!(client.fired.get(), 1);
+
+    pin.toggle();
+    pin.clear();
+    assert!(!pin.level());
+    assert_eq!(pin.writes(), 2);
+    // The pin does not interrupt for its own writes.
+    assert_eq!(client.fired.get(), 1);
+}
+
+struct RngClient {
+    numbers: Cell<[u32; 4]>,
+    count: Cell<usize>,
+}
+
+impl rng::Client for RngClient {
+    fn randomness_available(
+        &self,
+        randomness: &mut dyn Iterator<Item = u32>,
+        error: Result<(), ErrorCode>,
+    ) -> rng::Continue {
+        assert_eq!(error, Ok(()));
+        let mut numbers = self.numbers.get();
+        // Take two numbers per callback.
+        for number in randomness.take(2) {
+            numbers[self.count.get()] = number;
+            self.count.set(self.count.get() + 1);
+        }
+        self.numbers.set(numbers);
+        if self.count.get() == numbers.len() {
+            rng::Continue::Done
+        } else {
+            rng::Continue::More
+        }
+    }
+}
+
+#[test]
+fn rng_returns_scripted_numbers_first() {
+    let rng = leak(MockRng::new());
+    let client = leak(RngClient {
+        numbers: Cell::new([0; 4]),
+        count: Cell::new(0),
+    });
+    rng.set_client(client);
+    rng.push_numbers(&[1, 2, 3]);
+
+    assert!(!rng.complete());
+    assert!(rng.get().is_ok());
+    assert!(rng.complete());
+    assert!(rng.is_requested());
+    assert!(rng.complete());
+    assert!(!rng.is_requested());
+    let numbers = client.numbers.get();
+    assert_eq!(&numbers[..3], &[1, 2, 3]);
+    assert_ne!(numbers[3], 0);
+}
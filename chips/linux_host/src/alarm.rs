@@ -0,0 +1,89 @@
+//! Alarm backed by the host clock.
+//!
+//! Time is counted in microseconds since the alarm was created, and wraps
+//! around like a 32 bit hardware counter.
+
+use core::cell::Cell;
+use std::time::{Duration, Instant};
+
+use kernel::common::cells::OptionalCell;
+use kernel::hil::time::{self, Ticks, Ticks32, Time};
+use kernel::ErrorCode;
+use linux::interrupts::InterruptController;
+
+use crate::interrupts;
+
+pub struct Alarm<'a> {
+    interrupts: &'static InterruptController,
+    start: Instant,
+    client: OptionalCell<&'a dyn time::AlarmClient>,
+    /// When the alarm fires, if it is armed.
+    alarm: Cell<Option<Ticks32>>,
+}
+
+impl<'a> Alarm<'a> {
+    pub fn new(interrupts: &'static InterruptController) -> Alarm<'a> {
+        Alarm {
+            interrupts,
+            start: Instant::now(),
+            client: OptionalCell::empty(),
+            alarm: Cell::new(None),
+        }
+    }
+
+    pub fn handle_interrupt(&self) {
+        if self.alarm.take().is_some() {
+            self.client.map(|client| client.alarm());
+        }
+    }
+}
+
+impl<'a> Time for Alarm<'a> {
+    type Frequency = time::Freq1MHz;
+    type Ticks = Ticks32;
+
+    fn now(&self) -> Ticks32 {
+        Ticks32::from(self.start.elapsed().as_micros() as u32)
+    }
+}
+
+impl<'a> time::Alarm<'a> for Alarm<'a> {
+    fn set_alarm_client(&self, client: &'a dyn time::AlarmClient) {
+        self.client.set(client);
+    }
+
+    fn set_alarm(&self, reference: Ticks32, dt: Ticks32) {
+        let expiration = reference.wrapping_add(dt);
+        let now = self.now();
+        // If the alarm is in the past, it fires right away.
+        let remaining = if now.within_range(reference, expiration) {
+            expiration.wrapping_sub(now).into_u32()
+        } else {
+            0
+        };
+
+        self.alarm.set(Some(expiration));
+        self.interrupts.set_timer(
+            interrupts::ALARM,
+            Some(Instant::now() + Duration::from_micros(remaining as u64)),
+        );
+    }
+
+    fn get_alarm(&self) -> Ticks32 {
+        self.alarm.get().unwrap_or_else(|| Ticks32::from(0))
+    }
+
+    fn disarm(&self) -> Result<(), ErrorCode> {
+        self.alarm.set(None);
+        self.interrupts.set_timer(interrupts::ALARM, None);
+        Ok(())
+    }
+
+    fn is_armed(&self) -> bool {
+        self.alarm.get().is_some()
+    }
+
+    fn minimum_dt(&self) -> Ticks32 {
+        Ticks32::from(1)
+    }
+}
@@ -0,0 +1,132 @@
+//! Interrupt mapping and the `Chip` implementation for the host.
+
+use core::fmt::Write;
+use kernel::{Chip, InterruptService};
+use linux::interrupts::{InterruptController, SCHEDULER_TIMER_IRQ};
+
+use crate::interrupts;
+
+pub struct LinuxHost<'a, I: InterruptService<()> + 'a> {
+    interrupts: &'static InterruptController,
+    userspace_kernel_boundary: linux::syscall::SysCall,
+    scheduler_timer: linux::scheduler_timer::SchedulerTimer,
+    interrupt_service: &'a I,
+}
+
+pub struct LinuxHostDefaultPeripherals<'a> {
+    pub uart: crate::uart::Uart<'a>,
+    pub alarm: crate::alarm::Alarm<'a>,
+    pub flash: crate::flash::Flash,
+}
+
+impl<'a> LinuxHostDefaultPeripherals<'a> {
+    /// Create the peripherals, with `flash` backed by `flash_file`. See
+    /// `Flash::new()`.
+    pub fn new(
+        interrupts: &'static InterruptController,
+        flash_file: std::fs::File,
+        flash_offset: u64,
+        flash_pages: usize,
+    ) -> Self {
+        Self {
+            uart: crate::uart::Uart::new(interrupts),
+            alarm: crate::alarm::Alarm::new(interrupts),
+            flash: crate::flash::Flash::new(interrupts, flash_file, flash_offset, flash_pages),
+        }
+    }
+}
+
+impl<'a> InterruptService<()> for LinuxHostDefaultPeripherals<'a> {
+    unsafe fn service_interrupt(&self, interrupt: u32) -> bool {
+        match interrupt {
+            interrupts::UART_TX => self.uart.handle_tx_interrupt(),
+            interrupts::UART_RX => self.uart.handle_rx_interrupt(),
+            interrupts::ALARM => self.alarm.handle_interrupt(),
+            interrupts::FLASH => self.flash.handle_interrupt(),
+            _ => return false,
+        }
+        true
+    }
+
+    unsafe fn service_deferred_call(&self, _: ()) -> bool {
+        false
+    }
+}
+
+impl<'a, I: InterruptService<()> + 'a> LinuxHost<'a, I> {
+    /// Create the chip. `apps` are the functions processes can run, see
+    /// `linux::app`.
+    pub fn new(
+        interrupts: &'static InterruptController,
+        apps: &'static [linux::app::NativeApp],
+        interrupt_service: &'a I,
+    ) -> Self {
+        Self {
+            interrupts,
+            userspace_kernel_boundary: linux::syscall::SysCall::new(interrupts, apps),
+            scheduler_timer: linux::scheduler_timer::SchedulerTimer::new(interrupts),
+            interrupt_service,
+        }
+    }
+}
+
+impl<'a, I: InterruptService<()> + 'a> Chip for LinuxHost<'a, I> {
+    type MPU = ();
+    type UserspaceKernelBoundary = linux::syscall::SysCall;
+    type SchedulerTimer = linux::scheduler_timer::SchedulerTimer;
+    type WatchDog = ();
+
+    fn service_pending_interrupts(&self) {
+        while let Some(interrupt) = self.interrupts.next_pending() {
+            if interrupt == SCHEDULER_TIMER_IRQ {
+                // The kernel checks the scheduler timer itself, the interrupt
+                // only wakes it up.
+                continue;
+            }
+            if !unsafe { self.interrupt_service.service_interrupt(interrupt) } {
+                panic!("unhandled interrupt {}", interrupt);
+            }
+        }
+    }
+
+    fn has_pending_interrupts(&self) -> bool {
+        self.interrupts.has_pending()
+    }
+
+    fn mpu(&self) -> &Self::MPU {
+        &()
+    }
+
+    fn scheduler_timer(&self) -> &Self::SchedulerTimer {
+        &self.scheduler_timer
+    }
+
+    fn watchdog(&self) -> &Self::WatchDog {
+        &()
+    }
+
+    fn userspace_kernel_boundary(&self) -> &Self::UserspaceKernelBoundary {
+        &self.userspace_kernel_boundary
+    }
+
+    fn sleep(&self) {
+        let seen = self.interrupts.events();
+        self.interrupts.wait_for_event(seen);
+    }
+
+    unsafe fn atomic<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        // Interrupts are only serviced on the kernel thread, so nothing can
+        // interrupt `f`.
+        f()
+    }
+
+    unsafe fn print_state(&self, writer: &mut dyn Write) {
+        let _ = writer.write_fmt(format_args!(
+            "\r\n---| Linux host |---\r\n Host process ID: {}\r\n",
+            std::process::id()
+        ));
+    }
+}
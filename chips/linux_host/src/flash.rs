@@ -0,0 +1,175 @@
+//! Flash backed by a file.
+//!
+//! Each operation is done on the file right away, and the callback happens
+//! from the `FLASH` interrupt. Erased flash reads as `0xFF`.
+
+use core::cell::Cell;
+use core::ops::{Index, IndexMut};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use kernel::common::cells::{OptionalCell, TakeCell};
+use kernel::hil;
+use kernel::ErrorCode;
+use linux::interrupts::InterruptController;
+
+use crate::interrupts;
+
+pub const PAGE_SIZE: usize = 512;
+
+pub struct HostPage(pub [u8; PAGE_SIZE]);
+
+impl Default for HostPage {
+    fn default() -> Self {
+        Self([0; PAGE_SIZE])
+    }
+}
+
+impl Index<usize> for HostPage {
+    type Output = u8;
+
+    fn index(&self, idx: usize) -> &u8 {
+        &self.0[idx]
+    }
+}
+
+impl IndexMut<usize> for HostPage {
+    fn index_mut(&mut self, idx: usize) -> &mut u8 {
+        &mut self.0[idx]
+    }
+}
+
+impl AsMut<[u8]> for HostPage {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Operation {
+    Read,
+    Write,
+    Erase,
+}
+
+pub struct Flash {
+    interrupts: &'static InterruptController,
+    file: File,
+    /// Where the flash starts in the file.
+    offset: u64,
+    pages: usize,
+    client: OptionalCell<&'static dyn hil::flash::Client<Flash>>,
+    operation: Cell<Option<(Operation, hil::flash::Error)>>,
+    buffer: TakeCell<'static, HostPage>,
+}
+
+impl Flash {
+    /// Create a flash of `pages` pages, stored in `file` from `offset`.
+    pub fn new(
+        interrupts: &'static InterruptController,
+        file: File,
+        offset: u64,
+        pages: usize,
+    ) -> Flash {
+        Flash {
+            interrupts,
+            file,
+            offset,
+            pages,
+            client: OptionalCell::empty(),
+            operation: Cell::new(None),
+            buffer: TakeCell::empty(),
+        }
+    }
+
+    pub fn handle_interrupt(&self) {
+        let (operation, error) = match self.operation.take() {
+            Some(operation) => operation,
+            None => return,
+        };
+        self.client.map(|client| match operation {
+            Operation::Read => {
+                self.buffer
+                    .take()
+                    .map(|buffer| client.read_complete(buffer, error));
+            }
+            Operation::Write => {
+                self.buffer
+                    .take()
+                    .map(|buffer| client.write_complete(buffer, error));
+            }
+            Operation::Erase => client.erase_complete(error),
+        });
+    }
+
+    /// Check that a new operation on `page_number` can start, and seek to
+    /// the page.
+    fn start(&self, page_number: usize) -> Result<(), ErrorCode> {
+        if page_number >= self.pages {
+            return Err(ErrorCode::INVAL);
+        }
+        if self.operation.get().is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        (&self.file)
+            .seek(SeekFrom::Start(
+                self.offset + (page_number * PAGE_SIZE) as u64,
+            ))
+            .map(|_| ())
+            .map_err(|_| ErrorCode::FAIL)
+    }
+
+    fn finish(&self, operation: Operation, result: std::io::Result<()>) {
+        let error = match result {
+            Ok(()) => hil::flash::Error::CommandComplete,
+            Err(_) => hil::flash::Error::FlashError,
+        };
+        self.operation.set(Some((operation, error)));
+        self.interrupts.raise(interrupts::FLASH);
+    }
+}
+
+impl<C: hil::flash::Client<Self>> hil::flash::HasClient<'static, C> for Flash {
+    fn set_client(&self, client: &'static C) {
+        self.client.set(client);
+    }
+}
+
+impl hil::flash::Flash for Flash {
+    type Page = HostPage;
+
+    fn read_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        if let Err(e) = self.start(page_number) {
+            return Err((e, buf));
+        }
+        let result = (&self.file).read_exact(&mut buf.0);
+        self.buffer.replace(buf);
+        self.finish(Operation::Read, result);
+        Ok(())
+    }
+
+    fn write_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        if let Err(e) = self.start(page_number) {
+            return Err((e, buf));
+        }
+        let result = (&self.file).write_all(&buf.0);
+        self.buffer.replace(buf);
+        self.finish(Operation::Write, result);
+        Ok(())
+    }
+
+    fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode> {
+        self.start(page_number)?;
+        let result = (&self.file).write_all(&[0xFF; PAGE_SIZE]);
+        self.finish(Operation::Erase, result);
+        Ok(())
+    }
+}
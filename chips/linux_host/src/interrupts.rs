@@ -0,0 +1,6 @@
+//! Named interrupts for the host chip.
+
+pub const UART_TX: u32 = 0;
+pub const UART_RX: u32 = 1;
+pub const ALARM: u32 = 2;
+pub const FLASH: u32 = 3;
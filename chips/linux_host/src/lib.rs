@@ -0,0 +1,15 @@
+//! Chip support for running Tock as a Linux process.
+//!
+//! The peripherals of the host chip are backed by the host: the UART reads
+//! stdin and writes stdout, the alarm uses the host clock, and the flash is a
+//! file. See the `linux` arch crate for how processes run.
+
+#![crate_name = "linux_host"]
+#![crate_type = "rlib"]
+
+pub mod interrupts;
+
+pub mod alarm;
+pub mod chip;
+pub mod flash;
+pub mod uart;
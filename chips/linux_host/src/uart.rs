@@ -0,0 +1,207 @@
+//! UART backed by stdin and stdout.
+//!
+//! Transmitted data is written to stdout right away, and the transmit
+//! callback happens from the `UART_TX` interrupt. A thread, started on the
+//! first receive, reads stdin and raises the `UART_RX` interrupt when data
+//! arrives.
+
+use core::cell::Cell;
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use kernel::common::cells::{OptionalCell, TakeCell};
+use kernel::hil::uart;
+use kernel::ErrorCode;
+use linux::interrupts::InterruptController;
+
+use crate::interrupts;
+
+pub struct Uart<'a> {
+    interrupts: &'static InterruptController,
+    tx_client: OptionalCell<&'a dyn uart::TransmitClient>,
+    rx_client: OptionalCell<&'a dyn uart::ReceiveClient>,
+    tx_buffer: TakeCell<'static, [u8]>,
+    tx_len: Cell<usize>,
+    rx_buffer: TakeCell<'static, [u8]>,
+    rx_len: Cell<usize>,
+    rx_index: Cell<usize>,
+    /// Data read from stdin that was not received yet.
+    input: Arc<Mutex<VecDeque<u8>>>,
+    input_started: Cell<bool>,
+}
+
+impl<'a> Uart<'a> {
+    pub fn new(interrupts: &'static InterruptController) -> Uart<'a> {
+        Uart {
+            interrupts,
+            tx_client: OptionalCell::empty(),
+            rx_client: OptionalCell::empty(),
+            tx_buffer: TakeCell::empty(),
+            tx_len: Cell::new(0),
+            rx_buffer: TakeCell::empty(),
+            rx_len: Cell::new(0),
+            rx_index: Cell::new(0),
+            input: Arc::new(Mutex::new(VecDeque::new())),
+            input_started: Cell::new(false),
+        }
+    }
+
+    /// Start the thread reading stdin, if it is not running yet.
+    fn start_input(&self) {
+        if self.input_started.replace(true) {
+            return;
+        }
+
+        let input = self.input.clone();
+        let interrupts = self.interrupts;
+        let _ = thread::Builder::new()
+            .name("uart-stdin".to_string())
+            .spawn(move || {
+                let mut buffer = [0; 64];
+                let stdin = std::io::stdin();
+                loop {
+                    match stdin.lock().read(&mut buffer) {
+                        Ok(0) | Err(_) => break,
+                        Ok(len) => {
+                            input.lock().unwrap().extend(&buffer[..len]);
+                            interrupts.raise(interrupts::UART_RX);
+                        }
+                    }
+                }
+            });
+    }
+
+    pub fn handle_tx_interrupt(&self) {
+        if let Some(buffer) = self.tx_buffer.take() {
+            self.tx_client
+                .map(move |client| client.transmitted_buffer(buffer, self.tx_len.get(), Ok(())));
+        }
+    }
+
+    pub fn handle_rx_interrupt(&self) {
+        let buffer = match self.rx_buffer.take() {
+            Some(buffer) => buffer,
+            // The data is kept until there is a receive.
+            None => return,
+        };
+
+        let mut input = self.input.lock().unwrap();
+        let mut index = self.rx_index.get();
+        while index < self.rx_len.get() {
+            match input.pop_front() {
+                Some(byte) => buffer[index] = byte,
+                None => break,
+            }
+            index += 1;
+        }
+        drop(input);
+        self.rx_index.set(index);
+
+        if index == self.rx_len.get() {
+            self.rx_client.map(move |client| {
+                client.received_buffer(buffer, index, Ok(()), uart::Error::None)
+            });
+        } else {
+            self.rx_buffer.replace(buffer);
+        }
+    }
+}
+
+impl<'a> uart::Configure for Uart<'a> {
+    fn configure(&self, _params: uart::Parameters) -> Result<(), ErrorCode> {
+        // The baud rate and framing do not matter for stdio.
+        Ok(())
+    }
+}
+
+impl<'a> uart::Transmit<'a> for Uart<'a> {
+    fn set_transmit_client(&self, client: &'a dyn uart::TransmitClient) {
+        self.tx_client.set(client);
+    }
+
+    fn transmit_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        tx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if tx_len == 0 || tx_len > tx_buffer.len() {
+            return Err((ErrorCode::SIZE, tx_buffer));
+        }
+        if self.tx_buffer.is_some() {
+            return Err((ErrorCode::BUSY, tx_buffer));
+        }
+
+        let mut stdout = std::io::stdout();
+        let _ = stdout.write_all(&tx_buffer[..tx_len]);
+        let _ = stdout.flush();
+
+        self.tx_buffer.replace(tx_buffer);
+        self.tx_len.set(tx_len);
+        self.interrupts.raise(interrupts::UART_TX);
+        Ok(())
+    }
+
+    fn transmit_word(&self, _word: u32) -> Result<(), ErrorCode> {
+        Err(ErrorCode::FAIL)
+    }
+
+    fn transmit_abort(&self) -> Result<(), ErrorCode> {
+        // Transmissions complete immediately.
+        Ok(())
+    }
+}
+
+impl<'a> uart::Receive<'a> for Uart<'a> {
+    fn set_receive_client(&self, client: &'a dyn uart::ReceiveClient) {
+        self.rx_client.set(client);
+    }
+
+    fn receive_buffer(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if rx_len == 0 || rx_len > rx_buffer.len() {
+            return Err((ErrorCode::SIZE, rx_buffer));
+        }
+        if self.rx_buffer.is_some() {
+            return Err((ErrorCode::BUSY, rx_buffer));
+        }
+
+        self.rx_buffer.replace(rx_buffer);
+        self.rx_len.set(rx_len);
+        self.rx_index.set(0);
+        self.start_input();
+        if !self.input.lock().unwrap().is_empty() {
+            // Deliver data that arrived before this receive.
+            self.interrupts.raise(interrupts::UART_RX);
+        }
+        Ok(())
+    }
+
+    fn receive_word(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::FAIL)
+    }
+
+    fn receive_abort(&self) -> Result<(), ErrorCode> {
+        match self.rx_buffer.take() {
+            Some(buffer) => {
+                self.rx_client.map(move |client| {
+                    client.received_buffer(
+                        buffer,
+                        self.rx_index.get(),
+                        Err(ErrorCode::CANCEL),
+                        uart::Error::Aborted,
+                    )
+                });
+                Err(ErrorCode::BUSY)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a> uart::Uart<'a> for Uart<'a> {}
+impl<'a> uart::UartData<'a> for Uart<'a> {}
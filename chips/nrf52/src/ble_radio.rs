@@ -787,6 +787,17 @@ impl<'a> Radio<'a> {
     }
 }
 
+/// The radio needs the HFXO while it is on.
+impl<'a> kernel::power::PowerClient for Radio<'a> {
+    fn deepest_sleep_state(&self) -> Option<usize> {
+        if self.registers.state.get() == nrf5x::constants::RADIO_STATE_DISABLE {
+            None
+        } else {
+            Some(crate::chip::SLEEP_STATE_IDLE)
+        }
+    }
+}
+
 impl<'a> ble_advertising::BleAdvertisementDriver<'a> for Radio<'a> {
     fn transmit_advertisement(&self, buf: &'static mut [u8], _len: usize, channel: RadioChannel) {
         let res = self.replace_radio_buffer(buf);
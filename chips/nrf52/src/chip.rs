@@ -3,17 +3,40 @@ use core::fmt::Write;
 use cortexm4::{self, nvic};
 use kernel::common::deferred_call;
 use kernel::hil::time::Alarm;
+use kernel::power::SleepState;
 use kernel::InterruptService;
 
+/// Index of the sleep state in which only the CPU stops.
+pub const SLEEP_STATE_IDLE: usize = 0;
+/// Index of the sleep state in which the 64 MHz crystal oscillator (HFXO) is
+/// also stopped. Peripherals that need the crystal, such as the radios and
+/// USB, must keep the chip out of this state while they are active.
+pub const SLEEP_STATE_HFXO_OFF: usize = 1;
+
+static SLEEP_STATES: [SleepState; 2] = [
+    SleepState {
+        name: "idle",
+        min_sleep_us: 0,
+    },
+    // The HFXO takes about 360 us to start again.
+    SleepState {
+        name: "hfxo off",
+        min_sleep_us: 400,
+    },
+];
+
 pub struct NRF52<'a, I: InterruptService<DeferredCallTask> + 'a> {
     mpu: cortexm4::mpu::MPU,
     userspace_kernel_boundary: cortexm4::syscall::SysCall,
     scheduler_timer: cortexm4::systick::SysTick,
     interrupt_service: &'a I,
+    clock: &'a crate::clock::Clock,
 }
 
 impl<'a, I: InterruptService<DeferredCallTask> + 'a> NRF52<'a, I> {
-    pub unsafe fn new(interrupt_service: &'a I) -> Self {
+    /// `clock` is the clock peripheral the board configures, which the chip
+    /// uses to stop the HFXO while sleeping.
+    pub unsafe fn new(interrupt_service: &'a I, clock: &'a crate::clock::Clock) -> Self {
         Self {
             mpu: cortexm4::mpu::MPU::new(),
             userspace_kernel_boundary: cortexm4::syscall::SysCall::new(),
@@ -21,6 +44,7 @@ impl<'a, I: InterruptService<DeferredCallTask> + 'a> NRF52<'a, I> {
             // 64Mhz CPU clock.
             scheduler_timer: cortexm4::systick::SysTick::new_with_calibration(64000000),
             interrupt_service,
+            clock,
         }
     }
 }
@@ -206,6 +230,36 @@ impl<'a, I: InterruptService<DeferredCallTask> + 'a> kernel::Chip for NRF52<'a,
         }
     }
 
+    fn sleep_states(&self) -> &'static [SleepState] {
+        &SLEEP_STATES
+    }
+
+    fn sleep_in(&self, state: usize) {
+        let stop_hfxo = state >= SLEEP_STATE_HFXO_OFF
+            && self.clock.high_running()
+            && matches!(
+                self.clock.high_source(),
+                crate::clock::HighClockSource::XTAL
+            );
+        if stop_hfxo {
+            self.clock.high_stop();
+        }
+
+        unsafe {
+            cortexm4::support::wfi();
+        }
+
+        // Start the crystal again, but do not wait the ~360 us it takes with
+        // interrupts disabled. The high frequency clock runs from the
+        // internal oscillator until then, and `high_started()` reports when
+        // the crystal is back. Peripherals that need the crystal keep the
+        // chip out of `SLEEP_STATE_HFXO_OFF` while they are active.
+        if stop_hfxo {
+            self.clock.high_clear_started();
+            self.clock.high_start();
+        }
+    }
+
     unsafe fn atomic<F, R>(&self, f: F) -> R
     where
         F: FnOnce() -> R,
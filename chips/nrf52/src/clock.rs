@@ -33,7 +33,7 @@ register_structs! {
         (0x014 => tasks_ctstart: WriteOnly<u32, Control::Register>),
         (0x018 => tasks_ctstop: WriteOnly<u32, Control::Register>),
         (0x01C => _reserved1),
-        (0x100 => events_hfclkstarted: ReadOnly<u32, Status::Register>),
+        (0x100 => events_hfclkstarted: ReadWrite<u32, Status::Register>),
         (0x104 => events_lfclkstarted: ReadOnly<u32, Status::Register>),
         (0x108 => _reserved2),
         (0x10C => events_done: ReadOnly<u32, Status::Register>),
@@ -223,6 +223,14 @@ impl Clock {
             .matches_all(Status::READY.val(1))
     }
 
+    /// Clear the event that the high frequency clock has started, so that
+    /// `high_started()` reports the next start
+    pub fn high_clear_started(&self) {
+        self.registers
+            .events_hfclkstarted
+            .write(Status::READY::CLEAR);
+    }
+
     /// Read clock source from the high frequency clock
     pub fn high_source(&self) -> HighClockSource {
         match self.registers.hfclkstat.read(HfClkStat::SRC) {
@@ -1016,6 +1016,17 @@ impl<'p> Radio<'p> {
     }
 }
 
+/// The radio needs the HFXO while it is on.
+impl<'p> kernel::power::PowerClient for Radio<'p> {
+    fn deepest_sleep_state(&self) -> Option<usize> {
+        if self.registers.state.get() == nrf5x::constants::RADIO_STATE_DISABLE {
+            None
+        } else {
+            Some(crate::chip::SLEEP_STATE_IDLE)
+        }
+    }
+}
+
 impl<'p> kernel::hil::radio::Radio for Radio<'p> {}
 
 impl<'p> kernel::hil::radio::RadioConfig for Radio<'p> {
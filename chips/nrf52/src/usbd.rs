@@ -1924,6 +1924,17 @@ impl<'a> power::PowerClient for Usbd<'a> {
     }
 }
 
+/// The USB peripheral needs the HFXO while it is enabled.
+impl<'a> kernel::power::PowerClient for Usbd<'a> {
+    fn deepest_sleep_state(&self) -> Option<usize> {
+        if self.state.contains(&UsbState::Disabled) {
+            None
+        } else {
+            Some(crate::chip::SLEEP_STATE_IDLE)
+        }
+    }
+}
+
 impl<'a> hil::usb::UsbController<'a> for Usbd<'a> {
     fn set_client(&self, client: &'a dyn hil::usb::Client<'a>) {
         self.client.set(client);
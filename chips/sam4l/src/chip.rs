@@ -6,8 +6,28 @@ use crate::pm;
 use core::fmt::Write;
 use cortexm4;
 use kernel::common::deferred_call;
+use kernel::power::SleepState;
 use kernel::{Chip, InterruptService};
 
+/// Index of the sleep state in which only the core clock stops.
+pub const SLEEP_STATE_SLEEP: usize = 0;
+/// Index of the sleep state in which the core enters deep sleep, if no
+/// peripheral that needs its clocks is enabled.
+pub const SLEEP_STATE_DEEP_SLEEP: usize = 1;
+
+static SLEEP_STATES: [SleepState; 2] = [
+    SleepState {
+        name: "sleep",
+        min_sleep_us: 0,
+    },
+    // The clocks take a while to restart after deep sleep. This is a
+    // conservative bound that keeps short alarms precise.
+    SleepState {
+        name: "deep sleep",
+        min_sleep_us: 1000,
+    },
+];
+
 pub struct Sam4l<I: InterruptService<Task> + 'static> {
     mpu: cortexm4::mpu::MPU,
     userspace_kernel_boundary: cortexm4::syscall::SysCall,
@@ -285,7 +305,15 @@ impl<I: InterruptService<Task> + 'static> Chip for Sam4l<I> {
     }
 
     fn sleep(&self) {
-        if pm::deep_sleep_ready() {
+        self.sleep_in(SLEEP_STATE_DEEP_SLEEP);
+    }
+
+    fn sleep_states(&self) -> &'static [SleepState] {
+        &SLEEP_STATES
+    }
+
+    fn sleep_in(&self, state: usize) {
+        if state >= SLEEP_STATE_DEEP_SLEEP && pm::deep_sleep_ready() {
             unsafe {
                 cortexm4::scb::set_sleepdeep();
             }
@@ -65,6 +65,7 @@
 //! );
 //! ```
 
+use crate::capabilities::MainLoopCapability;
 use crate::common::cells::OptionalCell;
 use core::cell::Cell;
 
@@ -134,7 +135,9 @@ impl DynamicDeferredCall {
     ///
     /// Returns `true` if a global instance was registered and has been called.
     pub unsafe fn call_global_instance() -> bool {
-        DYNAMIC_DEFERRED_CALL.map(|ddc| ddc.call()).is_some()
+        DYNAMIC_DEFERRED_CALL
+            .map(|ddc| ddc.call_while(|| true))
+            .is_some()
     }
 
     /// Call the globally registered instance while the supplied predicate
@@ -214,8 +217,10 @@ impl DynamicDeferredCall {
     /// Call all registered and to-be-scheduled deferred calls
     ///
     /// It may be called without holding the `DynamicDeferredCall` reference through
-    /// `call_global_instance`.
-    pub(self) fn call(&self) {
+    /// `call_global_instance`. Outside of the kernel, this is for running the
+    /// deferred calls of an instance which is not the global one, as tests
+    /// do, so it requires the `MainLoopCapability`.
+    pub fn call(&self, _capability: &dyn MainLoopCapability) {
         self.call_while(|| true)
     }
 
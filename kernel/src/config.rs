@@ -40,6 +40,15 @@ pub(crate) struct Config {
     /// into which SRAM addresses. This can be useful to debug whether the kernel could
     /// successfully load processes, and whether the allocated SRAM is as expected.
     pub(crate) debug_load_processes: bool,
+
+    /// Whether the kernel should paint the memory of processes when it loads or restarts them.
+    ///
+    /// If enabled, the kernel fills the memory of each process with a known pattern before the
+    /// process starts, so that it can later find how deep the process stack has ever grown. This
+    /// makes sizing `minimum_ram_size` in TBF headers easier, at the cost of writing all process
+    /// memory once per start. If disabled, the kernel can only report the deepest stack pointer it
+    /// saw when switching away from the process, which underestimates the stack use.
+    pub(crate) paint_process_stacks: bool,
 }
 
 /// A unique instance of `Config` where compile-time configuration options are defined. These
@@ -47,4 +56,5 @@ pub(crate) struct Config {
 pub(crate) const CONFIG: Config = Config {
     trace_syscalls: false,
     debug_load_processes: false,
+    paint_process_stacks: false,
 };
@@ -4,8 +4,8 @@
 //!
 //! # System-call Overview
 //!
-//! Tock supports six system calls. The `yield` and `memop` system calls are
-//! handled by the core kernel, while four others are implemented by drivers:
+//! Tock supports seven system calls. The `yield` and `memop` system calls are
+//! handled by the core kernel, while five others are implemented by drivers:
 //!
 //!   * `subscribe` passes a upcall to the driver which it can
 //!   invoke on the process later, when an event has occurred or data
@@ -19,6 +19,9 @@
 //!   * `allow_readonly` provides the driver read-only access to an
 //!   application buffer.
 //!
+//!   * `allow_userspace_readable` provides the driver read-write access to an
+//!   application buffer that the application may continue to read.
+//!
 //! ## Mapping system-calls to drivers
 //!
 //! Each of these three system calls takes at least two
@@ -147,6 +150,48 @@ impl CommandReturn {
     pub fn success_u64_u32(data0: u64, data1: u32) -> Self {
         CommandReturn(SyscallReturn::SuccessU64U32(data0, data1))
     }
+
+    /// Whether the command succeeded, with or without data
+    pub fn is_success(&self) -> bool {
+        match self.0 {
+            SyscallReturn::Success
+            | SyscallReturn::SuccessU32(_)
+            | SyscallReturn::SuccessU32U32(_, _)
+            | SyscallReturn::SuccessU32U32U32(_, _, _)
+            | SyscallReturn::SuccessU64(_)
+            | SyscallReturn::SuccessU64U32(_, _) => true,
+            _ => false,
+        }
+    }
+
+    /// The error code of a failed command, with or without data
+    pub fn get_failure(&self) -> Option<ErrorCode> {
+        match self.0 {
+            SyscallReturn::Failure(rc)
+            | SyscallReturn::FailureU32(rc, _)
+            | SyscallReturn::FailureU32U32(rc, _, _)
+            | SyscallReturn::FailureU64(rc, _) => Some(rc),
+            _ => None,
+        }
+    }
+
+    /// The data field of a successful command with an additional 32-bit data
+    /// field
+    pub fn get_success_u32(&self) -> Option<u32> {
+        match self.0 {
+            SyscallReturn::SuccessU32(data0) => Some(data0),
+            _ => None,
+        }
+    }
+
+    /// The data fields of a successful command with two additional 32-bit
+    /// data fields
+    pub fn get_success_u32_u32(&self) -> Option<(u32, u32)> {
+        match self.0 {
+            SyscallReturn::SuccessU32U32(data0, data1) => Some((data0, data1)),
+            _ => None,
+        }
+    }
 }
 
 impl From<Result<(), ErrorCode>> for CommandReturn {
@@ -204,7 +249,9 @@ pub trait Driver {
     /// System call for a process to pass a buffer (a ReadWriteAppSlice) to
     /// the kernel that the kernel can either read or write. The kernel calls
     /// this method only after it checks that the entire buffer is
-    /// within memory the process can both read and write.
+    /// within memory the process can both read and write. On success, the
+    /// driver returns the buffer it held for `which` before, so the process
+    /// can pass the same or an overlapping buffer again.
     fn allow_readwrite(
         &self,
         app: ProcessId,
@@ -228,4 +275,25 @@ pub trait Driver {
     ) -> Result<ReadOnlyAppSlice, (ReadOnlyAppSlice, ErrorCode)> {
         Err((slice, ErrorCode::NOSUPPORT))
     }
+
+    /// System call for a process to pass a buffer (a ReadWriteAppSlice) to
+    /// the kernel that the kernel can write and the process can still read.
+    /// This lets a driver publish state (e.g., a sample counter) that the
+    /// process reads when it needs it, without scheduling an upcall for
+    /// every update. The kernel calls this method only after it checks that
+    /// the entire buffer is within memory the process can both read and
+    /// write.
+    ///
+    /// As the process may read the buffer at any time, a driver must not
+    /// rely on the process observing a multi-word update atomically, and
+    /// should only write to the buffer. Drivers usually define a layout in
+    /// which the process can detect a torn read, such as a sequence counter.
+    fn allow_userspace_readable(
+        &self,
+        app: ProcessId,
+        which: usize,
+        slice: ReadWriteAppSlice,
+    ) -> Result<ReadWriteAppSlice, (ReadWriteAppSlice, ErrorCode)> {
+        Err((slice, ErrorCode::NOSUPPORT))
+    }
 }
@@ -0,0 +1,289 @@
+//! Table of the syscall drivers a board provides.
+//!
+//! Instead of matching on driver numbers in `Platform::with_driver()`, a board
+//! can list its drivers in a `DriverRegistry`, which the `driver_registry!`
+//! macro creates. The registry finds the driver for a driver number, and
+//! knows the name of every driver, so that tools such as the process console
+//! can list the drivers the board provides.
+//!
+//! The registry is also a syscall driver itself, with driver number
+//! `DRIVER_NUM`, through which processes can check which drivers exist.
+//!
+//! ```rust,ignore
+//! struct Hail {
+//!     drivers: &'static kernel::driver_registry::DriverRegistry,
+//! }
+//!
+//! impl Platform for Hail {
+//!     fn with_driver<F, R>(&self, driver_num: usize, f: F) -> R
+//!     where
+//!         F: FnOnce(Option<&dyn kernel::Driver>) -> R,
+//!     {
+//!         self.drivers.with_driver(driver_num, f)
+//!     }
+//! }
+//!
+//! let hail = Hail {
+//!     drivers: kernel::driver_registry!(
+//!         capsules::console::DRIVER_NUM => "console": console,
+//!         capsules::led::DRIVER_NUM => "led": led,
+//!         kernel::ipc::DRIVER_NUM => "ipc": ipc,
+//!     ),
+//! };
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! ### Command
+//!
+//! - `0`: Driver check.
+//! - `1`: Check whether the driver with number `data` exists. Returns
+//!   `NODEVICE` if it does not.
+//! - `2`: Return the number of drivers in the registry, not counting the
+//!   registry itself.
+//! - `3`: Return the driver number of driver `data` of the registry, or
+//!   `INVAL` if there are not that many drivers.
+
+use crate::driver::{CommandReturn, Driver};
+use crate::errorcode::ErrorCode;
+use crate::process::ProcessId;
+
+/// Syscall number
+pub const DRIVER_NUM: usize = 0x10004;
+
+/// A syscall driver of the board, with its driver number and a name for
+/// humans.
+pub struct DriverEntry {
+    pub driver_num: usize,
+    pub name: &'static str,
+    pub driver: &'static dyn Driver,
+}
+
+/// The syscall drivers of a board.
+pub struct DriverRegistry {
+    drivers: &'static [DriverEntry],
+}
+
+impl DriverRegistry {
+    /// Create a registry of `drivers`.
+    ///
+    /// Panics if two drivers have the same driver number, or if a driver has
+    /// the driver number of the registry itself, as only one of them could
+    /// ever be called.
+    pub fn new(drivers: &'static [DriverEntry]) -> DriverRegistry {
+        if let Some(driver_num) = duplicate_driver_num(drivers) {
+            panic!("Driver number {:#x} is used more than once", driver_num);
+        }
+        DriverRegistry { drivers }
+    }
+
+    /// Find the entry for the driver with number `driver_num`. This does not
+    /// find the registry itself.
+    pub fn find(&self, driver_num: usize) -> Option<&DriverEntry> {
+        self.drivers
+            .iter()
+            .find(|entry| entry.driver_num == driver_num)
+    }
+
+    /// The drivers in the registry, in the order the board listed them.
+    pub fn drivers(&self) -> &'static [DriverEntry] {
+        self.drivers
+    }
+
+    /// Call `f` with the driver with number `driver_num`, or with `None` if
+    /// there is none. This implements `Platform::with_driver()` for boards
+    /// that use a registry.
+    pub fn with_driver<F, R>(&self, driver_num: usize, f: F) -> R
+    where
+        F: FnOnce(Option<&dyn Driver>) -> R,
+    {
+        match self.find(driver_num) {
+            Some(entry) => f(Some(entry.driver)),
+            None if driver_num == DRIVER_NUM => f(Some(self)),
+            None => f(None),
+        }
+    }
+}
+
+/// The first driver number in `drivers` that is listed more than once, or
+/// that is `DRIVER_NUM`.
+fn duplicate_driver_num(drivers: &[DriverEntry]) -> Option<usize> {
+    drivers.iter().enumerate().find_map(|(i, entry)| {
+        if entry.driver_num == DRIVER_NUM
+            || drivers[..i]
+                .iter()
+                .any(|other| other.driver_num == entry.driver_num)
+        {
+            Some(entry.driver_num)
+        } else {
+            None
+        }
+    })
+}
+
+impl Driver for DriverRegistry {
+    /// Check which drivers exist.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Check whether the driver with number `data` exists.
+    /// - `2`: Return the number of drivers.
+    /// - `3`: Return the driver number of driver `data`.
+    fn command(
+        &self,
+        command_num: usize,
+        data: usize,
+        _: usize,
+        _processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => {
+                if data == DRIVER_NUM || self.find(data).is_some() {
+                    CommandReturn::success()
+                } else {
+                    CommandReturn::failure(ErrorCode::NODEVICE)
+                }
+            }
+            2 => CommandReturn::success_u32(self.drivers.len() as u32),
+            3 => match self.drivers.get(data) {
+                Some(entry) => CommandReturn::success_u32(entry.driver_num as u32),
+                None => CommandReturn::failure(ErrorCode::INVAL),
+            },
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+}
+
+/// Create a `DriverRegistry` with the drivers listed as
+/// `driver_num => "name": driver`, and return a `&'static` reference to it.
+///
+/// # Safety
+///
+/// Like `static_init!()`, this must only run once for each place it is used.
+#[macro_export]
+macro_rules! driver_registry {
+    ($($driver_num:expr => $name:literal : $driver:expr),* $(,)?) => {{
+        let drivers = $crate::static_init!(
+            [$crate::driver_registry::DriverEntry; [$($name),*].len()],
+            [$(
+                $crate::driver_registry::DriverEntry {
+                    driver_num: $driver_num,
+                    name: $name,
+                    driver: $driver,
+                },
+            )*]
+        );
+        $crate::static_init!(
+            $crate::driver_registry::DriverRegistry,
+            $crate::driver_registry::DriverRegistry::new(drivers)
+        )
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::boxed::Box;
+
+    use crate::driver::Driver;
+    use crate::errorcode::ErrorCode;
+    use crate::process::ProcessId;
+    use crate::sched::Kernel;
+    use crate::syscall::SyscallReturn;
+
+    use super::{duplicate_driver_num, DriverEntry, DriverRegistry, DRIVER_NUM};
+
+    struct MockDriver;
+
+    impl Driver for MockDriver {}
+
+    static MOCK_DRIVER: MockDriver = MockDriver;
+
+    fn entry(driver_num: usize) -> DriverEntry {
+        DriverEntry {
+            driver_num,
+            name: "mock",
+            driver: &MOCK_DRIVER,
+        }
+    }
+
+    fn registry() -> &'static DriverRegistry {
+        let driver: &'static MockDriver = Box::leak(Box::new(MockDriver));
+        let drivers: &'static [DriverEntry] = Box::leak(Box::new([
+            DriverEntry {
+                driver_num: 0x1,
+                name: "console",
+                driver,
+            },
+            DriverEntry {
+                driver_num: 0x60000,
+                name: "temperature",
+                driver,
+            },
+        ]));
+        Box::leak(Box::new(DriverRegistry::new(drivers)))
+    }
+
+    fn command(command_num: usize, data: usize) -> SyscallReturn {
+        let kernel: &'static Kernel = Box::leak(Box::new(Kernel::new(&[])));
+        registry()
+            .command(command_num, data, 0, ProcessId::new(kernel, 0, 0))
+            .into_inner()
+    }
+
+    #[test]
+    fn test_with_driver() {
+        let registry = registry();
+        assert!(registry.with_driver(0x1, |driver| driver.is_some()));
+        assert!(registry.with_driver(0x60000, |driver| driver.is_some()));
+        assert!(registry.with_driver(DRIVER_NUM, |driver| driver.is_some()));
+        assert!(registry.with_driver(0x2, |driver| driver.is_none()));
+        assert_eq!(
+            registry.find(0x60000).map(|entry| entry.name),
+            Some("temperature")
+        );
+    }
+
+    #[test]
+    fn test_command() {
+        assert!(matches!(command(1, 0x1), SyscallReturn::Success));
+        assert!(matches!(command(1, DRIVER_NUM), SyscallReturn::Success));
+        assert!(matches!(
+            command(1, 0x2),
+            SyscallReturn::Failure(ErrorCode::NODEVICE)
+        ));
+        assert!(matches!(command(2, 0), SyscallReturn::SuccessU32(2)));
+        assert!(matches!(command(3, 1), SyscallReturn::SuccessU32(0x60000)));
+        assert!(matches!(
+            command(3, 2),
+            SyscallReturn::Failure(ErrorCode::INVAL)
+        ));
+    }
+
+    #[test]
+    fn test_duplicate_driver_num() {
+        assert_eq!(duplicate_driver_num(&[]), None);
+        assert_eq!(duplicate_driver_num(&[entry(0x1), entry(0x2)]), None);
+        assert_eq!(
+            duplicate_driver_num(&[entry(0x1), entry(0x2), entry(0x1)]),
+            Some(0x1)
+        );
+        // The registry answers its own driver number.
+        assert_eq!(
+            duplicate_driver_num(&[entry(0x1), entry(DRIVER_NUM)]),
+            Some(DRIVER_NUM)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Driver number 0x60000 is used more than once")]
+    fn test_new_rejects_duplicates() {
+        let drivers: &'static [DriverEntry] =
+            Box::leak(Box::new([entry(0x60000), entry(0x1), entry(0x60000)]));
+        DriverRegistry::new(drivers);
+    }
+}
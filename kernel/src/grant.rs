@@ -112,7 +112,7 @@ use core::mem::{align_of, size_of};
 use core::ops::{Deref, DerefMut};
 use core::ptr::{write, NonNull};
 
-use crate::process::{Error, Process, ProcessCustomGrantIdentifer, ProcessId};
+use crate::process::{Error, Process, ProcessCustomGrantIdentifer, ProcessId, ProcessSlot};
 use crate::sched::Kernel;
 
 /// This GrantMemory object provides access to the memory allocated for a grant
@@ -923,8 +923,8 @@ pub struct Iter<'a, T: 'a + Default> {
 
     /// Iterator over valid processes.
     subiter: core::iter::FilterMap<
-        core::slice::Iter<'a, Option<&'static dyn Process>>,
-        fn(&Option<&'static dyn Process>) -> Option<&'static dyn Process>,
+        core::slice::Iter<'a, ProcessSlot>,
+        fn(&ProcessSlot) -> Option<&'static dyn Process>,
     >,
 }
 
@@ -0,0 +1,111 @@
+//! Interface for a high level Key-Value (KV) store.
+//!
+//! This is level 3 of the KV store implementation described in
+//! `hil::kv_system`. Keys are unhashed byte strings and belong to an
+//! `Owner`. Every owner has its own namespace, so two owners can use the same
+//! key without seeing each other's values.
+//!
+//! Each value is stored with the owner that wrote it and its `Permissions`,
+//! which are checked whenever the value is accessed.
+
+use crate::ErrorCode;
+
+/// The identity that keys and their values belong to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Owner {
+    /// The kernel.
+    Kernel,
+    /// The application with this persistent identifier. Anonymous
+    /// applications have no persistent identity, so they can't own values.
+    App(u32),
+}
+
+/// What the owner of a value can do with it once it is stored.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Permissions {
+    /// The value can be read, replaced and deleted.
+    ReadWrite,
+    /// The value can only be read. It can't be replaced or deleted.
+    ReadOnly,
+}
+
+/// Implement this trait and use `set_client()` in order to receive callbacks.
+pub trait Client {
+    /// This callback is called when the get operation completes
+    ///
+    /// `result`: Nothing on success, 'ErrorCode' on error
+    /// `value`: The value of the key on success, empty on error
+    fn get_complete(&self, result: Result<(), ErrorCode>, value: &[u8]);
+
+    /// This callback is called when the set operation completes
+    ///
+    /// `result`: Nothing on success, 'ErrorCode' on error
+    fn set_complete(&self, result: Result<(), ErrorCode>);
+
+    /// This callback is called when the delete operation completes
+    ///
+    /// `result`: Nothing on success, 'ErrorCode' on error
+    fn delete_complete(&self, result: Result<(), ErrorCode>);
+}
+
+pub trait KVStore<'a> {
+    /// Set the client
+    fn set_client(&self, client: &'a dyn Client);
+
+    /// Retrieves the value of a key.
+    ///
+    /// `owner`: The owner of the key.
+    /// `key`: The unhashed key. It is copied before this returns.
+    ///
+    /// On success nothing will be returned.
+    /// On error a `ErrorCode` will be returned.
+    ///
+    /// The possible `ErrorCode`s, returned here or passed to the callback,
+    /// are:
+    ///    `BUSY`: An operation is already in progress
+    ///    `SIZE`: The key is too long
+    ///    `NOSUPPORT`: The key could not be found
+    ///    `NODEVICE`: The value belongs to a different owner
+    fn get(&self, owner: Owner, key: &[u8]) -> Result<(), ErrorCode>;
+
+    /// Stores the value of a key, replacing the value it already has.
+    ///
+    /// `owner`: The owner of the key.
+    /// `key`: The unhashed key. It is copied before this returns.
+    /// `value`: The value to store. It is copied before this returns.
+    /// `permissions`: What the owner can do with the value once it is stored.
+    ///
+    /// On success nothing will be returned.
+    /// On error a `ErrorCode` will be returned.
+    ///
+    /// The possible `ErrorCode`s, returned here or passed to the callback,
+    /// are:
+    ///    `BUSY`: An operation is already in progress
+    ///    `SIZE`: The key or the value is too long
+    ///    `NODEVICE`: The stored value belongs to a different owner, or is
+    ///                read only
+    ///    `NOMEM`: The value could not be stored due to no more space.
+    fn set(
+        &self,
+        owner: Owner,
+        key: &[u8],
+        value: &[u8],
+        permissions: Permissions,
+    ) -> Result<(), ErrorCode>;
+
+    /// Deletes a key and its value.
+    ///
+    /// `owner`: The owner of the key.
+    /// `key`: The unhashed key. It is copied before this returns.
+    ///
+    /// On success nothing will be returned.
+    /// On error a `ErrorCode` will be returned.
+    ///
+    /// The possible `ErrorCode`s, returned here or passed to the callback,
+    /// are:
+    ///    `BUSY`: An operation is already in progress
+    ///    `SIZE`: The key is too long
+    ///    `NOSUPPORT`: The key could not be found
+    ///    `NODEVICE`: The value belongs to a different owner, or is read only
+    fn delete(&self, owner: Owner, key: &[u8]) -> Result<(), ErrorCode>;
+}
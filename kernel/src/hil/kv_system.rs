@@ -30,7 +30,7 @@
 //! This level is also in charge of generating the key hash by calling into
 //! level 2.
 //!
-//! There is currently no implementation of this HIL in Tock.
+//! This level is described by `hil::kv_store`.
 //!
 //! The expected setup inside Tock will look like this:
 //! +-----------------------+
@@ -39,7 +39,7 @@
 //! |                       |
 //! +-----------------------+
 //!
-//!    hil::kv_store (not written yet)
+//!    hil::kv_store
 //!
 //! +-----------------------+
 //! |                       |
@@ -47,7 +47,7 @@
 //! |                       |
 //! +-----------------------+
 //!
-//!    hil::kv_system (this PR)
+//!    hil::kv_system
 //!
 //! +-----------------------+
 //! |                       |
@@ -65,18 +65,29 @@ pub trait KeyType: Eq + Copy + Clone + Sized + AsRef<[u8]> + AsMut<[u8]> {}
 
 impl KeyType for [u8; 8] {}
 
+/// A key found by `KVSystem::next_key()`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyInfo {
+    /// The length of the value stored with the key
+    pub value_length: usize,
+    /// The region of the storage the key is stored in
+    pub region: usize,
+    /// The position to pass to `next_key()` to find the following key
+    pub next: usize,
+}
+
 /// Implement this trait and use `set_client()` in order to receive callbacks.
 pub trait Client<K: KeyType> {
-    /// This callback is called when the append_key operation completes
+    /// This callback is called when the generate_key operation completes
     ///
     /// `result`: Nothing on success, 'ErrorCode' on error
     /// `unhashed_key`: The unhashed_key buffer
-    /// `key_buf`: The key_buf buffer
+    /// `key_buf`: The key_buf buffer, which holds the hashed key on success
     fn generate_key_complete(
         &self,
         result: Result<(), ErrorCode>,
-        unhashed_key: &'static [u8],
-        key_buf: &'static K,
+        unhashed_key: &'static mut [u8],
+        key_buf: &'static mut K,
     );
 
     /// This callback is called when the append_key operation completes
@@ -88,7 +99,19 @@ pub trait Client<K: KeyType> {
         &self,
         result: Result<(), ErrorCode>,
         key: &'static mut K,
-        value: &'static [u8],
+        value: &'static mut [u8],
+    );
+
+    /// This callback is called when the update_key operation completes
+    ///
+    /// `result`: Nothing on success, 'ErrorCode' on error
+    /// `key`: The key buffer
+    /// `value`: The value buffer
+    fn update_key_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: &'static mut K,
+        value: &'static mut [u8],
     );
 
     /// This callback is called when the get_value operation completes
@@ -113,6 +136,13 @@ pub trait Client<K: KeyType> {
     ///
     /// `result`: Nothing on success, 'ErrorCode' on error
     fn garbage_collect_complete(&self, result: Result<(), ErrorCode>);
+
+    /// This callback is called when the next_key operation completes
+    ///
+    /// `result`: The `KeyInfo` of the key on success, 'ErrorCode' on error.
+    ///           `NOSUPPORT` means there are no more keys.
+    /// `key`: The key buffer, which holds the found key on success
+    fn next_key_complete(&self, result: Result<KeyInfo, ErrorCode>, key: &'static mut K);
 }
 
 pub trait KVSystem<'a> {
@@ -147,6 +177,7 @@ pub trait KVSystem<'a> {
     /// `key`: A hashed key. This key will be used in future to retrieve
     ///        or remove the `value`.
     /// `value`: A buffer containing the data to be stored to flash.
+    /// `length`: The number of bytes of `value` to store.
     ///
     /// On success nothing will be returned.
     /// On error the key, value and a `Result<(), ErrorCode>` will be returned.
@@ -160,8 +191,48 @@ pub trait KVSystem<'a> {
     fn append_key(
         &self,
         key: &'static mut Self::K,
-        value: &'static [u8],
-    ) -> Result<(), (&'static mut Self::K, &'static [u8], Result<(), ErrorCode>)>;
+        value: &'static mut [u8],
+        length: usize,
+    ) -> Result<
+        (),
+        (
+            &'static mut Self::K,
+            &'static mut [u8],
+            Result<(), ErrorCode>,
+        ),
+    >;
+
+    /// Replaces the value of a key that is already stored.
+    ///
+    /// Unlike invalidating the key and appending it again, the key keeps
+    /// either its old or its new value if the update is interrupted.
+    ///
+    /// `key`: A hashed key.
+    /// `value`: A buffer containing the new data to be stored to flash.
+    /// `length`: The number of bytes of `value` to store.
+    ///
+    /// On success nothing will be returned.
+    /// On error the key, value and a `Result<(), ErrorCode>` will be returned.
+    ///
+    /// The possible `Result<(), ErrorCode>`s are:
+    ///    `BUSY`: An operation is already in progress
+    ///    `INVAL`: An invalid parameter was passed
+    ///    `NODEVICE`: No KV store was setup
+    ///    `ENOSUPPORT`: The key could not be found.
+    ///    `NOMEM`: The key could not be updated due to no more space.
+    fn update_key(
+        &self,
+        key: &'static mut Self::K,
+        value: &'static mut [u8],
+        length: usize,
+    ) -> Result<
+        (),
+        (
+            &'static mut Self::K,
+            &'static mut [u8],
+            Result<(), ErrorCode>,
+        ),
+    >;
 
     /// Retrieves the value from a specified key.
     ///
@@ -219,4 +290,25 @@ pub trait KVSystem<'a> {
     ///    `INVAL`: An invalid parameter was passed
     ///    `NODEVICE`: No KV store was setup
     fn garbage_collect(&self) -> Result<usize, Result<(), ErrorCode>>;
+
+    /// Finds the next key in the KV Store
+    ///
+    /// `start`: The position to look from. Use 0 to find the first key and
+    ///          the `next` field of the returned `KeyInfo` to find the
+    ///          following ones.
+    /// `key`: A buffer to store the hashed key that is found.
+    ///
+    /// On success nothing will be returned.
+    /// On error the key and a `Result<(), ErrorCode>` will be returned.
+    ///
+    /// The possible `Result<(), ErrorCode>`s are:
+    ///    `BUSY`: An operation is already in progress
+    ///    `INVAL`: An invalid parameter was passed
+    ///    `NODEVICE`: No KV store was setup
+    ///    `ENOSUPPORT`: There are no more keys.
+    fn next_key(
+        &self,
+        start: usize,
+        key: &'static mut Self::K,
+    ) -> Result<(), (&'static mut Self::K, Result<(), ErrorCode>)>;
 }
@@ -14,10 +14,37 @@ use core::cell::Cell;
 
 use crate::capabilities::ProcessManagementCapability;
 use crate::common::cells::NumericCellExt;
+use crate::hil::time::{Frequency, Ticks, Time};
 use crate::process;
-use crate::process::ProcessId;
+use crate::process::{AppId, ProcessId};
 use crate::sched::Kernel;
 
+/// Free running clock the kernel measures the CPU time of processes with when
+/// the scheduler gives them no timeslice, as the scheduler timer is not
+/// started then. Every `Time` is a `CpuClock`.
+pub trait CpuClock {
+    /// Return the current value of the clock.
+    fn timestamp(&self) -> u32;
+
+    /// Return how many microseconds passed since `timestamp()` returned
+    /// `timestamp`.
+    fn us_since(&self, timestamp: u32) -> u32;
+}
+
+impl<T: Time> CpuClock for T {
+    fn timestamp(&self) -> u32 {
+        self.now().into_u32()
+    }
+
+    fn us_since(&self, timestamp: u32) -> u32 {
+        let ticks = self
+            .now()
+            .wrapping_sub(T::Ticks::from(timestamp))
+            .into_u32();
+        (ticks as u64 * 1_000_000 / T::Frequency::frequency() as u64) as u32
+    }
+}
+
 /// This struct provides the inspection functions.
 pub struct KernelInfo {
     kernel: &'static Kernel,
@@ -81,6 +108,16 @@ impl KernelInfo {
             .process_map_or("unknown", app, |process| process.get_process_name())
     }
 
+    /// Get the persistent identifier of the application the process runs.
+    pub fn process_app_id(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> AppId {
+        self.kernel
+            .process_map_or(AppId::Anonymous, app, |process| process.get_app_id())
+    }
+
     /// Returns the number of syscalls the app has called.
     pub fn number_app_syscalls(
         &self,
@@ -123,6 +160,81 @@ impl KernelInfo {
             .process_map_or(0, app, |process| process.debug_timeslice_expiration_count())
     }
 
+    /// Returns the number of times this app has used up its CPU time budget
+    /// for a period under a deadline scheduler.
+    pub fn number_app_budget_overruns(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> usize {
+        self.kernel
+            .process_map_or(0, app, |process| process.debug_budget_overrun_count())
+    }
+
+    /// Returns how long the app has run, in microseconds.
+    pub fn app_cpu_time_us(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> u64 {
+        self.kernel
+            .process_map_or(0, app, |process| process.debug_cpu_time_us())
+    }
+
+    /// Returns the number of times the kernel has switched to this app.
+    pub fn number_app_context_switches(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> usize {
+        self.kernel
+            .process_map_or(0, app, |process| process.debug_context_switch_count())
+    }
+
+    /// Returns the number of times this app has been woken up to run an
+    /// upcall after it yielded.
+    pub fn number_app_wakeups(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> usize {
+        self.kernel
+            .process_map_or(0, app, |process| process.debug_wakeup_count())
+    }
+
+    /// Returns the number of times this app has missed the deadline of its
+    /// software watchdog.
+    pub fn number_app_watchdog_expirations(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> usize {
+        self.kernel
+            .process_map_or(0, app, |process| process.debug_watchdog_expiration_count())
+    }
+
+    /// Returns the most stack the app has used, in bytes, if the app told the
+    /// kernel where its stack starts.
+    pub fn app_stack_high_water_mark(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> Option<usize> {
+        self.kernel
+            .process_map_or(None, app, |process| process.debug_stack_high_water_mark())
+    }
+
+    /// Returns the most heap the app has used, in bytes, if the app told the
+    /// kernel where its heap starts.
+    pub fn app_heap_high_water_mark(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> Option<usize> {
+        self.kernel
+            .process_map_or(None, app, |process| process.debug_heap_high_water_mark())
+    }
+
     /// Returns a tuple of the (the number of grants in the grant region this
     /// app has allocated, total number of grants that exist in the system).
     pub fn number_app_grant_uses(
@@ -152,4 +264,87 @@ impl KernelInfo {
         });
         count.get()
     }
+
+    /// Returns the total number of times all processes have used up their CPU
+    /// time budgets under a deadline scheduler.
+    pub fn budget_overruns(&self, _capability: &dyn ProcessManagementCapability) -> usize {
+        let count: Cell<usize> = Cell::new(0);
+        self.kernel.process_each(|proc| {
+            count.add(proc.debug_budget_overrun_count());
+        });
+        count.get()
+    }
+
+    /// Returns how long all processes have run, in microseconds.
+    pub fn cpu_time_us(&self, _capability: &dyn ProcessManagementCapability) -> u64 {
+        let total: Cell<u64> = Cell::new(0);
+        self.kernel.process_each(|proc| {
+            total.set(total.get() + proc.debug_cpu_time_us());
+        });
+        total.get()
+    }
+
+    /// Returns the total number of times the kernel has switched to a
+    /// process.
+    pub fn context_switches(&self, _capability: &dyn ProcessManagementCapability) -> usize {
+        let count: Cell<usize> = Cell::new(0);
+        self.kernel.process_each(|proc| {
+            count.add(proc.debug_context_switch_count());
+        });
+        count.get()
+    }
+
+    /// Returns the total number of times processes have been woken up to run
+    /// an upcall after they yielded.
+    pub fn wakeups(&self, _capability: &dyn ProcessManagementCapability) -> usize {
+        let count: Cell<usize> = Cell::new(0);
+        self.kernel.process_each(|proc| {
+            count.add(proc.debug_wakeup_count());
+        });
+        count.get()
+    }
+
+    /// Returns the total number of times processes have missed the deadlines
+    /// of their software watchdogs.
+    pub fn watchdog_expirations(&self, _capability: &dyn ProcessManagementCapability) -> usize {
+        let count: Cell<usize> = Cell::new(0);
+        self.kernel.process_each(|proc| {
+            count.add(proc.debug_watchdog_expiration_count());
+        });
+        count.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+
+    use crate::hil::time::{Freq32KHz, Ticks24, Time};
+
+    use super::CpuClock;
+
+    /// A 24 bit 32 kHz counter, which only moves when the test sets it.
+    struct MockTime {
+        now: Cell<Ticks24>,
+    }
+
+    impl Time for MockTime {
+        type Frequency = Freq32KHz;
+        type Ticks = Ticks24;
+
+        fn now(&self) -> Ticks24 {
+            self.now.get()
+        }
+    }
+
+    #[test]
+    fn cpu_clock_measures_across_wrap() {
+        let time = MockTime {
+            now: Cell::new(Ticks24::from(0xff_fff0)),
+        };
+        let start = time.timestamp();
+        time.now.set(Ticks24::from(0x10));
+        // 0x20 ticks at 32768 Hz.
+        assert_eq!(time.us_since(start), 976);
+    }
 }
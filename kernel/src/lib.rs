@@ -93,10 +93,12 @@ pub mod capabilities;
 pub mod common;
 pub mod component;
 pub mod debug;
+pub mod driver_registry;
 pub mod hil;
 pub mod introspection;
 pub mod ipc;
 pub mod syscall;
+pub mod syscall_trace;
 
 mod config;
 mod driver;
@@ -106,7 +108,9 @@ mod mem;
 mod memop;
 mod platform;
 mod process;
+mod process_checker;
 mod process_policies;
+mod process_restart;
 mod process_standard;
 mod process_utilities;
 mod sched;
@@ -117,12 +121,15 @@ pub use crate::errorcode::into_statuscode;
 pub use crate::errorcode::ErrorCode;
 pub use crate::grant::{Grant, ProcessGrant};
 pub use crate::mem::{Read, ReadOnlyAppSlice, ReadWrite, ReadWriteAppSlice};
+pub use crate::platform::power;
 pub use crate::platform::scheduler_timer::{SchedulerTimer, VirtualSchedulerTimer};
 pub use crate::platform::watchdog;
 pub use crate::platform::{mpu, Chip, InterruptService, Platform};
 pub use crate::platform::{ClockInterface, NoClockControl, NO_CLOCK_CONTROL};
-pub use crate::process::ProcessId;
+pub use crate::platform::{SyscallFilter, TbfHeaderFilterDefaultAllow, TbfHeaderFilterDefaultDeny};
+pub use crate::process::{AppId, ProcessId};
 pub use crate::sched::cooperative::{CoopProcessNode, CooperativeSched};
+pub use crate::sched::edf::{EDFProcessNode, EDFSched};
 pub use crate::sched::mlfq::{MLFQProcessNode, MLFQSched};
 pub use crate::sched::priority::PrioritySched;
 pub use crate::sched::round_robin::{RoundRobinProcessNode, RoundRobinSched};
@@ -136,13 +143,25 @@ pub use crate::upcall::Upcall;
 /// Publicly available process-related objects.
 pub mod procs {
     pub use crate::process::{
-        Error, FaultAction, FunctionCall, FunctionCallSource, Process, State, Task,
+        AllowSlot, Error, FaultAction, FunctionCall, FunctionCallSource, Process, ProcessSlot,
+        State, Task, EMPTY_PROCESS_SLOT,
+    };
+    pub use crate::process_checker::{
+        AppCredentialsChecker, CheckResult, CredentialsStatus, NullCredentialsChecker,
     };
     pub use crate::process_policies::{
-        PanicFaultPolicy, ProcessFaultPolicy, RestartFaultPolicy, StopFaultPolicy,
-        StopWithDebugFaultPolicy, ThresholdRestartFaultPolicy,
-        ThresholdRestartThenPanicFaultPolicy,
+        AnonymousAppIdPolicy, AppIdPolicy, BackoffRestartFaultPolicy, EqualPriorityPolicy,
+        PanicFaultPolicy, PriorityLimit, PriorityPolicy, ProcessFaultPolicy, RestartFaultPolicy,
+        StopFaultPolicy, StopWithDebugFaultPolicy, TbfHeaderAppIdPolicy, TbfHeaderPriorityPolicy,
+        ThresholdRestartFaultPolicy, ThresholdRestartThenPanicFaultPolicy,
     };
+    pub use crate::process_restart::{DelayedRestart, DelayedRestartAlarm};
     pub use crate::process_standard::ProcessStandard;
-    pub use crate::process_utilities::{load_processes, ProcessLoadError};
+    pub use crate::process_utilities::{
+        load_and_check_processes, load_processes, DynamicProcessLoader, DynamicProcessLoading,
+        ProcessLoadError,
+    };
+    pub use tock_tbf::types::{
+        CommandPermissions, TbfFooterV2Credentials, TbfFooterV2CredentialsType,
+    };
 }
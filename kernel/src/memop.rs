@@ -37,6 +37,11 @@ use crate::ErrorCode;
 ///   where the app has put the start of its heap. This is not strictly
 ///   necessary for correct operation, but allows for better debugging if the
 ///   app crashes.
+/// - `12`: Get the most stack the app has used so far, in bytes. Returns
+///   `FAIL` if the app has not told the kernel where its stack starts.
+/// - `13`: Get the most heap the app has used so far, in bytes, which is the
+///   highest the app break has been minus the start of the heap. Returns
+///   `FAIL` if the app has not told the kernel where its heap starts.
 pub(crate) fn memop(process: &dyn Process, op_type: usize, r1: usize) -> SyscallReturn {
     match op_type {
         // Op Type 0: BRK
@@ -107,6 +112,20 @@ pub(crate) fn memop(process: &dyn Process, op_type: usize, r1: usize) -> Syscall
             SyscallReturn::Success
         }
 
+        // Op Type 12: Stack high-water mark.
+        12 => process
+            .debug_stack_high_water_mark()
+            .map_or(SyscallReturn::Failure(ErrorCode::FAIL), |bytes| {
+                SyscallReturn::SuccessU32(bytes as u32)
+            }),
+
+        // Op Type 13: Heap high-water mark.
+        13 => process
+            .debug_heap_high_water_mark()
+            .map_or(SyscallReturn::Failure(ErrorCode::FAIL), |bytes| {
+                SyscallReturn::SuccessU32(bytes as u32)
+            }),
+
         _ => SyscallReturn::Failure(ErrorCode::NOSUPPORT),
     }
 }
@@ -5,8 +5,10 @@ use crate::errorcode;
 use crate::process;
 use crate::syscall;
 use core::fmt::Write;
+use tock_tbf::types::CommandPermissions;
 
 pub mod mpu;
+pub mod power;
 pub(crate) mod scheduler_timer;
 pub mod watchdog;
 
@@ -40,6 +42,11 @@ pub mod watchdog;
 ///     }
 /// }
 /// ```
+///
+/// Boards can instead list their drivers in a
+/// `kernel::driver_registry::DriverRegistry`, which implements `with_driver()`
+/// and also lets processes and the process console find out which drivers
+/// exist.
 pub trait Platform {
     /// Platform-specific mapping of syscall numbers to objects that implement
     /// the Driver methods for that syscall.
@@ -101,6 +108,100 @@ pub trait Platform {
     }
 }
 
+/// Reusable system call filtering policy.
+///
+/// Boards can implement `Platform::filter_syscall` by calling a
+/// `SyscallFilter`, rather than writing their own filtering logic:
+///
+/// ```ignore
+/// fn filter_syscall(
+///     &self,
+///     process: &dyn kernel::procs::Process,
+///     syscall: &kernel::syscall::Syscall,
+/// ) -> Result<(), kernel::ErrorCode> {
+///     kernel::TbfHeaderFilterDefaultAllow {}.filter_syscall(process, syscall)
+/// }
+/// ```
+pub trait SyscallFilter {
+    /// Return `Ok(())` if `process` may make the system call `syscall`, or the
+    /// `ErrorCode` to return to the process otherwise.
+    fn filter_syscall(
+        &self,
+        process: &dyn process::Process,
+        syscall: &syscall::Syscall,
+    ) -> Result<(), errorcode::ErrorCode>;
+}
+
+/// Check a system call against the permissions in the process's TBF header.
+///
+/// Commands must be allowed in the bitmask for their driver, and subscribe and
+/// allow calls are allowed for every driver the header lists. Processes
+/// without a permissions section in their header are handled according to
+/// `default`.
+fn filter_tbf_header_permissions(
+    process: &dyn process::Process,
+    syscall: &syscall::Syscall,
+    default: Result<(), errorcode::ErrorCode>,
+) -> Result<(), errorcode::ErrorCode> {
+    let (driver_number, offset, mask_bit) = match *syscall {
+        syscall::Syscall::Command {
+            driver_number,
+            subdriver_number,
+            ..
+        } => (
+            driver_number,
+            subdriver_number / 64,
+            Some(subdriver_number % 64),
+        ),
+        syscall::Syscall::Subscribe { driver_number, .. }
+        | syscall::Syscall::ReadWriteAllow { driver_number, .. }
+        | syscall::Syscall::ReadOnlyAllow { driver_number, .. }
+        | syscall::Syscall::UserspaceReadableAllow { driver_number, .. } => {
+            (driver_number, 0, None)
+        }
+        // Other system calls do not use a driver.
+        _ => return Ok(()),
+    };
+
+    match process.get_command_permissions(driver_number, offset) {
+        CommandPermissions::NoPermsAtAll => default,
+        CommandPermissions::NoPermsThisDriver => Err(errorcode::ErrorCode::NODEVICE),
+        CommandPermissions::Mask(mask) => match mask_bit {
+            Some(bit) if mask & (1 << bit) == 0 => Err(errorcode::ErrorCode::NODEVICE),
+            _ => Ok(()),
+        },
+    }
+}
+
+/// Filter system calls using the permissions in each process's TBF header.
+/// Processes whose header does not include permissions may make every system
+/// call.
+pub struct TbfHeaderFilterDefaultAllow {}
+
+impl SyscallFilter for TbfHeaderFilterDefaultAllow {
+    fn filter_syscall(
+        &self,
+        process: &dyn process::Process,
+        syscall: &syscall::Syscall,
+    ) -> Result<(), errorcode::ErrorCode> {
+        filter_tbf_header_permissions(process, syscall, Ok(()))
+    }
+}
+
+/// Filter system calls using the permissions in each process's TBF header.
+/// Processes whose header does not include permissions may not use any driver.
+pub struct TbfHeaderFilterDefaultDeny {}
+
+impl SyscallFilter for TbfHeaderFilterDefaultDeny {
+    fn filter_syscall(
+        &self,
+        process: &dyn process::Process,
+        syscall: &syscall::Syscall,
+    ) -> Result<(), errorcode::ErrorCode> {
+        filter_tbf_header_permissions(process, syscall, Err(errorcode::ErrorCode::NODEVICE))
+    }
+}
+
 /// Interface for individual MCUs.
 ///
 /// The trait defines chip-specific properties of Tock's operation. These
@@ -158,6 +259,20 @@ pub trait Chip {
     /// chip and resumes the scheduler.
     fn sleep(&self);
 
+    /// The sleep states of the chip, from the lightest to the deepest, that
+    /// the kernel can choose from with a `power::SleepPolicy`. Chips that only
+    /// sleep one way return no states.
+    fn sleep_states(&self) -> &'static [power::SleepState] {
+        &[]
+    }
+
+    /// Like `sleep()`, but enter the sleep state with index `state` in
+    /// `sleep_states()`. The chip may still sleep less deeply, for example if
+    /// a peripheral it knows about is busy.
+    fn sleep_in(&self, _state: usize) {
+        self.sleep();
+    }
+
     /// Run a function in an atomic state, which means that interrupts are
     /// disabled so that an interrupt will not fire during the passed in
     /// function's execution.
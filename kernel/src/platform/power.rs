@@ -0,0 +1,197 @@
+//! Interface for choosing how deeply the chip sleeps.
+//!
+//! A chip that can sleep in more than one way lists its sleep states with
+//! `Chip::sleep_states()`, from the lightest to the deepest. Deeper states
+//! save more power, but take longer to wake up from and stop clocks that some
+//! peripherals need. Before the kernel puts the chip to sleep, it asks the
+//! board's `SleepPolicy` which state to enter.
+//!
+//! `PowerManager` is a `SleepPolicy` that chooses the deepest state that
+//! every registered `PowerClient` allows, and that is worth entering before
+//! the next alarm fires:
+//!
+//! ```rust,ignore
+//! let power_manager = static_init!(
+//!     kernel::power::PowerManager<2>,
+//!     kernel::power::PowerManager::new()
+//! );
+//! power_manager.set_wakeup_deadline(mux_alarm);
+//! power_manager.register(&base_peripherals.uarte0).unwrap();
+//! power_manager.register(&base_peripherals.ble_radio).unwrap();
+//! board_kernel.set_sleep_policy(power_manager, &main_loop_capability);
+//! ```
+
+use crate::common::cells::OptionalCell;
+use crate::errorcode::ErrorCode;
+
+/// A sleep state of a chip.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SleepState {
+    pub name: &'static str,
+    /// The shortest time, in microseconds, the chip must be able to sleep
+    /// for this state to be worth entering. This covers the time the chip
+    /// takes to wake up from the state.
+    pub min_sleep_us: u32,
+}
+
+/// A peripheral or capsule that needs the chip to stay out of deep sleep
+/// states while it is busy.
+pub trait PowerClient {
+    /// Return the deepest sleep state the chip may enter now, as an index
+    /// into `Chip::sleep_states()`, or `None` if it may enter any state.
+    fn deepest_sleep_state(&self) -> Option<usize>;
+}
+
+/// Tells the kernel when it next has to be awake, such as the alarm that
+/// multiplexes all virtual alarms.
+pub trait WakeupDeadline {
+    /// Return how many microseconds are left until the next wakeup, or
+    /// `None` if no wakeup is scheduled.
+    fn us_until_wakeup(&self) -> Option<u32>;
+}
+
+/// Chooses the sleep state the kernel puts the chip in.
+pub trait SleepPolicy {
+    /// Return the state to sleep in, as an index into `states`, or `None` to
+    /// let the chip decide with `Chip::sleep()`.
+    fn choose_sleep_state(&self, states: &[SleepState]) -> Option<usize>;
+}
+
+/// `SleepPolicy` for up to `NUM_CLIENTS` power clients and an optional
+/// wakeup deadline.
+pub struct PowerManager<const NUM_CLIENTS: usize> {
+    clients: [OptionalCell<&'static dyn PowerClient>; NUM_CLIENTS],
+    wakeup_deadline: OptionalCell<&'static dyn WakeupDeadline>,
+}
+
+impl<const NUM_CLIENTS: usize> PowerManager<NUM_CLIENTS> {
+    pub const fn new() -> PowerManager<NUM_CLIENTS> {
+        const NO_CLIENT: OptionalCell<&'static dyn PowerClient> = OptionalCell::empty();
+        PowerManager {
+            clients: [NO_CLIENT; NUM_CLIENTS],
+            wakeup_deadline: OptionalCell::empty(),
+        }
+    }
+
+    /// Let `client` limit how deeply the chip sleeps. Returns `NOMEM` if
+    /// `NUM_CLIENTS` clients are already registered.
+    pub fn register(&self, client: &'static dyn PowerClient) -> Result<(), ErrorCode> {
+        let slot = self
+            .clients
+            .iter()
+            .find(|slot| slot.is_none())
+            .ok_or(ErrorCode::NOMEM)?;
+        slot.set(client);
+        Ok(())
+    }
+
+    /// Only choose sleep states that are worth entering before the wakeup
+    /// that `wakeup_deadline` reports.
+    pub fn set_wakeup_deadline(&self, wakeup_deadline: &'static dyn WakeupDeadline) {
+        self.wakeup_deadline.set(wakeup_deadline);
+    }
+}
+
+impl<const NUM_CLIENTS: usize> SleepPolicy for PowerManager<NUM_CLIENTS> {
+    fn choose_sleep_state(&self, states: &[SleepState]) -> Option<usize> {
+        let deepest = self
+            .clients
+            .iter()
+            .filter_map(|slot| slot.map_or(None, |client| client.deepest_sleep_state()))
+            .fold(states.len().checked_sub(1)?, |deepest, limit| {
+                deepest.min(limit)
+            });
+        let us_until_wakeup = self
+            .wakeup_deadline
+            .map_or(None, |wakeup_deadline| wakeup_deadline.us_until_wakeup());
+
+        // The lightest state is always allowed, even if the chip will not
+        // sleep long.
+        Some(
+            (1..=deepest)
+                .rev()
+                .find(|&state| us_until_wakeup.map_or(true, |us| us >= states[state].min_sleep_us))
+                .unwrap_or(0),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::Cell;
+    use std::boxed::Box;
+
+    use crate::errorcode::ErrorCode;
+
+    use super::{PowerClient, PowerManager, SleepPolicy, SleepState, WakeupDeadline};
+
+    const STATES: [SleepState; 3] = [
+        SleepState {
+            name: "sleep",
+            min_sleep_us: 0,
+        },
+        SleepState {
+            name: "deep sleep",
+            min_sleep_us: 100,
+        },
+        SleepState {
+            name: "off",
+            min_sleep_us: 1000,
+        },
+    ];
+
+    struct MockClient(Cell<Option<usize>>);
+
+    impl PowerClient for MockClient {
+        fn deepest_sleep_state(&self) -> Option<usize> {
+            self.0.get()
+        }
+    }
+
+    struct MockDeadline(Cell<Option<u32>>);
+
+    impl WakeupDeadline for MockDeadline {
+        fn us_until_wakeup(&self) -> Option<u32> {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn test_clients_limit_sleep_state() {
+        let uart: &'static MockClient = Box::leak(Box::new(MockClient(Cell::new(None))));
+        let radio: &'static MockClient = Box::leak(Box::new(MockClient(Cell::new(None))));
+        let power_manager = PowerManager::<2>::new();
+        assert_eq!(power_manager.choose_sleep_state(&[]), None);
+        assert_eq!(power_manager.choose_sleep_state(&STATES), Some(2));
+
+        power_manager.register(uart).unwrap();
+        power_manager.register(radio).unwrap();
+        assert_eq!(power_manager.register(radio), Err(ErrorCode::NOMEM));
+
+        radio.0.set(Some(1));
+        assert_eq!(power_manager.choose_sleep_state(&STATES), Some(1));
+        uart.0.set(Some(0));
+        assert_eq!(power_manager.choose_sleep_state(&STATES), Some(0));
+        uart.0.set(None);
+        radio.0.set(None);
+        assert_eq!(power_manager.choose_sleep_state(&STATES), Some(2));
+    }
+
+    #[test]
+    fn test_wakeup_deadline_limits_sleep_state() {
+        let deadline: &'static MockDeadline =
+            Box::leak(Box::new(MockDeadline(Cell::new(Some(500)))));
+        let power_manager = PowerManager::<1>::new();
+        power_manager.set_wakeup_deadline(deadline);
+        assert_eq!(power_manager.choose_sleep_state(&STATES), Some(1));
+
+        deadline.0.set(Some(50));
+        assert_eq!(power_manager.choose_sleep_state(&STATES), Some(0));
+        deadline.0.set(Some(1000));
+        assert_eq!(power_manager.choose_sleep_state(&STATES), Some(2));
+        deadline.0.set(None);
+        assert_eq!(power_manager.choose_sleep_state(&STATES), Some(2));
+    }
+}
@@ -11,9 +11,11 @@ use crate::errorcode::ErrorCode;
 use crate::ipc;
 use crate::mem::{ReadOnlyAppSlice, ReadWriteAppSlice};
 use crate::platform::mpu::{self};
+use crate::process_checker::CredentialsStatus;
 use crate::sched::Kernel;
 use crate::syscall::{self, Syscall, SyscallReturn};
 use crate::upcall::UpcallId;
+use tock_tbf::types::CommandPermissions;
 
 /// Userspace process identifier.
 ///
@@ -147,6 +149,18 @@ impl ProcessId {
         self.identifier
     }
 
+    /// Get the persistent identifier of the application this process is
+    /// running.
+    ///
+    /// Unlike `id()`, this does not change when the process restarts or the
+    /// board reboots, so capsules can use it to scope persistent resources
+    /// (such as stored data) to an application. Returns `AppId::Anonymous` if
+    /// the process no longer exists.
+    pub fn get_app_id(&self) -> AppId {
+        self.kernel
+            .process_map_or(AppId::Anonymous, *self, |process| process.get_app_id())
+    }
+
     /// Returns the full address of the start and end of the flash region that
     /// the app owns and can write to. This includes the app's code and data and
     /// any padding at the end of the app. It does not include the TBF header,
@@ -160,6 +174,74 @@ impl ProcessId {
     }
 }
 
+/// Persistent identifier of the application a process runs.
+///
+/// A `ProcessId` identifies one execution of a process and changes whenever
+/// the process restarts or the board reboots. An `AppId` instead identifies
+/// the application itself: it is derived from the process's TBF header by the
+/// board's `AppIdPolicy` when the process is loaded, and is the same every time
+/// that application is loaded.
+///
+/// The kernel guarantees that no two loaded processes share the same
+/// `AppId::Persistent` value, so it can be used to decide which application
+/// owns a resource.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AppId {
+    /// The application has a persistent identity.
+    Persistent(u32),
+
+    /// The application has no persistent identity. Anonymous processes are
+    /// never considered to be the same application as any other process.
+    Anonymous,
+}
+
+impl AppId {
+    /// Get the numeric identifier, or `None` if the application is anonymous.
+    pub fn id(&self) -> Option<u32> {
+        match self {
+            AppId::Persistent(id) => Some(*id),
+            AppId::Anonymous => None,
+        }
+    }
+}
+
+/// Where a process shares a buffer with a capsule.
+///
+/// Each allow system call class has its own buffer identifiers for each
+/// driver, so a slot is identified by all three.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct AllowSlot {
+    /// The allow system call class the buffer was shared with.
+    pub class: syscall::SyscallClass,
+    /// The driver the buffer was shared with.
+    pub driver_number: usize,
+    /// The buffer identifier within the driver.
+    pub subdriver_number: usize,
+}
+
+impl AllowSlot {
+    /// Whether buffers in this slot may only be read by the kernel.
+    pub fn is_readonly(&self) -> bool {
+        self.class == syscall::SyscallClass::ReadOnlyAllow
+    }
+}
+
+/// A slot of the processes array a board passes to `Kernel::new()`.
+///
+/// Processes can be added and removed while the kernel runs, while the kernel
+/// and the scheduler hold references to the array, so every slot is a `Cell`.
+/// Slots are only changed through `Kernel::add_process()` and
+/// `Kernel::remove_process()`.
+pub type ProcessSlot = Cell<Option<&'static dyn Process>>;
+
+/// An empty `ProcessSlot`, for boards to initialize their processes array
+/// with:
+///
+/// ```rust,ignore
+/// static mut PROCESSES: [ProcessSlot; NUM_PROCS] = [EMPTY_PROCESS_SLOT; NUM_PROCS];
+/// ```
+pub const EMPTY_PROCESS_SLOT: ProcessSlot = Cell::new(None);
+
 /// This trait represents a generic process that the Tock scheduler can
 /// schedule.
 pub trait Process {
@@ -228,6 +310,41 @@ pub trait Process {
     /// Get the name of the process. Used for IPC.
     fn get_process_name(&self) -> &'static str;
 
+    /// Whether the credentials of this process were verified by the board's
+    /// `AppCredentialsChecker` when the process was loaded.
+    fn get_credentials_status(&self) -> CredentialsStatus;
+
+    /// Get the persistent identifier the board's `AppIdPolicy` assigned to
+    /// this process when it was loaded.
+    fn get_app_id(&self) -> AppId;
+
+    /// Get the commands of driver `driver_num` that this process's TBF header
+    /// permits it to call, out of the commands `offset * 64` to
+    /// `offset * 64 + 63`.
+    fn get_command_permissions(&self, driver_num: usize, offset: usize) -> CommandPermissions;
+
+    /// Get the period and the CPU time budget per period, both in
+    /// microseconds, that this process's TBF header declares for deadline
+    /// schedulers. Returns `None` if the process has no timing requirements.
+    fn get_deadline(&self) -> Option<(u32, u32)>;
+
+    /// Get the scheduling priority this process's TBF header requests, where
+    /// lower numbers are higher priorities. Returns `None` if the process
+    /// requests no priority. The board's `PriorityPolicy` decides the
+    /// priority the process actually gets.
+    fn get_priority(&self) -> Option<u32>;
+
+    /// Get the priority the board's `PriorityPolicy` assigned to this process
+    /// since it was last started. Returns `None` if no scheduler has assigned
+    /// a priority yet, which is the case for newly created and restarted
+    /// processes.
+    fn get_assigned_priority(&self) -> Option<u32>;
+
+    /// Store the priority the board's `PriorityPolicy` assigned to this
+    /// process, so that schedulers only ask the policy once every time the
+    /// process starts. The priority is cleared when the process restarts.
+    fn set_assigned_priority(&self, priority: u32);
+
     /// Stop and clear a process's state, putting it into the `Terminated`
     /// state.
     ///
@@ -331,10 +448,18 @@ pub trait Process {
     ///   memory space / `buf_start_addr` and `size` are not a valid
     ///   read-write buffer (any byte in the range is not read/write
     ///   accessible to the process), [`ErrorCode::INVAL`]
+    /// - if the buffer overlaps a buffer the process currently shares,
+    ///   including through `slot` itself, [`ErrorCode::INVAL`]
+    /// - if the process already shares as many buffers as the kernel can
+    ///   track, [`ErrorCode::NOMEM`]
     /// - if the process is not active: [`ErrorCode::FAIL`]
     /// - for all other errors: [`ErrorCode::FAIL`]
+    ///
+    /// The buffer is only considered shared once the caller reports the
+    /// result of the allow with [`Process::allow_complete`].
     fn build_readwrite_appslice(
         &self,
+        slot: AllowSlot,
         buf_start_addr: *mut u8,
         size: usize,
     ) -> Result<ReadWriteAppSlice, ErrorCode>;
@@ -353,14 +478,42 @@ pub trait Process {
     ///   memory space / `buf_start_addr` and `size` are not a valid
     ///   read-only buffer (any byte in the range is not
     ///   read-accessible to the process), [`ErrorCode::INVAL`]
+    /// - if the buffer overlaps a buffer the process currently shares
+    ///   read-write through any slot, [`ErrorCode::INVAL`]
+    /// - if the process already shares as many buffers as the kernel can
+    ///   track, [`ErrorCode::NOMEM`]
     /// - if the process is not active: [`ErrorCode::FAIL`]
     /// - for all other errors: [`ErrorCode::FAIL`]
+    ///
+    /// The buffer is only considered shared once the caller reports the
+    /// result of the allow with [`Process::allow_complete`].
     fn build_readonly_appslice(
         &self,
+        slot: AllowSlot,
         buf_start_addr: *const u8,
         size: usize,
     ) -> Result<ReadOnlyAppSlice, ErrorCode>;
 
+    /// Record the result of passing an app slice created with
+    /// `build_readwrite_appslice` or `build_readonly_appslice` for `slot` to
+    /// a capsule.
+    ///
+    /// `allowed` is the pointer and length of the buffer the process passed,
+    /// and `returned` is the pointer and length of the app slice the capsule
+    /// handed back. `accepted` is whether the capsule accepted the allow.
+    ///
+    /// The process uses this to track which buffers capsules hold, so that
+    /// no two app slices can give the kernel aliasing mutable access to the
+    /// same memory. This must be called for every app slice that was built
+    /// and passed to a capsule.
+    fn allow_complete(
+        &self,
+        slot: AllowSlot,
+        allowed: (*const u8, usize),
+        returned: (*const u8, usize),
+        accepted: bool,
+    );
+
     /// Set a single byte within the process address space at
     /// `addr` to `value`. Return true if `addr` is within the RAM
     /// bounds currently exposed to the process (thereby writable
@@ -516,6 +669,13 @@ pub trait Process {
     /// context, and the state of the memory protection unit (MPU).
     fn print_full_process(&self, writer: &mut dyn Write);
 
+    /// Store the architecture specific registers the kernel saved when the
+    /// process last stopped running in `out` (see
+    /// `UserspaceKernelBoundary::store_context()`). Returns the number of
+    /// bytes stored, `SIZE` if `out` is too small, or `FAIL` if the process
+    /// has no saved registers.
+    fn get_stored_state(&self, out: &mut [u8]) -> Result<usize, ErrorCode>;
+
     // debug
 
     /// Returns how many syscalls this app has called.
@@ -530,10 +690,56 @@ pub trait Process {
     /// Increment the number of times the process has exceeded its timeslice.
     fn debug_timeslice_expired(&self);
 
+    /// Returns how many times this process has used up its CPU time budget
+    /// for a period before finishing its work.
+    fn debug_budget_overrun_count(&self) -> usize;
+
+    /// Increment the number of times the process has used up its CPU time
+    /// budget.
+    fn debug_budget_overrun(&self);
+
+    /// Returns how long this process has run, in microseconds, as measured
+    /// with the scheduler timer, or with the board's `CpuClock` when the
+    /// process runs without a timeslice.
+    fn debug_cpu_time_us(&self) -> u64;
+
+    /// Add `us` microseconds to the time this process has run.
+    fn debug_add_cpu_time(&self, us: u32);
+
+    /// Returns how many times the kernel has switched to this process.
+    fn debug_context_switch_count(&self) -> usize;
+
+    /// Increment the number of times the kernel has switched to this process.
+    fn debug_context_switched(&self);
+
+    /// Returns how many times this process has been woken up to run an upcall
+    /// after it yielded.
+    fn debug_wakeup_count(&self) -> usize;
+
+    /// Increment the number of times this process has been woken up.
+    fn debug_woken(&self);
+
+    /// Returns how many times this process has missed the deadline of its
+    /// software watchdog.
+    fn debug_watchdog_expiration_count(&self) -> usize;
+
+    /// Increment the number of times this process has missed the deadline of
+    /// its software watchdog.
+    fn debug_watchdog_expired(&self);
+
     /// Increment the number of times the process called a syscall and record
     /// the last syscall that was called.
     fn debug_syscall_called(&self, last_syscall: Syscall);
 
+    /// Returns the last syscall the process called, if any.
+    fn debug_last_syscall(&self) -> Option<Syscall>;
+
+    /// Copy the top of the process stack, starting at the stack pointer from
+    /// when the process last stopped running, into `out`. Returns the number
+    /// of bytes copied, which is less than the length of `out` if the stack
+    /// is smaller, and zero if the stack pointer is not known.
+    fn debug_copy_stack(&self, out: &mut [u8]) -> usize;
+
     /// Return the address of the start of the process heap, if known.
     fn debug_heap_start(&self) -> Option<*const u8>;
 
@@ -542,6 +748,17 @@ pub trait Process {
 
     /// Return the lowest recorded address of the process stack, if known.
     fn debug_stack_end(&self) -> Option<*const u8>;
+
+    /// Return the most stack the process has used since it started, in bytes,
+    /// if the start of its stack is known. If the kernel paints process memory
+    /// this is exact, otherwise it is based on the stack pointers seen when the
+    /// process stopped running and can be too low.
+    fn debug_stack_high_water_mark(&self) -> Option<usize>;
+
+    /// Return the most heap the process has used since it started, in bytes,
+    /// which is the highest app break minus the start of the heap, if the start
+    /// of the heap is known.
+    fn debug_heap_high_water_mark(&self) -> Option<usize>;
 }
 
 /// Opaque identifier for custom grants allocated dynamically from a process's
@@ -634,6 +851,10 @@ pub enum State {
     /// The process faulted and cannot be run.
     Faulted,
 
+    /// The process faulted and its fault policy delayed its restart. The
+    /// process cannot be run until the kernel restarts it.
+    WaitingToRestart,
+
     /// The process exited with the `exit-terminate` system call and
     /// cannot be run.
     Terminated,
@@ -699,6 +920,12 @@ pub enum FaultAction {
 
     /// Stop the process by no longer scheduling it to run.
     Stop,
+
+    /// Clean up the process like `Restart`, but only restart it after this
+    /// many milliseconds. Until then the process is in the `WaitingToRestart`
+    /// state. The board must give the kernel a `DelayedRestart` timer to wait
+    /// with; without one the process is restarted right away.
+    RestartAfter(u32),
 }
 
 /// Tasks that can be enqueued for a process.
@@ -0,0 +1,279 @@
+//! Policies for checking the credentials of processes before they run.
+//!
+//! A TBF can carry one or more credentials footers after the application
+//! binary, for example a SHA-256 hash of the binary or an RSA signature over
+//! it. When processes are loaded the kernel passes each credential to the
+//! board's `AppCredentialsChecker`, which decides whether the process is
+//! allowed to run.
+
+use crate::config;
+use crate::debug;
+use tock_tbf::types::{TbfFooterV2Credentials, TbfFooterV2CredentialsType, TbfHeader};
+
+/// The decision an `AppCredentialsChecker` makes about a single credential.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CheckResult {
+    /// The credential is valid and the process may run.
+    Accept,
+
+    /// The checker has no opinion on this credential, for example because it
+    /// does not support the credential's format. The next credential (if any)
+    /// is checked.
+    Pass,
+
+    /// The credential is invalid or explicitly disallowed. The process must
+    /// not run, regardless of any other credentials it carries.
+    Reject,
+}
+
+/// The outcome of checking all of the credentials of a process.
+///
+/// This is recorded for every loaded process so that the rest of the kernel
+/// (and board policies) can tell whether the process was verified.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CredentialsStatus {
+    /// A credential was accepted by the checker. The accepted credential is
+    /// kept so that it can be inspected later.
+    Verified(TbfFooterV2Credentials),
+
+    /// No credential was accepted, but the checker does not require processes
+    /// to have credentials so the process was allowed to run anyway.
+    Unverified,
+}
+
+/// Board-supplied policy which decides whether a process may run based on the
+/// credentials stored in its TBF footers.
+///
+/// The checker is consulted once per enabled process while processes are
+/// loaded. Credentials are offered in the order in which they are stored in
+/// flash. The first `Accept` or `Reject` decides the outcome. If every
+/// credential is passed (or the process has none), `require_credentials()`
+/// decides whether the process still runs as `Unverified`.
+pub trait AppCredentialsChecker {
+    /// Whether a process without any accepted credential must be refused.
+    fn require_credentials(&self) -> bool;
+
+    /// Check a single credential.
+    ///
+    /// `binary` is the region of flash the credential covers: the TBF header
+    /// followed by the application binary, up to the start of the footers.
+    fn check_credentials(
+        &self,
+        credentials: &TbfFooterV2Credentials,
+        binary: &'static [u8],
+    ) -> CheckResult;
+}
+
+/// Checker that does not verify anything and lets every process run as
+/// `Unverified`. This matches the behavior of boards which do not check
+/// credentials at all.
+pub struct NullCredentialsChecker {}
+
+impl AppCredentialsChecker for NullCredentialsChecker {
+    fn require_credentials(&self) -> bool {
+        false
+    }
+
+    fn check_credentials(&self, _: &TbfFooterV2Credentials, _: &'static [u8]) -> CheckResult {
+        CheckResult::Pass
+    }
+}
+
+/// Run the credentials checker over every footer of the process stored in
+/// `app_flash`.
+///
+/// Returns the status the process should be created with, or `None` if the
+/// process must not be run.
+pub(crate) fn check_process_credentials(
+    checker: &dyn AppCredentialsChecker,
+    app_flash: &'static [u8],
+    header: &TbfHeader,
+) -> Option<CredentialsStatus> {
+    let binary_end = header.get_binary_end() as usize;
+    let binary = app_flash.get(0..binary_end)?;
+    let mut footers = app_flash.get(binary_end..).unwrap_or(&[]);
+
+    while footers.len() > 0 {
+        let (credentials, footer_len) = match tock_tbf::parse::parse_tbf_footer(footers) {
+            Ok(footer) => footer,
+            Err(err) => {
+                // A malformed footer ends the footer region. Any credentials
+                // after it cannot be trusted to be found correctly.
+                if config::CONFIG.debug_load_processes {
+                    debug!(
+                        "Stopped parsing footers of process at {:#010X}: {:?}",
+                        app_flash.as_ptr() as usize,
+                        err
+                    );
+                }
+                break;
+            }
+        };
+
+        // Reserved space carries no credential.
+        if credentials.format() != TbfFooterV2CredentialsType::Reserved {
+            match checker.check_credentials(&credentials, binary) {
+                CheckResult::Accept => return Some(CredentialsStatus::Verified(credentials)),
+                CheckResult::Reject => return None,
+                CheckResult::Pass => {}
+            }
+        }
+
+        footers = footers.get(footer_len as usize..).unwrap_or(&[]);
+    }
+
+    if checker.require_credentials() {
+        None
+    } else {
+        Some(CredentialsStatus::Unverified)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::Cell;
+    use std::boxed::Box;
+    use std::vec;
+
+    use tock_tbf::builder::{credentials_footer_size, write_credentials_footer, TbfHeaderBuilder};
+    use tock_tbf::parse::parse_tbf_header;
+    use tock_tbf::types::{TbfFooterV2Credentials, TbfFooterV2CredentialsType, TbfHeader};
+
+    use super::{check_process_credentials, AppCredentialsChecker, CheckResult, CredentialsStatus};
+
+    const SHA256: u32 = TbfFooterV2CredentialsType::SHA256 as u32;
+    const SHA384: u32 = TbfFooterV2CredentialsType::SHA384 as u32;
+    const SHA512: u32 = TbfFooterV2CredentialsType::SHA512 as u32;
+    const RESERVED: u32 = TbfFooterV2CredentialsType::Reserved as u32;
+
+    /// Length of the header and binary of the test TBFs.
+    const BINARY_END: usize = 0x100;
+
+    /// Decides on credentials by their format, and counts how many it saw.
+    struct FormatChecker {
+        require: bool,
+        results: &'static [(TbfFooterV2CredentialsType, CheckResult)],
+        checked: Cell<usize>,
+    }
+
+    impl FormatChecker {
+        fn new(
+            require: bool,
+            results: &'static [(TbfFooterV2CredentialsType, CheckResult)],
+        ) -> FormatChecker {
+            FormatChecker {
+                require,
+                results,
+                checked: Cell::new(0),
+            }
+        }
+    }
+
+    impl AppCredentialsChecker for FormatChecker {
+        fn require_credentials(&self) -> bool {
+            self.require
+        }
+
+        fn check_credentials(
+            &self,
+            credentials: &TbfFooterV2Credentials,
+            binary: &'static [u8],
+        ) -> CheckResult {
+            assert_eq!(binary.len(), BINARY_END);
+            self.checked.set(self.checked.get() + 1);
+            self.results
+                .iter()
+                .find(|(format, _)| *format == credentials.format())
+                .map_or(CheckResult::Pass, |(_, result)| *result)
+        }
+    }
+
+    /// A TBF whose binary is followed by `footers`, given as TLV type,
+    /// credentials format and data. The TLV type replaces the type of the
+    /// credentials footer, so that tests can put other TLVs among footers.
+    fn tbf(footers: &[(u16, u32, &[u8])]) -> (&'static [u8], TbfHeader) {
+        let mut flash = vec![0xa5; BINARY_END];
+        for (tipe, format, data) in footers {
+            let offset = flash.len();
+            flash.resize(offset + credentials_footer_size(data.len()), 0);
+            write_credentials_footer(*format, data, &mut flash[offset..]).unwrap();
+            flash[offset..offset + 2].copy_from_slice(&tipe.to_le_bytes());
+        }
+        let builder = TbfHeaderBuilder::new()
+            .enabled(true)
+            .program(0, 0, 0x800, BINARY_END as u32, 0)
+            .total_size(flash.len() as u32);
+        let header_size = builder.write(&mut flash).unwrap();
+        let flash: &'static [u8] = Box::leak(flash.into_boxed_slice());
+        let header = parse_tbf_header(&flash[..header_size], 2).unwrap_or_else(|_| panic!());
+        (flash, header)
+    }
+
+    #[test]
+    fn first_decisive_credential_wins() {
+        static RESULTS: [(TbfFooterV2CredentialsType, CheckResult); 2] = [
+            (TbfFooterV2CredentialsType::SHA512, CheckResult::Accept),
+            (TbfFooterV2CredentialsType::SHA384, CheckResult::Reject),
+        ];
+        let (flash, header) = tbf(&[
+            (128, SHA256, &[1; 32]),
+            (128, RESERVED, &[0; 8]),
+            (128, SHA512, &[2; 64]),
+            (128, SHA384, &[3; 48]),
+        ]);
+        let checker = FormatChecker::new(true, &RESULTS);
+        match check_process_credentials(&checker, flash, &header) {
+            Some(CredentialsStatus::Verified(credentials)) => {
+                assert_eq!(credentials.format(), TbfFooterV2CredentialsType::SHA512);
+                assert_eq!(credentials.data(), &[2; 64]);
+            }
+            status => panic!("{:?}", status),
+        }
+        // The reserved footer is not offered to the checker.
+        assert_eq!(checker.checked.get(), 2);
+
+        let (flash, header) = tbf(&[(128, SHA384, &[3; 48]), (128, SHA512, &[2; 64])]);
+        let checker = FormatChecker::new(false, &RESULTS);
+        assert_eq!(check_process_credentials(&checker, flash, &header), None);
+        assert_eq!(checker.checked.get(), 1);
+    }
+
+    #[test]
+    fn passed_credentials_are_unverified_unless_required() {
+        let (flash, header) = tbf(&[(128, SHA256, &[1; 32])]);
+        let checker = FormatChecker::new(false, &[]);
+        assert_eq!(
+            check_process_credentials(&checker, flash, &header),
+            Some(CredentialsStatus::Unverified)
+        );
+        let checker = FormatChecker::new(true, &[]);
+        assert_eq!(check_process_credentials(&checker, flash, &header), None);
+
+        let (flash, header) = tbf(&[]);
+        assert_eq!(check_process_credentials(&checker, flash, &header), None);
+        assert_eq!(checker.checked.get(), 1);
+    }
+
+    #[test]
+    fn malformed_footer_ends_footers() {
+        static RESULTS: [(TbfFooterV2CredentialsType, CheckResult); 1] =
+            [(TbfFooterV2CredentialsType::SHA512, CheckResult::Accept)];
+        // A header TLV where a footer should be, and a hash that is too short.
+        for footers in [
+            [(1, SHA512, &[0; 8][..]), (128, SHA512, &[2; 64][..])],
+            [(128, SHA256, &[1; 16][..]), (128, SHA512, &[2; 64][..])],
+        ]
+        .iter()
+        {
+            let (flash, header) = tbf(footers);
+            let checker = FormatChecker::new(false, &RESULTS);
+            assert_eq!(
+                check_process_credentials(&checker, flash, &header),
+                Some(CredentialsStatus::Unverified)
+            );
+            assert_eq!(checker.checked.get(), 0);
+        }
+    }
+}
@@ -5,7 +5,9 @@
 //! decisions such as whether a specific process should be restarted.
 
 use crate::process;
-use crate::process::Process;
+use crate::process::{AppId, Process};
+use crate::process_checker::CredentialsStatus;
+use tock_tbf::types::TbfHeader;
 
 /// Generic trait for implementing a policy on what to do when a process faults.
 ///
@@ -105,3 +107,261 @@ impl ProcessFaultPolicy for ThresholdRestartThenPanicFaultPolicy {
         }
     }
 }
+
+/// Implementation of `ProcessFaultPolicy` that restarts a faulted process
+/// after a delay that doubles with every restart, so a process that keeps
+/// faulting does not keep the rest of the system from running. The first
+/// restart waits `initial_delay_ms`, and no restart waits longer than
+/// `max_delay_ms`.
+///
+/// The board must give the kernel a `DelayedRestart` timer, otherwise
+/// processes are restarted right away.
+pub struct BackoffRestartFaultPolicy {
+    initial_delay_ms: u32,
+    max_delay_ms: u32,
+}
+
+impl BackoffRestartFaultPolicy {
+    pub const fn new(initial_delay_ms: u32, max_delay_ms: u32) -> BackoffRestartFaultPolicy {
+        BackoffRestartFaultPolicy {
+            initial_delay_ms,
+            max_delay_ms,
+        }
+    }
+
+    /// The delay before restarting a process that was restarted
+    /// `restart_count` times.
+    fn delay_ms(&self, restart_count: usize) -> u32 {
+        let delay = (self.initial_delay_ms as u64) << restart_count.min(32);
+        delay.min(self.max_delay_ms as u64) as u32
+    }
+}
+
+impl ProcessFaultPolicy for BackoffRestartFaultPolicy {
+    fn action(&self, process: &dyn Process) -> process::FaultAction {
+        process::FaultAction::RestartAfter(self.delay_ms(process.get_restart_count()))
+    }
+}
+
+/// Generic trait for implementing a policy on the scheduling priority of
+/// processes, for schedulers that use priorities such as `PrioritySched` and
+/// `MLFQSched`.
+///
+/// Lower numbers are higher priorities, and 0 is the highest. Schedulers run
+/// processes with the same priority in the order of the processes array.
+///
+/// Schedulers ask the policy once every time a process starts, and use the
+/// priority the process was assigned until it restarts.
+pub trait PriorityPolicy {
+    /// Decide the priority of `process`.
+    fn priority(&self, process: &dyn Process) -> u32;
+}
+
+/// The priority `policy` assigned to `process`, asking the policy only if the
+/// process has not been assigned a priority since it started.
+pub(crate) fn assigned_priority(policy: &dyn PriorityPolicy, process: &dyn Process) -> u32 {
+    process.get_assigned_priority().unwrap_or_else(|| {
+        let priority = policy.priority(process);
+        process.set_assigned_priority(priority);
+        priority
+    })
+}
+
+/// Give every process the same priority, so that schedulers only use the
+/// order of the processes array. This matches the behavior of boards that do
+/// not assign priorities.
+pub struct EqualPriorityPolicy {}
+
+impl PriorityPolicy for EqualPriorityPolicy {
+    fn priority(&self, _: &dyn Process) -> u32 {
+        0
+    }
+}
+
+/// Allows the process with the persistent identifier `app_id` to request
+/// priorities up to `highest`, instead of the limit that applies to all other
+/// processes. Anonymous processes never match a limit.
+pub struct PriorityLimit {
+    pub app_id: u32,
+    pub highest: u32,
+}
+
+/// Use the priority a process requests in its TBF header, so that priorities
+/// do not depend on the order in which the processes are stored in flash.
+///
+/// Processes that do not request a priority get `default`. No process gets a
+/// higher priority than `highest`, unless one of `limits` allows the process
+/// more, so that, for example, only a trusted process can outrank all others.
+pub struct TbfHeaderPriorityPolicy {
+    default: u32,
+    highest: u32,
+    limits: &'static [PriorityLimit],
+}
+
+impl TbfHeaderPriorityPolicy {
+    pub const fn new(
+        default: u32,
+        highest: u32,
+        limits: &'static [PriorityLimit],
+    ) -> TbfHeaderPriorityPolicy {
+        TbfHeaderPriorityPolicy {
+            default,
+            highest,
+            limits,
+        }
+    }
+}
+
+impl PriorityPolicy for TbfHeaderPriorityPolicy {
+    fn priority(&self, process: &dyn Process) -> u32 {
+        let app_id = process.get_app_id();
+        let highest = self
+            .limits
+            .iter()
+            .find(|limit| app_id == AppId::Persistent(limit.app_id))
+            .map_or(self.highest, |limit| limit.highest);
+        process.get_priority().unwrap_or(self.default).max(highest)
+    }
+}
+
+/// Generic trait for implementing a policy on which persistent identifier
+/// (`AppId`) a process is given.
+///
+/// The kernel calls the policy once for every process it loads, before the
+/// process is created. If the policy assigns an `AppId::Persistent` value that
+/// another loaded process already has, the new process is not loaded.
+pub trait AppIdPolicy {
+    /// Decide the `AppId` of the process named `process_name` with the TBF
+    /// header `header`. `credentials` is the result of checking the
+    /// process's credentials, so a policy can, for example, only give
+    /// persistent identities to verified processes.
+    fn assign_app_id(
+        &self,
+        process_name: &'static str,
+        header: &TbfHeader,
+        credentials: CredentialsStatus,
+    ) -> AppId;
+}
+
+/// Give every process `AppId::Anonymous`. This matches the behavior of boards
+/// that do not use persistent application identifiers.
+pub struct AnonymousAppIdPolicy {}
+
+impl AppIdPolicy for AnonymousAppIdPolicy {
+    fn assign_app_id(&self, _: &'static str, _: &TbfHeader, _: CredentialsStatus) -> AppId {
+        AppId::Anonymous
+    }
+}
+
+/// Derive the `AppId` of verified processes from their TBF header.
+///
+/// If the header includes an App ID TLV that value is used. Otherwise the
+/// identifier is a hash of the package name, and processes without a package
+/// name are anonymous. Since two applications with the same package name hash
+/// to the same identifier, only one of them can be loaded at a time.
+///
+/// Any application can put any App ID or package name in its header, so only
+/// processes whose credentials were accepted get a persistent identifier; all
+/// other processes are anonymous. The identifiers are therefore only as
+/// trustworthy as the board's credentials checker. A checker that only
+/// checks the integrity of processes, such as a hash checker, accepts any
+/// application that carries a matching hash, so identities are only protected
+/// if the checker verifies who built the process, for example with a
+/// signature.
+pub struct TbfHeaderAppIdPolicy {}
+
+impl AppIdPolicy for TbfHeaderAppIdPolicy {
+    fn assign_app_id(
+        &self,
+        process_name: &'static str,
+        header: &TbfHeader,
+        credentials: CredentialsStatus,
+    ) -> AppId {
+        match credentials {
+            CredentialsStatus::Verified(_) => match header.get_app_id() {
+                Some(id) => AppId::Persistent(id),
+                None if process_name.len() > 0 => AppId::Persistent(fnv1a_hash(process_name)),
+                None => AppId::Anonymous,
+            },
+            CredentialsStatus::Unverified => AppId::Anonymous,
+        }
+    }
+}
+
+/// 32-bit FNV-1a hash of `name`. This is not a cryptographic hash; it is only
+/// used to turn package names into stable identifiers.
+fn fnv1a_hash(name: &str) -> u32 {
+    name.bytes().fold(0x811c9dc5, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x01000193)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::convert::TryFrom;
+    use std::boxed::Box;
+    use std::vec;
+
+    use tock_tbf::builder::TbfHeaderBuilder;
+    use tock_tbf::parse::parse_tbf_header;
+    use tock_tbf::types::{TbfFooterV2Credentials, TbfHeader};
+
+    use super::{fnv1a_hash, AppIdPolicy, TbfHeaderAppIdPolicy};
+    use crate::process::AppId;
+    use crate::process_checker::CredentialsStatus;
+
+    /// Parse the header `builder` creates.
+    fn header(builder: TbfHeaderBuilder) -> TbfHeader {
+        let builder = builder.main(0, 0, 0x400).total_size(0x400);
+        let mut flash = vec![0; builder.header_size()];
+        builder.write(&mut flash).unwrap();
+        parse_tbf_header(Box::leak(flash.into_boxed_slice()), 2).unwrap_or_else(|_| panic!())
+    }
+
+    fn verified() -> CredentialsStatus {
+        // A SHA-256 footer.
+        let mut credentials = [0u8; 36];
+        credentials[0] = 5;
+        CredentialsStatus::Verified(
+            TbfFooterV2Credentials::try_from(&Box::leak(Box::new(credentials))[..]).unwrap(),
+        )
+    }
+
+    #[test]
+    fn verified_processes_get_header_identity() {
+        let policy = TbfHeaderAppIdPolicy {};
+        let with_app_id = header(TbfHeaderBuilder::new().package_name("app").app_id(7));
+        assert_eq!(
+            policy.assign_app_id("app", &with_app_id, verified()),
+            AppId::Persistent(7)
+        );
+        let named = header(TbfHeaderBuilder::new().package_name("app"));
+        assert_eq!(
+            policy.assign_app_id("app", &named, verified()),
+            AppId::Persistent(fnv1a_hash("app"))
+        );
+        let unnamed = header(TbfHeaderBuilder::new());
+        assert_eq!(
+            policy.assign_app_id("", &unnamed, verified()),
+            AppId::Anonymous
+        );
+    }
+
+    #[test]
+    fn unverified_processes_are_anonymous() {
+        // Otherwise any process could claim the identity of another.
+        let policy = TbfHeaderAppIdPolicy {};
+        let with_app_id = header(TbfHeaderBuilder::new().package_name("app").app_id(7));
+        assert_eq!(
+            policy.assign_app_id("app", &with_app_id, CredentialsStatus::Unverified),
+            AppId::Anonymous
+        );
+        let named = header(TbfHeaderBuilder::new().package_name("app"));
+        assert_eq!(
+            policy.assign_app_id("app", &named, CredentialsStatus::Unverified),
+            AppId::Anonymous
+        );
+    }
+}
@@ -0,0 +1,312 @@
+//! Delayed restarts of processes that faulted.
+//!
+//! A `ProcessFaultPolicy` can return `FaultAction::RestartAfter` to restart a
+//! faulted process only after a delay, for example to back off a process that
+//! keeps faulting. The process waits in the `WaitingToRestart` state until the
+//! `DelayedRestart` timer the board gave the kernel restarts it.
+//!
+//! ```rust,ignore
+//! let restart_alarm = static_init!(
+//!     VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+//!     VirtualMuxAlarm::new(mux_alarm)
+//! );
+//! let delayed_restart = static_init!(
+//!     kernel::procs::DelayedRestartAlarm<
+//!         'static,
+//!         VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+//!         NUM_PROCS,
+//!     >,
+//!     kernel::procs::DelayedRestartAlarm::new(board_kernel, restart_alarm)
+//! );
+//! restart_alarm.set_alarm_client(delayed_restart);
+//! board_kernel.set_delayed_restart(delayed_restart, &process_management_capability);
+//! ```
+
+use core::cell::Cell;
+
+use crate::errorcode::ErrorCode;
+use crate::hil::time::{self, Alarm, Ticks};
+use crate::process::{ProcessId, State};
+use crate::process_standard::COMPLETION_FAULT;
+use crate::sched::Kernel;
+
+/// Timer the kernel uses to restart processes after a delay.
+pub trait DelayedRestart {
+    /// Restart the process `processid` in `delay_ms` milliseconds, unless it
+    /// is no longer in the `WaitingToRestart` state by then. Replaces an
+    /// earlier restart of the same process.
+    fn restart_after(&self, processid: ProcessId, delay_ms: u32) -> Result<(), ErrorCode>;
+}
+
+#[derive(Copy, Clone)]
+struct PendingRestart<T: Ticks> {
+    processid: ProcessId,
+    reference: T,
+    dt: T,
+}
+
+impl<T: Ticks> PendingRestart<T> {
+    const NONE: Cell<Option<PendingRestart<T>>> = Cell::new(None);
+
+    /// Ticks until the process is restarted, or `None` if it is due.
+    fn remaining(&self, now: T) -> Option<T> {
+        let end = self.reference.wrapping_add(self.dt);
+        if now.within_range(self.reference, end) {
+            Some(end.wrapping_sub(now))
+        } else {
+            None
+        }
+    }
+}
+
+/// `DelayedRestart` timer using an alarm, for boards with up to `NUM_PROCS`
+/// processes.
+pub struct DelayedRestartAlarm<'a, A: Alarm<'a>, const NUM_PROCS: usize> {
+    kernel: &'static Kernel,
+    alarm: &'a A,
+    /// The pending restart of each process, by process index.
+    restarts: [Cell<Option<PendingRestart<A::Ticks>>>; NUM_PROCS],
+}
+
+impl<'a, A: Alarm<'a>, const NUM_PROCS: usize> DelayedRestartAlarm<'a, A, NUM_PROCS> {
+    pub fn new(kernel: &'static Kernel, alarm: &'a A) -> DelayedRestartAlarm<'a, A, NUM_PROCS> {
+        DelayedRestartAlarm {
+            kernel,
+            alarm,
+            restarts: [PendingRestart::NONE; NUM_PROCS],
+        }
+    }
+
+    /// Set the alarm for the earliest pending restart, or disarm it if there
+    /// is none.
+    fn reset_alarm(&self) {
+        let now = self.alarm.now();
+        let earliest = self
+            .restarts
+            .iter()
+            .filter_map(|restart| restart.get())
+            .map(|restart| restart.remaining(now).unwrap_or_else(|| A::Ticks::from(0)))
+            .min();
+        match earliest {
+            Some(remaining) => self.alarm.set_alarm(now, remaining),
+            None => {
+                let _ = self.alarm.disarm();
+            }
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, const NUM_PROCS: usize> DelayedRestart
+    for DelayedRestartAlarm<'a, A, NUM_PROCS>
+{
+    fn restart_after(&self, processid: ProcessId, delay_ms: u32) -> Result<(), ErrorCode> {
+        let restart = self.restarts.get(processid.index).ok_or(ErrorCode::SIZE)?;
+        restart.set(Some(PendingRestart {
+            processid,
+            reference: self.alarm.now(),
+            dt: A::ticks_from_ms(delay_ms),
+        }));
+        self.reset_alarm();
+        Ok(())
+    }
+}
+
+impl<'a, A: Alarm<'a>, const NUM_PROCS: usize> time::AlarmClient
+    for DelayedRestartAlarm<'a, A, NUM_PROCS>
+{
+    fn alarm(&self) {
+        let now = self.alarm.now();
+        for restart in self.restarts.iter() {
+            if let Some(pending) = restart.get() {
+                if pending.remaining(now).is_none() {
+                    restart.set(None);
+                    // The process may have been removed, or restarted from
+                    // the process console, while it waited.
+                    self.kernel
+                        .process_map_or((), pending.processid, |process| {
+                            if process.get_state() == State::WaitingToRestart {
+                                process.try_restart(COMPLETION_FAULT);
+                            }
+                        });
+                }
+            }
+        }
+        self.reset_alarm();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::Cell;
+    use std::boxed::Box;
+    use std::vec;
+
+    use crate::capabilities::ProcessManagementCapability;
+    use crate::errorcode::ErrorCode;
+    use crate::hil::time::{Alarm, AlarmClient, Freq1KHz, Ticks, Ticks24, Time};
+    use crate::process::{Process, ProcessSlot, State, EMPTY_PROCESS_SLOT};
+    use crate::process_policies::BackoffRestartFaultPolicy;
+    use crate::process_standard::tests::{
+        tbf_with_minimum_ram, MockChip, MockUserspaceKernelBoundary,
+    };
+    use crate::process_utilities::load_processes;
+    use crate::sched::Kernel;
+
+    use super::DelayedRestartAlarm;
+
+    struct Capability;
+    unsafe impl ProcessManagementCapability for Capability {}
+
+    const BACKOFF_POLICY: BackoffRestartFaultPolicy = BackoffRestartFaultPolicy::new(100, 300);
+
+    /// A 24 bit alarm counting milliseconds, which only moves when the test
+    /// sets the time.
+    struct MockAlarm {
+        now: Cell<Ticks24>,
+        armed: Cell<Option<(Ticks24, Ticks24)>>,
+    }
+
+    impl Time for MockAlarm {
+        type Frequency = Freq1KHz;
+        type Ticks = Ticks24;
+
+        fn now(&self) -> Ticks24 {
+            self.now.get()
+        }
+    }
+
+    impl<'a> Alarm<'a> for MockAlarm {
+        fn set_alarm_client(&'a self, _client: &'a dyn AlarmClient) {}
+
+        fn set_alarm(&self, reference: Ticks24, dt: Ticks24) {
+            self.armed.set(Some((reference, dt)));
+        }
+
+        fn get_alarm(&self) -> Ticks24 {
+            self.armed
+                .get()
+                .map_or(Ticks24::from(0), |(reference, dt)| {
+                    reference.wrapping_add(dt)
+                })
+        }
+
+        fn disarm(&self) -> Result<(), ErrorCode> {
+            self.armed.set(None);
+            Ok(())
+        }
+
+        fn is_armed(&self) -> bool {
+            self.armed.get().is_some()
+        }
+
+        fn minimum_dt(&self) -> Ticks24 {
+            Ticks24::from(1)
+        }
+    }
+
+    /// Load a process that restarts with `BACKOFF_POLICY` through a
+    /// `DelayedRestartAlarm`, with the alarm at `now`.
+    fn create_process(
+        now: u32,
+    ) -> (
+        &'static dyn Process,
+        &'static MockAlarm,
+        &'static DelayedRestartAlarm<'static, MockAlarm, 1>,
+    ) {
+        let procs: &'static [ProcessSlot] = Box::leak(Box::new([EMPTY_PROCESS_SLOT]));
+        let kernel: &'static Kernel = Box::leak(Box::new(Kernel::new(procs)));
+        let chip: &'static MockChip = Box::leak(Box::new(MockChip {
+            userspace_kernel_boundary: MockUserspaceKernelBoundary {},
+        }));
+        let alarm: &'static MockAlarm = Box::leak(Box::new(MockAlarm {
+            now: Cell::new(Ticks24::from(now)),
+            armed: Cell::new(None),
+        }));
+        let timer = Box::leak(Box::new(DelayedRestartAlarm::new(kernel, alarm)));
+        kernel.set_delayed_restart(timer, &Capability);
+
+        // Word-aligned memory, as an MPU would allocate.
+        let memory: &'static mut [u64] = Box::leak(vec![0u64; 1024].into_boxed_slice());
+        let memory = unsafe {
+            core::slice::from_raw_parts_mut(memory.as_mut_ptr() as *mut u8, memory.len() * 8)
+        };
+        load_processes(
+            kernel,
+            chip,
+            tbf_with_minimum_ram(0, &[]),
+            memory,
+            &BACKOFF_POLICY,
+            &Capability,
+        )
+        .unwrap();
+        (procs[0].get().unwrap(), alarm, timer)
+    }
+
+    #[test]
+    fn restarts_process_after_delay() {
+        let (process, alarm, timer) = create_process(1000);
+
+        process.set_fault_state();
+        assert_eq!(process.get_state(), State::WaitingToRestart);
+        assert_eq!(
+            alarm.armed.get(),
+            Some((Ticks24::from(1000), Ticks24::from(100)))
+        );
+
+        // An early alarm only sets the alarm for the rest of the delay.
+        alarm.now.set(Ticks24::from(1060));
+        timer.alarm();
+        assert_eq!(process.get_state(), State::WaitingToRestart);
+        assert_eq!(
+            alarm.armed.get(),
+            Some((Ticks24::from(1060), Ticks24::from(40)))
+        );
+
+        alarm.now.set(Ticks24::from(1100));
+        timer.alarm();
+        assert_eq!(process.get_state(), State::Unstarted);
+        assert_eq!(process.get_restart_count(), 1);
+        assert!(!alarm.is_armed());
+    }
+
+    #[test]
+    fn delay_spans_wrap_of_24_bit_ticks() {
+        let (process, alarm, timer) = create_process(0x00FF_FFF0);
+
+        process.set_fault_state();
+        assert_eq!(process.get_state(), State::WaitingToRestart);
+
+        // The counter wrapped, but only 0x20 of the 100 ticks passed.
+        alarm.now.set(Ticks24::from(0x10));
+        timer.alarm();
+        assert_eq!(process.get_state(), State::WaitingToRestart);
+        assert_eq!(
+            alarm.armed.get(),
+            Some((Ticks24::from(0x10), Ticks24::from(100 - 0x20)))
+        );
+
+        alarm.now.set(Ticks24::from(0x54));
+        timer.alarm();
+        assert_eq!(process.get_state(), State::Unstarted);
+        assert_eq!(process.get_restart_count(), 1);
+    }
+
+    #[test]
+    fn process_restarted_while_waiting_is_not_restarted_again() {
+        let (process, alarm, timer) = create_process(0);
+
+        process.set_fault_state();
+        assert_eq!(process.get_state(), State::WaitingToRestart);
+        // For example from the process console.
+        process.try_restart(0);
+        assert_eq!(process.get_restart_count(), 1);
+
+        alarm.now.set(Ticks24::from(100));
+        timer.alarm();
+        assert_eq!(process.get_state(), State::Unstarted);
+        assert_eq!(process.get_restart_count(), 1);
+        assert!(!alarm.is_armed());
+    }
+}
@@ -17,16 +17,37 @@ use crate::errorcode::ErrorCode;
 use crate::mem::{ReadOnlyAppSlice, ReadWriteAppSlice};
 use crate::platform::mpu::{self, MPU};
 use crate::platform::Chip;
-use crate::process::{Error, FunctionCall, FunctionCallSource, Process, State, Task};
+use crate::process::{AllowSlot, AppId, Error, FunctionCall, FunctionCallSource, Process};
 use crate::process::{FaultAction, ProcessCustomGrantIdentifer, ProcessId, ProcessStateCell};
-use crate::process_policies::ProcessFaultPolicy;
+use crate::process::{State, Task};
+use crate::process_checker::{self, AppCredentialsChecker, CredentialsStatus};
+use crate::process_policies::{AppIdPolicy, ProcessFaultPolicy};
 use crate::process_utilities::ProcessLoadError;
 use crate::sched::Kernel;
 use crate::syscall::{self, Syscall, SyscallReturn, UserspaceKernelBoundary};
 use crate::upcall::UpcallId;
 
 // The completion code for a process if it faulted.
-const COMPLETION_FAULT: u32 = 0xffffffff;
+pub(crate) const COMPLETION_FAULT: u32 = 0xffffffff;
+
+// The word the kernel fills process memory with before the process starts,
+// to find out later how deep the process stack has grown.
+const STACK_PAINT: u32 = 0xCAFE_F00D;
+
+// The number of buffers a process can share with capsules at the same time.
+const MAX_ALLOWED_BUFFERS: usize = 16;
+
+/// A buffer that a process currently shares with a capsule through allow.
+#[derive(Clone, Copy)]
+struct AllowedBuffer {
+    /// The slot the buffer was allowed to. This is `None` if the process has
+    /// since allowed another buffer to the same slot but the capsule did not
+    /// hand this one back, so the capsule may still hold it.
+    slot: Option<AllowSlot>,
+    start: *const u8,
+    size: usize,
+    readonly: bool,
+}
 
 /// State for helping with debugging apps.
 ///
@@ -54,6 +75,12 @@ struct ProcessStandardDebug {
     /// How low have we ever seen the stack pointer.
     app_stack_min_pointer: Option<*const u8>,
 
+    /// How high the app break has been since the process started.
+    app_break_max: *const u8,
+
+    /// Where the stack pointer was when the process last stopped running.
+    app_stack_pointer: Option<*const u8>,
+
     /// How many syscalls have occurred since the process started.
     syscall_count: usize,
 
@@ -67,6 +94,27 @@ struct ProcessStandardDebug {
     /// How many times this process has been paused because it exceeded its
     /// timeslice.
     timeslice_expiration_count: usize,
+
+    /// How many times this process has used up its CPU time budget for a
+    /// period before finishing its work.
+    budget_overrun_count: usize,
+
+    /// How long this process has run, in microseconds. Unlike the counters
+    /// above, this and the following counters are kept when the process
+    /// restarts, so a process which keeps crashing still shows the CPU time
+    /// and wakeups it used.
+    cpu_time_us: u64,
+
+    /// How many times the kernel has switched to this process.
+    context_switch_count: usize,
+
+    /// How many times this process has been woken up to run an upcall after
+    /// it yielded.
+    wakeup_count: usize,
+
+    /// How many times this process has missed the deadline of its software
+    /// watchdog.
+    watchdog_expiration_count: usize,
 }
 
 /// A type for userspace processes in Tock.
@@ -137,6 +185,11 @@ pub struct ProcessStandard<'a, C: 'static + Chip> {
     /// Pointer to high water mark for process buffers shared through `allow`
     allow_high_water_mark: Cell<*const u8>,
 
+    /// Buffers capsules currently hold app slices to. These are tracked so
+    /// that the kernel never creates two app slices which alias the same
+    /// memory where either can write it.
+    allowed_buffers: [Cell<Option<AllowedBuffer>>; MAX_ALLOWED_BUFFERS],
+
     /// Process flash segment. This is the region of nonvolatile flash that
     /// the process occupies.
     flash: &'static [u8],
@@ -178,9 +231,20 @@ pub struct ProcessStandard<'a, C: 'static + Chip> {
     /// determine if the process should be restarted or not.
     restart_count: Cell<usize>,
 
+    /// The priority the board's `PriorityPolicy` assigned to this process
+    /// since it was last started, cached for the scheduler.
+    assigned_priority: Cell<Option<u32>>,
+
     /// Name of the app.
     process_name: &'static str,
 
+    /// Whether the credentials of this process were verified when it was
+    /// loaded.
+    credentials: CredentialsStatus,
+
+    /// Persistent identifier of the application this process runs.
+    app_id: AppId,
+
     /// Values kept so that we can print useful debug messages when apps fault.
     debug: MapCell<ProcessStandardDebug>,
 }
@@ -298,6 +362,13 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
                 self.terminate(COMPLETION_FAULT);
                 self.state.update(State::Faulted);
             }
+            FaultAction::RestartAfter(delay_ms) => {
+                self.terminate(COMPLETION_FAULT);
+                match self.kernel.restart_after(self.processid(), delay_ms) {
+                    Ok(()) => self.state.update(State::WaitingToRestart),
+                    Err(_) => self.try_restart(COMPLETION_FAULT),
+                }
+            }
         }
     }
 
@@ -479,6 +550,11 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
                     let old_break = self.app_break.get();
                     self.app_break.set(new_break);
                     self.chip.mpu().configure_mpu(&config, &self.processid());
+                    self.debug.map(|debug| {
+                        if new_break > debug.app_break_max {
+                            debug.app_break_max = new_break;
+                        }
+                    });
                     Ok(old_break)
                 }
             })
@@ -487,6 +563,7 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
     #[allow(clippy::not_unsafe_ptr_arg_deref)]
     fn build_readwrite_appslice(
         &self,
+        slot: AllowSlot,
         buf_start_addr: *mut u8,
         size: usize,
     ) -> Result<ReadWriteAppSlice, ErrorCode> {
@@ -519,7 +596,8 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
             // can encapsulate the unsafe.
             Ok(unsafe { ReadWriteAppSlice::new(buf_start_addr, 0, self.processid()) })
         } else if self.in_app_owned_memory(buf_start_addr, size) {
-            // TODO: Check for buffer aliasing here
+            // Make sure no capsule holds an app slice to any of this memory.
+            self.check_allowed_buffer(slot, buf_start_addr, size)?;
 
             // Valid slice, we need to adjust the app's watermark
             // note: in_app_owned_memory ensures this offset does not wrap
@@ -544,7 +622,7 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
             //
             // ### Safety
             //
-            // We encapsulate the unsafe here on the condition in the TODO
+            // We encapsulate the unsafe here on the condition of the check
             // above, as we must ensure that this `ReadWriteAppSlice` will be
             // the only reference to this memory.
             Ok(unsafe { ReadWriteAppSlice::new(buf_start_addr, size, self.processid()) })
@@ -556,6 +634,7 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
     #[allow(clippy::not_unsafe_ptr_arg_deref)]
     fn build_readonly_appslice(
         &self,
+        slot: AllowSlot,
         buf_start_addr: *const u8,
         size: usize,
     ) -> Result<ReadOnlyAppSlice, ErrorCode> {
@@ -590,7 +669,9 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
         } else if self.in_app_owned_memory(buf_start_addr, size)
             || self.in_app_flash_memory(buf_start_addr, size)
         {
-            // TODO: Check for buffer aliasing here
+            // Make sure no capsule holds a writeable app slice to any of this
+            // memory.
+            self.check_allowed_buffer(slot, buf_start_addr, size)?;
 
             // Valid slice, we need to adjust the app's watermark
             // note: in_app_owned_memory ensures this offset does not wrap
@@ -616,15 +697,65 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
             //
             // ### Safety
             //
-            // We encapsulate the unsafe here on the condition in the TODO
-            // above, as we must ensure that this `ReadOnlyAppSlice` will be
-            // the only reference to this memory.
+            // We encapsulate the unsafe here on the condition of the check
+            // above, as we must ensure that no writeable reference to this
+            // memory exists while this `ReadOnlyAppSlice` does.
             Ok(unsafe { ReadOnlyAppSlice::new(buf_start_addr, size, self.processid()) })
         } else {
             Err(ErrorCode::INVAL)
         }
     }
 
+    fn allow_complete(
+        &self,
+        slot: AllowSlot,
+        allowed: (*const u8, usize),
+        returned: (*const u8, usize),
+        accepted: bool,
+    ) {
+        // The capsule handed back the same memory it was passed, so it holds
+        // the same buffers as before.
+        if allowed == returned {
+            return;
+        }
+
+        if accepted {
+            // The capsule now holds the new buffer for this slot. It should
+            // have handed back the buffer it held for the slot before, but if
+            // it did not, keep tracking that buffer as it may still use it.
+            for entry in self.allowed_buffers.iter() {
+                if let Some(mut buffer) = entry.get() {
+                    if buffer.slot == Some(slot) {
+                        buffer.slot = None;
+                        entry.set(Some(buffer));
+                    }
+                }
+            }
+        }
+
+        if returned.1 != 0 {
+            self.remove_allowed_buffer(returned.0, returned.1, slot.is_readonly());
+        }
+
+        if allowed.1 != 0 {
+            // `check_allowed_buffer()` ensured there is a free entry when the
+            // app slice was built, and only entries for buffers the capsule
+            // handed back have changed since.
+            let free = self
+                .allowed_buffers
+                .iter()
+                .find(|entry| entry.get().is_none());
+            free.map(|entry| {
+                entry.set(Some(AllowedBuffer {
+                    slot: if accepted { Some(slot) } else { None },
+                    start: allowed.0,
+                    size: allowed.1,
+                    readonly: slot.is_readonly(),
+                }))
+            });
+        }
+    }
+
     unsafe fn set_byte(&self, addr: *mut u8, value: u8) -> bool {
         if self.in_app_owned_memory(addr, 1) {
             // We verify that this will only write process-accessible memory,
@@ -823,6 +954,38 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
         self.process_name
     }
 
+    fn get_credentials_status(&self) -> CredentialsStatus {
+        self.credentials
+    }
+
+    fn get_app_id(&self) -> AppId {
+        self.app_id
+    }
+
+    fn get_command_permissions(
+        &self,
+        driver_num: usize,
+        offset: usize,
+    ) -> tock_tbf::types::CommandPermissions {
+        self.header.get_command_permissions(driver_num, offset)
+    }
+
+    fn get_deadline(&self) -> Option<(u32, u32)> {
+        self.header.get_deadline()
+    }
+
+    fn get_priority(&self) -> Option<u32> {
+        self.header.get_priority()
+    }
+
+    fn get_assigned_priority(&self) -> Option<u32> {
+        self.assigned_priority.get()
+    }
+
+    fn set_assigned_priority(&self, priority: u32) {
+        self.assigned_priority.set(Some(priority));
+    }
+
     fn set_syscall_return_value(&self, return_value: SyscallReturn) {
         match self.stored_state.map(|stored_state| unsafe {
             // Actually set the return value for a particular process.
@@ -930,6 +1093,7 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
         // debugging state. This is completely optional.
         stack_pointer.map(|sp| {
             self.debug.map(|debug| {
+                debug.app_stack_pointer = Some(sp);
                 match debug.app_stack_min_pointer {
                     None => debug.app_stack_min_pointer = Some(sp),
                     Some(asmp) => {
@@ -963,6 +1127,47 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
             .map(|debug| debug.timeslice_expiration_count += 1);
     }
 
+    fn debug_budget_overrun_count(&self) -> usize {
+        self.debug.map_or(0, |debug| debug.budget_overrun_count)
+    }
+
+    fn debug_budget_overrun(&self) {
+        self.debug.map(|debug| debug.budget_overrun_count += 1);
+    }
+
+    fn debug_cpu_time_us(&self) -> u64 {
+        self.debug.map_or(0, |debug| debug.cpu_time_us)
+    }
+
+    fn debug_add_cpu_time(&self, us: u32) {
+        self.debug.map(|debug| debug.cpu_time_us += us as u64);
+    }
+
+    fn debug_context_switch_count(&self) -> usize {
+        self.debug.map_or(0, |debug| debug.context_switch_count)
+    }
+
+    fn debug_context_switched(&self) {
+        self.debug.map(|debug| debug.context_switch_count += 1);
+    }
+
+    fn debug_wakeup_count(&self) -> usize {
+        self.debug.map_or(0, |debug| debug.wakeup_count)
+    }
+
+    fn debug_woken(&self) {
+        self.debug.map(|debug| debug.wakeup_count += 1);
+    }
+
+    fn debug_watchdog_expiration_count(&self) -> usize {
+        self.debug
+            .map_or(0, |debug| debug.watchdog_expiration_count)
+    }
+
+    fn debug_watchdog_expired(&self) {
+        self.debug.map(|debug| debug.watchdog_expiration_count += 1);
+    }
+
     fn debug_syscall_called(&self, last_syscall: Syscall) {
         self.debug.map(|debug| {
             debug.syscall_count += 1;
@@ -970,6 +1175,37 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
         });
     }
 
+    fn debug_last_syscall(&self) -> Option<Syscall> {
+        self.debug.map_or(None, |debug| debug.last_syscall)
+    }
+
+    fn debug_copy_stack(&self, out: &mut [u8]) -> usize {
+        let (stack_pointer, stack_start) = match self.debug.map_or(None, |debug| {
+            debug
+                .app_stack_pointer
+                .map(|sp| (sp as usize, debug.app_stack_start_pointer))
+        }) {
+            Some(stack) => stack,
+            None => return 0,
+        };
+
+        // Only copy memory the process can access, and stop at the start of
+        // the stack if the process told us where it is.
+        let app_break = self.app_break.get() as usize;
+        let stack_end = stack_start.map_or(app_break, |start| cmp::min(start as usize, app_break));
+        if stack_pointer < self.mem_start() as usize || stack_pointer >= stack_end {
+            return 0;
+        }
+
+        let length = cmp::min(out.len(), stack_end - stack_pointer);
+        // Safety: the copied memory is between the start of process memory
+        // and the app break, so it is memory the process owns. The process is
+        // not running while the kernel copies it.
+        let stack = unsafe { slice::from_raw_parts(stack_pointer as *const u8, length) };
+        out[..length].copy_from_slice(stack);
+        length
+    }
+
     fn debug_heap_start(&self) -> Option<*const u8> {
         self.debug
             .map_or(None, |debug| debug.app_heap_start_pointer.map(|p| p))
@@ -985,6 +1221,30 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
             .map_or(None, |debug| debug.app_stack_min_pointer.map(|p| p))
     }
 
+    #[allow(clippy::cast_ptr_alignment)]
+    fn debug_stack_high_water_mark(&self) -> Option<usize> {
+        let stack_start = self.debug_stack_start()? as usize;
+        if !config::CONFIG.paint_process_stacks {
+            return self
+                .debug_stack_end()
+                .map(|stack_end| stack_start.saturating_sub(stack_end as usize));
+        }
+
+        let stack_limit = cmp::min(stack_start, self.app_break.get() as usize);
+        // Safety: the words between the start of process memory, which is
+        // word aligned, and the app break are memory the process owns. The
+        // process is not running while the kernel reads it.
+        Some(unsafe { painted_stack_depth(self.mem_start() as usize, stack_limit, stack_start) })
+    }
+
+    fn debug_heap_high_water_mark(&self) -> Option<usize> {
+        self.debug.map_or(None, |debug| {
+            debug.app_heap_start_pointer.map(|heap_start| {
+                (debug.app_break_max as usize).saturating_sub(heap_start as usize)
+            })
+        })
+    }
+
     fn print_memory_map(&self, writer: &mut dyn Write) {
         // Flash
         let flash_end = self.flash.as_ptr().wrapping_add(self.flash.len()) as usize;
@@ -1018,6 +1278,8 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
         let last_syscall = self.debug.map(|debug| debug.last_syscall);
         let dropped_upcall_count = self.debug.map_or(0, |debug| debug.dropped_upcall_count);
         let restart_count = self.restart_count.get();
+        let stack_high_water_mark = self.debug_stack_high_water_mark();
+        let heap_high_water_mark = self.debug_heap_high_water_mark();
 
         let _ = writer.write_fmt(format_args!(
             "\
@@ -1032,6 +1294,15 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
             restart_count,
         ));
 
+        let _ = match stack_high_water_mark {
+            Some(bytes) => writer.write_fmt(format_args!(" Stack High-Water Mark: {}", bytes)),
+            None => writer.write_str(" Stack High-Water Mark: ?"),
+        };
+        let _ = match heap_high_water_mark {
+            Some(bytes) => writer.write_fmt(format_args!("   Heap High-Water Mark: {}\r\n", bytes)),
+            None => writer.write_str("   Heap High-Water Mark: ?\r\n"),
+        };
+
         let _ = match last_syscall {
             Some(syscall) => writer.write_fmt(format_args!(" Last Syscall: {:?}\r\n", syscall)),
             None => writer.write_str(" Last Syscall: None\r\n"),
@@ -1144,6 +1415,15 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
         ));
     }
 
+    fn get_stored_state(&self, out: &mut [u8]) -> Result<usize, ErrorCode> {
+        self.stored_state
+            .map_or(Err(ErrorCode::FAIL), |stored_state| {
+                self.chip
+                    .userspace_kernel_boundary()
+                    .store_context(stored_state, out)
+            })
+    }
+
     fn print_full_process(&self, writer: &mut dyn Write) {
         self.print_memory_map(writer);
 
@@ -1243,6 +1523,41 @@ fn exceeded_check(size: usize, allocated: usize) -> &'static str {
     }
 }
 
+/// Fill the words from `start` up to `end` with `STACK_PAINT`.
+///
+/// ### Safety
+///
+/// `start` must be word aligned, and the memory up to `end` must be writable
+/// and not in use.
+unsafe fn paint(start: usize, end: usize) {
+    let start = start as *mut u32;
+    let words = (end - start as usize) / mem::size_of::<u32>();
+    for word in 0..words {
+        ptr::write_volatile(start.add(word), STACK_PAINT);
+    }
+}
+
+/// How deep a stack that starts at `stack_start` has grown into memory
+/// painted by `paint()` from `start`, searching up to `limit`.
+///
+/// The stack grows down from its start towards `start`, so the lowest word
+/// that is no longer painted is the deepest the stack has been.
+///
+/// ### Safety
+///
+/// `start` must be word aligned, and the memory up to `limit` must be
+/// readable.
+unsafe fn painted_stack_depth(start: usize, limit: usize, stack_start: usize) -> usize {
+    let mut address = start;
+    while address + mem::size_of::<u32>() <= limit {
+        if ptr::read_volatile(address as *const u32) != STACK_PAINT {
+            break;
+        }
+        address += mem::size_of::<u32>();
+    }
+    stack_start.saturating_sub(address)
+}
+
 impl<C: 'static + Chip> ProcessStandard<'_, C> {
     // Memory offset for upcall ring buffer (10 element length).
     const CALLBACK_LEN: usize = 10;
@@ -1259,6 +1574,8 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
         app_version: u16,
         remaining_memory: &'a mut [u8],
         fault_policy: &'static dyn ProcessFaultPolicy,
+        credentials_checker: &dyn AppCredentialsChecker,
+        app_id_policy: &dyn AppIdPolicy,
         index: usize,
     ) -> Result<(Option<&'static dyn Process>, &'a mut [u8]), ProcessLoadError> {
         // Get a slice for just the app header.
@@ -1313,6 +1630,50 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
             return Ok((None, remaining_memory));
         }
 
+        // Before allocating anything for the process, ask the board whether
+        // its credentials allow it to run. A rejected process is skipped just
+        // like a disabled one.
+        let credentials = match process_checker::check_process_credentials(
+            credentials_checker,
+            app_flash,
+            &tbf_header,
+        ) {
+            Some(credentials) => credentials,
+            None => {
+                if config::CONFIG.debug_load_processes {
+                    debug!(
+                        "Process credentials rejected flash={:#010X}-{:#010X} process={:?}",
+                        app_flash.as_ptr() as usize,
+                        app_flash.as_ptr() as usize + app_flash.len() - 1,
+                        process_name
+                    );
+                }
+                return Ok((None, remaining_memory));
+            }
+        };
+
+        // Two processes must never share a persistent identity, as capsules
+        // use it to decide which application owns a resource. If an already
+        // loaded process has the same `AppId`, skip this one.
+        let app_id =
+            app_id_policy.assign_app_id(process_name.unwrap_or(""), &tbf_header, credentials);
+        if app_id != AppId::Anonymous
+            && kernel
+                .get_process_iter()
+                .any(|process| process.get_app_id() == app_id)
+        {
+            if config::CONFIG.debug_load_processes {
+                debug!(
+                    "Process has duplicate {:?} flash={:#010X}-{:#010X} process={:?}",
+                    app_id,
+                    app_flash.as_ptr() as usize,
+                    app_flash.as_ptr() as usize + app_flash.len() - 1,
+                    process_name
+                );
+            }
+            return Ok((None, remaining_memory));
+        }
+
         // Otherwise, actually load the app.
         let process_ram_requested_size = tbf_header.get_minimum_app_ram_size() as usize;
         let init_fn = app_flash
@@ -1561,6 +1922,7 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
         process.kernel = kernel;
         process.chip = chip;
         process.allow_high_water_mark = Cell::new(initial_allow_high_water_mark);
+        process.allowed_buffers = Default::default();
         process.memory_start = app_memory.as_ptr();
         process.memory_len = app_memory.len();
         process.header = tbf_header;
@@ -1575,6 +1937,7 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
         process.state = ProcessStateCell::new(process.kernel);
         process.fault_policy = fault_policy;
         process.restart_count = Cell::new(0);
+        process.assigned_priority = Cell::new(None);
 
         process.mpu_config = MapCell::new(mpu_config);
         process.mpu_regions = [
@@ -1587,6 +1950,8 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
         ];
         process.tasks = MapCell::new(tasks);
         process.process_name = process_name.unwrap_or("");
+        process.credentials = credentials;
+        process.app_id = app_id;
 
         process.debug = MapCell::new(ProcessStandardDebug {
             fixed_address_flash: fixed_address_flash,
@@ -1594,10 +1959,17 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
             app_heap_start_pointer: None,
             app_stack_start_pointer: None,
             app_stack_min_pointer: None,
+            app_break_max: initial_app_brk,
+            app_stack_pointer: None,
             syscall_count: 0,
             last_syscall: None,
             dropped_upcall_count: 0,
             timeslice_expiration_count: 0,
+            budget_overrun_count: 0,
+            cpu_time_us: 0,
+            context_switch_count: 0,
+            wakeup_count: 0,
+            watchdog_expiration_count: 0,
         });
 
         let flash_protected_size = process.header.get_protected_size() as usize;
@@ -1614,6 +1986,8 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
             }));
         });
 
+        process.paint_memory();
+
         // Handle any architecture-specific requirements for a new process.
         //
         // NOTE! We have to ensure that the start of process-accessible memory
@@ -1668,8 +2042,10 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
         self.debug.map(|debug| {
             debug.syscall_count = 0;
             debug.last_syscall = None;
+            debug.app_stack_pointer = None;
             debug.dropped_upcall_count = 0;
             debug.timeslice_expiration_count = 0;
+            debug.budget_overrun_count = 0;
         });
 
         // FLASH
@@ -1755,10 +2131,21 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
         // High water mark for `allow`ed memory is reset to the start of the
         // process's memory region.
         self.allow_high_water_mark.set(app_mpu_mem_start);
+        // App slices capsules hold for the old process identifier can no
+        // longer be used, so the restarted process has no allowed buffers.
+        for entry in self.allowed_buffers.iter() {
+            entry.set(None);
+        }
 
         // Drop the old config and use the clean one
         self.mpu_config.replace(mpu_config);
 
+        // The restarted process uses its stack and heap from scratch.
+        self.debug.map(|debug| debug.app_break_max = app_brk);
+        unsafe {
+            self.paint_memory();
+        }
+
         // Handle any architecture-specific requirements for a process when it
         // first starts (as it would when it is new).
         let ukb_init_process = self.stored_state.map_or(Err(()), |stored_state| unsafe {
@@ -1791,6 +2178,10 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
         // Mark that we restarted this process.
         self.restart_count.increment();
 
+        // Have the scheduler assign the restarted process a priority again,
+        // which also returns it to the initial queue of an MLFQ scheduler.
+        self.assigned_priority.set(None);
+
         // Enqueue the initial function.
         self.tasks.map(|tasks| {
             tasks.enqueue(Task::FunctionCall(FunctionCall {
@@ -1809,6 +2200,68 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
         Ok(())
     }
 
+    /// Check that the buffer represented by the passed in base pointer and size
+    /// can be allowed to `slot` without aliasing a buffer a capsule holds.
+    ///
+    /// Read-only buffers may overlap each other, as the kernel can only read
+    /// them. The buffer currently allowed to `slot` does not count, so a
+    /// process can re-allow the same buffer, or extend it, in one call: a
+    /// capsule hands back the buffer it held for the slot when it accepts a
+    /// new one, as the `Driver` allow methods require. Buffers a capsule did
+    /// not hand back are no longer associated with a slot and always count.
+    /// This also ensures there is room to track the new buffer.
+    fn check_allowed_buffer(
+        &self,
+        slot: AllowSlot,
+        buf_start_addr: *const u8,
+        size: usize,
+    ) -> Result<(), ErrorCode> {
+        let buf_end_addr = buf_start_addr.wrapping_add(size);
+        let mut has_free_entry = false;
+        for entry in self.allowed_buffers.iter() {
+            match entry.get() {
+                None => has_free_entry = true,
+                Some(buffer) if buffer.slot == Some(slot) => {}
+                Some(buffer) => {
+                    let overlaps = buf_start_addr < buffer.start.wrapping_add(buffer.size)
+                        && buffer.start < buf_end_addr;
+                    if overlaps && !(slot.is_readonly() && buffer.readonly) {
+                        return Err(ErrorCode::INVAL);
+                    }
+                }
+            }
+        }
+
+        if has_free_entry {
+            Ok(())
+        } else {
+            Err(ErrorCode::NOMEM)
+        }
+    }
+
+    /// Stop tracking a buffer a capsule handed back to the kernel. Buffers no
+    /// longer associated with a slot are removed first, as that is the buffer
+    /// a capsule hands back when a slot is allowed a new buffer.
+    fn remove_allowed_buffer(&self, buf_start_addr: *const u8, size: usize, readonly: bool) {
+        let matches = |buffer: &AllowedBuffer| {
+            buffer.start == buf_start_addr && buffer.size == size && buffer.readonly == readonly
+        };
+        let entry = self
+            .allowed_buffers
+            .iter()
+            .find(|entry| {
+                entry
+                    .get()
+                    .map_or(false, |b| b.slot.is_none() && matches(&b))
+            })
+            .or_else(|| {
+                self.allowed_buffers
+                    .iter()
+                    .find(|entry| entry.get().map_or(false, |b| matches(&b)))
+            });
+        entry.map(|entry| entry.set(None));
+    }
+
     /// Checks if the buffer represented by the passed in base pointer and size
     /// is within the RAM bounds currently exposed to the processes (i.e.
     /// ending at `app_break`). If this method returns `true`, the buffer
@@ -1834,6 +2287,25 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
             && buf_end_addr <= self.flash_end()
     }
 
+    /// Fill the memory the process can grow into, from the start of process
+    /// memory up to the kernel memory break, with `STACK_PAINT`, so that
+    /// `debug_stack_high_water_mark()` can find how much of its stack the
+    /// process has used. Does nothing unless `paint_process_stacks` is
+    /// enabled in the kernel configuration.
+    ///
+    /// This must only be called while the process is not running, and before
+    /// the architecture-specific code initializes the process state.
+    // The start of process memory is word aligned, as it is for
+    // `grant_ptrs_reset()`.
+    unsafe fn paint_memory(&self) {
+        if config::CONFIG.paint_process_stacks {
+            paint(
+                self.mem_start() as usize,
+                self.kernel_memory_break.get() as usize,
+            );
+        }
+    }
+
     /// Reset all `grant_ptr`s to NULL.
     // This is safe today, as MPU constraints ensure that `mem_end` will always
     // be aligned on at least a word boundary. While this is unlikely to
@@ -1950,6 +2422,812 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
     /// explicitly exits.
     fn is_active(&self) -> bool {
         let current_state = self.state.get();
-        current_state != State::Terminated && current_state != State::Faulted
+        current_state != State::Terminated
+            && current_state != State::Faulted
+            && current_state != State::WaitingToRestart
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    extern crate std;
+
+    use core::cell::{Cell, RefCell};
+    use core::fmt::Write;
+    use std::boxed::Box;
+    use std::vec;
+    use std::vec::Vec;
+
+    use crate::capabilities::ProcessManagementCapability;
+    use crate::errorcode::ErrorCode;
+    use crate::platform::{Chip, SyscallFilter};
+    use crate::platform::{TbfHeaderFilterDefaultAllow, TbfHeaderFilterDefaultDeny};
+    use crate::process::{AllowSlot, AppId, FunctionCall, Process, ProcessId, State};
+    use crate::process_checker::{CredentialsStatus, NullCredentialsChecker};
+    use crate::process_policies::{
+        assigned_priority, AnonymousAppIdPolicy, AppIdPolicy, BackoffRestartFaultPolicy,
+        PriorityLimit, PriorityPolicy, ProcessFaultPolicy, StopFaultPolicy, TbfHeaderAppIdPolicy,
+        TbfHeaderPriorityPolicy,
+    };
+    use crate::process_restart::DelayedRestart;
+    use crate::sched::Kernel;
+    use crate::syscall::UserspaceKernelBoundary;
+    use crate::syscall::{ContextSwitchReason, Syscall, SyscallClass, SyscallReturn};
+    use tock_tbf::types::TbfHeader;
+
+    use super::{paint, painted_stack_depth};
+    use super::{ProcessStandard, COMPLETION_FAULT, MAX_ALLOWED_BUFFERS};
+
+    // Process-accessible memory the process starts with.
+    const APP_MEMORY_SIZE: usize = 512;
+
+    pub(crate) struct MockUserspaceKernelBoundary {}
+
+    impl UserspaceKernelBoundary for MockUserspaceKernelBoundary {
+        type StoredState = ();
+
+        fn initial_process_app_brk_size(&self) -> usize {
+            APP_MEMORY_SIZE
+        }
+
+        unsafe fn initialize_process(
+            &self,
+            _accessible_memory_start: *const u8,
+            _app_brk: *const u8,
+            _state: &mut (),
+        ) -> Result<(), ()> {
+            Ok(())
+        }
+
+        unsafe fn set_syscall_return_value(
+            &self,
+            _accessible_memory_start: *const u8,
+            _app_brk: *const u8,
+            _state: &mut (),
+            _return_value: SyscallReturn,
+        ) -> Result<(), ()> {
+            Ok(())
+        }
+
+        unsafe fn set_process_function(
+            &self,
+            _accessible_memory_start: *const u8,
+            _app_brk: *const u8,
+            _state: &mut (),
+            _upcall: FunctionCall,
+        ) -> Result<(), ()> {
+            Ok(())
+        }
+
+        unsafe fn switch_to_process(
+            &self,
+            _accessible_memory_start: *const u8,
+            _app_brk: *const u8,
+            _state: &mut (),
+        ) -> (ContextSwitchReason, Option<*const u8>) {
+            (ContextSwitchReason::Interrupted, None)
+        }
+
+        unsafe fn print_context(
+            &self,
+            _accessible_memory_start: *const u8,
+            _app_brk: *const u8,
+            _state: &(),
+            _writer: &mut dyn Write,
+        ) {
+        }
+
+        fn store_context(&self, _state: &(), _out: &mut [u8]) -> Result<usize, ErrorCode> {
+            Ok(0)
+        }
+    }
+
+    pub(crate) struct MockChip {
+        pub(crate) userspace_kernel_boundary: MockUserspaceKernelBoundary,
+    }
+
+    impl Chip for MockChip {
+        type MPU = ();
+        type UserspaceKernelBoundary = MockUserspaceKernelBoundary;
+        type SchedulerTimer = ();
+        type WatchDog = ();
+
+        fn service_pending_interrupts(&self) {}
+
+        fn has_pending_interrupts(&self) -> bool {
+            false
+        }
+
+        fn mpu(&self) -> &() {
+            &()
+        }
+
+        fn scheduler_timer(&self) -> &() {
+            &()
+        }
+
+        fn watchdog(&self) -> &() {
+            &()
+        }
+
+        fn userspace_kernel_boundary(&self) -> &MockUserspaceKernelBoundary {
+            &self.userspace_kernel_boundary
+        }
+
+        fn sleep(&self) {}
+
+        unsafe fn atomic<F, R>(&self, f: F) -> R
+        where
+            F: FnOnce() -> R,
+        {
+            f()
+        }
+
+        unsafe fn print_state(&self, _writer: &mut dyn Write) {}
+    }
+
+    /// Create a TBF with a Main TLV and no code.
+    fn tbf() -> &'static [u8] {
+        tbf_with_tlvs(&[])
+    }
+
+    /// Create a TBF with a Main TLV, followed by the words of `tlvs`, and no
+    /// code.
+    fn tbf_with_tlvs(tlvs: &[u32]) -> &'static [u8] {
+        tbf_with_minimum_ram(0, tlvs)
+    }
+
+    /// Create a TBF with a Main TLV that requests `minimum_ram` bytes of
+    /// memory, followed by the words of `tlvs`, and no code.
+    pub(crate) fn tbf_with_minimum_ram(minimum_ram: u32, tlvs: &[u32]) -> &'static [u8] {
+        let header_size = 32 + tlvs.len() * 4;
+        let total_size = header_size + 32;
+        let mut words: Vec<u32> = vec![
+            // Version 2 and header size.
+            2 | (header_size as u32) << 16,
+            total_size as u32,
+            // Flags: enabled.
+            1,
+            // Checksum, filled in below.
+            0,
+            // Main TLV: type 1 and length 12.
+            1 | 12 << 16,
+            // Init function offset, protected size and minimum RAM size.
+            header_size as u32,
+            0,
+            minimum_ram,
+        ];
+        words.extend_from_slice(tlvs);
+        let checksum = words.iter().fold(0, |checksum, word| checksum ^ word);
+
+        let flash = Box::leak(vec![0u8; total_size].into_boxed_slice());
+        for (i, word) in words.iter().enumerate() {
+            let word = if i == 3 { checksum } else { *word };
+            flash[i * 4..(i + 1) * 4].copy_from_slice(&word.to_le_bytes());
+        }
+        flash
+    }
+
+    /// Load a process, returning it and the start of its memory.
+    fn create_process() -> (&'static dyn Process, *const u8) {
+        let kernel: &'static Kernel = Box::leak(Box::new(Kernel::new(&[])));
+        create_process_with_policy(kernel, &StopFaultPolicy {})
+    }
+
+    /// Load a process of `kernel` that faults according to `fault_policy`.
+    fn create_process_with_policy(
+        kernel: &'static Kernel,
+        fault_policy: &'static dyn ProcessFaultPolicy,
+    ) -> (&'static dyn Process, *const u8) {
+        create_process_from_tbf(kernel, fault_policy, tbf())
+    }
+
+    /// Load the process in `tbf` for `kernel`.
+    fn create_process_from_tbf(
+        kernel: &'static Kernel,
+        fault_policy: &'static dyn ProcessFaultPolicy,
+        tbf: &'static [u8],
+    ) -> (&'static dyn Process, *const u8) {
+        create_process_with_app_id_policy(kernel, fault_policy, &AnonymousAppIdPolicy {}, tbf)
+    }
+
+    fn create_process_with_app_id_policy(
+        kernel: &'static Kernel,
+        fault_policy: &'static dyn ProcessFaultPolicy,
+        app_id_policy: &dyn AppIdPolicy,
+        tbf: &'static [u8],
+    ) -> (&'static dyn Process, *const u8) {
+        let chip: &'static MockChip = Box::leak(Box::new(MockChip {
+            userspace_kernel_boundary: MockUserspaceKernelBoundary {},
+        }));
+        // Word-aligned memory, as an MPU would allocate.
+        let memory: &'static mut [u64] = Box::leak(vec![0u64; 1024].into_boxed_slice());
+        let memory = unsafe {
+            core::slice::from_raw_parts_mut(memory.as_mut_ptr() as *mut u8, memory.len() * 8)
+        };
+
+        let (process, _) = unsafe {
+            ProcessStandard::<MockChip>::create(
+                kernel,
+                chip,
+                tbf,
+                u16::from_le_bytes([tbf[2], tbf[3]]) as usize,
+                2,
+                memory,
+                fault_policy,
+                &NullCredentialsChecker {},
+                app_id_policy,
+                0,
+            )
+        }
+        .ok()
+        .unwrap();
+        let process = process.unwrap();
+        (process, process.mem_start())
+    }
+
+    fn slot(class: SyscallClass, subdriver_number: usize) -> AllowSlot {
+        AllowSlot {
+            class,
+            driver_number: 1,
+            subdriver_number,
+        }
+    }
+
+    fn rw(subdriver_number: usize) -> AllowSlot {
+        slot(SyscallClass::ReadWriteAllow, subdriver_number)
+    }
+
+    fn ro(subdriver_number: usize) -> AllowSlot {
+        slot(SyscallClass::ReadOnlyAllow, subdriver_number)
+    }
+
+    /// Allow `buffer` to `slot`, as the scheduler does for a capsule that
+    /// accepts the buffer and hands back `returned`.
+    fn allow(
+        process: &dyn Process,
+        slot: AllowSlot,
+        buffer: (*const u8, usize),
+        returned: (*const u8, usize),
+    ) -> Result<(), ErrorCode> {
+        if slot.is_readonly() {
+            process
+                .build_readonly_appslice(slot, buffer.0, buffer.1)
+                .map(|_| ())?;
+        } else {
+            process
+                .build_readwrite_appslice(slot, buffer.0 as *mut u8, buffer.1)
+                .map(|_| ())?;
+        }
+        process.allow_complete(slot, buffer, returned, true);
+        Ok(())
+    }
+
+    fn buffer(memory: *const u8, offset: usize, len: usize) -> (*const u8, usize) {
+        (memory.wrapping_add(offset), len)
+    }
+
+    const NONE: (*const u8, usize) = (core::ptr::null(), 0);
+
+    #[test]
+    fn test_overlapping_readwrite_rejected() {
+        let (process, memory) = create_process();
+
+        assert_eq!(allow(process, rw(0), buffer(memory, 0, 16), NONE), Ok(()));
+        assert_eq!(
+            allow(process, rw(1), buffer(memory, 8, 16), NONE),
+            Err(ErrorCode::INVAL)
+        );
+        assert_eq!(
+            allow(process, rw(1), buffer(memory, 15, 1), NONE),
+            Err(ErrorCode::INVAL)
+        );
+        // A buffer containing the allowed buffer also overlaps it.
+        assert_eq!(
+            allow(process, rw(1), buffer(memory, 0, 64), NONE),
+            Err(ErrorCode::INVAL)
+        );
+        // Adjacent buffers do not overlap.
+        assert_eq!(allow(process, rw(1), buffer(memory, 16, 16), NONE), Ok(()));
+    }
+
+    #[test]
+    fn test_same_slot_reallow() {
+        let (process, memory) = create_process();
+
+        assert_eq!(allow(process, rw(0), buffer(memory, 0, 16), NONE), Ok(()));
+        // The buffer the slot holds is swapped for the new one, so the same
+        // or an overlapping buffer can be allowed to the slot.
+        assert_eq!(
+            allow(process, rw(0), buffer(memory, 0, 16), buffer(memory, 0, 16)),
+            Ok(())
+        );
+        assert_eq!(
+            allow(process, rw(0), buffer(memory, 8, 16), buffer(memory, 0, 16)),
+            Ok(())
+        );
+        // The old buffer was handed back, the new one is still held.
+        assert_eq!(allow(process, rw(1), buffer(memory, 0, 8), NONE), Ok(()));
+        assert_eq!(
+            allow(process, rw(1), buffer(memory, 16, 8), buffer(memory, 0, 8)),
+            Err(ErrorCode::INVAL)
+        );
+        // Other slots still can't alias the buffer.
+        assert_eq!(
+            allow(process, ro(0), buffer(memory, 8, 16), NONE),
+            Err(ErrorCode::INVAL)
+        );
+    }
+
+    #[test]
+    fn test_same_slot_reallow_after_unallow() {
+        let (process, memory) = create_process();
+
+        assert_eq!(allow(process, rw(0), buffer(memory, 0, 16), NONE), Ok(()));
+        // Once the capsule handed the buffer back, an overlapping buffer can
+        // be allowed to the slot.
+        assert_eq!(
+            allow(process, rw(0), buffer(memory, 0, 0), buffer(memory, 0, 16)),
+            Ok(())
+        );
+        assert_eq!(allow(process, rw(0), buffer(memory, 8, 16), NONE), Ok(()));
+    }
+
+    #[test]
+    fn test_unallow_releases_buffer() {
+        let (process, memory) = create_process();
+
+        assert_eq!(allow(process, rw(0), buffer(memory, 0, 16), NONE), Ok(()));
+        // Allowing a zero-length buffer hands back the buffer.
+        assert_eq!(
+            allow(process, rw(0), buffer(memory, 0, 0), buffer(memory, 0, 16)),
+            Ok(())
+        );
+        assert_eq!(allow(process, rw(1), buffer(memory, 0, 16), NONE), Ok(()));
+    }
+
+    #[test]
+    fn test_readonly_overlap() {
+        let (process, memory) = create_process();
+
+        // Read-only buffers can overlap each other.
+        assert_eq!(allow(process, ro(0), buffer(memory, 0, 16), NONE), Ok(()));
+        assert_eq!(allow(process, ro(1), buffer(memory, 8, 16), NONE), Ok(()));
+
+        // But not a buffer the kernel can write, in either order.
+        assert_eq!(
+            allow(process, rw(0), buffer(memory, 4, 8), NONE),
+            Err(ErrorCode::INVAL)
+        );
+        assert_eq!(allow(process, rw(0), buffer(memory, 32, 8), NONE), Ok(()));
+        assert_eq!(
+            allow(process, ro(2), buffer(memory, 36, 8), NONE),
+            Err(ErrorCode::INVAL)
+        );
+    }
+
+    #[test]
+    fn test_allow_classes_are_separate_slots() {
+        let (process, memory) = create_process();
+        let userspace_readable = slot(SyscallClass::UserspaceReadableAllow, 0);
+
+        assert_eq!(allow(process, rw(0), buffer(memory, 0, 16), NONE), Ok(()));
+        // The same buffer identifier in another allow class is a different
+        // slot, and userspace readable buffers are writeable by the kernel.
+        assert_eq!(
+            allow(process, userspace_readable, buffer(memory, 0, 16), NONE),
+            Err(ErrorCode::INVAL)
+        );
+        assert_eq!(
+            allow(process, ro(0), buffer(memory, 0, 16), NONE),
+            Err(ErrorCode::INVAL)
+        );
+    }
+
+    #[test]
+    fn test_buffer_kept_by_capsule() {
+        let (process, memory) = create_process();
+
+        assert_eq!(allow(process, rw(0), buffer(memory, 0, 16), NONE), Ok(()));
+        // The capsule keeps the old buffer and hands back nothing.
+        assert_eq!(allow(process, rw(0), buffer(memory, 32, 16), NONE), Ok(()));
+
+        // The old buffer may still be in use, even from the same slot.
+        assert_eq!(
+            allow(process, rw(1), buffer(memory, 0, 16), NONE),
+            Err(ErrorCode::INVAL)
+        );
+        assert_eq!(
+            allow(
+                process,
+                rw(0),
+                buffer(memory, 0, 16),
+                buffer(memory, 32, 16)
+            ),
+            Err(ErrorCode::INVAL)
+        );
+    }
+
+    #[test]
+    fn test_rejected_allow_not_tracked() {
+        let (process, memory) = create_process();
+
+        let new = buffer(memory, 0, 16);
+        assert!(process
+            .build_readwrite_appslice(rw(0), new.0 as *mut u8, new.1)
+            .is_ok());
+        // The capsule rejects the buffer and hands it back.
+        process.allow_complete(rw(0), new, new, false);
+
+        assert_eq!(allow(process, rw(1), buffer(memory, 0, 16), NONE), Ok(()));
+    }
+
+    #[test]
+    fn test_too_many_buffers() {
+        let (process, memory) = create_process();
+
+        for i in 0..MAX_ALLOWED_BUFFERS {
+            assert_eq!(
+                allow(process, rw(i), buffer(memory, i * 4, 4), NONE),
+                Ok(())
+            );
+        }
+        assert_eq!(
+            allow(
+                process,
+                rw(MAX_ALLOWED_BUFFERS),
+                buffer(memory, MAX_ALLOWED_BUFFERS * 4, 4),
+                NONE
+            ),
+            Err(ErrorCode::NOMEM)
+        );
+        // Zero-length buffers do not need to be tracked.
+        assert_eq!(
+            allow(process, rw(MAX_ALLOWED_BUFFERS), buffer(memory, 0, 0), NONE),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_restart_releases_buffers() {
+        let (process, memory) = create_process();
+
+        assert_eq!(allow(process, rw(0), buffer(memory, 0, 16), NONE), Ok(()));
+        process.try_restart(0);
+        assert_eq!(allow(process, rw(1), buffer(memory, 0, 16), NONE), Ok(()));
+    }
+
+    #[test]
+    fn test_stack_high_water_mark() {
+        let (process, memory) = create_process();
+        assert_eq!(process.debug_stack_high_water_mark(), None);
+
+        process.update_stack_start_pointer(memory.wrapping_add(256));
+        assert_eq!(process.debug_stack_high_water_mark(), Some(0));
+    }
+
+    #[test]
+    fn test_painted_stack_depth() {
+        let mut memory = vec![0u32; 64];
+        let start = memory.as_mut_ptr() as usize;
+        let stack_start = start + 256;
+        unsafe {
+            paint(start, stack_start);
+            assert_eq!(painted_stack_depth(start, stack_start, stack_start), 0);
+        }
+
+        // The stack grew 56 bytes.
+        for word in memory[50..].iter_mut() {
+            *word = 0;
+        }
+        assert_eq!(
+            unsafe { painted_stack_depth(start, stack_start, stack_start) },
+            56
+        );
+
+        // Painting the memory again resets the depth.
+        unsafe {
+            paint(start, stack_start);
+            assert_eq!(painted_stack_depth(start, stack_start, stack_start), 0);
+        }
+    }
+
+    #[test]
+    fn test_heap_high_water_mark() {
+        let (process, memory) = create_process();
+        assert_eq!(process.debug_heap_high_water_mark(), None);
+
+        process.update_heap_start_pointer(memory.wrapping_add(100));
+        assert_eq!(
+            process.debug_heap_high_water_mark(),
+            Some(APP_MEMORY_SIZE - 100)
+        );
+
+        // Shrinking the heap does not lower the mark.
+        assert_eq!(process.sbrk(-200).map(|_| ()), Ok(()));
+        assert_eq!(process.sbrk(100).map(|_| ()), Ok(()));
+        assert_eq!(
+            process.debug_heap_high_water_mark(),
+            Some(APP_MEMORY_SIZE - 100)
+        );
+    }
+
+    struct Capability;
+    unsafe impl ProcessManagementCapability for Capability {}
+
+    const BACKOFF_POLICY: BackoffRestartFaultPolicy = BackoffRestartFaultPolicy::new(100, 300);
+
+    /// Records the delayed restarts the kernel asks for, without restarting.
+    struct MockDelayedRestart {
+        restarts: RefCell<Vec<(ProcessId, u32)>>,
+    }
+
+    impl DelayedRestart for MockDelayedRestart {
+        fn restart_after(&self, processid: ProcessId, delay_ms: u32) -> Result<(), ErrorCode> {
+            self.restarts.borrow_mut().push((processid, delay_ms));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_delayed_restart_waits() {
+        let kernel: &'static Kernel = Box::leak(Box::new(Kernel::new(&[])));
+        let timer: &'static MockDelayedRestart = Box::leak(Box::new(MockDelayedRestart {
+            restarts: RefCell::new(Vec::new()),
+        }));
+        kernel.set_delayed_restart(timer, &Capability);
+        let (process, _) = create_process_with_policy(kernel, &BACKOFF_POLICY);
+
+        let processid = process.processid();
+        process.set_fault_state();
+        assert_eq!(process.get_state(), State::WaitingToRestart);
+        assert_eq!(process.get_restart_count(), 0);
+        assert!(!process.ready());
+        assert_eq!(timer.restarts.borrow_mut().pop(), Some((processid, 100)));
+
+        // The delay doubles with every restart, up to the maximum.
+        for delay in [200, 300, 300] {
+            process.try_restart(COMPLETION_FAULT);
+            process.set_fault_state();
+            assert_eq!(
+                timer.restarts.borrow_mut().pop(),
+                Some((process.processid(), delay))
+            );
+        }
+    }
+
+    #[test]
+    fn test_delayed_restart_without_timer() {
+        let kernel: &'static Kernel = Box::leak(Box::new(Kernel::new(&[])));
+        let (process, _) = create_process_with_policy(kernel, &BACKOFF_POLICY);
+
+        process.set_fault_state();
+        assert_eq!(process.get_state(), State::Unstarted);
+        assert_eq!(process.get_restart_count(), 1);
+    }
+
+    /// Gives every named process a persistent `AppId` made of the bytes of its
+    /// name, whether or not it was verified, since the test processes carry no
+    /// credentials.
+    struct NameAppIdPolicy {}
+
+    impl AppIdPolicy for NameAppIdPolicy {
+        fn assign_app_id(&self, name: &'static str, _: &TbfHeader, _: CredentialsStatus) -> AppId {
+            match name.bytes().fold(0, |id, byte| id << 8 | byte as u32) {
+                0 => AppId::Anonymous,
+                id => AppId::Persistent(id),
+            }
+        }
+    }
+
+    /// Load a process named `name` that requests `priority` in its header.
+    fn create_process_with_priority(name: &[u8; 4], priority: Option<u32>) -> &'static dyn Process {
+        let kernel: &'static Kernel = Box::leak(Box::new(Kernel::new(&[])));
+        // Package name TLV: type 3, padded to a word.
+        let name_len = name.iter().position(|&c| c == 0).unwrap_or(4) as u32;
+        let mut tlvs = vec![3 | name_len << 16, u32::from_le_bytes(*name)];
+        if let Some(priority) = priority {
+            // Priority TLV: type 12 and length 4.
+            tlvs.extend_from_slice(&[12 | 4 << 16, priority]);
+        }
+        create_process_with_app_id_policy(
+            kernel,
+            &StopFaultPolicy {},
+            &NameAppIdPolicy {},
+            tbf_with_tlvs(&tlvs),
+        )
+        .0
+    }
+
+    #[test]
+    fn test_unverified_process_is_anonymous() {
+        let kernel: &'static Kernel = Box::leak(Box::new(Kernel::new(&[])));
+        // Package name TLV "app" and App ID TLV 7.
+        let tlvs = [3 | 3 << 16, u32::from_le_bytes(*b"app\0"), 10 | 4 << 16, 7];
+        let (process, _) = create_process_with_app_id_policy(
+            kernel,
+            &StopFaultPolicy {},
+            &TbfHeaderAppIdPolicy {},
+            tbf_with_tlvs(&tlvs),
+        );
+        assert_eq!(process.get_process_name(), "app");
+        assert_eq!(
+            process.get_credentials_status(),
+            CredentialsStatus::Unverified
+        );
+        assert_eq!(process.get_app_id(), AppId::Anonymous);
+    }
+
+    #[test]
+    fn test_priority_policy_limits_requests() {
+        let safety = create_process_with_priority(b"safe", Some(0));
+        let ui = create_process_with_priority(b"ui\0\0", Some(0));
+        let logger = create_process_with_priority(b"log\0", None);
+        assert_eq!(safety.get_priority(), Some(0));
+        assert_eq!(logger.get_priority(), None);
+
+        let safety_id = match safety.get_app_id() {
+            AppId::Persistent(id) => id,
+            AppId::Anonymous => panic!("named process is anonymous"),
+        };
+        let limits = Box::leak(Box::new([PriorityLimit {
+            app_id: safety_id,
+            highest: 0,
+        }]));
+        let policy = TbfHeaderPriorityPolicy::new(5, 2, limits);
+        assert_eq!(policy.priority(safety), 0);
+        assert_eq!(policy.priority(ui), 2);
+        assert_eq!(policy.priority(logger), 5);
+    }
+
+    #[test]
+    fn test_assigned_priority_is_cached_until_restart() {
+        struct CountingPolicy(Cell<u32>);
+        impl PriorityPolicy for CountingPolicy {
+            fn priority(&self, _: &dyn Process) -> u32 {
+                self.0.set(self.0.get() + 1);
+                self.0.get()
+            }
+        }
+
+        let process = create_process_with_priority(b"app\0", None);
+        let policy = CountingPolicy(Cell::new(0));
+        assert_eq!(process.get_assigned_priority(), None);
+        assert_eq!(assigned_priority(&policy, process), 1);
+        assert_eq!(assigned_priority(&policy, process), 1);
+        assert_eq!(process.get_assigned_priority(), Some(1));
+
+        process.try_restart(COMPLETION_FAULT);
+        assert_eq!(process.get_assigned_priority(), None);
+        assert_eq!(assigned_priority(&policy, process), 2);
+    }
+
+    /// Load a process whose header grants the `(driver_number, offset,
+    /// allowed_commands)` permissions in `permissions`, or that has no
+    /// permissions TLV if `permissions` is `None`.
+    fn create_process_with_permissions(
+        permissions: Option<&[(u32, u32, u64)]>,
+    ) -> &'static dyn Process {
+        let kernel: &'static Kernel = Box::leak(Box::new(Kernel::new(&[])));
+        let mut tlvs = Vec::new();
+        if let Some(permissions) = permissions {
+            // Permissions TLV: type 6 and 16 bytes per driver.
+            tlvs.push(6 | (16 * permissions.len() as u32) << 16);
+            for &(driver_number, offset, allowed_commands) in permissions {
+                tlvs.extend_from_slice(&[
+                    driver_number,
+                    offset,
+                    allowed_commands as u32,
+                    (allowed_commands >> 32) as u32,
+                ]);
+            }
+        }
+        create_process_from_tbf(kernel, &StopFaultPolicy {}, tbf_with_tlvs(&tlvs)).0
+    }
+
+    fn command(driver_number: usize, subdriver_number: usize) -> Syscall {
+        Syscall::Command {
+            driver_number,
+            subdriver_number,
+            arg0: 0,
+            arg1: 0,
+        }
+    }
+
+    fn subscribe(driver_number: usize) -> Syscall {
+        Syscall::Subscribe {
+            driver_number,
+            subdriver_number: 0,
+            upcall_ptr: core::ptr::null_mut(),
+            appdata: 0,
+        }
+    }
+
+    fn read_only_allow(driver_number: usize) -> Syscall {
+        Syscall::ReadOnlyAllow {
+            driver_number,
+            subdriver_number: 0,
+            allow_address: core::ptr::null(),
+            allow_size: 0,
+        }
+    }
+
+    #[test]
+    fn test_tbf_header_filter_checks_permissions() {
+        // Commands 1 and 65 of driver 0x10, and nothing of other drivers.
+        let process =
+            create_process_with_permissions(Some(&[(0x10, 0, 1 << 1), (0x10, 1, 1 << 1)]));
+        let filters: [&dyn SyscallFilter; 2] = [
+            &TbfHeaderFilterDefaultAllow {},
+            &TbfHeaderFilterDefaultDeny {},
+        ];
+        for filter in filters.iter() {
+            assert_eq!(filter.filter_syscall(process, &command(0x10, 1)), Ok(()));
+            assert_eq!(filter.filter_syscall(process, &command(0x10, 65)), Ok(()));
+            assert_eq!(
+                filter.filter_syscall(process, &command(0x10, 2)),
+                Err(ErrorCode::NODEVICE)
+            );
+            assert_eq!(
+                filter.filter_syscall(process, &command(0x10, 129)),
+                Err(ErrorCode::NODEVICE)
+            );
+            assert_eq!(
+                filter.filter_syscall(process, &command(0x20, 1)),
+                Err(ErrorCode::NODEVICE)
+            );
+
+            // Subscribe and allow only need the driver to be listed.
+            assert_eq!(filter.filter_syscall(process, &subscribe(0x10)), Ok(()));
+            assert_eq!(
+                filter.filter_syscall(process, &read_only_allow(0x10)),
+                Ok(())
+            );
+            assert_eq!(
+                filter.filter_syscall(process, &subscribe(0x20)),
+                Err(ErrorCode::NODEVICE)
+            );
+            assert_eq!(
+                filter.filter_syscall(process, &read_only_allow(0x20)),
+                Err(ErrorCode::NODEVICE)
+            );
+
+            // Syscalls that do not name a driver are not filtered.
+            let memop = Syscall::Memop {
+                operand: 0,
+                arg0: 0,
+            };
+            assert_eq!(filter.filter_syscall(process, &memop), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_tbf_header_filter_default_without_permissions() {
+        let process = create_process_with_permissions(None);
+        assert_eq!(
+            TbfHeaderFilterDefaultAllow {}.filter_syscall(process, &command(0x10, 1)),
+            Ok(())
+        );
+        assert_eq!(
+            TbfHeaderFilterDefaultAllow {}.filter_syscall(process, &subscribe(0x10)),
+            Ok(())
+        );
+        assert_eq!(
+            TbfHeaderFilterDefaultDeny {}.filter_syscall(process, &command(0x10, 1)),
+            Err(ErrorCode::NODEVICE)
+        );
+        assert_eq!(
+            TbfHeaderFilterDefaultDeny {}.filter_syscall(process, &subscribe(0x10)),
+            Err(ErrorCode::NODEVICE)
+        );
+
+        let yield_call = Syscall::Yield {
+            which: 1,
+            address: core::ptr::null_mut(),
+        };
+        assert_eq!(
+            TbfHeaderFilterDefaultDeny {}.filter_syscall(process, &yield_call),
+            Ok(())
+        );
     }
 }
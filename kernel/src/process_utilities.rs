@@ -1,14 +1,17 @@
 //! Helper functions related to Tock processes.
 
+use core::cell::Cell;
 use core::convert::TryInto;
 use core::fmt;
 
 use crate::capabilities::ProcessManagementCapability;
 use crate::config;
 use crate::debug;
+use crate::errorcode::ErrorCode;
 use crate::platform::Chip;
-use crate::process::Process;
-use crate::process_policies::ProcessFaultPolicy;
+use crate::process::{Process, ProcessId, State};
+use crate::process_checker::{AppCredentialsChecker, NullCredentialsChecker};
+use crate::process_policies::{AnonymousAppIdPolicy, AppIdPolicy, ProcessFaultPolicy};
 use crate::process_standard::ProcessStandard;
 use crate::sched::Kernel;
 
@@ -45,6 +48,13 @@ pub enum ProcessLoadError {
         expected_address: u32,
     },
 
+    /// There is no valid TBF where a process was expected to be loaded from.
+    NoValidTbf,
+
+    /// The TBF a process was to be loaded from is already used by a loaded
+    /// process.
+    AlreadyLoaded,
+
     /// Process loading error due (likely) to a bug in the kernel. If you get
     /// this error please open a bug report.
     InternalError,
@@ -98,6 +108,10 @@ impl fmt::Debug for ProcessLoadError {
                 actual_address, expected_address
             ),
 
+            ProcessLoadError::NoValidTbf => write!(f, "No valid TBF found"),
+
+            ProcessLoadError::AlreadyLoaded => write!(f, "Process is already loaded"),
+
             ProcessLoadError::InternalError => write!(f, "Error in kernel. Likely a bug."),
         }
     }
@@ -114,7 +128,7 @@ impl fmt::Debug for ProcessLoadError {
 /// ensuring that this code cannot hold onto the slice past the end of this function
 /// (instead, processes store a pointer and length), which necessary for later
 /// creation of `AppSlice`'s in this memory region to be sound.
-/// A reference to each process is stored in the provided `procs` array.
+/// A reference to each process is stored in the processes array of `kernel`.
 /// How process faults are handled by the
 /// kernel must be provided and is assigned to every created process.
 ///
@@ -130,10 +144,67 @@ pub fn load_processes<C: Chip>(
     chip: &'static C,
     app_flash: &'static [u8],
     app_memory: &mut [u8], // not static, so that process.rs cannot hold on to slice w/o unsafe
-    procs: &'static mut [Option<&'static dyn Process>],
     fault_policy: &'static dyn ProcessFaultPolicy,
-    _capability: &dyn ProcessManagementCapability,
+    capability: &dyn ProcessManagementCapability,
+) -> Result<(), ProcessLoadError> {
+    load_and_check_processes(
+        kernel,
+        chip,
+        app_flash,
+        app_memory,
+        fault_policy,
+        &NullCredentialsChecker {},
+        &AnonymousAppIdPolicy {},
+        capability,
+    )
+}
+
+/// Helper function to load processes from flash into an array of active
+/// processes, only running the processes whose credentials are accepted and
+/// whose identity is unique.
+///
+/// This works exactly like `load_processes()`, except that before each enabled
+/// process is created its credentials footers are passed to
+/// `credentials_checker`, and `app_id_policy` assigns it a persistent `AppId`.
+/// Processes the checker rejects, and processes with the same `AppId` as an
+/// already loaded process, are skipped. The outcome of the check and the
+/// `AppId` are recorded in every created process (see
+/// `Process::get_credentials_status()` and `Process::get_app_id()`).
+pub fn load_and_check_processes<C: Chip>(
+    kernel: &'static Kernel,
+    chip: &'static C,
+    app_flash: &'static [u8],
+    app_memory: &mut [u8], // not static, so that process.rs cannot hold on to slice w/o unsafe
+    fault_policy: &'static dyn ProcessFaultPolicy,
+    credentials_checker: &dyn AppCredentialsChecker,
+    app_id_policy: &dyn AppIdPolicy,
+    capability: &dyn ProcessManagementCapability,
 ) -> Result<(), ProcessLoadError> {
+    load_processes_from_flash(
+        kernel,
+        chip,
+        app_flash,
+        app_memory,
+        fault_policy,
+        credentials_checker,
+        app_id_policy,
+        capability,
+    )
+    .map(|_| ())
+}
+
+/// Load processes from `app_flash` into the processes array of `kernel`,
+/// returning the process memory that was not given to any process.
+fn load_processes_from_flash<'a, C: Chip>(
+    kernel: &'static Kernel,
+    chip: &'static C,
+    app_flash: &'static [u8],
+    app_memory: &'a mut [u8],
+    fault_policy: &'static dyn ProcessFaultPolicy,
+    credentials_checker: &dyn AppCredentialsChecker,
+    app_id_policy: &dyn AppIdPolicy,
+    capability: &dyn ProcessManagementCapability,
+) -> Result<&'a mut [u8], ProcessLoadError> {
     if config::CONFIG.debug_load_processes {
         debug!(
             "Loading processes from flash={:#010X}-{:#010X} into sram={:#010X}-{:#010X}",
@@ -147,50 +218,16 @@ pub fn load_processes<C: Chip>(
     let mut remaining_flash = app_flash;
     let mut remaining_memory = app_memory;
 
-    // Try to discover up to `procs.len()` processes in flash.
-    for i in 0..procs.len() {
-        // Get the first eight bytes of flash to check if there is another
-        // app.
-        let test_header_slice = match remaining_flash.get(0..8) {
-            Some(s) => s,
+    // Try to discover as many processes in flash as the processes array holds.
+    for i in 0..kernel.process_slot_count() {
+        let (version, header_length, entry_flash) = match find_tbf_entry(remaining_flash)? {
+            Some(entry) => entry,
             None => {
-                // Not enough flash to test for another app. This just means
-                // we are at the end of flash, and there are no more apps to
-                // load.
-                return Ok(());
+                // There are no more apps to load.
+                break;
             }
         };
 
-        // Pass the first eight bytes to tbfheader to parse out the length of
-        // the tbf header and app. We then use those values to see if we have
-        // enough flash remaining to parse the remainder of the header.
-        let (version, header_length, entry_length) = match tock_tbf::parse::parse_tbf_header_lengths(
-            test_header_slice
-                .try_into()
-                .or(Err(ProcessLoadError::InternalError))?,
-        ) {
-            Ok((v, hl, el)) => (v, hl, el),
-            Err(tock_tbf::types::InitialTbfParseError::InvalidHeader(entry_length)) => {
-                // If we could not parse the header, then we want to skip over
-                // this app and look for the next one.
-                (0, 0, entry_length)
-            }
-            Err(tock_tbf::types::InitialTbfParseError::UnableToParse) => {
-                // Since Tock apps use a linked list, it is very possible the
-                // header we started to parse is intentionally invalid to signal
-                // the end of apps. This is ok and just means we have finished
-                // loading apps.
-                return Ok(());
-            }
-        };
-
-        // Now we can get a slice which only encompasses the length of flash
-        // described by this tbf header.  We will either parse this as an actual
-        // app, or skip over this region.
-        let entry_flash = remaining_flash
-            .get(0..entry_length as usize)
-            .ok_or(ProcessLoadError::NotEnoughFlash)?;
-
         // Advance the flash slice for process discovery beyond this last entry.
         // This will be the start of where we look for a new process since Tock
         // processes are allocated back-to-back in flash.
@@ -205,38 +242,19 @@ pub fn load_processes<C: Chip>(
             // object. We also need to shrink the amount of remaining memory
             // based on whatever is assigned to the new process if one is
             // created.
-
-            // Try to create a process object from that app slice. If we don't
-            // get a process and we didn't get a loading error (aka we got to
-            // this point), then the app is a disabled process or just padding.
-            let (process_option, unused_memory) = unsafe {
-                ProcessStandard::create(
-                    kernel,
-                    chip,
-                    entry_flash,
-                    header_length as usize,
-                    version,
-                    remaining_memory,
-                    fault_policy,
-                    i,
-                )?
-            };
-            process_option.map(|process| {
-                if config::CONFIG.debug_load_processes {
-                    debug!(
-                        "Loaded process[{}] from flash={:#010X}-{:#010X} into sram={:#010X}-{:#010X} = {:?}",
-                        i,
-                        entry_flash.as_ptr() as usize,
-                        entry_flash.as_ptr() as usize + entry_flash.len() - 1,
-                        process.mem_start() as usize,
-                        process.mem_end() as usize - 1,
-                        process.get_process_name()
-                    );
-                }
-
-                // Save the reference to this process in the processes array.
-                procs[i] = Some(process);
-            });
+            let (_, unused_memory) = create_process(
+                kernel,
+                chip,
+                entry_flash,
+                header_length,
+                version,
+                remaining_memory,
+                i,
+                fault_policy,
+                credentials_checker,
+                app_id_policy,
+                capability,
+            )?;
             unused_memory
         } else {
             // We are just skipping over this region of flash, so we have the
@@ -245,5 +263,653 @@ pub fn load_processes<C: Chip>(
         };
     }
 
-    Ok(())
+    Ok(remaining_memory)
+}
+
+/// Find the TBF entry at the start of `flash`.
+///
+/// Returns `None` if there is no TBF at the start of `flash`, which means the
+/// end of the app linked list has been reached. Otherwise returns the TBF
+/// version, the length of the TBF header, and the slice of flash the entry
+/// occupies. A header length of zero means the header is invalid and the entry
+/// should be skipped.
+fn find_tbf_entry(
+    flash: &'static [u8],
+) -> Result<Option<(u16, u16, &'static [u8])>, ProcessLoadError> {
+    // Get the first eight bytes of flash to check if there is another
+    // app.
+    let test_header_slice = match flash.get(0..8) {
+        Some(s) => s,
+        None => {
+            // Not enough flash to test for another app. This just means
+            // we are at the end of flash, and there are no more apps to
+            // load.
+            return Ok(None);
+        }
+    };
+
+    // Pass the first eight bytes to tbfheader to parse out the length of
+    // the tbf header and app. We then use those values to see if we have
+    // enough flash remaining to parse the remainder of the header.
+    let (version, header_length, entry_length) = match tock_tbf::parse::parse_tbf_header_lengths(
+        test_header_slice
+            .try_into()
+            .or(Err(ProcessLoadError::InternalError))?,
+    ) {
+        Ok((v, hl, el)) => (v, hl, el),
+        Err(tock_tbf::types::InitialTbfParseError::InvalidHeader(entry_length)) => {
+            // If we could not parse the header, then we want to skip over
+            // this app and look for the next one.
+            (0, 0, entry_length)
+        }
+        Err(tock_tbf::types::InitialTbfParseError::UnableToParse) => {
+            // Since Tock apps use a linked list, it is very possible the
+            // header we started to parse is intentionally invalid to signal
+            // the end of apps. This is ok and just means we have finished
+            // loading apps.
+            return Ok(None);
+        }
+    };
+
+    // Now we can get a slice which only encompasses the length of flash
+    // described by this tbf header.  We will either parse this as an actual
+    // app, or skip over this region.
+    let entry_flash = flash
+        .get(0..entry_length as usize)
+        .ok_or(ProcessLoadError::NotEnoughFlash)?;
+
+    Ok(Some((version, header_length, entry_flash)))
+}
+
+/// Try to create a process from the TBF in `entry_flash` and store it in the
+/// empty slot `index` of the processes array.
+///
+/// Returns the created process, if any, and the process memory that is left
+/// over. If no process is created and there is no error, then the TBF is a
+/// disabled process, padding, or was rejected by the board's policies.
+fn create_process<'a, C: Chip>(
+    kernel: &'static Kernel,
+    chip: &'static C,
+    entry_flash: &'static [u8],
+    header_length: u16,
+    version: u16,
+    remaining_memory: &'a mut [u8],
+    index: usize,
+    fault_policy: &'static dyn ProcessFaultPolicy,
+    credentials_checker: &dyn AppCredentialsChecker,
+    app_id_policy: &dyn AppIdPolicy,
+    capability: &dyn ProcessManagementCapability,
+) -> Result<(Option<&'static dyn Process>, &'a mut [u8]), ProcessLoadError> {
+    // Try to create a process object from that app slice. If we don't
+    // get a process and we didn't get a loading error (aka we got to
+    // this point), then the app is a disabled process or just padding.
+    let (process_option, unused_memory) = unsafe {
+        ProcessStandard::create(
+            kernel,
+            chip,
+            entry_flash,
+            header_length as usize,
+            version,
+            remaining_memory,
+            fault_policy,
+            credentials_checker,
+            app_id_policy,
+            index,
+        )?
+    };
+    if let Some(process) = process_option {
+        if config::CONFIG.debug_load_processes {
+            debug!(
+                "Loaded process[{}] from flash={:#010X}-{:#010X} into sram={:#010X}-{:#010X} = {:?}",
+                index,
+                entry_flash.as_ptr() as usize,
+                entry_flash.as_ptr() as usize + entry_flash.len() - 1,
+                process.mem_start() as usize,
+                process.mem_end() as usize - 1,
+                process.get_process_name()
+            );
+        }
+
+        // Save the reference to this process in the processes array.
+        kernel
+            .add_process(index, process, capability)
+            .or(Err(ProcessLoadError::InternalError))?;
+    }
+    Ok((process_option, unused_memory))
+}
+
+/// Interface for loading and removing processes while the kernel is running.
+///
+/// This allows capsules (for example a field update service) to add an
+/// application without rebooting the board. The new TBF must first be written
+/// into the free region of app flash returned by `free_flash()`, which is
+/// directly after the last TBF in the app linked list. `load_new_process()`
+/// then validates it and creates the process.
+///
+/// Processes that have terminated or faulted can be removed with
+/// `remove_process()`, which returns their memory so that it can be used for
+/// other processes. A removed process can be loaded again from flash with
+/// `load_process()`.
+pub trait DynamicProcessLoading {
+    /// Get the start address and length of the region of app flash after the
+    /// last TBF, where the next TBF must be written to be loaded.
+    fn free_flash(&self, capability: &dyn ProcessManagementCapability) -> (usize, usize);
+
+    /// Load the TBF at the start of the free region of app flash. The TBF
+    /// must not be longer than `max_size` bytes.
+    ///
+    /// On success the TBF becomes part of the app linked list, so it will also
+    /// be found by `load_processes()` on the next boot. Returns the identifier
+    /// of the created process, or `None` if the TBF is valid but did not
+    /// create a process (it is disabled, padding, or was rejected by the
+    /// board's credentials checker or `AppIdPolicy`).
+    fn load_new_process(
+        &self,
+        max_size: usize,
+        capability: &dyn ProcessManagementCapability,
+    ) -> Result<Option<ProcessId>, ProcessLoadError>;
+
+    /// Load the TBF at `flash_address`, which must be the start of a TBF in
+    /// the app linked list that is not currently loaded. Returns the same as
+    /// `load_new_process()`.
+    fn load_process(
+        &self,
+        flash_address: usize,
+        capability: &dyn ProcessManagementCapability,
+    ) -> Result<Option<ProcessId>, ProcessLoadError>;
+
+    /// Find the address of the TBF in the app linked list with the package
+    /// name `name`.
+    fn find_process_flash(
+        &self,
+        name: &str,
+        capability: &dyn ProcessManagementCapability,
+    ) -> Option<usize>;
+
+    /// Remove the process from the kernel's processes array and free its
+    /// memory. The process must be in the `Terminated` or `Faulted` state.
+    ///
+    /// Returns `INVAL` if the process does not exist and `BUSY` if it can
+    /// still run.
+    fn remove_process(
+        &self,
+        process_id: ProcessId,
+        capability: &dyn ProcessManagementCapability,
+    ) -> Result<(), ErrorCode>;
+}
+
+/// Process loader that keeps track of app flash and process memory after boot
+/// so that processes can be added and removed at runtime.
+///
+/// Boards that use this call `DynamicProcessLoader::load_processes()` instead
+/// of `load_processes()` at boot:
+///
+/// ```rust,ignore
+/// let loader = static_init!(
+///     kernel::procs::DynamicProcessLoader<nrf52840::chip::NRF52<Nrf52840DefaultPeripherals>>,
+///     kernel::procs::DynamicProcessLoader::new(
+///         board_kernel,
+///         chip,
+///         core::slice::from_raw_parts(
+///             &_sapps as *const u8,
+///             &_eapps as *const u8 as usize - &_sapps as *const u8 as usize,
+///         ),
+///         &mut APP_MEMORY,
+///         &FAULT_RESPONSE,
+///         &kernel::procs::NullCredentialsChecker {},
+///         &kernel::procs::AnonymousAppIdPolicy {},
+///         &process_management_capability,
+///     )
+/// );
+/// loader.load_processes(&process_management_capability)?;
+/// ```
+///
+/// The loader adds and removes processes through the processes array of the
+/// kernel. It does not keep a separate allocator: any part of `app_memory`
+/// that is not used by a process in the processes array is free. New
+/// processes are placed in the first free region they fit in. The loader only
+/// keeps the address range of `app_memory`, and creates a slice of a free
+/// region when a process is created in it, so that it never holds a
+/// reference to memory that a process owns.
+pub struct DynamicProcessLoader<C: 'static + Chip> {
+    kernel: &'static Kernel,
+    chip: &'static C,
+    app_flash: &'static [u8],
+    /// Offset in `app_flash` of the end of the app linked list.
+    apps_end: Cell<usize>,
+    /// Start and end address of all of the memory processes can be placed
+    /// in, including the memory of processes that are loaded.
+    app_memory: (usize, usize),
+    fault_policy: &'static dyn ProcessFaultPolicy,
+    credentials_checker: &'static dyn AppCredentialsChecker,
+    app_id_policy: &'static dyn AppIdPolicy,
+}
+
+impl<C: 'static + Chip> DynamicProcessLoader<C> {
+    /// Create a loader for the apps in `app_flash`, which loads processes
+    /// into the processes array of `kernel`.
+    pub fn new(
+        kernel: &'static Kernel,
+        chip: &'static C,
+        app_flash: &'static [u8],
+        app_memory: &'static mut [u8],
+        fault_policy: &'static dyn ProcessFaultPolicy,
+        credentials_checker: &'static dyn AppCredentialsChecker,
+        app_id_policy: &'static dyn AppIdPolicy,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> DynamicProcessLoader<C> {
+        DynamicProcessLoader {
+            kernel,
+            chip,
+            app_flash,
+            apps_end: Cell::new(0),
+            app_memory: (
+                app_memory.as_ptr() as usize,
+                app_memory.as_ptr() as usize + app_memory.len(),
+            ),
+            fault_policy,
+            credentials_checker,
+            app_id_policy,
+        }
+    }
+
+    /// Load the processes that are in flash at boot. This works like
+    /// `load_and_check_processes()`, but keeps track of the memory that is not
+    /// given to a process for processes loaded later.
+    pub fn load_processes(
+        &self,
+        capability: &dyn ProcessManagementCapability,
+    ) -> Result<(), ProcessLoadError> {
+        self.apps_end.set(find_apps_end(self.app_flash));
+
+        // All of the memory is only free if no process was loaded yet.
+        if self.kernel.get_process_iter().next().is_some() {
+            return Err(ProcessLoadError::InternalError);
+        }
+
+        let (memory_start, memory_end) = self.app_memory;
+        load_processes_from_flash(
+            self.kernel,
+            self.chip,
+            self.app_flash,
+            unsafe { free_memory(memory_start, memory_end) },
+            self.fault_policy,
+            self.credentials_checker,
+            self.app_id_policy,
+            capability,
+        )
+        .map(|_| ())
+    }
+
+    /// Create a process from the TBF at the start of `flash` in the first free
+    /// region of process memory it fits in.
+    fn load_from_flash(
+        &self,
+        flash: &'static [u8],
+        capability: &dyn ProcessManagementCapability,
+    ) -> Result<(Option<ProcessId>, usize), ProcessLoadError> {
+        let (version, header_length, entry_flash) = match find_tbf_entry(flash)? {
+            Some((_, 0, _)) | None => return Err(ProcessLoadError::NoValidTbf),
+            Some(entry) => entry,
+        };
+
+        if self
+            .kernel
+            .get_process_iter()
+            .any(|process| process.flash_start() as usize == entry_flash.as_ptr() as usize)
+        {
+            return Err(ProcessLoadError::AlreadyLoaded);
+        }
+
+        // Use the first empty slot in the processes array.
+        let index = self
+            .kernel
+            .free_process_slot()
+            .ok_or(ProcessLoadError::NotEnoughMemory)?;
+
+        let (memory_start, memory_end) = self.app_memory;
+        let mut region_start = memory_start;
+        let mut result = Err(ProcessLoadError::NotEnoughMemory);
+        while let Some((start, end)) = next_free_region(self.kernel, region_start, memory_end) {
+            result = create_process(
+                self.kernel,
+                self.chip,
+                entry_flash,
+                header_length,
+                version,
+                unsafe { free_memory(start, end) },
+                index,
+                self.fault_policy,
+                self.credentials_checker,
+                self.app_id_policy,
+                capability,
+            )
+            .map(|(process_option, _)| process_option.map(|process| process.processid()));
+            match result {
+                // The process may fit in a later free region.
+                Err(ProcessLoadError::NotEnoughMemory)
+                | Err(ProcessLoadError::MemoryAddressMismatch { .. }) => region_start = end,
+                _ => break,
+            }
+        }
+        result.map(|process_id| (process_id, entry_flash.len()))
+    }
+}
+
+impl<C: 'static + Chip> DynamicProcessLoading for DynamicProcessLoader<C> {
+    fn free_flash(&self, _capability: &dyn ProcessManagementCapability) -> (usize, usize) {
+        let apps_end = self.apps_end.get();
+        (
+            self.app_flash.as_ptr() as usize + apps_end,
+            self.app_flash.len() - apps_end,
+        )
+    }
+
+    fn load_new_process(
+        &self,
+        max_size: usize,
+        capability: &dyn ProcessManagementCapability,
+    ) -> Result<Option<ProcessId>, ProcessLoadError> {
+        let apps_end = self.apps_end.get();
+        let free_flash = self
+            .app_flash
+            .get(apps_end..apps_end.saturating_add(max_size))
+            .ok_or(ProcessLoadError::NotEnoughFlash)?;
+
+        self.load_from_flash(free_flash, capability)
+            .map(|(process_id, entry_length)| {
+                // The TBF is now part of the app linked list.
+                self.apps_end.set(apps_end + entry_length);
+                process_id
+            })
+    }
+
+    fn load_process(
+        &self,
+        flash_address: usize,
+        capability: &dyn ProcessManagementCapability,
+    ) -> Result<Option<ProcessId>, ProcessLoadError> {
+        // Only load TBFs that are in the app linked list.
+        let mut offset = 0;
+        while offset < self.apps_end.get()
+            && self.app_flash.as_ptr() as usize + offset != flash_address
+        {
+            match find_tbf_entry(&self.app_flash[offset..])? {
+                Some((_, _, entry_flash)) => offset += entry_flash.len(),
+                None => break,
+            }
+        }
+        if offset >= self.apps_end.get() {
+            return Err(ProcessLoadError::NoValidTbf);
+        }
+
+        self.load_from_flash(&self.app_flash[offset..self.apps_end.get()], capability)
+            .map(|(process_id, _)| process_id)
+    }
+
+    fn find_process_flash(
+        &self,
+        name: &str,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> Option<usize> {
+        let mut offset = 0;
+        while let Ok(Some((version, header_length, entry_flash))) = self
+            .app_flash
+            .get(offset..self.apps_end.get())
+            .map_or(Ok(None), find_tbf_entry)
+        {
+            let package_name = entry_flash
+                .get(0..header_length as usize)
+                .and_then(|header| tock_tbf::parse::parse_tbf_header(header, version).ok())
+                .and_then(|header| header.get_package_name());
+            if header_length > 0 && package_name == Some(name) {
+                return Some(entry_flash.as_ptr() as usize);
+            }
+            offset += entry_flash.len();
+        }
+        None
+    }
+
+    fn remove_process(
+        &self,
+        process_id: ProcessId,
+        capability: &dyn ProcessManagementCapability,
+    ) -> Result<(), ErrorCode> {
+        // A process that can still run may be using its memory.
+        match self
+            .kernel
+            .process_map_or(None, process_id, |process| Some(process.get_state()))
+        {
+            Some(State::Terminated) | Some(State::Faulted) | Some(State::WaitingToRestart) => {}
+            Some(_) => return Err(ErrorCode::BUSY),
+            None => return Err(ErrorCode::INVAL),
+        }
+
+        // The process's memory becomes free once it is no longer in the
+        // processes array. Any `ProcessId` (and the grants and buffers that
+        // use it) referring to the removed process is no longer valid, so
+        // capsules cannot access the memory either.
+        self.kernel
+            .remove_process(process_id, capability)
+            .map(|_| ())
+    }
+}
+
+/// Get the slice of process memory between `start` and `end`.
+///
+/// # Safety
+///
+/// The memory must be part of the loader's `app_memory` and not be used by a
+/// process in the processes array. The slice must not outlive the creation of
+/// a process in it; processes keep pointers to their memory rather than the
+/// slice.
+unsafe fn free_memory<'a>(start: usize, end: usize) -> &'a mut [u8] {
+    core::slice::from_raw_parts_mut(start as *mut u8, end - start)
+}
+
+/// Find the first region of process memory between `from` and `memory_end`
+/// that is not used by a process of `kernel`.
+fn next_free_region(kernel: &Kernel, from: usize, memory_end: usize) -> Option<(usize, usize)> {
+    // Skip over the processes that use the memory at `start`.
+    let mut start = from;
+    while let Some(process) = kernel
+        .get_process_iter()
+        .find(|p| p.mem_start() as usize <= start && start < p.mem_end() as usize)
+    {
+        start = process.mem_end() as usize;
+    }
+    if start >= memory_end {
+        return None;
+    }
+
+    // The region ends at the next process in memory.
+    let end = kernel
+        .get_process_iter()
+        .map(|p| p.mem_start() as usize)
+        .filter(|&process_start| process_start > start)
+        .fold(memory_end, core::cmp::min);
+    Some((start, end))
+}
+
+/// Get the offset in `app_flash` of the end of the app linked list.
+fn find_apps_end(app_flash: &'static [u8]) -> usize {
+    let mut apps_end = 0;
+    while let Ok(Some((_, _, entry_flash))) =
+        app_flash.get(apps_end..).map_or(Ok(None), find_tbf_entry)
+    {
+        apps_end += entry_flash.len();
+    }
+    apps_end
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::Cell;
+    use std::boxed::Box;
+    use std::vec;
+    use std::vec::Vec;
+
+    use crate::capabilities::ProcessManagementCapability;
+    use crate::process::{Process, ProcessId, ProcessSlot};
+    use crate::process_checker::NullCredentialsChecker;
+    use crate::process_policies::{AnonymousAppIdPolicy, StopFaultPolicy};
+    use crate::process_standard::tests::{
+        tbf_with_minimum_ram, MockChip, MockUserspaceKernelBoundary,
+    };
+    use crate::sched::Kernel;
+
+    use super::{DynamicProcessLoader, DynamicProcessLoading, ProcessLoadError};
+
+    struct Capability;
+    unsafe impl ProcessManagementCapability for Capability {}
+
+    const FLASH_SIZE: usize = 1024;
+    const MEMORY_SIZE: usize = 8192;
+
+    struct Setup {
+        loader: &'static DynamicProcessLoader<MockChip>,
+        procs: &'static [ProcessSlot],
+        flash: *mut u8,
+        memory: *const u8,
+    }
+
+    impl Setup {
+        /// Create a loader with `num_procs` slots for processes, and load the
+        /// TBFs in `apps` at boot.
+        fn new(num_procs: usize, apps: &[&[u8]]) -> Setup {
+            let flash: &'static mut [u8] = Box::leak(vec![0xFF; FLASH_SIZE].into_boxed_slice());
+            let mut offset = 0;
+            for app in apps {
+                flash[offset..offset + app.len()].copy_from_slice(app);
+                offset += app.len();
+            }
+            let flash = flash.as_mut_ptr();
+
+            // Word-aligned memory, as an MPU would allocate.
+            let memory: &'static mut [u64] =
+                Box::leak(vec![0u64; MEMORY_SIZE / 8].into_boxed_slice());
+            let memory = memory.as_mut_ptr() as *mut u8;
+
+            let procs: &'static [ProcessSlot] = Box::leak(
+                (0..num_procs)
+                    .map(|_| Cell::new(None))
+                    .collect::<Vec<_>>()
+                    .into_boxed_slice(),
+            );
+            let kernel: &'static Kernel = Box::leak(Box::new(Kernel::new(procs)));
+            let chip: &'static MockChip = Box::leak(Box::new(MockChip {
+                userspace_kernel_boundary: MockUserspaceKernelBoundary {},
+            }));
+
+            let loader = Box::leak(Box::new(unsafe {
+                DynamicProcessLoader::new(
+                    kernel,
+                    chip,
+                    core::slice::from_raw_parts(flash, FLASH_SIZE),
+                    core::slice::from_raw_parts_mut(memory, MEMORY_SIZE),
+                    &StopFaultPolicy {},
+                    &NullCredentialsChecker {},
+                    &AnonymousAppIdPolicy {},
+                    &Capability,
+                )
+            }));
+            loader.load_processes(&Capability).unwrap();
+
+            Setup {
+                loader,
+                procs,
+                flash,
+                memory,
+            }
+        }
+
+        fn process(&self, index: usize) -> Option<&'static dyn Process> {
+            self.procs[index].get()
+        }
+
+        /// Write `tbf` to the start of the free app flash.
+        fn write_app(&self, tbf: &[u8]) {
+            let (start, length) = self.loader.free_flash(&Capability);
+            assert!(tbf.len() <= length);
+            assert!(start >= self.flash as usize);
+            unsafe {
+                core::ptr::copy_nonoverlapping(tbf.as_ptr(), start as *mut u8, tbf.len());
+            }
+        }
+
+        fn load_new_process(&self) -> Result<Option<ProcessId>, ProcessLoadError> {
+            let (_, length) = self.loader.free_flash(&Capability);
+            self.loader.load_new_process(length, &Capability)
+        }
+    }
+
+    #[test]
+    fn new_process_is_loaded_after_boot_processes() {
+        let setup = Setup::new(2, &[tbf_with_minimum_ram(0, &[])]);
+        let first = setup.process(0).unwrap();
+        assert_eq!(first.mem_start(), setup.memory);
+
+        // The boot processes' memory is no longer free.
+        assert!(matches!(
+            setup.loader.load_processes(&Capability),
+            Err(ProcessLoadError::InternalError)
+        ));
+
+        setup.write_app(tbf_with_minimum_ram(0, &[]));
+        let process_id = setup.load_new_process().unwrap().unwrap();
+        let second = setup.process(1).unwrap();
+        assert_eq!(second.processid(), process_id);
+        assert!(second.mem_start() >= first.mem_end());
+    }
+
+    #[test]
+    fn failed_load_keeps_memory_for_later_processes() {
+        let setup = Setup::new(2, &[]);
+        assert!(matches!(
+            setup.load_new_process(),
+            Err(ProcessLoadError::NoValidTbf)
+        ));
+
+        setup.write_app(tbf_with_minimum_ram(2 * MEMORY_SIZE as u32, &[]));
+        assert!(matches!(
+            setup.load_new_process(),
+            Err(ProcessLoadError::NotEnoughMemory)
+        ));
+        assert!(setup.process(0).is_none());
+
+        // The TBF that failed to load did not become part of the app linked
+        // list, so it can be replaced.
+        setup.write_app(tbf_with_minimum_ram(0, &[]));
+        let process_id = setup.load_new_process().unwrap().unwrap();
+        let process = setup.process(0).unwrap();
+        assert_eq!(process.processid(), process_id);
+        assert_eq!(process.mem_start(), setup.memory);
+    }
+
+    #[test]
+    fn load_without_free_slot_keeps_memory() {
+        let setup = Setup::new(1, &[tbf_with_minimum_ram(0, &[])]);
+        let first = setup.process(0).unwrap();
+        let first_memory = (first.mem_start(), first.mem_end());
+
+        setup.write_app(tbf_with_minimum_ram(0, &[]));
+        assert!(matches!(
+            setup.load_new_process(),
+            Err(ProcessLoadError::NotEnoughMemory)
+        ));
+
+        first.terminate(0);
+        setup
+            .loader
+            .remove_process(first.processid(), &Capability)
+            .unwrap();
+
+        // The removed process's memory is reused.
+        let process_id = setup.load_new_process().unwrap().unwrap();
+        let second = setup.process(0).unwrap();
+        assert_eq!(second.processid(), process_id);
+        assert_eq!((second.mem_start(), second.mem_end()), first_memory);
+    }
 }
@@ -6,6 +6,7 @@
 //! selected by a board.
 
 pub(crate) mod cooperative;
+pub(crate) mod edf;
 pub(crate) mod mlfq;
 pub(crate) mod priority;
 pub(crate) mod round_robin;
@@ -14,23 +15,27 @@ use core::cell::Cell;
 use core::ptr::NonNull;
 
 use crate::capabilities;
-use crate::common::cells::NumericCellExt;
+use crate::common::cells::{NumericCellExt, OptionalCell};
 use crate::common::dynamic_deferred_call::DynamicDeferredCall;
 use crate::config;
 use crate::debug;
 use crate::driver::CommandReturn;
 use crate::errorcode::ErrorCode;
 use crate::grant::Grant;
+use crate::introspection::CpuClock;
 use crate::ipc;
 use crate::memop;
 use crate::platform::mpu::MPU;
+use crate::platform::power::SleepPolicy;
 use crate::platform::scheduler_timer::SchedulerTimer;
 use crate::platform::watchdog::WatchDog;
 use crate::platform::{Chip, Platform};
-use crate::process::ProcessId;
 use crate::process::{self, Task};
-use crate::syscall::{ContextSwitchReason, SyscallReturn};
+use crate::process::{AllowSlot, ProcessId, ProcessSlot};
+use crate::process_restart::DelayedRestart;
+use crate::syscall::{ContextSwitchReason, SyscallClass, SyscallReturn};
 use crate::syscall::{Syscall, YieldCall};
+use crate::syscall_trace::SyscallTracer;
 use crate::upcall::{Upcall, UpcallId};
 
 /// Threshold in microseconds to consider a process's timeslice to be exhausted.
@@ -130,7 +135,7 @@ pub struct Kernel {
     work: Cell<usize>,
 
     /// This holds a pointer to the static array of Process pointers.
-    processes: &'static [Option<&'static dyn process::Process>],
+    processes: &'static [ProcessSlot],
 
     /// A counter which keeps track of how many process identifiers have been
     /// created. This is used to create new unique identifiers for processes.
@@ -146,6 +151,25 @@ pub struct Kernel {
     /// created and the data structures for grants have already been
     /// established.
     grants_finalized: Cell<bool>,
+
+    /// Optional tracer that is passed every system call the kernel handles.
+    syscall_tracer: OptionalCell<&'static dyn SyscallTracer>,
+
+    /// Flag to mark that the kernel stopped tickling the hardware watchdog so
+    /// that it resets the board.
+    watchdog_reset: Cell<bool>,
+
+    /// Optional timer that restarts processes whose fault policy delays their
+    /// restart.
+    delayed_restart: OptionalCell<&'static dyn DelayedRestart>,
+
+    /// Optional policy that chooses the sleep state of the chip when the
+    /// kernel has nothing to do.
+    sleep_policy: OptionalCell<&'static dyn SleepPolicy>,
+
+    /// Optional clock that measures how long processes run when the
+    /// scheduler gives them no timeslice.
+    cpu_clock: OptionalCell<&'static dyn CpuClock>,
 }
 
 /// Enum used to inform scheduler why a process stopped executing (aka why
@@ -174,16 +198,89 @@ pub enum StoppedExecutingReason {
 }
 
 impl Kernel {
-    pub fn new(processes: &'static [Option<&'static dyn process::Process>]) -> Kernel {
+    pub fn new(processes: &'static [ProcessSlot]) -> Kernel {
         Kernel {
             work: Cell::new(0),
             processes,
             process_identifier_max: Cell::new(0),
             grant_counter: Cell::new(0),
             grants_finalized: Cell::new(false),
+            syscall_tracer: OptionalCell::empty(),
+            watchdog_reset: Cell::new(false),
+            delayed_restart: OptionalCell::empty(),
+            sleep_policy: OptionalCell::empty(),
+            cpu_clock: OptionalCell::empty(),
         }
     }
 
+    /// Pass every system call the kernel handles, and the value returned to
+    /// the process, to `tracer`.
+    ///
+    /// This is restricted with a capability because the tracer can observe
+    /// the system calls of every process.
+    pub fn set_syscall_tracer(
+        &self,
+        tracer: &'static dyn SyscallTracer,
+        _capability: &dyn capabilities::ProcessManagementCapability,
+    ) {
+        self.syscall_tracer.set(tracer);
+    }
+
+    /// Use `timer` to restart processes whose fault policy returns
+    /// `FaultAction::RestartAfter`.
+    pub fn set_delayed_restart(
+        &self,
+        timer: &'static dyn DelayedRestart,
+        _capability: &dyn capabilities::ProcessManagementCapability,
+    ) {
+        self.delayed_restart.set(timer);
+    }
+
+    /// Use `policy` to choose which of the chip's sleep states to enter when
+    /// the kernel has nothing to do. Without a policy, the kernel calls
+    /// `Chip::sleep()`.
+    pub fn set_sleep_policy(
+        &self,
+        policy: &'static dyn SleepPolicy,
+        _capability: &dyn capabilities::MainLoopCapability,
+    ) {
+        self.sleep_policy.set(policy);
+    }
+
+    /// Use `clock` to measure how long processes run when the scheduler gives
+    /// them no timeslice, such as with the cooperative scheduler. Without a
+    /// clock, only time measured with the scheduler timer is counted.
+    pub fn set_cpu_clock(
+        &self,
+        clock: &'static dyn CpuClock,
+        _capability: &dyn capabilities::ProcessManagementCapability,
+    ) {
+        self.cpu_clock.set(clock);
+    }
+
+    /// Restart the process `processid` after `delay_ms` milliseconds. Returns
+    /// `NOSUPPORT` if the board did not set a `DelayedRestart` timer.
+    pub(crate) fn restart_after(
+        &self,
+        processid: ProcessId,
+        delay_ms: u32,
+    ) -> Result<(), ErrorCode> {
+        self.delayed_restart
+            .map_or(Err(ErrorCode::NOSUPPORT), |timer| {
+                timer.restart_after(processid, delay_ms)
+            })
+    }
+
+    /// Stop tickling the hardware watchdog, so that it resets the board.
+    ///
+    /// The kernel keeps running, but no longer sleeps, until the watchdog
+    /// fires. On chips without a hardware watchdog (`Chip::WatchDog` is `()`)
+    /// this does not reset the board, and the kernel keeps running without
+    /// sleeping, so boards without one must not call this.
+    pub fn watchdog_reset(&self, _capability: &dyn capabilities::ProcessManagementCapability) {
+        self.watchdog_reset.set(true);
+    }
+
     /// Something was scheduled for a process, so there is more work to do.
     ///
     /// This is only exposed in the core kernel crate.
@@ -257,7 +354,7 @@ impl Kernel {
             .map_or(None, |process_entry| {
                 // Check if there is any process state here, or if the entry is
                 // `None`.
-                process_entry.map_or(None, |process| {
+                process_entry.get().map_or(None, |process| {
                     // Check that the process stored here matches the identifier
                     // in the `appid`.
                     if process.processid() == appid {
@@ -277,9 +374,9 @@ impl Kernel {
         F: Fn(&dyn process::Process),
     {
         for process in self.processes.iter() {
-            match process {
+            match process.get() {
                 Some(p) => {
-                    closure(*p);
+                    closure(p);
                 }
                 None => {}
             }
@@ -290,13 +387,11 @@ impl Kernel {
     pub(crate) fn get_process_iter(
         &self,
     ) -> core::iter::FilterMap<
-        core::slice::Iter<Option<&dyn process::Process>>,
-        fn(&Option<&'static dyn process::Process>) -> Option<&'static dyn process::Process>,
+        core::slice::Iter<ProcessSlot>,
+        fn(&ProcessSlot) -> Option<&'static dyn process::Process>,
     > {
-        fn keep_some(
-            &x: &Option<&'static dyn process::Process>,
-        ) -> Option<&'static dyn process::Process> {
-            x
+        fn keep_some(slot: &ProcessSlot) -> Option<&'static dyn process::Process> {
+            slot.get()
         }
         self.processes.iter().filter_map(keep_some)
     }
@@ -315,9 +410,9 @@ impl Kernel {
         F: Fn(&dyn process::Process),
     {
         for process in self.processes.iter() {
-            match process {
+            match process.get() {
                 Some(p) => {
-                    closure(*p);
+                    closure(p);
                 }
                 None => {}
             }
@@ -332,9 +427,9 @@ impl Kernel {
         F: Fn(&dyn process::Process) -> Option<T>,
     {
         for process in self.processes.iter() {
-            match process {
+            match process.get() {
                 Some(p) => {
-                    let ret = closure(*p);
+                    let ret = closure(p);
                     if ret.is_some() {
                         return ret;
                     }
@@ -350,8 +445,8 @@ impl Kernel {
     /// as from userspace) and needs to be expanded to a full `ProcessId` for use
     /// with other APIs.
     pub(crate) fn lookup_app_by_identifier(&self, identifier: usize) -> Option<ProcessId> {
-        self.processes.iter().find_map(|&p| {
-            p.map_or(None, |p2| {
+        self.processes.iter().find_map(|p| {
+            p.get().map_or(None, |p2| {
                 if p2.processid().id() == identifier {
                     Some(p2.processid())
                 } else {
@@ -369,10 +464,61 @@ impl Kernel {
     /// verify that the referenced app is still at the correct index.
     pub(crate) fn processid_is_valid(&self, appid: &ProcessId) -> bool {
         self.processes.get(appid.index).map_or(false, |p| {
-            p.map_or(false, |process| process.processid().id() == appid.id())
+            p.get()
+                .map_or(false, |process| process.processid().id() == appid.id())
         })
     }
 
+    /// Store `process` in the empty slot `index` of the processes array.
+    ///
+    /// Returns `INVAL` if there is no such slot and `BUSY` if the slot holds a
+    /// process.
+    pub fn add_process(
+        &self,
+        index: usize,
+        process: &'static dyn process::Process,
+        _capability: &dyn capabilities::ProcessManagementCapability,
+    ) -> Result<(), ErrorCode> {
+        let slot = self.processes.get(index).ok_or(ErrorCode::INVAL)?;
+        if slot.get().is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        slot.set(Some(process));
+        Ok(())
+    }
+
+    /// Remove the process `processid` from the processes array, and return
+    /// it. Any `ProcessId` referring to the process is no longer valid
+    /// afterwards, and the syscall tracer forgets the process.
+    ///
+    /// Returns `INVAL` if the process is not in the processes array.
+    pub fn remove_process(
+        &self,
+        processid: ProcessId,
+        _capability: &dyn capabilities::ProcessManagementCapability,
+    ) -> Result<&'static dyn process::Process, ErrorCode> {
+        let slot = self
+            .processes
+            .get(processid.index)
+            .filter(|slot| slot.get().map_or(false, |p| p.processid() == processid))
+            .ok_or(ErrorCode::INVAL)?;
+        let process = slot.get().ok_or(ErrorCode::INVAL)?;
+        slot.set(None);
+        self.syscall_tracer
+            .map(|tracer| tracer.process_removed(processid));
+        Ok(process)
+    }
+
+    /// The index of the first empty slot of the processes array, if any.
+    pub(crate) fn free_process_slot(&self) -> Option<usize> {
+        self.processes.iter().position(|slot| slot.get().is_none())
+    }
+
+    /// The number of slots in the processes array.
+    pub(crate) fn process_slot_count(&self) -> usize {
+        self.processes.len()
+    }
+
     /// Create a new grant. This is used in board initialization to setup grants
     /// that capsules use to interact with processes.
     ///
@@ -448,7 +594,7 @@ impl Kernel {
     /// apps.
     pub fn hardfault_all_apps<C: capabilities::ProcessManagementCapability>(&self, _c: &C) {
         for p in self.processes.iter() {
-            p.map(|process| {
+            p.get().map(|process| {
                 process.set_fault_state();
             });
         }
@@ -480,7 +626,9 @@ impl Kernel {
         no_sleep: bool,
         _capability: &dyn capabilities::MainLoopCapability,
     ) {
-        chip.watchdog().tickle();
+        if !self.watchdog_reset.get() {
+            chip.watchdog().tickle();
+        }
         unsafe {
             // Ask the scheduler if we should do tasks inside of the kernel,
             // such as handle interrupts. A scheduler may want to prioritize
@@ -524,12 +672,21 @@ impl Kernel {
                                     // starts, the interrupt will not be
                                     // serviced and the chip will never wake
                                     // from sleep.
+                                    // Sleeping suspends the watchdog, so the
+                                    // kernel does not sleep while it waits for
+                                    // the watchdog to reset the board.
                                     if !chip.has_pending_interrupts()
                                         && !DynamicDeferredCall::global_instance_calls_pending()
                                             .unwrap_or(false)
+                                        && !self.watchdog_reset.get()
                                     {
                                         chip.watchdog().suspend();
-                                        chip.sleep();
+                                        match self.sleep_policy.map_or(None, |policy| {
+                                            policy.choose_sleep_state(chip.sleep_states())
+                                        }) {
+                                            Some(state) => chip.sleep_in(state),
+                                            None => chip.sleep(),
+                                        }
                                         chip.watchdog().resume();
                                     }
                                 });
@@ -661,12 +818,25 @@ impl Kernel {
                     // underlying timer is not affected.
                     process.setup_mpu();
 
+                    // Without a timeslice the scheduler timer is not started,
+                    // so measure how long the process runs with the CPU clock.
+                    let cpu_time_start = match timeslice_us {
+                        Some(_) => None,
+                        None => self.cpu_clock.map(|clock| clock.timestamp()),
+                    };
+                    process.debug_context_switched();
+
                     chip.mpu().enable_app_mpu();
                     scheduler_timer.arm();
                     let context_switch_reason = process.switch_to();
                     scheduler_timer.disarm();
                     chip.mpu().disable_app_mpu();
 
+                    if let Some(start) = cpu_time_start {
+                        self.cpu_clock
+                            .map(|clock| process.debug_add_cpu_time(clock.us_since(start)));
+                    }
+
                     // Now the process has returned back to the kernel. Check
                     // why and handle the process as appropriate.
                     match context_switch_reason {
@@ -710,6 +880,9 @@ impl Kernel {
                         None => break,
                         Some(cb) => match cb {
                             Task::FunctionCall(ccb) => {
+                                if process.get_state() == process::State::Yielded {
+                                    process.debug_woken();
+                                }
                                 if config::CONFIG.trace_syscalls {
                                     debug!(
                                         "[{:?}] function_call @{:#x}({:#x}, {:#x}, {:#x}, {:#x})",
@@ -748,7 +921,9 @@ impl Kernel {
                         },
                     }
                 }
-                process::State::Faulted | process::State::Terminated => {
+                process::State::Faulted
+                | process::State::Terminated
+                | process::State::WaitingToRestart => {
                     // We should never be scheduling a process in fault.
                     panic!("Attempted to schedule a faulty process");
                 }
@@ -779,6 +954,10 @@ impl Kernel {
             }
         });
 
+        if let Some(us) = time_executed_us {
+            process.debug_add_cpu_time(us);
+        }
+
         // Reset the scheduler timer in case it unconditionally triggers
         // interrupts upon expiration. We do not want it to expire while the
         // chip is sleeping, for example.
@@ -788,10 +967,8 @@ impl Kernel {
     }
 
     /// Method to invoke a system call on a particular process.
-    /// Applies the kernel system call filtering policy (if any).
-    /// Handles `Yield` and `Exit`, dispatches `Memop` to `memop::memop`,
-    /// and dispatches peripheral driver system calls to peripheral
-    /// driver capsules through the platforms `with_driver` method.
+    /// Sets the return value of the system call, if it has one, and passes
+    /// the system call to the syscall tracer (if any).
     #[inline]
     fn handle_syscall<P: Platform>(
         &self,
@@ -802,6 +979,34 @@ impl Kernel {
         // Hook for process debugging.
         process.debug_syscall_called(syscall);
 
+        // The process identifier changes if the process restarts, so save it
+        // for the tracer first.
+        let process_id = process.processid();
+
+        let result = self.dispatch_syscall(platform, process, syscall);
+        if let Some(result) = result {
+            process.set_syscall_return_value(result);
+        }
+
+        self.syscall_tracer.map(|tracer| {
+            tracer.trace_syscall(process_id, syscall, result);
+        });
+    }
+
+    /// Applies the kernel system call filtering policy (if any).
+    /// Handles `Yield` and `Exit`, dispatches `Memop` to `memop::memop`,
+    /// and dispatches peripheral driver system calls to peripheral
+    /// driver capsules through the platforms `with_driver` method.
+    ///
+    /// Returns the value to return to the process, or `None` if the system
+    /// call does not return a value.
+    #[inline]
+    fn dispatch_syscall<P: Platform>(
+        &self,
+        platform: &P,
+        process: &dyn process::Process,
+        syscall: Syscall,
+    ) -> Option<SyscallReturn> {
         // Enforce platform-specific syscall filtering here.
         //
         // Before continuing to handle non-yield syscalls
@@ -832,9 +1037,7 @@ impl Kernel {
             _ => {
                 // Check all other syscalls for filtering
                 if let Err(response) = platform.filter_syscall(process, &syscall) {
-                    process.set_syscall_return_value(SyscallReturn::Failure(response));
-
-                    return;
+                    return Some(SyscallReturn::Failure(response));
                 }
             }
         }
@@ -852,7 +1055,7 @@ impl Kernel {
                         rval
                     );
                 }
-                process.set_syscall_return_value(rval);
+                Some(rval)
             }
             Syscall::Yield { which, address } => {
                 if config::CONFIG.trace_syscalls {
@@ -863,7 +1066,7 @@ impl Kernel {
                     // yield system call, Yield does not have a return
                     // value because it can push a function call onto
                     // the stack; just return control to the process.
-                    return;
+                    return None;
                 }
                 let wait = which == (YieldCall::Wait as usize);
                 // If this is a yield-no-wait AND there are no pending
@@ -895,6 +1098,7 @@ impl Kernel {
                     }
                     process.set_yielded_state();
                 }
+                None
             }
             Syscall::Subscribe {
                 driver_number,
@@ -945,7 +1149,7 @@ impl Kernel {
                     );
                 }
 
-                process.set_syscall_return_value(rval);
+                Some(rval)
             }
             Syscall::Command {
                 driver_number,
@@ -971,7 +1175,7 @@ impl Kernel {
                         res,
                     );
                 }
-                process.set_syscall_return_value(res);
+                Some(res)
             }
             Syscall::ReadWriteAllow {
                 driver_number,
@@ -979,15 +1183,20 @@ impl Kernel {
                 allow_address,
                 allow_size,
             } => {
+                let slot = AllowSlot {
+                    class: SyscallClass::ReadWriteAllow,
+                    driver_number,
+                    subdriver_number,
+                };
+                let allowed = (allow_address as *const u8, allow_size);
                 let res = platform.with_driver(driver_number, |driver| match driver {
                     Some(d) => {
                         // Try to create an appropriate [`ReadWriteAppSlice`].
                         // This method will ensure that the memory in question
-                        // is located in the process-accessible memory space.
-                        //
-                        // TODO: Enforce anti buffer-aliasing guarantees to avoid
-                        // undefined behavior in Rust.
-                        match process.build_readwrite_appslice(allow_address, allow_size) {
+                        // is located in the process-accessible memory space,
+                        // and that it does not alias any other buffer allowed
+                        // to a capsule.
+                        match process.build_readwrite_appslice(slot, allow_address, allow_size) {
                             Ok(appslice) => {
                                 // Creating the [`ReadWriteAppSlice`] worked,
                                 // provide it to the capsule.
@@ -1004,10 +1213,12 @@ impl Kernel {
                                         // TODO: Prevent swapping of AppSlices by
                                         // the capsule
                                         let (ptr, len) = returned_appslice.consume();
+                                        process.allow_complete(slot, allowed, (ptr, len), true);
                                         SyscallReturn::AllowReadWriteSuccess(ptr, len)
                                     }
                                     Err((rejected_appslice, err)) => {
                                         let (ptr, len) = rejected_appslice.consume();
+                                        process.allow_complete(slot, allowed, (ptr, len), false);
                                         SyscallReturn::AllowReadWriteFailure(err, ptr, len)
                                     }
                                 }
@@ -1041,7 +1252,7 @@ impl Kernel {
                         res
                     );
                 }
-                process.set_syscall_return_value(res);
+                Some(res)
             }
             Syscall::ReadOnlyAllow {
                 driver_number,
@@ -1049,15 +1260,19 @@ impl Kernel {
                 allow_address,
                 allow_size,
             } => {
+                let slot = AllowSlot {
+                    class: SyscallClass::ReadOnlyAllow,
+                    driver_number,
+                    subdriver_number,
+                };
+                let allowed = (allow_address, allow_size);
                 let res = platform.with_driver(driver_number, |driver| match driver {
                     Some(d) => {
                         // Try to create an appropriate [`ReadOnlyAppSlice`].
                         // This method will ensure that the memory in question
-                        // is located in the process-accessible memory space.
-                        //
-                        // TODO: Enforce anti buffer-aliasing guarantees to avoid
-                        // undefined behavior in Rust.
-                        match process.build_readonly_appslice(allow_address, allow_size) {
+                        // is located in the process-accessible memory space,
+                        // and that no capsule holds a writeable buffer to it.
+                        match process.build_readonly_appslice(slot, allow_address, allow_size) {
                             Ok(appslice) => {
                                 // Creating the [`ReadOnlyAppSlice`] worked,
                                 // provide it to the capsule.
@@ -1074,6 +1289,7 @@ impl Kernel {
                                         // TODO: Prevent swapping of AppSlices by
                                         // the capsule
                                         let (ptr, len) = returned_appslice.consume();
+                                        process.allow_complete(slot, allowed, (ptr, len), true);
                                         SyscallReturn::AllowReadOnlySuccess(ptr, len)
                                     }
                                     Err((rejected_appslice, err)) => {
@@ -1084,6 +1300,7 @@ impl Kernel {
                                         // TODO: Ensure that the capsule has passed
                                         // the newly constructed AppSlice back
                                         let (ptr, len) = rejected_appslice.consume();
+                                        process.allow_complete(slot, allowed, (ptr, len), false);
                                         SyscallReturn::AllowReadOnlyFailure(err, ptr, len)
                                     }
                                 }
@@ -1118,20 +1335,259 @@ impl Kernel {
                     );
                 }
 
-                process.set_syscall_return_value(res);
+                Some(res)
+            }
+            Syscall::UserspaceReadableAllow {
+                driver_number,
+                subdriver_number,
+                allow_address,
+                allow_size,
+            } => {
+                let slot = AllowSlot {
+                    class: SyscallClass::UserspaceReadableAllow,
+                    driver_number,
+                    subdriver_number,
+                };
+                let allowed = (allow_address as *const u8, allow_size);
+                let res = platform.with_driver(driver_number, |driver| match driver {
+                    Some(d) => {
+                        // The process keeps read access to the buffer, so the
+                        // buffer must be memory the process can both read and
+                        // write, exactly as for a [`ReadWriteAppSlice`].
+                        match process.build_readwrite_appslice(slot, allow_address, allow_size) {
+                            Ok(appslice) => {
+                                match d.allow_userspace_readable(
+                                    process.processid(),
+                                    subdriver_number,
+                                    appslice,
+                                ) {
+                                    Ok(returned_appslice) => {
+                                        // The capsule has accepted the allow
+                                        // operation. Pass the previous buffer
+                                        // information back to the process.
+                                        let (ptr, len) = returned_appslice.consume();
+                                        process.allow_complete(slot, allowed, (ptr, len), true);
+                                        SyscallReturn::UserspaceReadableAllowSuccess(ptr, len)
+                                    }
+                                    Err((rejected_appslice, err)) => {
+                                        let (ptr, len) = rejected_appslice.consume();
+                                        process.allow_complete(slot, allowed, (ptr, len), false);
+                                        SyscallReturn::UserspaceReadableAllowFailure(err, ptr, len)
+                                    }
+                                }
+                            }
+                            Err(allow_error) => {
+                                // There was an error creating the
+                                // [`ReadWriteAppSlice`]. Report back to the
+                                // process.
+                                SyscallReturn::UserspaceReadableAllowFailure(
+                                    allow_error,
+                                    allow_address,
+                                    allow_size,
+                                )
+                            }
+                        }
+                    }
+                    None => SyscallReturn::UserspaceReadableAllowFailure(
+                        ErrorCode::NODEVICE,
+                        allow_address,
+                        allow_size,
+                    ),
+                });
+
+                if config::CONFIG.trace_syscalls {
+                    debug!(
+                        "[{:?}] userspace readable allow({:#x}, {}, @{:#x}, {:#x}) = {:?}",
+                        process.processid(),
+                        driver_number,
+                        subdriver_number,
+                        allow_address as usize,
+                        allow_size,
+                        res
+                    );
+                }
+                Some(res)
             }
             Syscall::Exit {
                 which,
                 completion_code,
             } => match which {
                 // The process called the `exit-terminate` system call.
-                0 => process.terminate(completion_code as u32),
+                0 => {
+                    process.terminate(completion_code as u32);
+                    None
+                }
                 // The process called the `exit-restart` system call.
-                1 => process.try_restart(completion_code as u32),
+                1 => {
+                    process.try_restart(completion_code as u32);
+                    None
+                }
                 // The process called an invalid variant of the Exit
                 // system call class.
-                _ => process.set_syscall_return_value(SyscallReturn::Failure(ErrorCode::NOSUPPORT)),
+                _ => Some(SyscallReturn::Failure(ErrorCode::NOSUPPORT)),
             },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::RefCell;
+    use core::mem;
+    use std::boxed::Box;
+    use std::vec;
+
+    use crate::capabilities::ProcessManagementCapability;
+    use crate::driver::Driver;
+    use crate::errorcode::ErrorCode;
+    use crate::mem::{ReadWrite, ReadWriteAppSlice};
+    use crate::platform::Platform;
+    use crate::process::{Process, ProcessId, ProcessSlot, EMPTY_PROCESS_SLOT};
+    use crate::process_policies::StopFaultPolicy;
+    use crate::process_standard::tests::{
+        tbf_with_minimum_ram, MockChip, MockUserspaceKernelBoundary,
+    };
+    use crate::process_utilities::load_processes;
+    use crate::syscall::{Syscall, SyscallReturn};
+
+    use super::Kernel;
+
+    struct Capability;
+    unsafe impl ProcessManagementCapability for Capability {}
+
+    const DRIVER_NUM: usize = 0x90000;
+
+    /// Driver that publishes a counter in the first byte of userspace
+    /// readable buffer `0`.
+    struct CounterDriver {
+        buffer: RefCell<ReadWriteAppSlice>,
+    }
+
+    impl Driver for CounterDriver {
+        fn allow_userspace_readable(
+            &self,
+            _app: ProcessId,
+            which: usize,
+            mut slice: ReadWriteAppSlice,
+        ) -> Result<ReadWriteAppSlice, (ReadWriteAppSlice, ErrorCode)> {
+            if which != 0 {
+                return Err((slice, ErrorCode::NOSUPPORT));
+            }
+            slice.mut_map_or((), |buffer| buffer[0] = 42);
+            mem::swap(&mut *self.buffer.borrow_mut(), &mut slice);
+            Ok(slice)
+        }
+    }
+
+    struct MockPlatform {
+        driver: CounterDriver,
+    }
+
+    impl Platform for MockPlatform {
+        fn with_driver<F, R>(&self, driver_num: usize, f: F) -> R
+        where
+            F: FnOnce(Option<&dyn Driver>) -> R,
+        {
+            match driver_num {
+                DRIVER_NUM => f(Some(&self.driver)),
+                _ => f(None),
+            }
+        }
+    }
+
+    /// Load a process, returning its kernel and the process.
+    fn create_process() -> (&'static Kernel, &'static dyn Process) {
+        let procs: &'static [ProcessSlot] = Box::leak(Box::new([EMPTY_PROCESS_SLOT]));
+        let kernel: &'static Kernel = Box::leak(Box::new(Kernel::new(procs)));
+        let chip: &'static MockChip = Box::leak(Box::new(MockChip {
+            userspace_kernel_boundary: MockUserspaceKernelBoundary {},
+        }));
+
+        // Word-aligned memory, as an MPU would allocate.
+        let memory: &'static mut [u64] = Box::leak(vec![0u64; 1024].into_boxed_slice());
+        let memory = unsafe {
+            core::slice::from_raw_parts_mut(memory.as_mut_ptr() as *mut u8, memory.len() * 8)
+        };
+        load_processes(
+            kernel,
+            chip,
+            tbf_with_minimum_ram(0, &[]),
+            memory,
+            &StopFaultPolicy {},
+            &Capability,
+        )
+        .unwrap();
+        (kernel, procs[0].get().unwrap())
+    }
+
+    fn allow(driver_number: usize, subdriver_number: usize, address: *mut u8) -> Syscall {
+        Syscall::UserspaceReadableAllow {
+            driver_number,
+            subdriver_number,
+            allow_address: address,
+            allow_size: 8,
+        }
+    }
+
+    #[test]
+    fn userspace_readable_allow_shares_buffer_with_driver() {
+        let (kernel, process) = create_process();
+        let platform = MockPlatform {
+            driver: CounterDriver {
+                buffer: RefCell::new(ReadWriteAppSlice::default()),
+            },
+        };
+        let first = process.mem_start() as *mut u8;
+        let second = first.wrapping_add(16);
+
+        match kernel.dispatch_syscall(&platform, process, allow(DRIVER_NUM, 0, first)) {
+            Some(SyscallReturn::UserspaceReadableAllowSuccess(ptr, 0)) => assert!(ptr.is_null()),
+            result => panic!("{:?}", result),
+        }
+        // The process can still read what the driver wrote.
+        assert_eq!(unsafe { *first }, 42);
+
+        // Allowing another buffer returns the previous one.
+        match kernel.dispatch_syscall(&platform, process, allow(DRIVER_NUM, 0, second)) {
+            Some(SyscallReturn::UserspaceReadableAllowSuccess(ptr, 8)) => assert_eq!(ptr, first),
+            result => panic!("{:?}", result),
+        }
+        assert_eq!(unsafe { *second }, 42);
+    }
+
+    #[test]
+    fn userspace_readable_allow_failures_return_buffer() {
+        let (kernel, process) = create_process();
+        let platform = MockPlatform {
+            driver: CounterDriver {
+                buffer: RefCell::new(ReadWriteAppSlice::default()),
+            },
+        };
+        let address = process.mem_start() as *mut u8;
+
+        match kernel.dispatch_syscall(&platform, process, allow(DRIVER_NUM, 1, address)) {
+            Some(SyscallReturn::UserspaceReadableAllowFailure(ErrorCode::NOSUPPORT, ptr, 8)) => {
+                assert_eq!(ptr, address)
+            }
+            result => panic!("{:?}", result),
+        }
+        match kernel.dispatch_syscall(&platform, process, allow(DRIVER_NUM + 1, 0, address)) {
+            Some(SyscallReturn::UserspaceReadableAllowFailure(ErrorCode::NODEVICE, ptr, 8)) => {
+                assert_eq!(ptr, address)
+            }
+            result => panic!("{:?}", result),
+        }
+
+        // The buffer must be memory the process can write.
+        let outside = process.app_memory_break() as *mut u8;
+        match kernel.dispatch_syscall(&platform, process, allow(DRIVER_NUM, 0, outside)) {
+            Some(SyscallReturn::UserspaceReadableAllowFailure(ErrorCode::INVAL, ptr, 8)) => {
+                assert_eq!(ptr, outside)
+            }
+            result => panic!("{:?}", result),
+        }
+        assert_eq!(unsafe { *address }, 0);
+    }
+}
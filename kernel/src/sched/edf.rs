@@ -0,0 +1,526 @@
+//! Earliest Deadline First scheduler for Tock
+//!
+//! This scheduler is meant for processes with timing requirements, such as
+//! control loops, that share the CPU with processes that have none. A process
+//! declares its requirements with the `Deadline` element of its TBF header: a
+//! period, and a budget of CPU time it needs in each period. The end of each
+//! period is the deadline for that period.
+//!
+//! The scheduler follows these rules:
+//!
+//! - Rule 1: A ready process with a deadline and budget left for its current
+//!           period runs before any other process. Among those, the process
+//!           with the earliest deadline runs.
+//! - Rule 2: A process with a deadline runs for at most its remaining budget.
+//!           If it uses up its budget before it yields, it has overrun its
+//!           budget. The overrun is counted (see `KernelInfo`), and the process
+//!           is treated as a process without a deadline until its next period
+//!           starts.
+//! - Rule 3: At the start of each period the budget of the process is
+//!           restored.
+//! - Rule 4: When no process with a deadline is ready, processes without a
+//!           deadline (and processes that overran their budget) run in
+//!           round-robin fashion.
+//!
+//! Processes with deadlines preempt other processes as soon as they become
+//! ready, so the budgets of all processes with deadlines should add up to
+//! less than the CPU time available.
+//!
+//! Deadlines only come from the TBF header. There is no system call for a
+//! process to declare or change its deadline at runtime, so a process that
+//! needs one must be rebuilt with the TLV.
+
+use crate::common::cells::OptionalCell;
+use crate::common::dynamic_deferred_call::DynamicDeferredCall;
+use crate::common::list::{List, ListLink, ListNode};
+use crate::hil::time::{self, Frequency, Ticks};
+use crate::platform::Chip;
+use crate::process::{ProcessId, ProcessSlot};
+use crate::sched::{
+    Kernel, Scheduler, SchedulingDecision, StoppedExecutingReason, MIN_QUANTA_THRESHOLD_US,
+};
+use core::cell::Cell;
+use core::cmp;
+
+#[derive(Default)]
+struct EDFProcState {
+    /// The process this state belongs to. The state is reset when a new
+    /// process is in the slot, or the process restarts.
+    process_id: Cell<Option<ProcessId>>,
+    /// Time until the end of the current period of the process.
+    us_until_deadline: Cell<u32>,
+    /// CPU time the process may still use in the current period.
+    us_budget_remaining: Cell<u32>,
+}
+
+/// Nodes store per-process state
+pub struct EDFProcessNode<'a> {
+    proc: &'static ProcessSlot,
+    state: EDFProcState,
+    next: ListLink<'a, EDFProcessNode<'a>>,
+}
+
+impl<'a> EDFProcessNode<'a> {
+    pub fn new(proc: &'static ProcessSlot) -> EDFProcessNode<'a> {
+        EDFProcessNode {
+            proc,
+            state: EDFProcState::default(),
+            next: ListLink::empty(),
+        }
+    }
+
+    /// Move the period of the process forward by `elapsed_us`, starting a new
+    /// period if the current one ended.
+    fn advance(&self, elapsed_us: u32) {
+        let (proc, (period_us, budget_us)) = match self
+            .proc
+            .get()
+            .and_then(|proc| proc.get_deadline().map(|deadline| (proc, deadline)))
+        {
+            Some(deadline) => deadline,
+            None => return,
+        };
+
+        let state = &self.state;
+        if state.process_id.get() != Some(proc.processid()) {
+            // The first period of the process starts now.
+            state.process_id.set(Some(proc.processid()));
+            state.us_until_deadline.set(period_us);
+            state.us_budget_remaining.set(budget_us);
+        } else if elapsed_us < state.us_until_deadline.get() {
+            state
+                .us_until_deadline
+                .set(state.us_until_deadline.get() - elapsed_us);
+        } else {
+            // One or more periods ended, skip to the current one.
+            let into_period_us = (elapsed_us - state.us_until_deadline.get()) % period_us;
+            state.us_until_deadline.set(period_us - into_period_us);
+            state.us_budget_remaining.set(budget_us);
+        }
+    }
+
+    /// Whether the process is ready, has a deadline, and has budget left to
+    /// run before it.
+    fn ready_with_deadline(&self) -> bool {
+        self.proc.get().map_or(false, |proc| {
+            proc.ready()
+                && proc.get_deadline().is_some()
+                && self.state.process_id.get() == Some(proc.processid())
+                && self.state.us_budget_remaining.get() > MIN_QUANTA_THRESHOLD_US
+        })
+    }
+}
+
+impl<'a> ListNode<'a, EDFProcessNode<'a>> for EDFProcessNode<'a> {
+    fn next(&'a self) -> &'a ListLink<'a, EDFProcessNode<'a>> {
+        &self.next
+    }
+}
+
+pub struct EDFSched<'a, A: 'static + time::Alarm<'static>> {
+    alarm: &'static A,
+    pub processes: List<'a, EDFProcessNode<'a>>,
+    /// When the periods of the processes were last advanced.
+    last_update: Cell<A::Ticks>,
+    /// The process that is running, and whether it runs before its deadline.
+    running: OptionalCell<(&'a EDFProcessNode<'a>, bool)>,
+    /// Index of the process to try first when no process with a deadline is
+    /// ready.
+    next_best_effort: Cell<usize>,
+}
+
+impl<'a, A: 'static + time::Alarm<'static>> EDFSched<'a, A> {
+    /// How long a process without a deadline can run before being preempted
+    pub const BEST_EFFORT_TIMESLICE_US: u32 = 10000;
+
+    pub fn new(alarm: &'static A) -> Self {
+        Self {
+            alarm,
+            processes: List::new(),
+            last_update: Cell::new(A::Ticks::from(0)),
+            running: OptionalCell::empty(),
+            next_best_effort: Cell::new(0),
+        }
+    }
+
+    /// Advance the periods of all processes to the current time.
+    ///
+    /// Like the MLFQ scheduler, this assumes the scheduler runs at least once
+    /// before the alarm wraps around.
+    fn update_periods(&self) {
+        let now = self.alarm.now();
+        let elapsed_ticks = now.wrapping_sub(self.last_update.get()).into_u32() as u64;
+        let elapsed_us = cmp::min(
+            elapsed_ticks * 1_000_000 / A::Frequency::frequency() as u64,
+            u32::MAX as u64,
+        ) as u32;
+        // Only account for whole microseconds, so the remainder is not lost.
+        self.last_update.set(
+            self.last_update
+                .get()
+                .wrapping_add(A::ticks_from_us(elapsed_us)),
+        );
+
+        for node in self.processes.iter() {
+            node.advance(elapsed_us);
+        }
+    }
+
+    /// Returns the ready process with budget left and the earliest deadline.
+    fn earliest_deadline_node(&self) -> Option<&'a EDFProcessNode<'a>> {
+        self.processes
+            .iter()
+            .filter(|node| node.ready_with_deadline())
+            .min_by_key(|node| node.state.us_until_deadline.get())
+    }
+
+    /// Returns the next ready process in round-robin order.
+    fn next_best_effort_node(&self) -> Option<&'a EDFProcessNode<'a>> {
+        let start = self.next_best_effort.get();
+        let ready = |(_, node): &(usize, &'a EDFProcessNode<'a>)| {
+            node.proc.get().map_or(false, |proc| proc.ready())
+        };
+        let next = self
+            .processes
+            .iter()
+            .enumerate()
+            .skip(start)
+            .find(ready)
+            .or_else(|| self.processes.iter().enumerate().take(start).find(ready));
+
+        next.map(|(idx, node)| {
+            self.next_best_effort.set(idx + 1);
+            node
+        })
+    }
+}
+
+impl<'a, A: 'static + time::Alarm<'static>, C: Chip> Scheduler<C> for EDFSched<'a, A> {
+    fn next(&self, kernel: &Kernel) -> SchedulingDecision {
+        self.update_periods();
+
+        if kernel.processes_blocked() {
+            // No processes ready
+            return SchedulingDecision::TrySleep;
+        }
+
+        let (node, real_time) = match self.earliest_deadline_node() {
+            Some(node) => (node, true),
+            None => match self.next_best_effort_node() {
+                Some(node) => (node, false),
+                None => return SchedulingDecision::TrySleep,
+            },
+        };
+        let timeslice = if real_time {
+            node.state.us_budget_remaining.get()
+        } else {
+            Self::BEST_EFFORT_TIMESLICE_US
+        };
+        self.running.set((node, real_time));
+
+        let next = node.proc.get().unwrap().processid(); // Only ready processes are chosen
+        SchedulingDecision::RunProcess((next, Some(timeslice)))
+    }
+
+    fn result(&self, result: StoppedExecutingReason, execution_time_us: Option<u32>) {
+        let execution_time_us = execution_time_us.unwrap_or(0); // We never run cooperatively
+        if let Some((node, true)) = self.running.take() {
+            let state = &node.state;
+            if result == StoppedExecutingReason::TimesliceExpired {
+                // The process still has work to do, but used up its budget.
+                state.us_budget_remaining.set(0);
+                node.proc.get().map(|proc| proc.debug_budget_overrun());
+            } else {
+                state.us_budget_remaining.set(
+                    state
+                        .us_budget_remaining
+                        .get()
+                        .saturating_sub(execution_time_us),
+                );
+            }
+        }
+    }
+
+    unsafe fn continue_process(&self, _: ProcessId, chip: &C) -> bool {
+        // In addition to checking for interrupts, also checks if a process with
+        // an earlier deadline has become ready, for example because this
+        // process sent it an IPC message.
+        !(chip.has_pending_interrupts()
+            || DynamicDeferredCall::global_instance_calls_pending().unwrap_or(false)
+            || self.earliest_deadline_node().map_or(false, |earliest| {
+                self.running.map_or(false, |&mut (running, real_time)| {
+                    !real_time
+                        || earliest.state.us_until_deadline.get()
+                            < running.state.us_until_deadline.get()
+                })
+            }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::Cell;
+    use std::boxed::Box;
+    use std::vec;
+    use std::vec::Vec;
+
+    use crate::capabilities::ProcessManagementCapability;
+    use crate::errorcode::ErrorCode;
+    use crate::hil::time::{Alarm, AlarmClient, Freq1MHz, Ticks, Ticks32, Time};
+    use crate::process::{Process, ProcessId, ProcessSlot, EMPTY_PROCESS_SLOT};
+    use crate::process_policies::StopFaultPolicy;
+    use crate::process_standard::tests::{
+        tbf_with_minimum_ram, MockChip, MockUserspaceKernelBoundary,
+    };
+    use crate::process_utilities::load_processes;
+    use crate::sched::{Kernel, Scheduler, SchedulingDecision, StoppedExecutingReason};
+
+    use super::{EDFProcessNode, EDFSched};
+
+    struct Capability;
+    unsafe impl ProcessManagementCapability for Capability {}
+
+    /// A 1 MHz alarm, which only moves when the test sets the time.
+    struct MockAlarm {
+        now: Cell<Ticks32>,
+    }
+
+    impl MockAlarm {
+        fn advance(&self, us: u32) {
+            self.now.set(self.now.get().wrapping_add(Ticks32::from(us)));
+        }
+    }
+
+    impl Time for MockAlarm {
+        type Frequency = Freq1MHz;
+        type Ticks = Ticks32;
+
+        fn now(&self) -> Ticks32 {
+            self.now.get()
+        }
+    }
+
+    impl<'a> Alarm<'a> for MockAlarm {
+        fn set_alarm_client(&'a self, _client: &'a dyn AlarmClient) {}
+
+        fn set_alarm(&self, _reference: Ticks32, _dt: Ticks32) {}
+
+        fn get_alarm(&self) -> Ticks32 {
+            Ticks32::from(0)
+        }
+
+        fn disarm(&self) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+
+        fn is_armed(&self) -> bool {
+            false
+        }
+
+        fn minimum_dt(&self) -> Ticks32 {
+            Ticks32::from(1)
+        }
+    }
+
+    struct Setup {
+        kernel: &'static Kernel,
+        alarm: &'static MockAlarm,
+        sched: &'static EDFSched<'static, MockAlarm>,
+        processes: Vec<&'static dyn Process>,
+    }
+
+    impl Setup {
+        /// Load a process for each of `deadlines`, given as its period and
+        /// budget if it has one, and schedule them with an EDF scheduler.
+        fn new(deadlines: &[Option<(u32, u32)>]) -> Setup {
+            let procs: &'static [ProcessSlot] = Box::leak(
+                deadlines
+                    .iter()
+                    .map(|_| EMPTY_PROCESS_SLOT)
+                    .collect::<Vec<_>>()
+                    .into_boxed_slice(),
+            );
+            let kernel: &'static Kernel = Box::leak(Box::new(Kernel::new(procs)));
+            let chip: &'static MockChip = Box::leak(Box::new(MockChip {
+                userspace_kernel_boundary: MockUserspaceKernelBoundary {},
+            }));
+            let flash: Vec<u8> = deadlines
+                .iter()
+                .flat_map(|deadline| {
+                    // Deadline TLV: type 11 and length 8.
+                    let tlv = deadline.map_or(vec![], |(period_us, budget_us)| {
+                        vec![11 | 8 << 16, period_us, budget_us]
+                    });
+                    tbf_with_minimum_ram(0, &tlv).iter().copied()
+                })
+                .collect();
+            // Word-aligned memory, as an MPU would allocate.
+            let memory: &'static mut [u64] =
+                Box::leak(vec![0u64; deadlines.len() * 1024].into_boxed_slice());
+            let memory = unsafe {
+                core::slice::from_raw_parts_mut(memory.as_mut_ptr() as *mut u8, memory.len() * 8)
+            };
+            load_processes(
+                kernel,
+                chip,
+                Box::leak(flash.into_boxed_slice()),
+                memory,
+                &StopFaultPolicy {},
+                &Capability,
+            )
+            .unwrap();
+
+            let alarm: &'static MockAlarm = Box::leak(Box::new(MockAlarm {
+                now: Cell::new(Ticks32::from(0)),
+            }));
+            let sched: &'static EDFSched<'static, MockAlarm> =
+                Box::leak(Box::new(EDFSched::new(alarm)));
+            for slot in procs.iter() {
+                sched
+                    .processes
+                    .push_tail(Box::leak(Box::new(EDFProcessNode::new(slot))));
+            }
+            Setup {
+                kernel,
+                alarm,
+                sched,
+                processes: procs.iter().map(|slot| slot.get().unwrap()).collect(),
+            }
+        }
+
+        /// The process the scheduler chooses to run next, and its timeslice.
+        fn next(&self) -> Option<(ProcessId, u32)> {
+            match Scheduler::<MockChip>::next(self.sched, self.kernel) {
+                SchedulingDecision::RunProcess((processid, timeslice)) => {
+                    Some((processid, timeslice.unwrap()))
+                }
+                SchedulingDecision::TrySleep => None,
+            }
+        }
+
+        /// Run the process the scheduler chooses for `us` microseconds, after
+        /// which it stops for `reason`. Returns the process and its timeslice.
+        fn run(&self, us: u32, reason: StoppedExecutingReason) -> Option<(ProcessId, u32)> {
+            let next = self.next();
+            self.alarm.advance(us);
+            self.stopped(reason, us);
+            next
+        }
+
+        /// Tell the scheduler the running process stopped for `reason` after
+        /// `us` microseconds.
+        fn stopped(&self, reason: StoppedExecutingReason, us: u32) {
+            Scheduler::<MockChip>::result(self.sched, reason, Some(us));
+        }
+
+        /// The time until the deadline of process `index` when the scheduler
+        /// last chose a process.
+        fn us_until_deadline(&self, index: usize) -> u32 {
+            let node = self.sched.processes.iter().nth(index).unwrap();
+            node.state.us_until_deadline.get()
+        }
+
+        fn processid(&self, index: usize) -> ProcessId {
+            self.processes[index].processid()
+        }
+    }
+
+    const BEST_EFFORT: u32 = EDFSched::<'static, MockAlarm>::BEST_EFFORT_TIMESLICE_US;
+
+    #[test]
+    fn earliest_deadline_runs_first() {
+        let setup = Setup::new(&[Some((10_000, 1000)), None, Some((5000, 2000))]);
+
+        // The process with the earlier deadline runs for its budget, even
+        // though it has more budget than the other.
+        assert_eq!(
+            setup.run(2000, StoppedExecutingReason::NoWorkLeft),
+            Some((setup.processid(2), 2000))
+        );
+        assert_eq!(
+            setup.run(1000, StoppedExecutingReason::NoWorkLeft),
+            Some((setup.processid(0), 1000))
+        );
+        // With no budget left, all processes run round-robin.
+        assert_eq!(setup.next(), Some((setup.processid(0), BEST_EFFORT)));
+        assert_eq!(setup.next(), Some((setup.processid(1), BEST_EFFORT)));
+        assert_eq!(setup.next(), Some((setup.processid(2), BEST_EFFORT)));
+    }
+
+    #[test]
+    fn processes_without_budget_left_do_not_run_first() {
+        let setup = Setup::new(&[Some((10_000, 3000)), None]);
+
+        // The process is preempted with budget left, and runs first again.
+        assert_eq!(
+            setup.run(2000, StoppedExecutingReason::KernelPreemption),
+            Some((setup.processid(0), 3000))
+        );
+        assert_eq!(setup.next(), Some((setup.processid(0), 1000)));
+        // The rest of the budget is too short to run the process.
+        setup.stopped(StoppedExecutingReason::NoWorkLeft, 600);
+        assert_eq!(setup.next(), Some((setup.processid(0), BEST_EFFORT)));
+        assert_eq!(setup.next(), Some((setup.processid(1), BEST_EFFORT)));
+    }
+
+    #[test]
+    fn period_rollover_restores_budget() {
+        let setup = Setup::new(&[Some((5000, 1000)), None]);
+
+        assert_eq!(
+            setup.run(1000, StoppedExecutingReason::NoWorkLeft),
+            Some((setup.processid(0), 1000))
+        );
+        assert_eq!(
+            setup.run(3000, StoppedExecutingReason::TimesliceExpired),
+            Some((setup.processid(0), BEST_EFFORT))
+        );
+        assert_eq!(setup.us_until_deadline(0), 5000 - 1000);
+
+        // The next period starts at 5000 us.
+        setup.alarm.advance(1000);
+        assert_eq!(setup.next(), Some((setup.processid(0), 1000)));
+        assert_eq!(setup.us_until_deadline(0), 5000);
+
+        // Periods the scheduler did not see are skipped: at 17500 us the
+        // process is halfway into its fourth period.
+        setup.stopped(StoppedExecutingReason::NoWorkLeft, 1000);
+        setup.alarm.advance(12_500);
+        assert_eq!(setup.next(), Some((setup.processid(0), 1000)));
+        assert_eq!(setup.us_until_deadline(0), 2500);
+    }
+
+    #[test]
+    fn budget_overrun_is_counted() {
+        let setup = Setup::new(&[Some((5000, 1000)), None]);
+        let process = setup.processes[0];
+
+        assert_eq!(
+            setup.run(1000, StoppedExecutingReason::TimesliceExpired),
+            Some((setup.processid(0), 1000))
+        );
+        assert_eq!(process.debug_budget_overrun_count(), 1);
+
+        // The process runs like a process without a deadline for the rest of
+        // the period, and running out of that timeslice is not an overrun.
+        assert_eq!(
+            setup.run(1000, StoppedExecutingReason::TimesliceExpired),
+            Some((setup.processid(0), BEST_EFFORT))
+        );
+        assert_eq!(
+            setup.run(1000, StoppedExecutingReason::TimesliceExpired),
+            Some((setup.processid(1), BEST_EFFORT))
+        );
+        assert_eq!(process.debug_budget_overrun_count(), 1);
+
+        // The next period starts with the full budget.
+        setup.alarm.advance(2000);
+        assert_eq!(
+            setup.run(1000, StoppedExecutingReason::TimesliceExpired),
+            Some((setup.processid(0), 1000))
+        );
+        assert_eq!(process.debug_budget_overrun_count(), 2);
+    }
+}
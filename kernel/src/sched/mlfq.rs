@@ -9,8 +9,11 @@
 //!           doesn't).
 //! - Rule 2: If Priority(A) = Priority(B), A & B run in round-robin fashion
 //!           using the time slice (quantum length) of the given queue.
-//! - Rule 3: When a job enters the system, it is placed at the highest priority
-//!           (the topmost queue).
+//! - Rule 3: When a job enters the system, or restarts, it is placed in the
+//!           queue for the priority the board's `PriorityPolicy` gives it.
+//!           Priority 0 is the topmost queue, and priorities below the lowest
+//!           queue are placed in the lowest queue. With
+//!           `EqualPriorityPolicy`, all jobs start in the topmost queue.
 //! - Rule 4: Once a job uses up its time allotment at a given level (regardless
 //!           of how many times it has given up the CPU), its priority is
 //!           reduced (i.e., it moves down one queue).
@@ -21,8 +24,9 @@ use crate::common::list::{List, ListLink, ListNode};
 use crate::hil::time;
 use crate::hil::time::Ticks;
 use crate::platform::Chip;
-use crate::process::Process;
 use crate::process::ProcessId;
+use crate::process::ProcessSlot;
+use crate::process_policies::{assigned_priority, PriorityPolicy};
 use crate::sched::{Kernel, Scheduler, SchedulingDecision, StoppedExecutingReason};
 use core::cell::Cell;
 
@@ -34,13 +38,13 @@ struct MfProcState {
 
 /// Nodes store per-process state
 pub struct MLFQProcessNode<'a> {
-    proc: &'static Option<&'static dyn Process>,
+    proc: &'static ProcessSlot,
     state: MfProcState,
     next: ListLink<'a, MLFQProcessNode<'a>>,
 }
 
 impl<'a> MLFQProcessNode<'a> {
-    pub fn new(proc: &'static Option<&'static dyn Process>) -> MLFQProcessNode<'a> {
+    pub fn new(proc: &'static ProcessSlot) -> MLFQProcessNode<'a> {
         MLFQProcessNode {
             proc,
             state: MfProcState::default(),
@@ -57,6 +61,7 @@ impl<'a> ListNode<'a, MLFQProcessNode<'a>> for MLFQProcessNode<'a> {
 
 pub struct MLFQSched<'a, A: 'static + time::Alarm<'static>> {
     alarm: &'static A,
+    policy: &'static dyn PriorityPolicy,
     pub processes: [List<'a, MLFQProcessNode<'a>>; 3], // Using Self::NUM_QUEUES causes rustc to crash..
     next_reset: Cell<A::Ticks>,
     last_reset_check: Cell<A::Ticks>,
@@ -69,9 +74,10 @@ impl<'a, A: 'static + time::Alarm<'static>> MLFQSched<'a, A> {
     pub const PRIORITY_REFRESH_PERIOD_MS: u32 = 5000;
     pub const NUM_QUEUES: usize = 3;
 
-    pub fn new(alarm: &'static A) -> Self {
+    pub fn new(alarm: &'static A, policy: &'static dyn PriorityPolicy) -> Self {
         Self {
             alarm,
+            policy,
             processes: [List::new(), List::new(), List::new()],
             next_reset: Cell::new(A::Ticks::from(0)),
             last_reset_check: Cell::new(A::Ticks::from(0)),
@@ -80,6 +86,35 @@ impl<'a, A: 'static + time::Alarm<'static>> MLFQSched<'a, A> {
         }
     }
 
+    /// Move the processes that were created or restarted since the last
+    /// scheduling decision to the queue for their priority.
+    fn place_started_procs(&self) {
+        let started = |node: &MLFQProcessNode| {
+            node.proc
+                .get()
+                .map_or(false, |proc| proc.get_assigned_priority().is_none())
+        };
+        for queue in self.processes.iter() {
+            if !queue.iter().any(started) {
+                continue;
+            }
+            // Rotate through the queue once, so the other processes keep
+            // their order.
+            for _ in 0..queue.iter().count() {
+                let node = queue.pop_head().unwrap();
+                match node.proc.get().filter(|_| started(node)) {
+                    Some(proc) => {
+                        let priority = assigned_priority(self.policy, proc);
+                        let queue_idx = (priority as usize).min(Self::NUM_QUEUES - 1);
+                        node.state.us_used_this_queue.set(0);
+                        self.processes[queue_idx].push_tail(node);
+                    }
+                    None => queue.push_tail(node),
+                }
+            }
+        }
+    }
+
     fn get_timeslice_us(&self, queue_idx: usize) -> u32 {
         match queue_idx {
             0 => 10000,
@@ -110,7 +145,7 @@ impl<'a, A: 'static + time::Alarm<'static>> MLFQSched<'a, A> {
         for (idx, queue) in self.processes.iter().enumerate() {
             let next = queue
                 .iter()
-                .find(|node_ref| node_ref.proc.map_or(false, |proc| proc.ready()));
+                .find(|node_ref| node_ref.proc.get().map_or(false, |proc| proc.ready()));
             if next.is_some() {
                 // pop procs to back until we get to match
                 loop {
@@ -153,11 +188,12 @@ impl<'a, A: 'static + time::Alarm<'static>, C: Chip> Scheduler<C> for MLFQSched<
                 self.redeem_all_procs();
             }
             self.last_reset_check.set(now);
+            self.place_started_procs();
             let (node_ref_opt, queue_idx) = self.get_next_ready_process_node();
             let node_ref = node_ref_opt.unwrap(); // Panic if fail bc processes_blocked()!
             let timeslice =
                 self.get_timeslice_us(queue_idx) - node_ref.state.us_used_this_queue.get();
-            let next = node_ref.proc.unwrap().processid(); // Panic if fail bc processes_blocked()!
+            let next = node_ref.proc.get().unwrap().processid(); // Panic if fail bc processes_blocked()!
             self.last_queue_idx.set(queue_idx);
             self.last_timeslice.set(timeslice);
 
@@ -1,9 +1,12 @@
 //! Fixed Priority Scheduler for Tock
 //!
-//! This scheduler assigns priority to processes based on their order in the
-//! `PROCESSES` array, and runs the highest priority process available at any
-//! point in time. Kernel tasks (bottom half interrupt handling / deferred call
-//! handling) always take priority over userspace processes.
+//! This scheduler assigns priority to processes with the board's
+//! `PriorityPolicy`, and runs the highest priority process available at any
+//! point in time. Processes with the same priority are ordered by their
+//! position in the `PROCESSES` array, so with `EqualPriorityPolicy` the order
+//! of the array alone decides. The priority of a process is decided once
+//! every time the process starts. Kernel tasks (bottom half interrupt handling /
+//! deferred call handling) always take priority over userspace processes.
 //!
 //! Notably, there is no need to enforce timeslices, as it is impossible for a
 //! process running to not be the highest priority process at any point while it
@@ -13,22 +16,34 @@
 use crate::common::cells::OptionalCell;
 use crate::common::dynamic_deferred_call::DynamicDeferredCall;
 use crate::platform::Chip;
-use crate::process::ProcessId;
+use crate::process::{Process, ProcessId};
+use crate::process_policies::{assigned_priority, PriorityPolicy};
 use crate::sched::{Kernel, Scheduler, SchedulingDecision, StoppedExecutingReason};
 
-/// Priority scheduler based on the order of processes in the `PROCESSES` array.
+/// Priority scheduler based on the board's `PriorityPolicy` and the order of
+/// processes in the `PROCESSES` array.
 pub struct PrioritySched {
     kernel: &'static Kernel,
+    policy: &'static dyn PriorityPolicy,
     running: OptionalCell<ProcessId>,
 }
 
 impl PrioritySched {
-    pub const fn new(kernel: &'static Kernel) -> Self {
+    pub const fn new(kernel: &'static Kernel, policy: &'static dyn PriorityPolicy) -> Self {
         Self {
             kernel,
+            policy,
             running: OptionalCell::empty(),
         }
     }
+
+    /// Where `process` ranks among the processes; lower ranks run first.
+    fn rank(&self, process: &dyn Process) -> (u32, usize) {
+        (
+            assigned_priority(self.policy, process),
+            process.processid().index,
+        )
+    }
 }
 
 impl<C: Chip> Scheduler<C> for PrioritySched {
@@ -37,13 +52,13 @@ impl<C: Chip> Scheduler<C> for PrioritySched {
             // No processes ready
             SchedulingDecision::TrySleep
         } else {
-            // Iterates in-order through the process array, always running the
-            // first process it finds that is ready to run. This enforces the
-            // priorities of all processes.
+            // Always runs the highest ranked process that is ready to run.
+            // This enforces the priorities of all processes.
             let next = self
                 .kernel
                 .get_process_iter()
-                .find(|&proc| proc.ready())
+                .filter(|&proc| proc.ready())
+                .min_by_key(|&proc| self.rank(proc))
                 .map_or(None, |proc| Some(proc.processid()));
             self.running.insert(next);
 
@@ -58,14 +73,14 @@ impl<C: Chip> Scheduler<C> for PrioritySched {
         // this app is communicating via IPC with a higher priority app.
         !(chip.has_pending_interrupts()
             || DynamicDeferredCall::global_instance_calls_pending().unwrap_or(false)
-            || self
-                .kernel
-                .get_process_iter()
-                .position(|proc| proc.ready())
-                .map_or(false, |ready_idx| {
-                    self.running
-                        .map_or(false, |running| ready_idx < running.index)
-                }))
+            || self.running.map_or(false, |running| {
+                self.kernel.process_map_or(false, *running, |running| {
+                    let running_rank = self.rank(running);
+                    self.kernel
+                        .get_process_iter()
+                        .any(|proc| proc.ready() && self.rank(proc) < running_rank)
+                })
+            }))
     }
 
     fn result(&self, _: StoppedExecutingReason, _: Option<u32>) {
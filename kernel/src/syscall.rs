@@ -28,7 +28,7 @@ fn u64_to_be_u32s(src: u64) -> (u32, u32) {
 /// These are encoded as 8 bit values as on some architectures the value can
 /// be encoded in the instruction itself.
 #[repr(u8)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum SyscallClass {
     Yield = 0,
     Subscribe = 1,
@@ -37,6 +37,7 @@ pub enum SyscallClass {
     ReadOnlyAllow = 4,
     Memop = 5,
     Exit = 6,
+    UserspaceReadableAllow = 7,
 }
 
 /// Enumeration of the yield system calls based on the Yield identifier
@@ -62,6 +63,7 @@ impl TryFrom<u8> for SyscallClass {
             4 => Ok(SyscallClass::ReadOnlyAllow),
             5 => Ok(SyscallClass::Memop),
             6 => Ok(SyscallClass::Exit),
+            7 => Ok(SyscallClass::UserspaceReadableAllow),
             i => Err(i),
         }
     }
@@ -129,6 +131,17 @@ pub enum Syscall {
         which: usize,
         completion_code: usize,
     },
+
+    /// Structure representing an invocation of the UserspaceReadableAllow
+    /// system call class. `driver_number` is the driver identifier,
+    /// `subdriver_number` is the buffer identifier, `allow_address` is the
+    /// address, and `allow_size` is the size.
+    UserspaceReadableAllow {
+        driver_number: usize,
+        subdriver_number: usize,
+        allow_address: *mut u8,
+        allow_size: usize,
+    },
 }
 
 impl Syscall {
@@ -184,9 +197,96 @@ impl Syscall {
                 which: r0,
                 completion_code: r1,
             }),
+            Ok(SyscallClass::UserspaceReadableAllow) => Some(Syscall::UserspaceReadableAllow {
+                driver_number: r0,
+                subdriver_number: r1,
+                allow_address: r2 as *mut u8,
+                allow_size: r3,
+            }),
             Err(_) => None,
         }
     }
+
+    /// The inverse of `from_register_arguments()`: get the system call class
+    /// and the four register arguments that encode this system call. Unused
+    /// arguments are zero.
+    pub fn to_register_arguments(&self) -> (SyscallClass, [usize; 4]) {
+        match *self {
+            Syscall::Yield { which, address } => {
+                (SyscallClass::Yield, [which, address as usize, 0, 0])
+            }
+            Syscall::Subscribe {
+                driver_number,
+                subdriver_number,
+                upcall_ptr,
+                appdata,
+            } => (
+                SyscallClass::Subscribe,
+                [
+                    driver_number,
+                    subdriver_number,
+                    upcall_ptr as usize,
+                    appdata,
+                ],
+            ),
+            Syscall::Command {
+                driver_number,
+                subdriver_number,
+                arg0,
+                arg1,
+            } => (
+                SyscallClass::Command,
+                [driver_number, subdriver_number, arg0, arg1],
+            ),
+            Syscall::ReadWriteAllow {
+                driver_number,
+                subdriver_number,
+                allow_address,
+                allow_size,
+            } => (
+                SyscallClass::ReadWriteAllow,
+                [
+                    driver_number,
+                    subdriver_number,
+                    allow_address as usize,
+                    allow_size,
+                ],
+            ),
+            Syscall::ReadOnlyAllow {
+                driver_number,
+                subdriver_number,
+                allow_address,
+                allow_size,
+            } => (
+                SyscallClass::ReadOnlyAllow,
+                [
+                    driver_number,
+                    subdriver_number,
+                    allow_address as usize,
+                    allow_size,
+                ],
+            ),
+            Syscall::Memop { operand, arg0 } => (SyscallClass::Memop, [operand, arg0, 0, 0]),
+            Syscall::Exit {
+                which,
+                completion_code,
+            } => (SyscallClass::Exit, [which, completion_code, 0, 0]),
+            Syscall::UserspaceReadableAllow {
+                driver_number,
+                subdriver_number,
+                allow_address,
+                allow_size,
+            } => (
+                SyscallClass::UserspaceReadableAllow,
+                [
+                    driver_number,
+                    subdriver_number,
+                    allow_address as usize,
+                    allow_size,
+                ],
+            ),
+        }
+    }
 }
 
 // ---------- SYSCALL RETURN VALUE ENCODING ----------
@@ -275,6 +375,13 @@ pub enum SyscallReturn {
     /// buffer and size to the process.
     AllowReadOnlyFailure(ErrorCode, *const u8, usize),
 
+    /// Userspace readable allow success case, returns the previous allowed
+    /// buffer and size to the process.
+    UserspaceReadableAllowSuccess(*mut u8, usize),
+    /// Userspace readable allow failure case, returns the passed allowed
+    /// buffer and size to the process.
+    UserspaceReadableAllowFailure(ErrorCode, *mut u8, usize),
+
     /// Subscribe success case, returns the previous upcall function
     /// pointer and application data.
     SubscribeSuccess(*const u8, usize),
@@ -376,6 +483,17 @@ impl SyscallReturn {
                 *a2 = ptr as u32;
                 *a3 = len as u32;
             }
+            &SyscallReturn::UserspaceReadableAllowSuccess(ptr, len) => {
+                *a0 = SyscallReturnVariant::SuccessU32U32 as u32;
+                *a1 = ptr as u32;
+                *a2 = len as u32;
+            }
+            &SyscallReturn::UserspaceReadableAllowFailure(err, ptr, len) => {
+                *a0 = SyscallReturnVariant::FailureU32U32 as u32;
+                *a1 = usize::from(err) as u32;
+                *a2 = ptr as u32;
+                *a3 = len as u32;
+            }
             &SyscallReturn::SubscribeSuccess(ptr, data) => {
                 *a0 = SyscallReturnVariant::SuccessU32U32 as u32;
                 *a1 = ptr as u32;
@@ -586,4 +704,92 @@ pub trait UserspaceKernelBoundary {
         state: &Self::StoredState,
         writer: &mut dyn Write,
     );
+
+    /// Store the architecture specific registers of a process, from the
+    /// stored state for that process, in `out`. The registers are stored as
+    /// little endian 32 bit words, in an order defined by the architecture.
+    /// Registers the architecture keeps on the process stack are not included.
+    ///
+    /// Returns the number of bytes stored, or `SIZE` if `out` is too small.
+    fn store_context(&self, state: &Self::StoredState, out: &mut [u8]) -> Result<usize, ErrorCode>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Syscall, SyscallClass, SyscallReturn, SyscallReturnVariant};
+    use crate::errorcode::ErrorCode;
+
+    /// The registers `result` is encoded into, starting from registers that
+    /// are all `0xdead`.
+    fn encode(result: SyscallReturn) -> [u32; 4] {
+        let mut registers = [0xdead; 4];
+        let [a0, a1, a2, a3] = &mut registers;
+        result.encode_syscall_return(a0, a1, a2, a3);
+        registers
+    }
+
+    #[test]
+    fn userspace_readable_allow_round_trips_through_registers() {
+        let syscall = Syscall::from_register_arguments(7, 0x90000, 2, 0x2000_0100, 16).unwrap();
+        assert_eq!(
+            syscall,
+            Syscall::UserspaceReadableAllow {
+                driver_number: 0x90000,
+                subdriver_number: 2,
+                allow_address: 0x2000_0100 as *mut u8,
+                allow_size: 16,
+            }
+        );
+        assert_eq!(
+            syscall.to_register_arguments(),
+            (
+                SyscallClass::UserspaceReadableAllow,
+                [0x90000, 2, 0x2000_0100, 16]
+            )
+        );
+
+        // There is no system call class after userspace readable allow.
+        assert_eq!(Syscall::from_register_arguments(8, 0x90000, 2, 0, 0), None);
+    }
+
+    #[test]
+    fn userspace_readable_allow_returns_are_encoded_like_allows() {
+        assert_eq!(
+            encode(SyscallReturn::UserspaceReadableAllowSuccess(
+                0x2000_0100 as *mut u8,
+                16
+            )),
+            [
+                SyscallReturnVariant::SuccessU32U32 as u32,
+                0x2000_0100,
+                16,
+                0xdead
+            ]
+        );
+        assert_eq!(
+            encode(SyscallReturn::UserspaceReadableAllowFailure(
+                ErrorCode::INVAL,
+                0x2000_0100 as *mut u8,
+                16
+            )),
+            [
+                SyscallReturnVariant::FailureU32U32 as u32,
+                usize::from(ErrorCode::INVAL) as u32,
+                0x2000_0100,
+                16
+            ]
+        );
+
+        // The same as for a read-write allow.
+        assert_eq!(
+            encode(SyscallReturn::UserspaceReadableAllowSuccess(
+                0x2000_0100 as *mut u8,
+                16
+            )),
+            encode(SyscallReturn::AllowReadWriteSuccess(
+                0x2000_0100 as *mut u8,
+                16
+            ))
+        );
+    }
 }
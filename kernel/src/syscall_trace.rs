@@ -0,0 +1,436 @@
+//! Record the system calls processes make.
+//!
+//! The `trace_syscalls` kernel configuration option prints every system call
+//! with `debug!()`, which requires rebuilding the kernel and is too slow to
+//! leave on for real workloads. Instead, a board can give the kernel a
+//! `SyscallTracer`, which the kernel passes every system call to after
+//! handling it.
+//!
+//! `SyscallTraceBuffer` is a `SyscallTracer` that records the system calls of
+//! selected processes, with a timestamp and the value returned to the process,
+//! in a fixed-size ring buffer. Tracing is started and stopped per process at
+//! runtime, and the recorded system calls can be read through
+//! `SyscallTraceControl`, for example by the process console.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let trace_buffer = static_init!(
+//!     [kernel::syscall_trace::SyscallTraceEntry; 64],
+//!     [kernel::syscall_trace::SyscallTraceEntry::default(); 64]
+//! );
+//! let syscall_trace = static_init!(
+//!     kernel::syscall_trace::SyscallTraceBuffer<'static, VirtualMuxAlarm<'static, Rtc>>,
+//!     kernel::syscall_trace::SyscallTraceBuffer::new(trace_alarm, trace_buffer)
+//! );
+//! board_kernel.set_syscall_tracer(syscall_trace, &process_management_capability);
+//! process_console.set_syscall_trace(syscall_trace);
+//! ```
+
+use core::cell::Cell;
+use core::ptr;
+
+use crate::capabilities::ProcessManagementCapability;
+use crate::common::cells::MapCell;
+use crate::common::{Queue, RingBuffer};
+use crate::errorcode::ErrorCode;
+use crate::hil::time::{Ticks, Time};
+use crate::process::ProcessId;
+use crate::syscall::{Syscall, SyscallReturn};
+
+/// Receives every system call the kernel handles.
+pub trait SyscallTracer {
+    /// Called after the kernel handled `syscall` for the process
+    /// `process_id`. `result` is the value returned to the process, or `None`
+    /// if the system call does not return a value (e.g. yield and exit).
+    fn trace_syscall(&self, process_id: ProcessId, syscall: Syscall, result: Option<SyscallReturn>);
+
+    /// Called when the kernel removed the process `process_id` from the
+    /// processes array. A process loaded later may reuse its index.
+    fn process_removed(&self, _process_id: ProcessId) {}
+}
+
+/// Control which processes are traced, and read the recorded system calls.
+pub trait SyscallTraceControl {
+    /// Start or stop recording the system calls of a process. Returns
+    /// `INVAL` if the process no longer exists, or `SIZE` if the process
+    /// cannot be traced.
+    fn set_tracing(
+        &self,
+        process_id: ProcessId,
+        enabled: bool,
+        capability: &dyn ProcessManagementCapability,
+    ) -> Result<(), ErrorCode>;
+
+    /// Whether the system calls of a process are recorded.
+    fn is_tracing(&self, process_id: ProcessId) -> bool;
+
+    /// Remove and return the oldest recorded system call.
+    fn pop_entry(&self, capability: &dyn ProcessManagementCapability) -> Option<SyscallTraceEntry>;
+
+    /// The number of recorded system calls.
+    fn len(&self) -> usize;
+
+    /// The number of recorded system calls that were overwritten because the
+    /// buffer was full.
+    fn overwritten(&self) -> usize;
+}
+
+/// A recorded system call.
+#[derive(Clone, Copy, Debug)]
+pub struct SyscallTraceEntry {
+    /// The time the system call was handled, in ticks of the tracer's clock.
+    pub timestamp: u32,
+    /// The identifier of the process that made the system call, as returned
+    /// by `ProcessId::id()`.
+    pub process_id: usize,
+    /// The system call.
+    pub syscall: Syscall,
+    /// The value returned to the process, if any.
+    pub result: Option<SyscallReturn>,
+}
+
+impl Default for SyscallTraceEntry {
+    fn default() -> Self {
+        SyscallTraceEntry {
+            timestamp: 0,
+            process_id: 0,
+            syscall: Syscall::Yield {
+                which: 0,
+                address: ptr::null_mut(),
+            },
+            result: None,
+        }
+    }
+}
+
+/// Records the system calls of selected processes in a ring buffer. When the
+/// buffer is full, the oldest entry is overwritten.
+///
+/// Processes are selected by their index in the processes array, so tracing
+/// continues if a process restarts. Tracing stops when the process is
+/// removed, so a process loaded into its index later is not traced. Only the
+/// first `usize::BITS` processes can be traced.
+pub struct SyscallTraceBuffer<'a, T: Time> {
+    time: &'a T,
+    entries: MapCell<RingBuffer<'a, SyscallTraceEntry>>,
+    /// Bitmask of the indices of the processes being traced.
+    traced_processes: Cell<usize>,
+    overwritten: Cell<usize>,
+}
+
+impl<'a, T: Time> SyscallTraceBuffer<'a, T> {
+    /// Create a trace buffer, which stores one less entry than the length of
+    /// `buffer`. No processes are traced initially.
+    pub fn new(time: &'a T, buffer: &'a mut [SyscallTraceEntry]) -> SyscallTraceBuffer<'a, T> {
+        SyscallTraceBuffer {
+            time,
+            entries: MapCell::new(RingBuffer::new(buffer)),
+            traced_processes: Cell::new(0),
+            overwritten: Cell::new(0),
+        }
+    }
+
+    fn process_bit(process_id: ProcessId) -> Option<usize> {
+        1usize.checked_shl(process_id.index as u32)
+    }
+}
+
+impl<T: Time> SyscallTracer for SyscallTraceBuffer<'_, T> {
+    fn trace_syscall(
+        &self,
+        process_id: ProcessId,
+        syscall: Syscall,
+        result: Option<SyscallReturn>,
+    ) {
+        if !self.is_tracing(process_id) {
+            return;
+        }
+
+        let entry = SyscallTraceEntry {
+            timestamp: self.time.now().into_u32(),
+            process_id: process_id.id(),
+            syscall,
+            result,
+        };
+        self.entries.map(|entries| {
+            if entries.push(entry).is_some() {
+                self.overwritten.set(self.overwritten.get() + 1);
+            }
+        });
+    }
+
+    fn process_removed(&self, process_id: ProcessId) {
+        if let Some(bit) = Self::process_bit(process_id) {
+            self.traced_processes
+                .set(self.traced_processes.get() & !bit);
+        }
+    }
+}
+
+impl<T: Time> SyscallTraceControl for SyscallTraceBuffer<'_, T> {
+    fn set_tracing(
+        &self,
+        process_id: ProcessId,
+        enabled: bool,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> Result<(), ErrorCode> {
+        if process_id.index().is_none() {
+            return Err(ErrorCode::INVAL);
+        }
+        let bit = Self::process_bit(process_id).ok_or(ErrorCode::SIZE)?;
+
+        let traced = self.traced_processes.get();
+        self.traced_processes
+            .set(if enabled { traced | bit } else { traced & !bit });
+        Ok(())
+    }
+
+    fn is_tracing(&self, process_id: ProcessId) -> bool {
+        Self::process_bit(process_id).map_or(false, |bit| self.traced_processes.get() & bit != 0)
+    }
+
+    fn pop_entry(
+        &self,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> Option<SyscallTraceEntry> {
+        self.entries.map_or(None, |entries| entries.dequeue())
+    }
+
+    fn len(&self) -> usize {
+        self.entries.map_or(0, |entries| entries.len())
+    }
+
+    fn overwritten(&self) -> usize {
+        self.overwritten.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::Cell;
+    use std::boxed::Box;
+    use std::vec;
+    use std::vec::Vec;
+
+    use crate::capabilities::ProcessManagementCapability;
+    use crate::errorcode::ErrorCode;
+    use crate::hil::time::{Freq1KHz, Ticks32, Time};
+    use crate::process::{Process, ProcessId, ProcessSlot, EMPTY_PROCESS_SLOT};
+    use crate::process_policies::StopFaultPolicy;
+    use crate::process_standard::tests::{
+        tbf_with_minimum_ram, MockChip, MockUserspaceKernelBoundary,
+    };
+    use crate::process_utilities::load_processes;
+    use crate::sched::Kernel;
+    use crate::syscall::{Syscall, SyscallReturn};
+
+    use super::{SyscallTraceBuffer, SyscallTraceControl, SyscallTraceEntry, SyscallTracer};
+
+    struct Capability;
+    unsafe impl ProcessManagementCapability for Capability {}
+
+    /// A clock which only moves when the test sets it.
+    struct MockTime {
+        now: Cell<Ticks32>,
+    }
+
+    impl Time for MockTime {
+        type Frequency = Freq1KHz;
+        type Ticks = Ticks32;
+
+        fn now(&self) -> Ticks32 {
+            self.now.get()
+        }
+    }
+
+    /// A trace buffer that stores `len - 1` entries, with the clock at 0.
+    fn create_trace_buffer(
+        len: usize,
+    ) -> (
+        &'static MockTime,
+        &'static SyscallTraceBuffer<'static, MockTime>,
+    ) {
+        let time: &'static MockTime = Box::leak(Box::new(MockTime {
+            now: Cell::new(Ticks32::from(0)),
+        }));
+        let buffer = Box::leak(vec![SyscallTraceEntry::default(); len].into_boxed_slice());
+        (
+            time,
+            Box::leak(Box::new(SyscallTraceBuffer::new(time, buffer))),
+        )
+    }
+
+    /// A kernel with `num_slots` empty process slots.
+    fn create_kernel(num_slots: usize) -> (&'static Kernel, &'static [ProcessSlot]) {
+        let procs: &'static [ProcessSlot] = Box::leak(
+            (0..num_slots)
+                .map(|_| EMPTY_PROCESS_SLOT)
+                .collect::<Vec<_>>()
+                .into_boxed_slice(),
+        );
+        (Box::leak(Box::new(Kernel::new(procs))), procs)
+    }
+
+    /// Load `count` processes into `procs`, the processes of `kernel`, at
+    /// boot.
+    fn load(
+        kernel: &'static Kernel,
+        procs: &'static [ProcessSlot],
+        count: usize,
+    ) -> Vec<&'static dyn Process> {
+        let chip: &'static MockChip = Box::leak(Box::new(MockChip {
+            userspace_kernel_boundary: MockUserspaceKernelBoundary {},
+        }));
+        let flash: Vec<u8> = (0..count)
+            .flat_map(|_| tbf_with_minimum_ram(0, &[]).iter().copied())
+            .collect();
+        // Word-aligned memory, as an MPU would allocate.
+        let memory: &'static mut [u64] = Box::leak(vec![0u64; count * 1024].into_boxed_slice());
+        let memory = unsafe {
+            core::slice::from_raw_parts_mut(memory.as_mut_ptr() as *mut u8, memory.len() * 8)
+        };
+        load_processes(
+            kernel,
+            chip,
+            Box::leak(flash.into_boxed_slice()),
+            memory,
+            &StopFaultPolicy {},
+            &Capability,
+        )
+        .unwrap();
+        procs.iter().filter_map(|slot| slot.get()).collect()
+    }
+
+    fn command(arg0: usize) -> Syscall {
+        Syscall::Command {
+            driver_number: 0,
+            subdriver_number: 1,
+            arg0,
+            arg1: 0,
+        }
+    }
+
+    #[test]
+    fn ring_overwrites_oldest_entries() {
+        let (kernel, procs) = create_kernel(1);
+        let process = load(kernel, procs, 1)[0];
+        let (time, trace) = create_trace_buffer(4);
+        trace
+            .set_tracing(process.processid(), true, &Capability)
+            .unwrap();
+
+        for i in 0..5 {
+            time.now.set(Ticks32::from(i as u32 * 10));
+            trace.trace_syscall(
+                process.processid(),
+                command(i),
+                Some(SyscallReturn::Success),
+            );
+        }
+        assert_eq!(trace.len(), 3);
+        assert_eq!(trace.overwritten(), 2);
+
+        for i in 2..5 {
+            let entry = trace.pop_entry(&Capability).unwrap();
+            assert_eq!(entry.timestamp, i as u32 * 10);
+            assert_eq!(entry.process_id, process.processid().id());
+            assert_eq!(entry.syscall, command(i));
+        }
+        assert!(trace.pop_entry(&Capability).is_none());
+        assert_eq!(trace.len(), 0);
+
+        // Entries that were read are not counted as overwritten.
+        for i in 0..3 {
+            trace.trace_syscall(process.processid(), command(i), None);
+        }
+        assert_eq!(trace.overwritten(), 2);
+    }
+
+    #[test]
+    fn traces_only_enabled_processes() {
+        let (kernel, procs) = create_kernel(2);
+        let processes = load(kernel, procs, 2);
+        let (traced, other) = (processes[0], processes[1]);
+        let (_, trace) = create_trace_buffer(8);
+
+        assert!(!trace.is_tracing(traced.processid()));
+        trace.trace_syscall(traced.processid(), command(0), None);
+        assert_eq!(trace.len(), 0);
+
+        trace
+            .set_tracing(traced.processid(), true, &Capability)
+            .unwrap();
+        assert!(trace.is_tracing(traced.processid()));
+        assert!(!trace.is_tracing(other.processid()));
+        trace.trace_syscall(traced.processid(), command(1), None);
+        trace.trace_syscall(other.processid(), command(2), None);
+        assert_eq!(trace.len(), 1);
+        assert_eq!(
+            trace.pop_entry(&Capability).unwrap().process_id,
+            traced.processid().id()
+        );
+
+        trace
+            .set_tracing(traced.processid(), false, &Capability)
+            .unwrap();
+        assert!(!trace.is_tracing(traced.processid()));
+        trace.trace_syscall(traced.processid(), command(3), None);
+        assert_eq!(trace.len(), 0);
+    }
+
+    #[test]
+    fn indices_beyond_bitmask_cannot_be_traced() {
+        let bits = usize::BITS as usize;
+        let (kernel, procs) = create_kernel(bits + 1);
+        let process = load(kernel, procs, 1)[0];
+        let (_, trace) = create_trace_buffer(8);
+
+        // Put the same process in the last slot, so that a `ProcessId` for
+        // that slot is valid.
+        kernel.add_process(bits, process, &Capability).unwrap();
+        let last = ProcessId::new(kernel, process.processid().id(), bits);
+        assert_eq!(last.index(), Some(bits));
+
+        assert_eq!(
+            trace.set_tracing(last, true, &Capability),
+            Err(ErrorCode::SIZE)
+        );
+        assert!(!trace.is_tracing(last));
+        trace.trace_syscall(last, command(0), None);
+        assert_eq!(trace.len(), 0);
+
+        // The first process still can be traced.
+        assert_eq!(
+            trace.set_tracing(process.processid(), true, &Capability),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn removed_process_is_no_longer_traced() {
+        let (kernel, procs) = create_kernel(1);
+        let process = load(kernel, procs, 1)[0];
+        let (_, trace) = create_trace_buffer(8);
+        kernel.set_syscall_tracer(trace, &Capability);
+        trace
+            .set_tracing(process.processid(), true, &Capability)
+            .unwrap();
+
+        kernel
+            .remove_process(process.processid(), &Capability)
+            .unwrap();
+        assert_eq!(
+            trace.set_tracing(process.processid(), true, &Capability),
+            Err(ErrorCode::INVAL)
+        );
+
+        // A process loaded into the same slot is not traced.
+        let reloaded = load(kernel, procs, 1)[0];
+        assert_eq!(reloaded.processid().index(), Some(0));
+        assert!(!trace.is_tracing(reloaded.processid()));
+    }
+}
@@ -100,11 +100,11 @@
 //! // when appending a key:
 //!
 //! // Add a key
-//! static VALUE: [u8; 32] = [0x23; 32];
-//! let ret = unsafe { tickv.append_key(get_hashed_key(b"ONE"), &VALUE) };
+//! static mut VALUE: [u8; 32] = [0x23; 32];
+//! let ret = unsafe { tickv.append_key(get_hashed_key(b"ONE"), &mut VALUE, 32) };
 //!
 //! match ret {
-//!     Err(ErrorCode::ReadNotReady(reg)) => {
+//!     Err((_, ErrorCode::ReadNotReady(reg))) => {
 //!         // There is no actual delay in the test, just continue now
 //!         tickv.set_read_buffer(&tickv.tickv.controller.buf.borrow()[reg]);
 //!         tickv
@@ -126,7 +126,7 @@
 use crate::error_codes::ErrorCode;
 use crate::flash_controller::FlashController;
 use crate::success_codes::SuccessCode;
-use crate::tickv::{State, TicKV};
+use crate::tickv::{KeyInfo, State, TicKV};
 use core::cell::Cell;
 
 /// The return type from the continue operation
@@ -142,8 +142,11 @@ pub struct AsyncTicKV<'a, C: FlashController<S>, const S: usize> {
     /// The main TicKV struct
     pub tickv: TicKV<'a, C, S>,
     key: Cell<Option<u64>>,
-    value: Cell<Option<&'static [u8]>>,
+    value: Cell<Option<&'static mut [u8]>>,
+    value_length: Cell<usize>,
     buf: Cell<Option<&'static mut [u8]>>,
+    start: Cell<usize>,
+    key_info: Cell<Option<KeyInfo>>,
 }
 
 impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
@@ -158,12 +161,16 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
             tickv: TicKV::<C, S>::new(controller, read_buffer, flash_size),
             key: Cell::new(None),
             value: Cell::new(None),
+            value_length: Cell::new(0),
             buf: Cell::new(None),
+            start: Cell::new(0),
+            key_info: Cell::new(None),
         }
     }
 
     /// This function setups the flash region to be used as a key-value store.
-    /// If the region is already initalised this won't make any changes.
+    /// If the region is already initalised this won't make any changes,
+    /// other than finishing an update that was interrupted by a power loss.
     ///
     /// `hashed_main_key`: The u64 hash of the const string `MAIN_KEY`.
     ///
@@ -171,7 +178,8 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
     /// the entire region will be erased.
     ///
     /// On success a `SuccessCode` will be returned.
-    /// On error a `ErrorCode` will be returned.
+    /// On error a `ErrorCode` will be returned. `WriteNotReady` means the
+    /// operation must be continued once the write has completed.
     pub fn initalise(&self, hashed_main_key: u64) -> Result<SuccessCode, ErrorCode> {
         self.key.replace(Some(hashed_main_key));
         self.tickv.initalise(hashed_main_key)
@@ -182,18 +190,26 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
     /// `hash`: A hashed key. This key will be used in future to retrieve
     ///         or remove the `value`.
     /// `value`: A buffer containing the data to be stored to flash.
+    /// `length`: The number of bytes of `value` to store.
     ///
-    /// On success nothing will be returned.
-    /// On error a `ErrorCode` will be returned.
-    pub fn append_key(&self, hash: u64, value: &'static [u8]) -> Result<SuccessCode, ErrorCode> {
-        match self.tickv.append_key(hash, value) {
-            Ok(code) => Ok(code),
-            Err(e) => {
-                self.key.replace(Some(hash));
-                self.value.replace(Some(value));
-                Err(e)
-            }
+    /// On success a `SuccessCode` will be returned.
+    /// On error a `ErrorCode` will be returned, along with the `value`
+    /// buffer unless the operation can be continued.
+    ///
+    /// The `value` buffer is kept until the operation has completed, it
+    /// can then be retrieved with `get_stored_value_buffer()`.
+    pub fn append_key(
+        &self,
+        hash: u64,
+        value: &'static mut [u8],
+        length: usize,
+    ) -> Result<SuccessCode, (Option<&'static mut [u8]>, ErrorCode)> {
+        if length > value.len() {
+            return Err((Some(value), ErrorCode::BufferTooSmall(length)));
         }
+
+        let ret = self.tickv.append_key(hash, &value[..length]);
+        self.store_value(hash, value, length, ret)
     }
 
     /// Retrieves the value from flash storage.
@@ -246,6 +262,58 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
         }
     }
 
+    /// Replaces the value of a key in flash storage.
+    ///
+    /// `hash`: A hashed key that is already stored.
+    /// `value`: A buffer containing the new value to be stored to flash.
+    /// `length`: The number of bytes of `value` to store.
+    ///
+    /// On success a `SuccessCode` will be returned.
+    /// On error a `ErrorCode` will be returned, along with the `value`
+    /// buffer unless the operation can be continued. Unlike the other
+    /// operations an update writes to flash more than once, so on
+    /// `WriteNotReady` `continue_operation()` must be called once the write
+    /// has completed.
+    ///
+    /// The `value` buffer is kept until the operation has completed, it
+    /// can then be retrieved with `get_stored_value_buffer()`.
+    pub fn update_key(
+        &self,
+        hash: u64,
+        value: &'static mut [u8],
+        length: usize,
+    ) -> Result<SuccessCode, (Option<&'static mut [u8]>, ErrorCode)> {
+        if length > value.len() {
+            return Err((Some(value), ErrorCode::BufferTooSmall(length)));
+        }
+
+        let ret = self.tickv.update_key(hash, &value[..length]);
+        self.store_value(hash, value, length, ret)
+    }
+
+    /// Keeps the `value` buffer of an append or update that has completed
+    /// or can be continued, otherwise hands it back with the error.
+    fn store_value(
+        &self,
+        hash: u64,
+        value: &'static mut [u8],
+        length: usize,
+        ret: Result<SuccessCode, ErrorCode>,
+    ) -> Result<SuccessCode, (Option<&'static mut [u8]>, ErrorCode)> {
+        match ret {
+            Ok(_)
+            | Err(ErrorCode::ReadNotReady(_))
+            | Err(ErrorCode::EraseNotReady(_))
+            | Err(ErrorCode::WriteNotReady(_)) => {
+                self.key.replace(Some(hash));
+                self.value.replace(Some(value));
+                self.value_length.set(length);
+                ret.map_err(|e| (None, e))
+            }
+            Err(e) => Err((Some(value), e)),
+        }
+    }
+
     /// Perform a garbage collection on TicKV
     ///
     /// On success the number of bytes freed will be returned.
@@ -254,6 +322,30 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
         self.tickv.garbage_collect()
     }
 
+    /// Finds the first valid object at or after the flash offset `start`.
+    ///
+    /// `start`: The offset from the start of the TicKV flash to look from.
+    ///          Use 0 to find the first object and the `next` field of the
+    ///          returned `KeyInfo` to find the following ones.
+    ///
+    /// On success the `KeyInfo` of the object will be returned.
+    /// On error a `ErrorCode` will be returned. `KeyNotFound` means there
+    /// are no more valid objects. Once the operation succeeds, either
+    /// straight away or after `continue_operation()`, the `KeyInfo` can
+    /// also be retrieved with `get_stored_key_info()`.
+    pub fn next_key(&self, start: usize) -> Result<KeyInfo, ErrorCode> {
+        match self.tickv.next_key(start) {
+            Ok(key_info) => {
+                self.key_info.set(Some(key_info));
+                Ok(key_info)
+            }
+            Err(e) => {
+                self.start.set(start);
+                Err(e)
+            }
+        }
+    }
+
     /// Copy data from `read_buffer` argument to the internal read_buffer.
     /// This should be used to copy the data that the implementation wanted
     /// to read when calling `read_region` after the async operation has
@@ -266,7 +358,7 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
 
     /// Get the `value` buffer that was passed in by previous
     /// commands.
-    pub fn get_stored_value_buffer(&self) -> Option<&'static [u8]> {
+    pub fn get_stored_value_buffer(&self) -> Option<&'static mut [u8]> {
         self.value.take()
     }
 
@@ -276,6 +368,12 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
         self.buf.take()
     }
 
+    /// Get the `KeyInfo` found by a `next_key()` operation that was
+    /// completed by `continue_operation()`.
+    pub fn get_stored_key_info(&self) -> Option<KeyInfo> {
+        self.key_info.take()
+    }
+
     /// Continue the last operation after the async operation has completed.
     /// This should be called from a read/erase complete callback.
     /// NOTE: If called from a read callback, `set_read_buffer` should be
@@ -294,9 +392,14 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
     pub fn continue_operation(&self) -> ContinueReturn {
         let ret = match self.tickv.state.get() {
             State::Init(_) => self.tickv.initalise(self.key.get().unwrap()),
-            State::AppendKey(_) => self
-                .tickv
-                .append_key(self.key.get().unwrap(), self.value.get().unwrap()),
+            State::AppendKey(_) => {
+                let value = self.value.take().unwrap();
+                let ret = self
+                    .tickv
+                    .append_key(self.key.get().unwrap(), &value[..self.value_length.get()]);
+                self.value.replace(Some(value));
+                ret
+            }
             State::GetKey(_) => {
                 let buf = self.buf.take().unwrap();
                 let ret = self.tickv.get_key(self.key.get().unwrap(), buf);
@@ -304,10 +407,25 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
                 ret
             }
             State::InvalidateKey(_) => self.tickv.invalidate_key(self.key.get().unwrap()),
+            State::UpdateKey(_) => {
+                let value = self.value.take().unwrap();
+                let ret = self
+                    .tickv
+                    .update_key(self.key.get().unwrap(), &value[..self.value_length.get()]);
+                self.value.replace(Some(value));
+                ret
+            }
             State::GarbageCollect(_) => match self.tickv.garbage_collect() {
                 Ok(_) => Ok(SuccessCode::Complete),
                 Err(e) => Err(e),
             },
+            State::NextKey(_) => match self.tickv.next_key(self.start.get()) {
+                Ok(key_info) => {
+                    self.key_info.set(Some(key_info));
+                    Ok(SuccessCode::Complete)
+                }
+                Err(e) => Err(e),
+            },
             _ => unreachable!(),
         };
 
@@ -319,7 +437,11 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
             Err(e) => match e {
                 ErrorCode::ReadNotReady(_) | ErrorCode::EraseNotReady(_) => (ret, None),
                 ErrorCode::WriteNotReady(_) => {
-                    self.tickv.state.set(State::None);
+                    match self.tickv.state.get() {
+                        // These continue once the write has completed
+                        State::Init(_) | State::UpdateKey(_) => {}
+                        _ => self.tickv.state.set(State::None),
+                    }
                     (ret, None)
                 }
                 _ => {
@@ -342,6 +464,7 @@ mod store_flast_ctrl {
     use std::cell::Cell;
     use std::cell::RefCell;
     use std::collections::hash_map::DefaultHasher;
+    use std::vec::Vec;
 
     fn check_region_main(buf: &[u8]) {
         // Check the version
@@ -542,11 +665,12 @@ mod store_flast_ctrl {
             ret = r;
         }
 
-        static VALUE: [u8; 32] = [0x23; 32];
+        static mut VALUE: [u8; 32] = [0x23; 32];
 
-        let ret = tickv.append_key(get_hashed_key(b"ONE"), &VALUE);
+        #[allow(unsafe_code)]
+        let ret = unsafe { tickv.append_key(get_hashed_key(b"ONE"), &mut VALUE, 32) };
         match ret {
-            Err(ErrorCode::ReadNotReady(reg)) => {
+            Err((_, ErrorCode::ReadNotReady(reg))) => {
                 // There is no actual delay in the test, just continue now
                 tickv.set_read_buffer(&tickv.tickv.controller.buf.borrow()[reg]);
                 tickv.continue_operation().0.unwrap();
@@ -555,9 +679,10 @@ mod store_flast_ctrl {
             _ => unreachable!(),
         }
 
-        let ret = tickv.append_key(get_hashed_key(b"TWO"), &VALUE);
+        #[allow(unsafe_code)]
+        let ret = unsafe { tickv.append_key(get_hashed_key(b"TWO"), &mut VALUE, 32) };
         match ret {
-            Err(ErrorCode::ReadNotReady(reg)) => {
+            Err((_, ErrorCode::ReadNotReady(reg))) => {
                 // There is no actual delay in the test, just continue now
                 tickv.set_read_buffer(&tickv.tickv.controller.buf.borrow()[reg]);
                 tickv.continue_operation().0.unwrap();
@@ -582,13 +707,14 @@ mod store_flast_ctrl {
             ret = r;
         }
 
-        static VALUE: [u8; 32] = [0x23; 32];
+        static mut VALUE: [u8; 32] = [0x23; 32];
         static mut BUF: [u8; 32] = [0; 32];
 
         println!("Add key ONE");
-        let ret = tickv.append_key(get_hashed_key(b"ONE"), &VALUE);
+        #[allow(unsafe_code)]
+        let ret = unsafe { tickv.append_key(get_hashed_key(b"ONE"), &mut VALUE, 32) };
         match ret {
-            Err(ErrorCode::ReadNotReady(reg)) => {
+            Err((_, ErrorCode::ReadNotReady(reg))) => {
                 // There is no actual delay in the test, just continue now
                 tickv.set_read_buffer(&tickv.tickv.controller.buf.borrow()[reg]);
                 tickv.continue_operation().0.unwrap();
@@ -617,9 +743,10 @@ mod store_flast_ctrl {
         }
 
         println!("Add key ONE again");
-        let ret = tickv.append_key(get_hashed_key(b"ONE"), &VALUE);
+        #[allow(unsafe_code)]
+        let ret = unsafe { tickv.append_key(get_hashed_key(b"ONE"), &mut VALUE, 32) };
         match ret {
-            Err(ErrorCode::ReadNotReady(reg)) => {
+            Err((_, ErrorCode::ReadNotReady(reg))) => {
                 // There is no actual delay in the test, just continue now
                 tickv.set_read_buffer(&tickv.tickv.controller.buf.borrow()[reg]);
                 assert_eq!(
@@ -627,14 +754,15 @@ mod store_flast_ctrl {
                     Err(ErrorCode::KeyAlreadyExists)
                 );
             }
-            Err(ErrorCode::KeyAlreadyExists) => {}
+            Err((_, ErrorCode::KeyAlreadyExists)) => {}
             _ => unreachable!(),
         }
 
         println!("Add key TWO");
-        let ret = tickv.append_key(get_hashed_key(b"TWO"), &VALUE);
+        #[allow(unsafe_code)]
+        let ret = unsafe { tickv.append_key(get_hashed_key(b"TWO"), &mut VALUE, 32) };
         match ret {
-            Err(ErrorCode::ReadNotReady(reg)) => {
+            Err((_, ErrorCode::ReadNotReady(reg))) => {
                 // There is no actual delay in the test, just continue now
                 tickv.set_read_buffer(&tickv.tickv.controller.buf.borrow()[reg]);
                 tickv.continue_operation().0.unwrap();
@@ -707,13 +835,14 @@ mod store_flast_ctrl {
             ret = r;
         }
 
-        static VALUE: [u8; 32] = [0x23; 32];
+        static mut VALUE: [u8; 32] = [0x23; 32];
         static mut BUF: [u8; 32] = [0; 32];
 
         println!("Add key ONE");
-        let ret = tickv.append_key(get_hashed_key(b"ONE"), &VALUE);
+        #[allow(unsafe_code)]
+        let ret = unsafe { tickv.append_key(get_hashed_key(b"ONE"), &mut VALUE, 32) };
         match ret {
-            Err(ErrorCode::ReadNotReady(reg)) => {
+            Err((_, ErrorCode::ReadNotReady(reg))) => {
                 // There is no actual delay in the test, just continue now
                 tickv.set_read_buffer(&tickv.tickv.controller.buf.borrow()[reg]);
                 tickv.continue_operation().0.unwrap();
@@ -764,7 +893,7 @@ mod store_flast_ctrl {
             ret = r;
         }
 
-        static VALUE: [u8; 32] = [0x23; 32];
+        static mut VALUE: [u8; 32] = [0x23; 32];
         static mut BUF: [u8; 32] = [0; 32];
 
         println!("Garbage collect empty flash");
@@ -778,9 +907,10 @@ mod store_flast_ctrl {
         }
 
         println!("Add key ONE");
-        let ret = tickv.append_key(get_hashed_key(b"ONE"), &VALUE);
+        #[allow(unsafe_code)]
+        let ret = unsafe { tickv.append_key(get_hashed_key(b"ONE"), &mut VALUE, 32) };
         match ret {
-            Err(ErrorCode::ReadNotReady(reg)) => {
+            Err((_, ErrorCode::ReadNotReady(reg))) => {
                 // There is no actual delay in the test, just continue now
                 tickv.set_read_buffer(&tickv.tickv.controller.buf.borrow()[reg]);
                 tickv.continue_operation().0.unwrap();
@@ -860,6 +990,193 @@ mod store_flast_ctrl {
         }
 
         println!("Add Key ONE");
-        tickv.append_key(get_hashed_key(b"ONE"), &VALUE).unwrap();
+        #[allow(unsafe_code)]
+        unsafe {
+            tickv
+                .append_key(get_hashed_key(b"ONE"), &mut VALUE, 32)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_next_key() {
+        let mut read_buf: [u8; 1024] = [0; 1024];
+        let mut hash_function = DefaultHasher::new();
+        MAIN_KEY.hash(&mut hash_function);
+        let main_key = hash_function.finish();
+
+        let tickv = AsyncTicKV::<FlashCtrl, 1024>::new(FlashCtrl::new(), &mut read_buf, 0x10000);
+
+        let mut ret = tickv.initalise(main_key);
+        while ret.is_err() {
+            // There is no actual delay in the test, just continue now
+            let (r, _buf) = tickv.continue_operation();
+            ret = r;
+        }
+
+        static mut VALUE: [u8; 32] = [0x23; 32];
+
+        println!("Add key ONE");
+        #[allow(unsafe_code)]
+        let ret = unsafe { tickv.append_key(get_hashed_key(b"ONE"), &mut VALUE, 32) };
+        match ret {
+            Err((_, ErrorCode::ReadNotReady(reg))) => {
+                // There is no actual delay in the test, just continue now
+                tickv.set_read_buffer(&tickv.tickv.controller.buf.borrow()[reg]);
+                tickv.continue_operation().0.unwrap();
+            }
+            Ok(_) => {}
+            _ => unreachable!(),
+        }
+
+        println!("List all keys");
+        let mut keys = Vec::new();
+        let mut start = 0;
+        loop {
+            let mut ret = tickv.next_key(start);
+            while let Err(ErrorCode::ReadNotReady(reg)) = ret {
+                // There is no actual delay in the test, just continue now
+                tickv.set_read_buffer(&tickv.tickv.controller.buf.borrow()[reg]);
+                ret = match tickv.continue_operation().0 {
+                    Ok(_) => Ok(tickv.get_stored_key_info().unwrap()),
+                    Err(e) => Err(e),
+                };
+            }
+            match ret {
+                Ok(key_info) => {
+                    keys.push((key_info.hashed_key, key_info.value_length));
+                    start = key_info.next;
+                }
+                Err(ErrorCode::KeyNotFound) => break,
+                _ => unreachable!("ret: {:?}", ret),
+            }
+        }
+        keys.sort();
+        let mut expected = vec![(main_key, 0), (get_hashed_key(b"ONE"), 32)];
+        expected.sort();
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn test_next_key_stores_key_info() {
+        let mut read_buf: [u8; 1024] = [0; 1024];
+        let mut hash_function = DefaultHasher::new();
+        MAIN_KEY.hash(&mut hash_function);
+        let main_key = hash_function.finish();
+
+        let tickv = AsyncTicKV::<FlashCtrl, 1024>::new(FlashCtrl::new(), &mut read_buf, 0x10000);
+
+        let mut ret = tickv.initalise(main_key);
+        while ret.is_err() {
+            // There is no actual delay in the test, just continue now
+            let (r, _buf) = tickv.continue_operation();
+            ret = r;
+        }
+
+        // The regions have to be read first
+        let mut ret = tickv
+            .next_key(0)
+            .map(|_| crate::success_codes::SuccessCode::Complete);
+        while let Err(ErrorCode::ReadNotReady(reg)) = ret {
+            tickv.set_read_buffer(&tickv.tickv.controller.buf.borrow()[reg]);
+            ret = tickv.continue_operation().0;
+        }
+        ret.unwrap();
+        let key_info = tickv.get_stored_key_info().unwrap();
+        assert_eq!(key_info.hashed_key, main_key);
+        assert_eq!(tickv.get_stored_key_info(), None);
+
+        // The region of the key was just read, so this succeeds straight away
+        let start = key_info.region * 1024;
+        assert_eq!(tickv.next_key(start), Ok(key_info));
+        assert_eq!(tickv.get_stored_key_info(), Some(key_info));
+    }
+
+    #[test]
+    fn test_update_key() {
+        let mut read_buf: [u8; 1024] = [0; 1024];
+        let mut hash_function = DefaultHasher::new();
+        MAIN_KEY.hash(&mut hash_function);
+
+        let tickv = AsyncTicKV::<FlashCtrl, 1024>::new(FlashCtrl::new(), &mut read_buf, 0x10000);
+
+        let mut ret = tickv.initalise(hash_function.finish());
+        while ret.is_err() {
+            // There is no actual delay in the test, just continue now
+            let (r, _buf) = tickv.continue_operation();
+            ret = r;
+        }
+
+        static mut VALUE: [u8; 32] = [0x23; 32];
+        static mut NEW_VALUE: [u8; 32] = [0x42; 32];
+
+        for key in [b"ONE", b"TWO"].iter() {
+            println!("Add key {:?}", key);
+            #[allow(unsafe_code)]
+            let ret = unsafe { tickv.append_key(get_hashed_key(*key), &mut VALUE, 32) };
+            match ret {
+                Err((_, ErrorCode::ReadNotReady(reg))) => {
+                    // There is no actual delay in the test, just continue now
+                    tickv.set_read_buffer(&tickv.tickv.controller.buf.borrow()[reg]);
+                    tickv.continue_operation().0.unwrap();
+                }
+                Ok(_) => {}
+                _ => unreachable!(),
+            }
+        }
+
+        println!("Update key ONE");
+        #[allow(unsafe_code)]
+        let mut ret = unsafe {
+            tickv
+                .update_key(get_hashed_key(b"ONE"), &mut NEW_VALUE, 32)
+                .map_err(|(_, e)| e)
+        };
+        loop {
+            match ret {
+                Err(ErrorCode::ReadNotReady(reg)) => {
+                    // There is no actual delay in the test, just continue now
+                    tickv.set_read_buffer(&tickv.tickv.controller.buf.borrow()[reg]);
+                    ret = tickv.continue_operation().0;
+                }
+                Ok(_) => break,
+                _ => unreachable!("ret: {:?}", ret),
+            }
+        }
+        assert!(tickv.get_stored_value_buffer().is_some());
+
+        println!("Get key ONE");
+        static mut BUF: [u8; 32] = [0; 32];
+        #[allow(unsafe_code)]
+        let ret = unsafe { tickv.get_key(get_hashed_key(b"ONE"), &mut BUF) };
+        match ret {
+            Err((_, ErrorCode::ReadNotReady(reg))) => {
+                // There is no actual delay in the test, just continue now
+                tickv.set_read_buffer(&tickv.tickv.controller.buf.borrow()[reg]);
+                let (ret, buf) = tickv.continue_operation();
+                ret.unwrap();
+                assert_eq!(buf.unwrap(), &[0x42; 32]);
+            }
+            Ok(_) => {
+                #[allow(unsafe_code)]
+                let buf = unsafe { BUF };
+                assert_eq!(buf, [0x42; 32]);
+            }
+            _ => unreachable!(),
+        }
+
+        println!("Update non-existant key THREE");
+        #[allow(unsafe_code)]
+        let mut ret = unsafe {
+            tickv
+                .update_key(get_hashed_key(b"THREE"), &mut NEW_VALUE, 32)
+                .map_err(|(_, e)| e)
+        };
+        while let Err(ErrorCode::ReadNotReady(reg)) = ret {
+            // There is no actual delay in the test, just continue now
+            tickv.set_read_buffer(&tickv.tickv.controller.buf.borrow()[reg]);
+            ret = tickv.continue_operation().0;
+        }
+        assert_eq!(ret, Err(ErrorCode::KeyNotFound));
     }
 }
@@ -32,8 +32,8 @@
 //! ## ACID characteristics
 //!
 //! TicKV provides ACID properties. For the purpose of ACID a transaction is a
-//! key operation, that is finding, adding, invalidating or fully removing
-//! (garbage collection) a key.
+//! key operation, that is finding, adding, updating, invalidating or fully
+//! removing (garbage collection) a key.
 //!
 //! To provide ACIS characteristics TicKV requires that the `FlashController`
 //! implementation complete all transactions in a single operation. That is the
@@ -178,6 +178,12 @@
 //! before it has completed then the operation probably did not complete and
 //! that data is lost.
 //!
+//! Replacing a value with `invalidate_key()` followed by `append_key()` loses
+//! the key if the power is lost in between. `update_key()` writes the new value
+//! before invalidating the old one, and `initalise()` finishes an update that
+//! was interrupted by a power loss, so the key keeps either the old or the new
+//! value.
+//!
 //! To help reduce this time to be as short as possible the `FlashController`
 //! is synchronous. Although flash writes can take a considerable amount of time
 //! and this will stall the application, this still seems like a good idea
@@ -222,6 +228,8 @@ pub use crate::error_codes::ErrorCode;
 #[doc(inline)]
 pub use crate::flash_controller::FlashController;
 #[doc(inline)]
+pub use crate::tickv::KeyInfo;
+#[doc(inline)]
 pub use crate::tickv::TicKV;
 pub use crate::tickv::MAIN_KEY;
 
@@ -5,6 +5,7 @@ use core::hash::{Hash, Hasher};
 use std::cell::Cell;
 use std::cell::RefCell;
 use std::collections::hash_map::DefaultHasher;
+use std::vec::Vec;
 
 fn check_region_main(buf: &[u8]) {
     // Check the version
@@ -414,6 +415,62 @@ mod store_flast_ctrl {
         println!("Add Key ONE");
         tickv.append_key(get_hashed_key(b"ONE"), &value).unwrap();
     }
+
+    #[test]
+    fn test_next_key() {
+        let mut read_buf: [u8; 1024] = [0; 1024];
+        let mut hash_function = DefaultHasher::new();
+        MAIN_KEY.hash(&mut hash_function);
+        let hash = hash_function.finish();
+
+        let tickv = TicKV::<FlashCtrl, 1024>::new(FlashCtrl::new(), &mut read_buf, 0x10000);
+        tickv.initalise(hash).unwrap();
+
+        let value: [u8; 32] = [0x23; 32];
+
+        println!("Add Keys ONE and TWO");
+        tickv.append_key(get_hashed_key(b"ONE"), &value).unwrap();
+        tickv.append_key(get_hashed_key(b"TWO"), &value).unwrap();
+
+        let list_keys = || {
+            let mut keys = Vec::new();
+            let mut start = 0;
+            loop {
+                match tickv.next_key(start) {
+                    Ok(key_info) => {
+                        assert_eq!(
+                            key_info.region,
+                            (key_info.hashed_key as usize & 0xFFFF) % 64
+                        );
+                        keys.push((key_info.hashed_key, key_info.value_length));
+                        start = key_info.next;
+                    }
+                    Err(ErrorCode::KeyNotFound) => return keys,
+                    Err(e) => panic!("Error listing keys: {:?}", e),
+                }
+            }
+        };
+
+        println!("List all keys");
+        let mut keys = list_keys();
+        keys.sort();
+        let mut expected = vec![
+            (hash, 0),
+            (get_hashed_key(b"ONE"), 32),
+            (get_hashed_key(b"TWO"), 32),
+        ];
+        expected.sort();
+        assert_eq!(keys, expected);
+
+        println!("Delete Key ONE and list keys again");
+        tickv.invalidate_key(get_hashed_key(b"ONE")).unwrap();
+        let keys = list_keys();
+        assert_eq!(keys.len(), 2);
+        assert!(!keys.contains(&(get_hashed_key(b"ONE"), 32)));
+
+        println!("Start past the end of flash");
+        assert_eq!(tickv.next_key(0x10000), Err(ErrorCode::KeyNotFound));
+    }
 }
 
 mod no_check_store_flast_ctrl {
@@ -558,3 +615,223 @@ mod no_check_store_flast_ctrl {
         );
     }
 }
+
+/// Tests that lose power at every write of an update
+mod power_loss_flash_ctrl {
+    use super::*;
+    use crate::success_codes::SuccessCode;
+
+    type Flash = [[u8; 1024]; 64];
+
+    static VALUE: [u8; 32] = [0x23; 32];
+    static NEW_VALUE: [u8; 32] = [0x42; 32];
+
+    // A FlashCtrl implementation that loses power at a write
+    struct FlashCtrl {
+        buf: RefCell<Flash>,
+        writes: Cell<usize>,
+        // The write that loses power and if it is half written
+        power_loss: Option<(usize, bool)>,
+    }
+
+    impl FlashCtrl {
+        fn new(buf: Flash, power_loss: Option<(usize, bool)>) -> Self {
+            Self {
+                buf: RefCell::new(buf),
+                writes: Cell::new(0),
+                power_loss,
+            }
+        }
+    }
+
+    impl FlashController<1024> for FlashCtrl {
+        fn read_region(
+            &self,
+            region_number: usize,
+            offset: usize,
+            buf: &mut [u8; 1024],
+        ) -> Result<(), ErrorCode> {
+            for (i, b) in buf.iter_mut().enumerate() {
+                *b = self.buf.borrow()[region_number][offset + i]
+            }
+
+            Ok(())
+        }
+
+        fn write(&self, address: usize, buf: &[u8]) -> Result<(), ErrorCode> {
+            let write = self.writes.get();
+            self.writes.set(write + 1);
+
+            let len = match self.power_loss {
+                Some((at, true)) if write == at => buf.len() / 2,
+                Some((at, _)) if write >= at => 0,
+                _ => buf.len(),
+            };
+            println!(
+                "Write {} to address: {:#x}, {} of {} bytes",
+                write,
+                address,
+                len,
+                buf.len()
+            );
+
+            for (i, d) in buf[..len].iter().enumerate() {
+                self.buf.borrow_mut()[address / 1024][(address % 1024) + i] = *d;
+            }
+
+            if len < buf.len() {
+                return Err(ErrorCode::WriteFail);
+            }
+
+            Ok(())
+        }
+
+        fn erase_region(&self, region_number: usize) -> Result<(), ErrorCode> {
+            for d in self.buf.borrow_mut()[region_number].iter_mut() {
+                *d = 0xFF;
+            }
+
+            Ok(())
+        }
+    }
+
+    fn main_key() -> u64 {
+        let mut hash_function = DefaultHasher::new();
+        MAIN_KEY.hash(&mut hash_function);
+        hash_function.finish()
+    }
+
+    /// Starts TicKV on `flash`, losing power at `power_loss`, and runs `op`.
+    ///
+    /// Returns the flash after the power loss and the results of
+    /// `initalise()` and `op`.
+    fn run(
+        flash: Flash,
+        power_loss: Option<(usize, bool)>,
+        op: impl Fn(&TicKV<FlashCtrl, 1024>) -> Result<SuccessCode, ErrorCode>,
+    ) -> (
+        Flash,
+        Result<SuccessCode, ErrorCode>,
+        Result<SuccessCode, ErrorCode>,
+    ) {
+        let mut read_buf: [u8; 1024] = [0; 1024];
+        let tickv = TicKV::<FlashCtrl, 1024>::new(
+            FlashCtrl::new(flash, power_loss),
+            &mut read_buf,
+            0x10000,
+        );
+
+        let init_ret = tickv.initalise(main_key());
+        let ret = match init_ret {
+            Ok(_) => op(&tickv),
+            Err(e) => Err(e),
+        };
+
+        let flash = *tickv.controller.buf.borrow();
+        (flash, init_ret, ret)
+    }
+
+    /// Checks that the flash has been recovered and that key ONE has
+    /// `value`.
+    fn check_recovered(flash: Flash, value: &[u8; 32]) {
+        let (recovered_flash, _init_ret, ret) = run(flash, None, |tickv| {
+            let mut buf: [u8; 32] = [0; 32];
+
+            tickv.get_key(get_hashed_key(b"ONE"), &mut buf)?;
+            assert_eq!(&buf, value);
+            tickv.get_key(get_hashed_key(b"TWO"), &mut buf)?;
+            assert_eq!(buf, VALUE);
+
+            // There is only one valid object for ONE
+            let mut ones = 0;
+            let mut start = 0;
+            loop {
+                match tickv.next_key(start) {
+                    Ok(key_info) => {
+                        if key_info.hashed_key == get_hashed_key(b"ONE") {
+                            ones += 1;
+                        }
+                        start = key_info.next;
+                    }
+                    Err(ErrorCode::KeyNotFound) => break,
+                    Err(e) => return Err(e),
+                }
+            }
+            assert_eq!(ones, 1);
+
+            Ok(SuccessCode::Complete)
+        });
+        ret.unwrap();
+
+        // Nothing is left to recover, and ONE can be updated again
+        let (_flash, init_ret, ret) = run(recovered_flash, None, |tickv| {
+            let mut buf: [u8; 32] = [0; 32];
+
+            tickv.update_key(get_hashed_key(b"ONE"), &NEW_VALUE)?;
+            tickv.get_key(get_hashed_key(b"ONE"), &mut buf)?;
+            assert_eq!(buf, NEW_VALUE);
+
+            Ok(SuccessCode::Complete)
+        });
+        assert_eq!(init_ret, Ok(SuccessCode::Complete));
+        ret.unwrap();
+    }
+
+    #[test]
+    fn test_update_power_loss() {
+        println!("Add keys ONE and TWO");
+        let (flash, _init_ret, ret) = run([[0xFF; 1024]; 64], None, |tickv| {
+            tickv.append_key(get_hashed_key(b"ONE"), &VALUE)?;
+            tickv.append_key(get_hashed_key(b"TWO"), &VALUE)
+        });
+        ret.unwrap();
+
+        println!("Update non-existant key THREE");
+        let (_flash, _init_ret, ret) = run(flash, None, |tickv| {
+            tickv.update_key(get_hashed_key(b"THREE"), &NEW_VALUE)
+        });
+        assert_eq!(ret, Err(ErrorCode::KeyNotFound));
+
+        let update = |tickv: &TicKV<FlashCtrl, 1024>| {
+            let ret = tickv.update_key(get_hashed_key(b"ONE"), &NEW_VALUE);
+            assert_eq!(tickv.controller.writes.get(), 3);
+            ret
+        };
+
+        println!("Update key ONE");
+        let (updated_flash, _init_ret, ret) = run(flash, None, update);
+        assert_eq!(ret, Ok(SuccessCode::Written));
+        check_recovered(updated_flash, &NEW_VALUE);
+
+        for write in 0..3 {
+            for &half_written in [false, true].iter() {
+                println!(
+                    "Update key ONE, losing power at write {} (half written: {})",
+                    write, half_written
+                );
+                let (flash, _init_ret, ret) = run(flash, Some((write, half_written)), |tickv| {
+                    tickv.update_key(get_hashed_key(b"ONE"), &NEW_VALUE)
+                });
+                assert_eq!(ret, Err(ErrorCode::WriteFail));
+
+                // The update happened once the new object is written
+                let value = if write == 0 { &VALUE } else { &NEW_VALUE };
+
+                // Also lose power at every write of the recovery
+                for recovery_write in 0.. {
+                    println!("Recover, losing power at write {}", recovery_write);
+                    let (recovery_flash, init_ret, _ret) =
+                        run(flash, Some((recovery_write, half_written)), |_tickv| {
+                            Ok(SuccessCode::Complete)
+                        });
+                    check_recovered(recovery_flash, value);
+
+                    if init_ret.is_ok() {
+                        break;
+                    }
+                    assert_eq!(init_ret, Err(ErrorCode::WriteFail));
+                }
+            }
+        }
+    }
+}
@@ -19,6 +19,16 @@ pub(crate) enum InitState {
     EraseComplete,
     /// Trying to read a region while appending a key
     AppendKeyReadRegion(usize),
+    /// Looking for interrupted updates from the first region
+    Recover,
+    /// Trying to read a region while looking for interrupted updates
+    RecoverReadRegion(usize),
+    /// Trying to read a region while looking for the old object of an
+    /// interrupted update: (region, new object, hashed key)
+    RecoverFindOldReadRegion(usize, usize, u64),
+    /// Trying to read a region while clearing flags of an object:
+    /// (object, flags)
+    RecoverClearReadRegion(usize, u8),
 }
 
 #[derive(Clone, Copy, PartialEq)]
@@ -27,6 +37,23 @@ pub(crate) enum KeyState {
     ReadRegion(usize),
 }
 
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum UpdateState {
+    /// Trying to read a region while finding the old object
+    FindReadRegion(usize),
+    /// Trying to read a region while appending the new object:
+    /// (region, old object)
+    AppendReadRegion(usize, usize),
+    /// Invalidating the old object: (old object, new object)
+    InvalidateOld(usize, usize),
+    /// Trying to read the region of the old object: (old object, new object)
+    InvalidateOldReadRegion(usize, usize),
+    /// Clearing the replace flag of the new object
+    ClearReplace(usize),
+    /// Trying to read the region of the new object
+    ClearReplaceReadRegion(usize),
+}
+
 #[derive(Clone, Copy, PartialEq)]
 pub(crate) enum RubbishState {
     ReadRegion(usize),
@@ -47,8 +74,12 @@ pub(crate) enum State {
     GetKey(KeyState),
     /// Invalidating a key
     InvalidateKey(KeyState),
+    /// Updating a key
+    UpdateKey(UpdateState),
     /// Running garbage collection
     GarbageCollect(RubbishState),
+    /// Finding the next key
+    NextKey(KeyState),
 }
 
 /// The struct storing all of the TicKV information.
@@ -60,6 +91,20 @@ pub struct TicKV<'a, C: FlashController<S>, const S: usize> {
     pub(crate) state: Cell<State>,
 }
 
+/// A valid object found by `next_key()`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyInfo {
+    /// The hashed key of the object
+    pub hashed_key: u64,
+    /// The length of the value stored in the object
+    pub value_length: usize,
+    /// The region the object is in
+    pub region: usize,
+    /// The flash offset to pass to `next_key()` to find the following
+    /// object
+    pub next: usize,
+}
+
 /// This is the current object header used for TicKV objects
 struct ObjectHeader {
     version: u8,
@@ -71,13 +116,16 @@ struct ObjectHeader {
 }
 
 pub(crate) const FLAGS_VALID: u8 = 8;
+/// Set on the new object written by `update_key()` until the old object has
+/// been invalidated.
+pub(crate) const FLAGS_REPLACE: u8 = 4;
 
 impl ObjectHeader {
-    fn new(hashed_key: u64, len: u16) -> Self {
+    fn new(hashed_key: u64, len: u16, flags: u8) -> Self {
         assert!(len < 0xFFF);
         Self {
             version: VERSION,
-            flags: FLAGS_VALID,
+            flags,
             len,
             hashed_key,
         }
@@ -91,6 +139,17 @@ pub(crate) const HASH_OFFSET: usize = 3;
 pub(crate) const HEADER_LENGTH: usize = HASH_OFFSET + 8;
 pub(crate) const CHECK_SUM_LEN: usize = 4;
 
+/// Adds the header of the object at `offset` in `region_data` to
+/// `check_sum`. The replace flag is left out, so clearing it doesn't change
+/// the check sum of the object.
+fn check_sum_header(check_sum: &mut crc32::Digest, region_data: &[u8], offset: usize) {
+    check_sum.update(&[
+        region_data[offset + VERSION_OFFSET],
+        region_data[offset + LEN_OFFSET] & !(FLAGS_REPLACE << 4),
+    ]);
+    check_sum.update(&region_data[(offset + LEN_OFFSET + 1)..(offset + HEADER_LENGTH)]);
+}
+
 /// The main key. A hashed version of this should be passed to
 /// `initalise()`.
 pub const MAIN_KEY: &[u8; 15] = b"tickv-super-key";
@@ -113,7 +172,8 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
     }
 
     /// This function setups the flash region to be used as a key-value store.
-    /// If the region is already initalised this won't make any changes.
+    /// If the region is already initalised this won't make any changes,
+    /// other than finishing an update that was interrupted by a power loss.
     ///
     /// `hashed_main_key`: The u64 hash of the const string `MAIN_KEY`.
     ///
@@ -121,7 +181,8 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
     /// the entire region will be erased.
     ///
     /// On success nothing will be returned.
-    /// On error a `ErrorCode` will be returned.
+    /// On error a `ErrorCode` will be returned. `WriteNotReady` means the
+    /// operation must be continued once the write has completed.
     pub fn initalise(&self, hashed_main_key: u64) -> Result<SuccessCode, ErrorCode> {
         let mut buf: [u8; 0] = [0; 0];
 
@@ -129,13 +190,17 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
             State::None => self.get_key(hashed_main_key, &mut buf),
             State::Init(state) => match state {
                 InitState::GetKeyReadRegion(_) => self.get_key(hashed_main_key, &mut buf),
+                InitState::Recover
+                | InitState::RecoverReadRegion(_)
+                | InitState::RecoverFindOldReadRegion(..)
+                | InitState::RecoverClearReadRegion(..) => return self.recover(),
                 _ => Err(ErrorCode::EraseNotReady(0)),
             },
             _ => unreachable!(),
         };
 
         match key_ret {
-            Ok(ret) => Ok(ret),
+            Ok(_) => self.recover(),
             Err(e) => {
                 match e {
                     ErrorCode::ReadNotReady(reg) => {
@@ -326,6 +391,24 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
     /// On success nothing will be returned.
     /// On error a `ErrorCode` will be returned.
     pub fn append_key(&self, hash: u64, value: &[u8]) -> Result<SuccessCode, ErrorCode> {
+        self.append_object(hash, value, FLAGS_VALID)
+            .map(|(code, _address)| code)
+    }
+
+    /// Appends an object with the header `flags` to flash storage.
+    ///
+    /// Unless `FLAGS_REPLACE` is set, this fails if there is already a
+    /// valid object for `hash`.
+    ///
+    /// On success the `SuccessCode` and the flash offset of the new object
+    /// will be returned.
+    /// On error a `ErrorCode` will be returned.
+    fn append_object(
+        &self,
+        hash: u64,
+        value: &[u8],
+        flags: u8,
+    ) -> Result<(SuccessCode, usize), ErrorCode> {
         let region = self.get_region(hash);
         let crc = crc32::Crc::new();
         let mut check_sum = crc.digest();
@@ -339,7 +422,7 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
         }
 
         // Create the header:
-        let header = ObjectHeader::new(hash, object_length as u16);
+        let header = ObjectHeader::new(hash, object_length as u16, flags);
 
         let mut region_offset: isize = 0;
 
@@ -358,15 +441,21 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                 State::AppendKey(key_state) => match key_state {
                     KeyState::ReadRegion(reg) => reg as isize,
                 },
+                State::UpdateKey(UpdateState::AppendReadRegion(reg, _)) => reg as isize,
                 State::GarbageCollect(RubbishState::ReadRegion(reg)) => reg as isize,
                 _ => unreachable!(),
             };
 
             let mut region_data = self.read_buffer.take().unwrap();
-            if self.state.get() != State::AppendKey(KeyState::ReadRegion(new_region as usize))
-                && self.state.get()
-                    != State::Init(InitState::AppendKeyReadRegion(new_region as usize))
-            {
+            let read_ready = match self.state.get() {
+                State::AppendKey(KeyState::ReadRegion(reg))
+                | State::Init(InitState::AppendKeyReadRegion(reg))
+                | State::UpdateKey(UpdateState::AppendReadRegion(reg, _)) => {
+                    reg == new_region as usize
+                }
+                _ => false,
+            };
+            if !read_ready {
                 match self
                     .controller
                     .read_region(new_region as usize, 0, &mut region_data)
@@ -382,7 +471,9 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                 };
             }
 
-            if self.find_key_offset(hash, region_data).is_ok() {
+            if flags & FLAGS_REPLACE != FLAGS_REPLACE
+                && self.find_key_offset(hash, region_data).is_ok()
+            {
                 // Check to make sure we don't already have this key
                 self.read_buffer.replace(Some(region_data));
                 return Err(ErrorCode::KeyAlreadyExists);
@@ -482,7 +573,7 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                 region_data[offset + HASH_OFFSET + 7] = (header.hashed_key) as u8;
 
                 // Hash the new header data
-                check_sum.update(&region_data[offset + VERSION_OFFSET..=offset + HASH_OFFSET + 7]);
+                check_sum_header(&mut check_sum, region_data, offset);
 
                 // Copy the value
                 let slice = &mut region_data[(offset + HEADER_LENGTH)..(offset + package_length)];
@@ -498,19 +589,20 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                 slice.copy_from_slice(&check_sum.to_ne_bytes());
 
                 // Write the data back to the region
+                let address = S * new_region as usize + offset;
                 if let Err(e) = self.controller.write(
-                    S * new_region as usize + offset,
+                    address,
                     &region_data[offset..(offset + package_length + CHECK_SUM_LEN)],
                 ) {
                     self.read_buffer.replace(Some(region_data));
                     match e {
-                        ErrorCode::WriteNotReady(_) => return Ok(SuccessCode::Queued),
+                        ErrorCode::WriteNotReady(_) => return Ok((SuccessCode::Queued, address)),
                         _ => return Err(e),
                     }
                 }
 
                 self.read_buffer.replace(Some(region_data));
-                return Ok(SuccessCode::Written);
+                return Ok((SuccessCode::Written, address));
             }
         }
     }
@@ -573,7 +665,7 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
             match self.find_key_offset(hash, region_data) {
                 Ok((offset, total_length)) => {
                     // Add the header data to the check hash
-                    check_sum.update(&region_data[offset..(HEADER_LENGTH + offset)]);
+                    check_sum_header(&mut check_sum, region_data, offset);
 
                     // Make sure if will fit in the buffer
                     if buf.len() < (total_length as usize - HEADER_LENGTH - CHECK_SUM_LEN) {
@@ -707,6 +799,460 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
         }
     }
 
+    /// Replaces the value of a key in flash storage.
+    ///
+    /// `hash`: A hashed key that is already stored.
+    /// `value`: A buffer containing the new value to be stored to flash.
+    ///
+    /// The new object is written before the old one is invalidated. It is
+    /// marked with the replace flag, which is cleared once the old object
+    /// has been invalidated. If a power loss interrupts the update
+    /// `initalise()` finishes it, so the key keeps either the old or the
+    /// new value.
+    ///
+    /// On success nothing will be returned.
+    /// On error a `ErrorCode` will be returned. `WriteNotReady` means the
+    /// operation must be continued once the write has completed.
+    pub fn update_key(&self, hash: u64, value: &[u8]) -> Result<SuccessCode, ErrorCode> {
+        let ret = self.update_object(hash, value);
+
+        match ret {
+            Err(ErrorCode::ReadNotReady(_))
+            | Err(ErrorCode::WriteNotReady(_))
+            | Err(ErrorCode::EraseNotReady(_)) => {}
+            _ => self.state.set(State::None),
+        }
+
+        ret
+    }
+
+    fn update_object(&self, hash: u64, value: &[u8]) -> Result<SuccessCode, ErrorCode> {
+        let (old, new) = match self.state.get() {
+            State::UpdateKey(UpdateState::InvalidateOld(old, new))
+            | State::UpdateKey(UpdateState::InvalidateOldReadRegion(old, new)) => (old, new),
+            State::UpdateKey(UpdateState::ClearReplace(new))
+            | State::UpdateKey(UpdateState::ClearReplaceReadRegion(new)) => {
+                return self.update_clear_replace(new);
+            }
+            state => {
+                let old = match state {
+                    State::UpdateKey(UpdateState::AppendReadRegion(_, old)) => old,
+                    _ => {
+                        let resume = match state {
+                            State::UpdateKey(UpdateState::FindReadRegion(reg)) => Some(reg),
+                            _ => None,
+                        };
+                        let old = self.find_key_address(hash, resume)?;
+                        // The new object is appended from the first region
+                        self.state.set(State::None);
+                        old
+                    }
+                };
+
+                // Write the new object, marked as replacing the old one
+                let new = match self.append_object(hash, value, FLAGS_VALID | FLAGS_REPLACE) {
+                    Ok((SuccessCode::Queued, new)) => {
+                        self.state
+                            .set(State::UpdateKey(UpdateState::InvalidateOld(old, new)));
+                        return Err(ErrorCode::WriteNotReady(new));
+                    }
+                    Ok((_, new)) => new,
+                    Err(ErrorCode::ReadNotReady(reg)) => {
+                        self.state
+                            .set(State::UpdateKey(UpdateState::AppendReadRegion(reg, old)));
+                        return Err(ErrorCode::ReadNotReady(reg));
+                    }
+                    Err(e) => return Err(e),
+                };
+
+                (old, new)
+            }
+        };
+
+        // Now that the new object is written the old one can be invalidated
+        match self.clear_flags(
+            old,
+            FLAGS_VALID,
+            State::UpdateKey(UpdateState::InvalidateOldReadRegion(old, new)),
+        ) {
+            Ok(_) => {}
+            Err(ErrorCode::WriteNotReady(address)) => {
+                self.state
+                    .set(State::UpdateKey(UpdateState::ClearReplace(new)));
+                return Err(ErrorCode::WriteNotReady(address));
+            }
+            Err(e) => return Err(e),
+        }
+
+        self.update_clear_replace(new)
+    }
+
+    fn update_clear_replace(&self, new: usize) -> Result<SuccessCode, ErrorCode> {
+        match self.clear_flags(
+            new,
+            FLAGS_REPLACE,
+            State::UpdateKey(UpdateState::ClearReplaceReadRegion(new)),
+        ) {
+            Err(ErrorCode::WriteNotReady(_)) => Ok(SuccessCode::Queued),
+            ret => ret,
+        }
+    }
+
+    /// Finds the flash offset of the valid object for `hash`, looking in
+    /// the regions in the same order as `get_key()`.
+    ///
+    /// `resume`: The region that was read if the search is continued after
+    ///           a `ReadNotReady` error.
+    fn find_key_address(&self, hash: u64, resume: Option<usize>) -> Result<usize, ErrorCode> {
+        let region = self.get_region(hash);
+        let mut new_region = resume.unwrap_or(region);
+        let mut read_ready = resume.is_some();
+
+        loop {
+            // Get the data from that region
+            let region_data = self.read_buffer.take().unwrap();
+            if !read_ready {
+                match self.controller.read_region(new_region, 0, region_data) {
+                    Ok(()) => {}
+                    Err(e) => {
+                        self.read_buffer.replace(Some(region_data));
+                        if let ErrorCode::ReadNotReady(reg) = e {
+                            self.state
+                                .set(State::UpdateKey(UpdateState::FindReadRegion(reg)));
+                        }
+                        return Err(e);
+                    }
+                };
+            }
+            read_ready = false;
+
+            let ret = self.find_key_offset(hash, region_data);
+            self.read_buffer.replace(Some(region_data));
+
+            match ret {
+                Ok((offset, _total_length)) => return Ok(S * new_region + offset),
+                Err((true, e)) => match self.increment_region_offset(new_region as isize) {
+                    Some(o) => new_region = (region as isize + o) as usize,
+                    None => return Err(e),
+                },
+                Err((false, e)) => return Err(e),
+            }
+        }
+    }
+
+    /// Clears `flags` in the header of the object at the flash offset
+    /// `address`.
+    ///
+    /// `read_state`: The state to set while waiting for the region of the
+    ///               object to be read.
+    fn clear_flags(
+        &self,
+        address: usize,
+        flags: u8,
+        read_state: State,
+    ) -> Result<SuccessCode, ErrorCode> {
+        let offset = address % S + LEN_OFFSET;
+
+        // Get the data from that region
+        let region_data = self.read_buffer.take().unwrap();
+        if self.state.get() != read_state {
+            if let Err(e) = self.controller.read_region(address / S, 0, region_data) {
+                self.read_buffer.replace(Some(region_data));
+                if let ErrorCode::ReadNotReady(_) = e {
+                    self.state.set(read_state);
+                }
+                return Err(e);
+            }
+        }
+
+        region_data[offset] &= !(flags << 4);
+
+        let ret = self
+            .controller
+            .write(S * (address / S) + offset, &region_data[offset..=offset]);
+        self.read_buffer.replace(Some(region_data));
+
+        ret.map(|()| SuccessCode::Written)
+    }
+
+    /// Finishes any update that was interrupted by a power loss.
+    ///
+    /// This looks for a valid object with the replace flag set. If its
+    /// check sum is valid the update is finished by invalidating the other
+    /// valid object for the key and clearing the flag, otherwise the new
+    /// object was not completely written and is invalidated. The search
+    /// starts again after each write, until no such object is left.
+    fn recover(&self) -> Result<SuccessCode, ErrorCode> {
+        let mut ret = SuccessCode::Complete;
+
+        loop {
+            let (address, flags) = match self.recover_next_write() {
+                Ok(Some(write)) => write,
+                Ok(None) => {
+                    self.state.set(State::None);
+                    return Ok(ret);
+                }
+                Err(e) => return self.recover_error(e),
+            };
+
+            match self.clear_flags(
+                address,
+                flags,
+                State::Init(InitState::RecoverClearReadRegion(address, flags)),
+            ) {
+                Ok(_) => {}
+                Err(ErrorCode::WriteNotReady(address)) => {
+                    self.state.set(State::Init(InitState::Recover));
+                    return Err(ErrorCode::WriteNotReady(address));
+                }
+                Err(e) => return self.recover_error(e),
+            }
+
+            self.state.set(State::Init(InitState::Recover));
+            ret = SuccessCode::Written;
+        }
+    }
+
+    fn recover_error(&self, e: ErrorCode) -> Result<SuccessCode, ErrorCode> {
+        if let ErrorCode::ReadNotReady(_) = e {
+            return Err(e);
+        }
+
+        self.state.set(State::None);
+        Err(e)
+    }
+
+    /// Finds the next write needed to finish an interrupted update.
+    ///
+    /// On success the flash offset of the object and the flags to clear
+    /// will be returned, or `None` if there are no interrupted updates.
+    fn recover_next_write(&self) -> Result<Option<(usize, u8)>, ErrorCode> {
+        let (new, hash) = match self.state.get() {
+            State::Init(InitState::RecoverClearReadRegion(address, flags)) => {
+                return Ok(Some((address, flags)));
+            }
+            State::Init(InitState::RecoverFindOldReadRegion(_, new, hash)) => (new, hash),
+            state => {
+                let start = match state {
+                    State::Init(InitState::RecoverReadRegion(reg)) => reg,
+                    _ => 0,
+                };
+                match self.recover_find_new(start)? {
+                    Some((new, hash, true)) => (new, hash),
+                    // The new object wasn't completely written, keep the old one
+                    Some((new, _hash, false)) => return Ok(Some((new, FLAGS_VALID))),
+                    None => return Ok(None),
+                }
+            }
+        };
+
+        let start = match self.state.get() {
+            State::Init(InitState::RecoverFindOldReadRegion(reg, _, _)) => reg,
+            _ => 0,
+        };
+        match self.recover_find_old(start, new, hash)? {
+            Some(old) => Ok(Some((old, FLAGS_VALID))),
+            // The old object is already invalid
+            None => Ok(Some((new, FLAGS_REPLACE))),
+        }
+    }
+
+    /// Finds the first valid object with the replace flag set, starting
+    /// from `start` region.
+    ///
+    /// On success the flash offset and hashed key of the object, and
+    /// whether its check sum is valid will be returned.
+    fn recover_find_new(&self, start: usize) -> Result<Option<(usize, u64, bool)>, ErrorCode> {
+        for region in start..(self.flash_size / S) {
+            let region_data =
+                self.read_region_for(region, State::Init(InitState::RecoverReadRegion(region)))?;
+
+            let found = Self::find_object(region_data, 0, |_offset, flags, _hash| {
+                flags & FLAGS_REPLACE == FLAGS_REPLACE
+            });
+            let ret = found.map(|found| {
+                found.map(|(offset, total_length)| {
+                    (
+                        S * region + offset,
+                        Self::object_hash(region_data, offset),
+                        Self::check_sum_valid(region_data, offset, total_length),
+                    )
+                })
+            });
+            self.read_buffer.replace(Some(region_data));
+
+            if let Some(found) = ret? {
+                return Ok(Some(found));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Finds a valid object for `hash` other than the one at the flash
+    /// offset `new`, starting from `start` region.
+    ///
+    /// On success the flash offset of the object will be returned.
+    fn recover_find_old(
+        &self,
+        start: usize,
+        new: usize,
+        hash: u64,
+    ) -> Result<Option<usize>, ErrorCode> {
+        for region in start..(self.flash_size / S) {
+            let region_data = self.read_region_for(
+                region,
+                State::Init(InitState::RecoverFindOldReadRegion(region, new, hash)),
+            )?;
+
+            let found = Self::find_object(region_data, 0, |offset, _flags, object_hash| {
+                object_hash == hash && S * region + offset != new
+            });
+            self.read_buffer.replace(Some(region_data));
+
+            if let Some((offset, _total_length)) = found? {
+                return Ok(Some(S * region + offset));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Takes the read buffer and reads `region` into it, unless `read_state`
+    /// is the current state, which means the region has already been read.
+    ///
+    /// `read_state`: The state to set while waiting for the region to be
+    ///               read.
+    fn read_region_for(
+        &self,
+        region: usize,
+        read_state: State,
+    ) -> Result<&'a mut [u8; S], ErrorCode> {
+        let region_data = self.read_buffer.take().unwrap();
+        if self.state.get() != read_state {
+            if let Err(e) = self.controller.read_region(region, 0, region_data) {
+                self.read_buffer.replace(Some(region_data));
+                if let ErrorCode::ReadNotReady(_) = e {
+                    self.state.set(read_state);
+                }
+                return Err(e);
+            }
+        }
+
+        Ok(region_data)
+    }
+
+    /// Finds the first valid object at or after `offset` in `region_data`
+    /// that `matches` accepts. `matches` is called with the offset, the
+    /// header flags and the hashed key of each valid object.
+    ///
+    /// On success the offset and total length of the object will be
+    /// returned, or `None` if the end of the region is reached first.
+    fn find_object(
+        region_data: &[u8; S],
+        mut offset: usize,
+        matches: impl Fn(usize, u8, u64) -> bool,
+    ) -> Result<Option<(usize, usize)>, ErrorCode> {
+        // Check the objects until we hit the end of the region
+        while offset + HEADER_LENGTH < S && region_data[offset + VERSION_OFFSET] != 0xFF {
+            // We found a version, check that we support it
+            if region_data[offset + VERSION_OFFSET] != VERSION {
+                return Err(ErrorCode::UnsupportedVersion);
+            }
+
+            // Find this entries length
+            let total_length = ((region_data[offset + LEN_OFFSET] as u16) & !0xF0) << 8
+                | region_data[offset + LEN_OFFSET + 1] as u16;
+
+            // Check to see if all fields are just 0
+            if total_length == 0 {
+                break;
+            }
+
+            // Check to see if the entry is still valid
+            let flags = region_data[offset + LEN_OFFSET] >> 4;
+            if flags & FLAGS_VALID == FLAGS_VALID
+                && matches(offset, flags, Self::object_hash(region_data, offset))
+            {
+                return Ok(Some((offset, total_length as usize)));
+            }
+
+            offset += total_length as usize;
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the hashed key of the object at `offset` in `region_data`.
+    fn object_hash(region_data: &[u8; S], offset: usize) -> u64 {
+        region_data[offset + HASH_OFFSET..offset + HEADER_LENGTH]
+            .iter()
+            .fold(0, |hash, b| hash << 8 | *b as u64)
+    }
+
+    /// Checks the check sum of the object at `offset` in `region_data`.
+    fn check_sum_valid(region_data: &[u8; S], offset: usize, total_length: usize) -> bool {
+        let end = offset + total_length;
+        if total_length < HEADER_LENGTH + CHECK_SUM_LEN || end > S {
+            return false;
+        }
+
+        let crc = crc32::Crc::new();
+        let mut check_sum = crc.digest();
+        check_sum_header(&mut check_sum, region_data, offset);
+        check_sum.update(&region_data[(offset + HEADER_LENGTH)..(end - CHECK_SUM_LEN)]);
+
+        region_data[(end - CHECK_SUM_LEN)..end] == check_sum.finalise().to_ne_bytes()
+    }
+
+    /// Finds the first valid object at or after the flash offset `start`.
+    ///
+    /// `start`: The offset from the start of the TicKV flash to look from.
+    ///          This must be the start of a region or of an object. Use 0 to
+    ///          find the first object and the `next` field of the returned
+    ///          `KeyInfo` to find the following ones.
+    ///
+    /// The objects are found in flash order, which includes the object of
+    /// the main key stored by `initalise()`. The check sum of the object is
+    /// not checked, `get_key()` does that when reading the value.
+    ///
+    /// On success the `KeyInfo` of the object will be returned.
+    /// On error a `ErrorCode` will be returned. `KeyNotFound` means there
+    /// are no more valid objects.
+    pub fn next_key(&self, start: usize) -> Result<KeyInfo, ErrorCode> {
+        let num_region = self.flash_size / S;
+        let (mut region, mut offset) = match self.state.get() {
+            // Continue from the region we were waiting to read
+            State::NextKey(KeyState::ReadRegion(reg)) if reg > start / S => (reg, 0),
+            _ => (start / S, start % S),
+        };
+
+        while region < num_region {
+            let region_data =
+                self.read_region_for(region, State::NextKey(KeyState::ReadRegion(region)))?;
+
+            let found = Self::find_object(region_data, offset, |_offset, _flags, _hash| true);
+            let ret = found.map(|found| {
+                found.map(|(offset, total_length)| KeyInfo {
+                    hashed_key: Self::object_hash(region_data, offset),
+                    value_length: total_length.saturating_sub(HEADER_LENGTH + CHECK_SUM_LEN),
+                    region,
+                    next: S * region + offset + total_length,
+                })
+            });
+            self.read_buffer.replace(Some(region_data));
+
+            if let Some(key_info) = ret? {
+                return Ok(key_info);
+            }
+
+            region += 1;
+            offset = 0;
+        }
+
+        Err(ErrorCode::KeyNotFound)
+    }
+
     fn garbage_collect_region(&self, region: usize) -> Result<usize, ErrorCode> {
         // Get the data from that region
         let mut region_data = self.read_buffer.take().unwrap();
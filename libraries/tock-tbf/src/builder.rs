@@ -0,0 +1,863 @@
+//! Tock Binary Format header building code.
+//!
+//! `TbfHeaderBuilder` creates the bytes of a TBF version 2 header, such as
+//! host tools need to package applications or to patch the headers of
+//! applications that are already packaged. A builder without any TLVs creates
+//! a padding header.
+//!
+//! ```rust
+//! use tock_tbf::builder::TbfHeaderBuilder;
+//!
+//! let builder = TbfHeaderBuilder::new()
+//!     .enabled(true)
+//!     .main(0x40, 0, 4096)
+//!     .package_name("blink");
+//! let header_size = builder.header_size();
+//! let builder = builder.total_size(header_size as u32 + 1024);
+//!
+//! let mut header = [0; 64];
+//! let len = builder.write(&mut header).unwrap();
+//! assert_eq!(len, header_size);
+//! ```
+//!
+//! The TLVs are written in the order of their type numbers, followed by the
+//! TLVs added with `tlvs()` in their order. Every TLV is padded to a multiple
+//! of 4 bytes.
+
+use core::convert::TryFrom;
+
+use crate::types::TbfHeaderTypes;
+
+/// Size of the fields every TBF version 2 header starts with.
+const BASE_SIZE: usize = 16;
+
+/// Offset of the checksum in the header.
+const CHECKSUM_OFFSET: usize = 12;
+
+/// Takes a value and rounds it up to be aligned % 4
+macro_rules! align4 {
+    ($e:expr $(,)?) => {
+        ($e) + ((4 - (($e) % 4)) % 4)
+    };
+}
+
+/// Error when building a TBF header.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TbfBuildError {
+    /// The buffer is too short for the header. The `usize` is the size of the
+    /// header.
+    BufferTooSmall(usize),
+
+    /// The header is longer than its 16 bit size field can describe.
+    HeaderTooLarge,
+
+    /// The value of a TLV is longer than its 16 bit length field can describe.
+    /// The `u16` is the type of the TLV.
+    TlvTooLong(u16),
+
+    /// The total size is smaller than the header.
+    TotalSizeTooSmall,
+
+    /// The header is shorter than the base fields or not a multiple of 4
+    /// bytes long.
+    BadHeaderSize,
+}
+
+/// A TLV the builder writes unchanged, such as one of a type it does not
+/// know.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TbfTlv<'a> {
+    pub tipe: u16,
+    pub value: &'a [u8],
+}
+
+/// The permission of an app to call commands on a driver: the driver number,
+/// the offset, and the bitmask of the allowed commands `offset * 64` to
+/// `offset * 64 + 63`.
+pub type TbfDriverPermission = (u32, u32, u64);
+
+/// Builder for TBF version 2 headers.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TbfHeaderBuilder<'a> {
+    total_size: u32,
+    flags: u32,
+    main: Option<[u32; 3]>,
+    writeable_flash_regions: &'a [(u32, u32)],
+    package_name: Option<&'a str>,
+    fixed_addresses: Option<(u32, u32)>,
+    permissions: Option<&'a [TbfDriverPermission]>,
+    program: Option<[u32; 5]>,
+    app_id: Option<u32>,
+    deadline: Option<(u32, u32)>,
+    priority: Option<u32>,
+    tlvs: &'a [TbfTlv<'a>],
+}
+
+impl<'a> TbfHeaderBuilder<'a> {
+    /// Create a builder for a header without TLVs, of a disabled app whose
+    /// total size is 0.
+    pub fn new() -> TbfHeaderBuilder<'a> {
+        TbfHeaderBuilder::default()
+    }
+
+    /// Set the size of the whole TBF, including the header, the binary and
+    /// the footers.
+    pub fn total_size(mut self, total_size: u32) -> TbfHeaderBuilder<'a> {
+        self.total_size = total_size;
+        self
+    }
+
+    /// Set whether the kernel starts the app.
+    pub fn enabled(mut self, enabled: bool) -> TbfHeaderBuilder<'a> {
+        self.flags = (self.flags & !0x1) | enabled as u32;
+        self
+    }
+
+    /// Set whether the app is sticky, so that tools ask for confirmation
+    /// before they remove it.
+    pub fn sticky(mut self, sticky: bool) -> TbfHeaderBuilder<'a> {
+        self.flags = (self.flags & !0x2) | (sticky as u32) << 1;
+        self
+    }
+
+    /// Add a Main TLV. The offsets are from the end of the header.
+    pub fn main(
+        mut self,
+        init_fn_offset: u32,
+        protected_size: u32,
+        minimum_ram_size: u32,
+    ) -> TbfHeaderBuilder<'a> {
+        self.main = Some([init_fn_offset, protected_size, minimum_ram_size]);
+        self
+    }
+
+    /// Add a Writeable Flash Regions TLV with the `(offset, size)` of each
+    /// region, unless `regions` is empty. The kernel only uses the first four
+    /// regions.
+    pub fn writeable_flash_regions(mut self, regions: &'a [(u32, u32)]) -> TbfHeaderBuilder<'a> {
+        self.writeable_flash_regions = regions;
+        self
+    }
+
+    /// Add a Package Name TLV.
+    pub fn package_name(mut self, package_name: &'a str) -> TbfHeaderBuilder<'a> {
+        self.package_name = Some(package_name);
+        self
+    }
+
+    /// Add a Fixed Addresses TLV. An address of `0xFFFFFFFF` means the app is
+    /// not fixed to an address in that memory.
+    pub fn fixed_addresses(
+        mut self,
+        start_process_ram: u32,
+        start_process_flash: u32,
+    ) -> TbfHeaderBuilder<'a> {
+        self.fixed_addresses = Some((start_process_ram, start_process_flash));
+        self
+    }
+
+    /// Add a Permissions TLV. Even an empty list restricts the app, as it may
+    /// then use no driver. The kernel rejects headers with more than
+    /// `NUM_DRIVER_PERMISSIONS` permissions.
+    pub fn permissions(mut self, permissions: &'a [TbfDriverPermission]) -> TbfHeaderBuilder<'a> {
+        self.permissions = Some(permissions);
+        self
+    }
+
+    /// Add a Program TLV, which the kernel uses instead of the Main TLV. The
+    /// offsets are from the end of the header, except `binary_end_offset`,
+    /// which is from the start of the TBF.
+    pub fn program(
+        mut self,
+        init_fn_offset: u32,
+        protected_size: u32,
+        minimum_ram_size: u32,
+        binary_end_offset: u32,
+        version: u32,
+    ) -> TbfHeaderBuilder<'a> {
+        self.program = Some([
+            init_fn_offset,
+            protected_size,
+            minimum_ram_size,
+            binary_end_offset,
+            version,
+        ]);
+        self
+    }
+
+    /// Add an App ID TLV.
+    pub fn app_id(mut self, app_id: u32) -> TbfHeaderBuilder<'a> {
+        self.app_id = Some(app_id);
+        self
+    }
+
+    /// Add a Deadline TLV.
+    pub fn deadline(mut self, period_us: u32, budget_us: u32) -> TbfHeaderBuilder<'a> {
+        self.deadline = Some((period_us, budget_us));
+        self
+    }
+
+    /// Add a Priority TLV.
+    pub fn priority(mut self, priority: u32) -> TbfHeaderBuilder<'a> {
+        self.priority = Some(priority);
+        self
+    }
+
+    /// Add TLVs that are written unchanged after all other TLVs, for example
+    /// to pass on TLVs of types this library does not know.
+    pub fn tlvs(mut self, tlvs: &'a [TbfTlv<'a>]) -> TbfHeaderBuilder<'a> {
+        self.tlvs = tlvs;
+        self
+    }
+
+    /// Call `f` with the type and the value of each TLV of the header, in the
+    /// order they are written.
+    fn each_tlv<F>(&self, mut f: F) -> Result<(), TbfBuildError>
+    where
+        F: FnMut(u16, TlvValue) -> Result<(), TbfBuildError>,
+    {
+        if let Some(main) = self.main {
+            f(TbfHeaderTypes::TbfHeaderMain as u16, TlvValue::Words(&main))?;
+        }
+        if !self.writeable_flash_regions.is_empty() {
+            f(
+                TbfHeaderTypes::TbfHeaderWriteableFlashRegions as u16,
+                TlvValue::Pairs(self.writeable_flash_regions),
+            )?;
+        }
+        if let Some(package_name) = self.package_name {
+            f(
+                TbfHeaderTypes::TbfHeaderPackageName as u16,
+                TlvValue::Bytes(package_name.as_bytes()),
+            )?;
+        }
+        if let Some((ram, flash)) = self.fixed_addresses {
+            f(
+                TbfHeaderTypes::TbfHeaderFixedAddresses as u16,
+                TlvValue::Words(&[ram, flash]),
+            )?;
+        }
+        if let Some(permissions) = self.permissions {
+            f(
+                TbfHeaderTypes::TbfHeaderPermissions as u16,
+                TlvValue::Permissions(permissions),
+            )?;
+        }
+        if let Some(program) = self.program {
+            f(
+                TbfHeaderTypes::TbfHeaderProgram as u16,
+                TlvValue::Words(&program),
+            )?;
+        }
+        if let Some(app_id) = self.app_id {
+            f(
+                TbfHeaderTypes::TbfHeaderAppId as u16,
+                TlvValue::Words(&[app_id]),
+            )?;
+        }
+        if let Some((period_us, budget_us)) = self.deadline {
+            f(
+                TbfHeaderTypes::TbfHeaderDeadline as u16,
+                TlvValue::Words(&[period_us, budget_us]),
+            )?;
+        }
+        if let Some(priority) = self.priority {
+            f(
+                TbfHeaderTypes::TbfHeaderPriority as u16,
+                TlvValue::Words(&[priority]),
+            )?;
+        }
+        for tlv in self.tlvs {
+            f(tlv.tipe, TlvValue::Bytes(tlv.value))?;
+        }
+        Ok(())
+    }
+
+    /// Return the size of the header in bytes.
+    pub fn header_size(&self) -> usize {
+        let mut size = BASE_SIZE;
+        let _ = self.each_tlv(|_, value| {
+            size += 4 + align4!(value.len());
+            Ok(())
+        });
+        size
+    }
+
+    /// Write the header, including its checksum, to the start of `buffer`,
+    /// and return its size in bytes.
+    pub fn write(&self, buffer: &mut [u8]) -> Result<usize, TbfBuildError> {
+        let header_size = self.header_size();
+        let header_size_field =
+            u16::try_from(header_size).or(Err(TbfBuildError::HeaderTooLarge))?;
+        if (self.total_size as usize) < header_size {
+            return Err(TbfBuildError::TotalSizeTooSmall);
+        }
+        let header = buffer
+            .get_mut(0..header_size)
+            .ok_or(TbfBuildError::BufferTooSmall(header_size))?;
+
+        // Version 2.
+        header[0..2].copy_from_slice(&2u16.to_le_bytes());
+        header[2..4].copy_from_slice(&header_size_field.to_le_bytes());
+        header[4..8].copy_from_slice(&self.total_size.to_le_bytes());
+        header[8..12].copy_from_slice(&self.flags.to_le_bytes());
+
+        let mut offset = BASE_SIZE;
+        self.each_tlv(|tipe, value| {
+            let length = u16::try_from(value.len()).or(Err(TbfBuildError::TlvTooLong(tipe)))?;
+            header[offset..offset + 2].copy_from_slice(&tipe.to_le_bytes());
+            header[offset + 2..offset + 4].copy_from_slice(&length.to_le_bytes());
+            offset += 4;
+            let padded_len = align4!(value.len());
+            let value_bytes = &mut header[offset..offset + padded_len];
+            value.write(value_bytes);
+            for padding in value_bytes[value.len()..].iter_mut() {
+                *padding = 0;
+            }
+            offset += padded_len;
+            Ok(())
+        })?;
+
+        update_checksum(header)?;
+        Ok(header_size)
+    }
+}
+
+/// The value of a TLV, in the form the builder stores it.
+#[derive(Clone, Copy)]
+enum TlvValue<'a> {
+    Words(&'a [u32]),
+    Pairs(&'a [(u32, u32)]),
+    Permissions(&'a [TbfDriverPermission]),
+    Bytes(&'a [u8]),
+}
+
+impl TlvValue<'_> {
+    fn len(&self) -> usize {
+        match self {
+            TlvValue::Words(words) => words.len() * 4,
+            TlvValue::Pairs(pairs) => pairs.len() * 8,
+            TlvValue::Permissions(permissions) => permissions.len() * 16,
+            TlvValue::Bytes(bytes) => bytes.len(),
+        }
+    }
+
+    /// Write the value to the start of `buffer`, which must be long enough.
+    fn write(&self, buffer: &mut [u8]) {
+        match self {
+            TlvValue::Words(words) => {
+                for (word, bytes) in words.iter().zip(buffer.chunks_exact_mut(4)) {
+                    bytes.copy_from_slice(&word.to_le_bytes());
+                }
+            }
+            TlvValue::Pairs(pairs) => {
+                for ((first, second), bytes) in pairs.iter().zip(buffer.chunks_exact_mut(8)) {
+                    bytes[0..4].copy_from_slice(&first.to_le_bytes());
+                    bytes[4..8].copy_from_slice(&second.to_le_bytes());
+                }
+            }
+            TlvValue::Permissions(permissions) => {
+                for ((driver_number, offset, allowed_commands), bytes) in
+                    permissions.iter().zip(buffer.chunks_exact_mut(16))
+                {
+                    bytes[0..4].copy_from_slice(&driver_number.to_le_bytes());
+                    bytes[4..8].copy_from_slice(&offset.to_le_bytes());
+                    bytes[8..16].copy_from_slice(&allowed_commands.to_le_bytes());
+                }
+            }
+            TlvValue::Bytes(bytes) => buffer[..bytes.len()].copy_from_slice(bytes),
+        }
+    }
+}
+
+/// Compute the checksum of `header`, which must hold exactly one TBF version 2
+/// header, and store it in the header. Tools that patch a field of a header,
+/// such as its flags, must update the checksum afterwards.
+pub fn update_checksum(header: &mut [u8]) -> Result<(), TbfBuildError> {
+    if header.len() < BASE_SIZE || header.len() % 4 != 0 {
+        return Err(TbfBuildError::BadHeaderSize);
+    }
+    let checksum = header
+        .chunks_exact(4)
+        .enumerate()
+        .filter(|&(i, _)| i != CHECKSUM_OFFSET / 4)
+        .fold(0, |checksum, (_, word)| {
+            checksum ^ u32::from_le_bytes([word[0], word[1], word[2], word[3]])
+        });
+    header[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 4].copy_from_slice(&checksum.to_le_bytes());
+    Ok(())
+}
+
+/// Return the size in bytes of a credentials footer holding `data_len` bytes
+/// of credentials data.
+pub fn credentials_footer_size(data_len: usize) -> usize {
+    8 + align4!(data_len)
+}
+
+/// Write a credentials footer to the start of `buffer`, and return its size in
+/// bytes. `format` is the `TbfFooterV2CredentialsType` of `data`; it is a raw
+/// value so that tools can write formats this library does not know.
+pub fn write_credentials_footer(
+    format: u32,
+    data: &[u8],
+    buffer: &mut [u8],
+) -> Result<usize, TbfBuildError> {
+    let tipe = TbfHeaderTypes::TbfFooterCredentials as u16;
+    let length = u16::try_from(4 + data.len()).or(Err(TbfBuildError::TlvTooLong(tipe)))?;
+    let footer_size = credentials_footer_size(data.len());
+    let footer = buffer
+        .get_mut(0..footer_size)
+        .ok_or(TbfBuildError::BufferTooSmall(footer_size))?;
+
+    footer[0..2].copy_from_slice(&tipe.to_le_bytes());
+    footer[2..4].copy_from_slice(&length.to_le_bytes());
+    footer[4..8].copy_from_slice(&format.to_le_bytes());
+    footer[8..8 + data.len()].copy_from_slice(data);
+    for padding in footer[8 + data.len()..].iter_mut() {
+        *padding = 0;
+    }
+    Ok(footer_size)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::convert::TryInto;
+    use std::boxed::Box;
+    use std::string::String;
+    use std::vec;
+    use std::vec::Vec;
+
+    use crate::parse::{parse_tbf_header, parse_tbf_header_lengths};
+    use crate::types::{CommandPermissions, TbfHeader, TbfParseError};
+
+    use super::{credentials_footer_size, update_checksum, write_credentials_footer};
+    use super::{TbfBuildError, TbfDriverPermission, TbfHeaderBuilder, TbfTlv};
+
+    /// Small deterministic random number generator, so that failures can be
+    /// reproduced.
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+
+        fn u32(&mut self) -> u32 {
+            self.next() as u32
+        }
+
+        fn maybe(&mut self) -> bool {
+            self.below(2) == 0
+        }
+    }
+
+    /// The contents of a random header. The builder borrows from it.
+    struct Fields {
+        enabled: bool,
+        sticky: bool,
+        total_size_extra: u32,
+        main: Option<[u32; 3]>,
+        regions: Vec<(u32, u32)>,
+        package_name: Option<String>,
+        fixed_addresses: Option<(u32, u32)>,
+        permissions: Option<Vec<TbfDriverPermission>>,
+        program: Option<[u32; 5]>,
+        app_id: Option<u32>,
+        deadline: Option<(u32, u32)>,
+        priority: Option<u32>,
+        unknown: Vec<(u16, Vec<u8>)>,
+    }
+
+    impl Fields {
+        fn random(rng: &mut XorShift) -> Fields {
+            // Sizes and offsets stay small enough that the header size can
+            // be added to them.
+            let small = |rng: &mut XorShift| rng.u32() & 0x0FFF_FFFF;
+            let mut fields = Fields {
+                enabled: rng.maybe(),
+                sticky: rng.maybe(),
+                total_size_extra: rng.below(0x10000) as u32,
+                main: None,
+                regions: (0..rng.below(5))
+                    .map(|_| (small(rng), small(rng)))
+                    .collect(),
+                package_name: None,
+                fixed_addresses: None,
+                permissions: None,
+                program: None,
+                app_id: None,
+                deadline: None,
+                priority: None,
+                unknown: (0..rng.below(3))
+                    .map(|i| {
+                        // Types the parser does not know.
+                        let tipe = 0x4000 + i as u16;
+                        let value = (0..rng.below(20)).map(|_| rng.next() as u8).collect();
+                        (tipe, value)
+                    })
+                    .collect(),
+            };
+            if rng.maybe() {
+                fields.main = Some([small(rng), small(rng), small(rng)]);
+            }
+            if rng.maybe() {
+                let len = rng.below(16);
+                fields.package_name = Some(
+                    (0..len)
+                        .map(|_| (b'a' + rng.below(26) as u8) as char)
+                        .collect(),
+                );
+            }
+            if rng.maybe() {
+                fields.fixed_addresses = Some((rng.u32() | 1, rng.u32() & !1));
+            }
+            if rng.maybe() {
+                // Distinct drivers, so each permission can be looked up.
+                fields.permissions = Some(
+                    (0..rng.below(9))
+                        .map(|i| {
+                            (
+                                i as u32 * 0x100 + rng.below(0x100) as u32,
+                                rng.below(4) as u32,
+                                rng.next(),
+                            )
+                        })
+                        .collect(),
+                );
+            }
+            if rng.maybe() {
+                // The binary end is counted from the end of the header here,
+                // as it must be within the TBF.
+                let binary_end = rng.below(fields.total_size_extra as u64 + 1) as u32;
+                fields.program = Some([small(rng), small(rng), small(rng), binary_end, rng.u32()]);
+            }
+            if rng.maybe() {
+                fields.app_id = Some(rng.u32());
+            }
+            if rng.maybe() {
+                let period_us = rng.u32() | 1;
+                fields.deadline = Some((period_us, rng.u32() % period_us));
+            }
+            if rng.maybe() {
+                fields.priority = Some(rng.u32());
+            }
+            fields
+        }
+
+        fn builder<'a>(&'a self, tlvs: &'a [TbfTlv<'a>], header_size: u32) -> TbfHeaderBuilder<'a> {
+            let mut builder = TbfHeaderBuilder::new()
+                .enabled(self.enabled)
+                .sticky(self.sticky)
+                .writeable_flash_regions(&self.regions)
+                .tlvs(tlvs);
+            if let Some([init_fn_offset, protected_size, minimum_ram_size]) = self.main {
+                builder = builder.main(init_fn_offset, protected_size, minimum_ram_size);
+            }
+            if let Some(package_name) = &self.package_name {
+                builder = builder.package_name(package_name);
+            }
+            if let Some((ram, flash)) = self.fixed_addresses {
+                builder = builder.fixed_addresses(ram, flash);
+            }
+            if let Some(permissions) = &self.permissions {
+                builder = builder.permissions(permissions);
+            }
+            if let Some([init_fn_offset, protected_size, minimum_ram_size, binary_end, version]) =
+                self.program
+            {
+                builder = builder.program(
+                    init_fn_offset,
+                    protected_size,
+                    minimum_ram_size,
+                    binary_end + header_size,
+                    version,
+                );
+            }
+            if let Some(app_id) = self.app_id {
+                builder = builder.app_id(app_id);
+            }
+            if let Some((period_us, budget_us)) = self.deadline {
+                builder = builder.deadline(period_us, budget_us);
+            }
+            if let Some(priority) = self.priority {
+                builder = builder.priority(priority);
+            }
+            builder
+        }
+
+        fn has_tlvs(&self) -> bool {
+            self.main.is_some()
+                || !self.regions.is_empty()
+                || self.package_name.is_some()
+                || self.fixed_addresses.is_some()
+                || self.permissions.is_some()
+                || self.program.is_some()
+                || self.app_id.is_some()
+                || self.deadline.is_some()
+                || self.priority.is_some()
+                || !self.unknown.is_empty()
+        }
+
+        /// Check that `header` holds these fields.
+        fn check(&self, header: &TbfHeader, header_size: u32, total_size: u32) {
+            assert_eq!(header.is_app(), self.has_tlvs());
+            if !header.is_app() {
+                return;
+            }
+            assert_eq!(header.enabled(), self.enabled);
+
+            let [init_fn_offset, protected_size, minimum_ram_size] = match self.program {
+                Some([init_fn_offset, protected_size, minimum_ram_size, _, _]) => {
+                    [init_fn_offset, protected_size, minimum_ram_size]
+                }
+                None => self.main.unwrap_or([0, 0, 0]),
+            };
+            assert_eq!(
+                header.get_init_function_offset(),
+                init_fn_offset + header_size
+            );
+            assert_eq!(header.get_protected_size(), protected_size + header_size);
+            assert_eq!(header.get_minimum_app_ram_size(), minimum_ram_size);
+            assert_eq!(
+                header.get_binary_end(),
+                self.program
+                    .map_or(total_size, |program| program[3] + header_size)
+            );
+            assert_eq!(
+                header.get_binary_version(),
+                self.program.map_or(0, |program| program[4])
+            );
+
+            assert_eq!(
+                header.get_package_name(),
+                Some(self.package_name.as_deref().unwrap_or(""))
+            );
+            assert_eq!(header.number_writeable_flash_regions(), self.regions.len());
+            for (i, region) in self.regions.iter().enumerate() {
+                assert_eq!(header.get_writeable_flash_region(i), *region);
+            }
+            assert_eq!(
+                header.get_fixed_address_ram(),
+                self.fixed_addresses.map(|(ram, _)| ram)
+            );
+            assert_eq!(
+                header.get_fixed_address_flash(),
+                self.fixed_addresses.map(|(_, flash)| flash)
+            );
+            match &self.permissions {
+                Some(permissions) => {
+                    for &(driver_number, offset, allowed_commands) in permissions {
+                        assert_eq!(
+                            header.get_command_permissions(driver_number as usize, offset as usize),
+                            CommandPermissions::Mask(allowed_commands)
+                        );
+                    }
+                    assert_eq!(
+                        header.get_command_permissions(0xFFFF, 0),
+                        CommandPermissions::NoPermsThisDriver
+                    );
+                }
+                None => assert_eq!(
+                    header.get_command_permissions(0, 0),
+                    CommandPermissions::NoPermsAtAll
+                ),
+            }
+            assert_eq!(header.get_app_id(), self.app_id);
+            assert_eq!(header.get_deadline(), self.deadline);
+            assert_eq!(header.get_priority(), self.priority);
+        }
+    }
+
+    /// Build the header for `fields` into flash.
+    fn build(fields: &Fields) -> &'static [u8] {
+        let tlvs: Vec<TbfTlv> = fields
+            .unknown
+            .iter()
+            .map(|(tipe, value)| TbfTlv { tipe: *tipe, value })
+            .collect();
+        // The header size does not depend on the values of the fields.
+        let header_size = fields.builder(&tlvs, 0).header_size();
+        let builder = fields
+            .builder(&tlvs, header_size as u32)
+            .total_size(header_size as u32 + fields.total_size_extra);
+        let mut flash = vec![0xFF; header_size + 4];
+        assert_eq!(builder.write(&mut flash), Ok(header_size));
+        flash.truncate(header_size);
+        Box::leak(flash.into_boxed_slice())
+    }
+
+    fn parse(header: &'static [u8]) -> Result<TbfHeader, TbfParseError> {
+        let lengths: &'static [u8; 8] = Box::leak(Box::new(header[0..8].try_into().unwrap()));
+        let (version, header_size, _) = match parse_tbf_header_lengths(lengths) {
+            Ok(lengths) => lengths,
+            Err(_) => panic!("lengths do not parse"),
+        };
+        assert_eq!(header_size as usize, header.len());
+        parse_tbf_header(header, version)
+    }
+
+    #[test]
+    fn test_padding_bytes() {
+        let mut header = [0; 16];
+        let builder = TbfHeaderBuilder::new().total_size(0x400);
+        assert_eq!(builder.write(&mut header), Ok(16));
+        assert_eq!(header, [2, 0, 16, 0, 0, 4, 0, 0, 0, 0, 0, 0, 2, 4, 16, 0]);
+    }
+
+    #[test]
+    fn test_main_and_name_bytes() {
+        let mut header = [0; 44];
+        let builder = TbfHeaderBuilder::new()
+            .enabled(true)
+            .main(0x2c, 0, 0x800)
+            .package_name("blink")
+            .total_size(0x400);
+        assert_eq!(builder.write(&mut header), Ok(44));
+        let words: Vec<u32> = header
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+            .collect();
+        let expected = [
+            2 | 44 << 16,
+            0x400,
+            1,
+            0,
+            1 | 12 << 16,
+            0x2c,
+            0,
+            0x800,
+            3 | 5 << 16,
+            u32::from_le_bytes(*b"blin"),
+            b'k' as u32,
+        ];
+        let checksum = expected.iter().fold(0, |checksum, word| checksum ^ word);
+        assert_eq!(words[..3], expected[..3]);
+        assert_eq!(words[3], checksum);
+        assert_eq!(words[4..], expected[4..]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut rng = XorShift(0x5EED_7B0C_0000_0001);
+        for _ in 0..1000 {
+            let fields = Fields::random(&mut rng);
+            let flash = build(&fields);
+            let total_size = u32::from_le_bytes(flash[4..8].try_into().unwrap());
+            let header = parse(flash).unwrap_or_else(|e| panic!("{:?}", e));
+            fields.check(&header, flash.len() as u32, total_size);
+        }
+    }
+
+    #[test]
+    fn test_unknown_tlvs_unchanged() {
+        let mut rng = XorShift(0x7175_0000_0000_0002);
+        for _ in 0..100 {
+            let mut fields = Fields::random(&mut rng);
+            fields
+                .unknown
+                .push((0x7FFF, vec![0xA5; 1 + rng.below(10) as usize]));
+            let flash = build(&fields);
+
+            // The passed-through TLVs are the last ones, in their order.
+            let mut tlvs = Vec::new();
+            let mut offset = 16;
+            while offset < flash.len() {
+                let tipe = u16::from_le_bytes([flash[offset], flash[offset + 1]]);
+                let len = u16::from_le_bytes([flash[offset + 2], flash[offset + 3]]) as usize;
+                tlvs.push((tipe, flash[offset + 4..offset + 4 + len].to_vec()));
+                let padded_len = (len + 3) / 4 * 4;
+                assert!(flash[offset + 4 + len..offset + 4 + padded_len]
+                    .iter()
+                    .all(|&b| b == 0));
+                offset += 4 + padded_len;
+            }
+            assert_eq!(offset, flash.len());
+            assert_eq!(
+                tlvs[tlvs.len() - fields.unknown.len()..],
+                fields.unknown[..]
+            );
+        }
+    }
+
+    #[test]
+    fn test_update_checksum() {
+        let mut header = [0; 32];
+        let builder = TbfHeaderBuilder::new()
+            .enabled(true)
+            .main(0x20, 0, 0x800)
+            .total_size(0x400);
+        builder.write(&mut header).unwrap();
+
+        // Disable the app.
+        header[8] = 0;
+        let unchecked: &'static [u8] = Box::leak(Box::new(header));
+        assert!(matches!(
+            parse(unchecked),
+            Err(TbfParseError::ChecksumMismatch(_, _))
+        ));
+        update_checksum(&mut header).unwrap();
+        let patched: &'static [u8] = Box::leak(Box::new(header));
+        assert!(!parse(patched).unwrap().enabled());
+
+        assert_eq!(
+            update_checksum(&mut [0; 18]),
+            Err(TbfBuildError::BadHeaderSize)
+        );
+    }
+
+    #[test]
+    fn test_build_errors() {
+        let builder = TbfHeaderBuilder::new().main(0, 0, 0);
+        assert_eq!(
+            builder.write(&mut [0; 32]),
+            Err(TbfBuildError::TotalSizeTooSmall)
+        );
+        let builder = builder.total_size(0x100);
+        assert_eq!(
+            builder.write(&mut [0; 31]),
+            Err(TbfBuildError::BufferTooSmall(32))
+        );
+
+        let long = vec![0; 0x10000];
+        let tlvs = [TbfTlv {
+            tipe: 0x4000,
+            value: &long,
+        }];
+        assert_eq!(
+            TbfHeaderBuilder::new()
+                .tlvs(&tlvs)
+                .total_size(0x20000)
+                .write(&mut vec![0; 0x20000]),
+            Err(TbfBuildError::HeaderTooLarge)
+        );
+    }
+
+    #[test]
+    fn test_credentials_footer() {
+        let mut footer = [0xFF; 16];
+        assert_eq!(credentials_footer_size(6), 16);
+        assert_eq!(write_credentials_footer(0, &[0xa5; 6], &mut footer), Ok(16));
+        assert_eq!(
+            footer,
+            [128, 0, 10, 0, 0, 0, 0, 0, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0, 0]
+        );
+
+        assert_eq!(
+            write_credentials_footer(5, &[0; 32], &mut footer),
+            Err(TbfBuildError::BufferTooSmall(40))
+        );
+        assert_eq!(
+            write_credentials_footer(5, &vec![0; 0x10000], &mut vec![0; 0x10010]),
+            Err(TbfBuildError::TlvTooLong(128))
+        );
+    }
+}
@@ -1,8 +1,9 @@
-//! Tock Binary Format (TBF) header parsing library.
+//! Tock Binary Format (TBF) header parsing and building library.
 
 // Parsing the headers does not require any unsafe operations.
 #![forbid(unsafe_code)]
 #![no_std]
 
+pub mod builder;
 pub mod parse;
 pub mod types;
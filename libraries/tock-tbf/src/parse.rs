@@ -126,10 +126,17 @@ pub fn parse_tbf_header(
                 // Places to save fields that we parse out of the header
                 // options.
                 let mut main_pointer: Option<types::TbfHeaderV2Main> = None;
+                let mut program_pointer: Option<types::TbfHeaderV2Program> = None;
                 let mut wfr_pointer: [Option<types::TbfHeaderV2WriteableFlashRegion>; 4] =
                     Default::default();
                 let mut app_name_str = "";
                 let mut fixed_address_pointer: Option<types::TbfHeaderV2FixedAddresses> = None;
+                let mut app_id_pointer: Option<types::TbfHeaderV2AppId> = None;
+                let mut deadline_pointer: Option<types::TbfHeaderV2Deadline> = None;
+                let mut priority_pointer: Option<types::TbfHeaderV2Priority> = None;
+                let mut permissions_pointer: Option<
+                    [Option<types::TbfHeaderDriverPermission>; types::NUM_DRIVER_PERMISSIONS],
+                > = None;
 
                 // Iterate the remainder of the header looking for TLV entries.
                 while remaining.len() > 0 {
@@ -157,6 +164,19 @@ pub fn parse_tbf_header(
                             }
                         }
 
+                        types::TbfHeaderTypes::TbfHeaderProgram => {
+                            let entry_len = mem::size_of::<types::TbfHeaderV2Program>();
+
+                            // The program TLV is a fixed size, just like main.
+                            if tlv_header.length as usize == entry_len {
+                                program_pointer = Some(remaining.try_into()?);
+                            } else {
+                                return Err(types::TbfParseError::BadTlvEntry(
+                                    tlv_header.tipe as usize,
+                                ));
+                            }
+                        }
+
                         types::TbfHeaderTypes::TbfHeaderWriteableFlashRegions => {
                             // Length must be a multiple of the size of a region definition.
                             if tlv_header.length as usize
@@ -219,6 +239,79 @@ pub fn parse_tbf_header(
                             }
                         }
 
+                        types::TbfHeaderTypes::TbfHeaderPermissions => {
+                            // Length must be a multiple of the size of a
+                            // permission, and since permissions restrict the
+                            // app we must not ignore any of them.
+                            let perm_len = mem::size_of::<types::TbfHeaderDriverPermission>();
+                            let number_perms = tlv_header.length as usize / perm_len;
+                            if tlv_header.length as usize % perm_len == 0
+                                && number_perms <= types::NUM_DRIVER_PERMISSIONS
+                            {
+                                // Capture a slice with just the permissions.
+                                let perms_slice = remaining
+                                    .get(0..tlv_header.length as usize)
+                                    .ok_or(types::TbfParseError::NotEnoughFlash)?;
+
+                                let mut perms: [Option<types::TbfHeaderDriverPermission>;
+                                    types::NUM_DRIVER_PERMISSIONS] = Default::default();
+                                for (i, perm) in perms.iter_mut().take(number_perms).enumerate() {
+                                    *perm = Some(
+                                        perms_slice
+                                            .get(i * perm_len..(i + 1) * perm_len)
+                                            .ok_or(types::TbfParseError::NotEnoughFlash)?
+                                            .try_into()?,
+                                    );
+                                }
+                                permissions_pointer = Some(perms);
+                            } else {
+                                return Err(types::TbfParseError::BadTlvEntry(
+                                    tlv_header.tipe as usize,
+                                ));
+                            }
+                        }
+
+                        types::TbfHeaderTypes::TbfHeaderAppId => {
+                            let entry_len = mem::size_of::<types::TbfHeaderV2AppId>();
+                            if tlv_header.length as usize == entry_len {
+                                app_id_pointer = Some(remaining.try_into()?);
+                            } else {
+                                return Err(types::TbfParseError::BadTlvEntry(
+                                    tlv_header.tipe as usize,
+                                ));
+                            }
+                        }
+
+                        types::TbfHeaderTypes::TbfHeaderDeadline => {
+                            let entry_len = mem::size_of::<types::TbfHeaderV2Deadline>();
+                            if tlv_header.length as usize == entry_len {
+                                let deadline: types::TbfHeaderV2Deadline = remaining.try_into()?;
+                                // A budget larger than the period can never be
+                                // met.
+                                if !deadline.is_valid() {
+                                    return Err(types::TbfParseError::BadTlvEntry(
+                                        tlv_header.tipe as usize,
+                                    ));
+                                }
+                                deadline_pointer = Some(deadline);
+                            } else {
+                                return Err(types::TbfParseError::BadTlvEntry(
+                                    tlv_header.tipe as usize,
+                                ));
+                            }
+                        }
+
+                        types::TbfHeaderTypes::TbfHeaderPriority => {
+                            let entry_len = mem::size_of::<types::TbfHeaderV2Priority>();
+                            if tlv_header.length as usize == entry_len {
+                                priority_pointer = Some(remaining.try_into()?);
+                            } else {
+                                return Err(types::TbfParseError::BadTlvEntry(
+                                    tlv_header.tipe as usize,
+                                ));
+                            }
+                        }
+
                         _ => {}
                     }
 
@@ -230,12 +323,29 @@ pub fn parse_tbf_header(
                         .ok_or(types::TbfParseError::NotEnoughFlash)?;
                 }
 
+                // The footers start where the binary ends, so the binary
+                // must end after the header and within the TBF.
+                if let Some(program) = program_pointer {
+                    if program.binary_end_offset < tbf_header_base.header_size as u32
+                        || program.binary_end_offset > tbf_header_base.total_size
+                    {
+                        return Err(types::TbfParseError::BadTlvEntry(
+                            types::TbfHeaderTypes::TbfHeaderProgram as usize,
+                        ));
+                    }
+                }
+
                 let tbf_header = types::TbfHeaderV2 {
                     base: tbf_header_base,
                     main: main_pointer,
+                    program: program_pointer,
                     package_name: Some(app_name_str),
                     writeable_regions: Some(wfr_pointer),
                     fixed_addresses: fixed_address_pointer,
+                    app_id: app_id_pointer,
+                    deadline: deadline_pointer,
+                    priority: priority_pointer,
+                    permissions: permissions_pointer,
                 };
 
                 Ok(types::TbfHeader::TbfHeaderV2(tbf_header))
@@ -244,3 +354,125 @@ pub fn parse_tbf_header(
         _ => Err(types::TbfParseError::UnsupportedVersion(version)),
     }
 }
+
+/// Parse one footer stored in flash after the application binary.
+///
+/// The `footers` slice must start at a footer and may contain any number of
+/// further footers after it. The caller should use
+/// `TbfHeader::get_binary_end()` to find where the first footer starts and
+/// then repeatedly call this function, advancing the slice by the returned
+/// length each time, until the slice is empty.
+///
+/// ## Return
+///
+/// On success this returns the credentials stored in the footer and the total
+/// number of bytes the footer occupies, including its TLV header.
+pub fn parse_tbf_footer(
+    footers: &'static [u8],
+) -> Result<(types::TbfFooterV2Credentials, u32), types::TbfParseError> {
+    let tlv_header: types::TbfHeaderTlv = footers.try_into()?;
+
+    match tlv_header.tipe {
+        types::TbfHeaderTypes::TbfFooterCredentials => {
+            // The footer must fit entirely in the space remaining after the
+            // binary.
+            let credentials_buf = footers
+                .get(4..4 + tlv_header.length as usize)
+                .ok_or(types::TbfParseError::NotEnoughFlash)?;
+            let credentials: types::TbfFooterV2Credentials = credentials_buf.try_into()?;
+
+            // Footers are padded to 4 bytes just like header TLVs.
+            let footer_len = 4 + align4!(tlv_header.length as usize);
+            Ok((credentials, footer_len as u32))
+        }
+        _ => Err(types::TbfParseError::BadTlvEntry(tlv_header.tipe as usize)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::boxed::Box;
+    use std::vec;
+    use std::vec::Vec;
+
+    use crate::builder::{credentials_footer_size, write_credentials_footer, TbfHeaderBuilder};
+    use crate::types::{TbfFooterV2CredentialsType, TbfHeader, TbfHeaderTypes, TbfParseError};
+
+    use super::{parse_tbf_footer, parse_tbf_header};
+
+    /// Parse a header with a Program TLV whose binary ends at `binary_end`.
+    fn parse_with_binary_end(binary_end: u32) -> Result<TbfHeader, TbfParseError> {
+        let builder = TbfHeaderBuilder::new()
+            .program(0, 0, 0x800, binary_end, 1)
+            .total_size(0x400);
+        let mut flash = vec![0; builder.header_size()];
+        builder.write(&mut flash).unwrap();
+        parse_tbf_header(Box::leak(flash.into_boxed_slice()), 2)
+    }
+
+    #[test]
+    fn test_binary_end_within_tbf() {
+        let header = parse_with_binary_end(0x3c0).unwrap_or_else(|e| panic!("{:?}", e));
+        assert_eq!(header.get_binary_end(), 0x3c0);
+        assert!(parse_with_binary_end(0x400).is_ok());
+
+        let program = TbfHeaderTypes::TbfHeaderProgram as usize;
+        for binary_end in [0x404, 0x10, u32::MAX] {
+            assert!(matches!(
+                parse_with_binary_end(binary_end),
+                Err(TbfParseError::BadTlvEntry(tipe)) if tipe == program
+            ));
+        }
+    }
+
+    /// Footers with the TLV headers for `credentials`, given as format and
+    /// data.
+    fn footers(credentials: &[(u32, &[u8])]) -> &'static [u8] {
+        let mut footers = Vec::new();
+        for (format, data) in credentials {
+            let offset = footers.len();
+            footers.resize(offset + credentials_footer_size(data.len()), 0);
+            write_credentials_footer(*format, data, &mut footers[offset..]).unwrap();
+        }
+        Box::leak(footers.into_boxed_slice())
+    }
+
+    #[test]
+    fn test_parse_footers() {
+        let footers = footers(&[(5, &[0xa5; 32]), (0, &[0; 6])]);
+        let (credentials, length) = parse_tbf_footer(footers).unwrap();
+        assert_eq!(credentials.format(), TbfFooterV2CredentialsType::SHA256);
+        assert_eq!(credentials.data(), &[0xa5; 32]);
+        assert_eq!(length, 40);
+
+        // Reserved space is padded to a word.
+        let (credentials, length) = parse_tbf_footer(&footers[40..]).unwrap();
+        assert_eq!(credentials.format(), TbfFooterV2CredentialsType::Reserved);
+        assert_eq!(credentials.data().len(), 6);
+        assert_eq!(length, 16);
+    }
+
+    #[test]
+    fn test_parse_bad_footers() {
+        let credentials = TbfHeaderTypes::TbfFooterCredentials as usize;
+        // A SHA-256 hash that is too short.
+        assert!(matches!(
+            parse_tbf_footer(footers(&[(5, &[0; 16])])),
+            Err(TbfParseError::BadTlvEntry(tipe)) if tipe == credentials
+        ));
+        // A footer longer than the flash left.
+        let truncated = footers(&[(5, &[0; 32])]);
+        assert!(matches!(
+            parse_tbf_footer(&truncated[..36]),
+            Err(TbfParseError::NotEnoughFlash)
+        ));
+        // Unknown credential formats and TLVs that are not footers.
+        assert!(parse_tbf_footer(footers(&[(100, &[0; 4])])).is_err());
+        assert!(matches!(
+            parse_tbf_footer(&[1, 0, 4, 0, 0, 0, 0, 0]),
+            Err(TbfParseError::BadTlvEntry(1))
+        ));
+    }
+}
@@ -105,6 +105,14 @@ pub enum TbfHeaderTypes {
     TbfHeaderWriteableFlashRegions = 2,
     TbfHeaderPackageName = 3,
     TbfHeaderFixedAddresses = 5,
+    TbfHeaderPermissions = 6,
+    TbfHeaderProgram = 9,
+    TbfHeaderAppId = 10,
+    TbfHeaderDeadline = 11,
+    TbfHeaderPriority = 12,
+
+    /// Credentials footer placed after the application binary.
+    TbfFooterCredentials = 128,
 
     /// Some field in the header that we do not understand. Since the TLV format
     /// specifies the length of each section, if we get a field we do not
@@ -130,6 +138,22 @@ pub struct TbfHeaderV2Main {
     minimum_ram_size: u32,
 }
 
+/// The v2 program section for apps.
+///
+/// This is a superset of the main section. In addition to the fields of the
+/// main section it records where the application binary ends, so that the
+/// kernel can find the footers that follow the binary, and a version number for
+/// the application. If both a main and a program section are present, the
+/// program section takes precedence.
+#[derive(Clone, Copy, Debug)]
+pub struct TbfHeaderV2Program {
+    init_fn_offset: u32,
+    protected_size: u32,
+    minimum_ram_size: u32,
+    pub(crate) binary_end_offset: u32,
+    version: u32,
+}
+
 /// Writeable flash regions only need an offset and size.
 ///
 /// There can be multiple (or zero) flash regions defined, so this is its own
@@ -164,6 +188,115 @@ pub struct TbfHeaderV2FixedAddresses {
     start_process_flash: u32,
 }
 
+/// The maximum number of driver permissions a TBF header can list. To enable a
+/// static buffer headers with more permissions are rejected.
+pub const NUM_DRIVER_PERMISSIONS: usize = 8;
+
+/// Permission for an app to use a driver.
+///
+/// `allowed_commands` is a bitmask of the command numbers `offset * 64` to
+/// `offset * 64 + 63` the app may call on the driver. Listing a driver at all
+/// also allows the app to subscribe and allow on it.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TbfHeaderDriverPermission {
+    driver_number: u32,
+    offset: u32,
+    allowed_commands: u64,
+}
+
+/// The permissions an app has to use a particular driver, as listed in its TBF
+/// header.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CommandPermissions {
+    /// The TBF header does not include a permissions section, so it does not
+    /// restrict the app.
+    NoPermsAtAll,
+
+    /// The TBF header includes a permissions section, but it does not list the
+    /// driver.
+    NoPermsThisDriver,
+
+    /// The bitmask of allowed commands for the requested offset. The mask may
+    /// be zero if the driver is listed, but not with this offset.
+    Mask(u64),
+}
+
+/// Optional persistent identifier of the application.
+///
+/// Unlike the package name this is a fixed-size value that the kernel can use
+/// directly to identify the application across restarts, reboots and updates.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TbfHeaderV2AppId {
+    app_id: u32,
+}
+
+/// Optional timing requirements of the application, for deadline schedulers.
+///
+/// The application runs periodically: in every `period_us` microsecond period
+/// it needs up to `budget_us` microseconds of CPU time, and it must get that
+/// time before the end of the period.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TbfHeaderV2Deadline {
+    period_us: u32,
+    budget_us: u32,
+}
+
+impl TbfHeaderV2Deadline {
+    /// Whether the timing requirements can be met at all: the period must not
+    /// be zero and the budget must fit in the period.
+    pub(crate) fn is_valid(&self) -> bool {
+        self.period_us > 0 && self.budget_us <= self.period_us
+    }
+}
+
+/// Optional scheduling priority the application requests, for priority
+/// schedulers. Lower numbers are higher priorities, and 0 is the highest. The
+/// board decides how much of the request it grants.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TbfHeaderV2Priority {
+    priority: u32,
+}
+
+/// Formats of credentials that can be stored in a credentials footer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TbfFooterV2CredentialsType {
+    /// Space reserved for a credential that has not been written yet. Also
+    /// used to pad the footer region.
+    Reserved = 0,
+    Rsa3072Key = 1,
+    Rsa4096Key = 2,
+    Rsa3072KeyWithID = 3,
+    Rsa4096KeyWithID = 4,
+    SHA256 = 5,
+    SHA384 = 6,
+    SHA512 = 7,
+}
+
+/// A credential stored in a footer after the application binary.
+///
+/// The credential covers the TBF header and the application binary, i.e.
+/// everything from the start of the TBF up to the binary end offset recorded in
+/// the program header. The format determines how `data` must be interpreted,
+/// for example as a hash of the binary or as a public key followed by a
+/// signature.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TbfFooterV2Credentials {
+    format: TbfFooterV2CredentialsType,
+    data: &'static [u8],
+}
+
+impl TbfFooterV2Credentials {
+    /// The format of the credential.
+    pub fn format(&self) -> TbfFooterV2CredentialsType {
+        self.format
+    }
+
+    /// The raw credential, whose meaning depends on `format()`.
+    pub fn data(&self) -> &'static [u8] {
+        self.data
+    }
+}
+
 // Conversion functions from slices to the various TBF fields.
 
 impl core::convert::TryFrom<&[u8]> for TbfHeaderV2Base {
@@ -209,6 +342,12 @@ impl core::convert::TryFrom<u16> for TbfHeaderTypes {
             2 => Ok(TbfHeaderTypes::TbfHeaderWriteableFlashRegions),
             3 => Ok(TbfHeaderTypes::TbfHeaderPackageName),
             5 => Ok(TbfHeaderTypes::TbfHeaderFixedAddresses),
+            6 => Ok(TbfHeaderTypes::TbfHeaderPermissions),
+            9 => Ok(TbfHeaderTypes::TbfHeaderProgram),
+            10 => Ok(TbfHeaderTypes::TbfHeaderAppId),
+            11 => Ok(TbfHeaderTypes::TbfHeaderDeadline),
+            12 => Ok(TbfHeaderTypes::TbfHeaderPriority),
+            128 => Ok(TbfHeaderTypes::TbfFooterCredentials),
             _ => Ok(TbfHeaderTypes::Unknown),
         }
     }
@@ -258,6 +397,40 @@ impl core::convert::TryFrom<&[u8]> for TbfHeaderV2Main {
     }
 }
 
+impl core::convert::TryFrom<&[u8]> for TbfHeaderV2Program {
+    type Error = TbfParseError;
+
+    fn try_from(b: &[u8]) -> Result<TbfHeaderV2Program, Self::Error> {
+        Ok(TbfHeaderV2Program {
+            init_fn_offset: u32::from_le_bytes(
+                b.get(0..4)
+                    .ok_or(TbfParseError::InternalError)?
+                    .try_into()?,
+            ),
+            protected_size: u32::from_le_bytes(
+                b.get(4..8)
+                    .ok_or(TbfParseError::InternalError)?
+                    .try_into()?,
+            ),
+            minimum_ram_size: u32::from_le_bytes(
+                b.get(8..12)
+                    .ok_or(TbfParseError::InternalError)?
+                    .try_into()?,
+            ),
+            binary_end_offset: u32::from_le_bytes(
+                b.get(12..16)
+                    .ok_or(TbfParseError::InternalError)?
+                    .try_into()?,
+            ),
+            version: u32::from_le_bytes(
+                b.get(16..20)
+                    .ok_or(TbfParseError::InternalError)?
+                    .try_into()?,
+            ),
+        })
+    }
+}
+
 impl core::convert::TryFrom<&[u8]> for TbfHeaderV2WriteableFlashRegion {
     type Error = TbfParseError;
 
@@ -296,6 +469,129 @@ impl core::convert::TryFrom<&[u8]> for TbfHeaderV2FixedAddresses {
     }
 }
 
+impl core::convert::TryFrom<&[u8]> for TbfHeaderV2AppId {
+    type Error = TbfParseError;
+
+    fn try_from(b: &[u8]) -> Result<TbfHeaderV2AppId, Self::Error> {
+        Ok(TbfHeaderV2AppId {
+            app_id: u32::from_le_bytes(
+                b.get(0..4)
+                    .ok_or(TbfParseError::InternalError)?
+                    .try_into()?,
+            ),
+        })
+    }
+}
+
+impl core::convert::TryFrom<&[u8]> for TbfHeaderV2Deadline {
+    type Error = TbfParseError;
+
+    fn try_from(b: &[u8]) -> Result<TbfHeaderV2Deadline, Self::Error> {
+        Ok(TbfHeaderV2Deadline {
+            period_us: u32::from_le_bytes(
+                b.get(0..4)
+                    .ok_or(TbfParseError::InternalError)?
+                    .try_into()?,
+            ),
+            budget_us: u32::from_le_bytes(
+                b.get(4..8)
+                    .ok_or(TbfParseError::InternalError)?
+                    .try_into()?,
+            ),
+        })
+    }
+}
+
+impl core::convert::TryFrom<&[u8]> for TbfHeaderV2Priority {
+    type Error = TbfParseError;
+
+    fn try_from(b: &[u8]) -> Result<TbfHeaderV2Priority, Self::Error> {
+        Ok(TbfHeaderV2Priority {
+            priority: u32::from_le_bytes(
+                b.get(0..4)
+                    .ok_or(TbfParseError::InternalError)?
+                    .try_into()?,
+            ),
+        })
+    }
+}
+
+impl core::convert::TryFrom<&[u8]> for TbfHeaderDriverPermission {
+    type Error = TbfParseError;
+
+    fn try_from(b: &[u8]) -> Result<TbfHeaderDriverPermission, Self::Error> {
+        Ok(TbfHeaderDriverPermission {
+            driver_number: u32::from_le_bytes(
+                b.get(0..4)
+                    .ok_or(TbfParseError::InternalError)?
+                    .try_into()?,
+            ),
+            offset: u32::from_le_bytes(
+                b.get(4..8)
+                    .ok_or(TbfParseError::InternalError)?
+                    .try_into()?,
+            ),
+            allowed_commands: u64::from_le_bytes(
+                b.get(8..16)
+                    .ok_or(TbfParseError::InternalError)?
+                    .try_into()?,
+            ),
+        })
+    }
+}
+
+impl core::convert::TryFrom<u32> for TbfFooterV2CredentialsType {
+    type Error = TbfParseError;
+
+    fn try_from(format: u32) -> Result<TbfFooterV2CredentialsType, Self::Error> {
+        match format {
+            0 => Ok(TbfFooterV2CredentialsType::Reserved),
+            1 => Ok(TbfFooterV2CredentialsType::Rsa3072Key),
+            2 => Ok(TbfFooterV2CredentialsType::Rsa4096Key),
+            3 => Ok(TbfFooterV2CredentialsType::Rsa3072KeyWithID),
+            4 => Ok(TbfFooterV2CredentialsType::Rsa4096KeyWithID),
+            5 => Ok(TbfFooterV2CredentialsType::SHA256),
+            6 => Ok(TbfFooterV2CredentialsType::SHA384),
+            7 => Ok(TbfFooterV2CredentialsType::SHA512),
+            _ => Err(TbfParseError::BadTlvEntry(
+                TbfHeaderTypes::TbfFooterCredentials as usize,
+            )),
+        }
+    }
+}
+
+impl core::convert::TryFrom<&'static [u8]> for TbfFooterV2Credentials {
+    type Error = TbfParseError;
+
+    fn try_from(b: &'static [u8]) -> Result<TbfFooterV2Credentials, Self::Error> {
+        let format: TbfFooterV2CredentialsType = u32::from_le_bytes(
+            b.get(0..4)
+                .ok_or(TbfParseError::NotEnoughFlash)?
+                .try_into()?,
+        )
+        .try_into()?;
+
+        // Every format except `Reserved` has a fixed length. For RSA keys the
+        // data is the public key followed by the signature, the "WithID"
+        // variants additionally start with a 4 byte key identifier.
+        let length = match format {
+            TbfFooterV2CredentialsType::Reserved => b.len() - 4,
+            TbfFooterV2CredentialsType::Rsa3072Key => 768,
+            TbfFooterV2CredentialsType::Rsa4096Key => 1024,
+            TbfFooterV2CredentialsType::Rsa3072KeyWithID => 772,
+            TbfFooterV2CredentialsType::Rsa4096KeyWithID => 1028,
+            TbfFooterV2CredentialsType::SHA256 => 32,
+            TbfFooterV2CredentialsType::SHA384 => 48,
+            TbfFooterV2CredentialsType::SHA512 => 64,
+        };
+        let data = b.get(4..4 + length).ok_or(TbfParseError::BadTlvEntry(
+            TbfHeaderTypes::TbfFooterCredentials as usize,
+        ))?;
+
+        Ok(TbfFooterV2Credentials { format, data })
+    }
+}
+
 /// Single header that can contain all parts of a v2 header.
 ///
 /// Note, this struct limits the number of writeable regions an app can have to
@@ -305,9 +601,14 @@ impl core::convert::TryFrom<&[u8]> for TbfHeaderV2FixedAddresses {
 pub struct TbfHeaderV2 {
     pub(crate) base: TbfHeaderV2Base,
     pub(crate) main: Option<TbfHeaderV2Main>,
+    pub(crate) program: Option<TbfHeaderV2Program>,
     pub(crate) package_name: Option<&'static str>,
     pub(crate) writeable_regions: Option<[Option<TbfHeaderV2WriteableFlashRegion>; 4]>,
     pub(crate) fixed_addresses: Option<TbfHeaderV2FixedAddresses>,
+    pub(crate) app_id: Option<TbfHeaderV2AppId>,
+    pub(crate) deadline: Option<TbfHeaderV2Deadline>,
+    pub(crate) priority: Option<TbfHeaderV2Priority>,
+    pub(crate) permissions: Option<[Option<TbfHeaderDriverPermission>; NUM_DRIVER_PERMISSIONS]>,
 }
 
 /// Type that represents the fields of the Tock Binary Format header.
@@ -348,7 +649,10 @@ impl TbfHeader {
     /// needed for this app.
     pub fn get_minimum_app_ram_size(&self) -> u32 {
         match *self {
-            TbfHeader::TbfHeaderV2(hd) => hd.main.map_or(0, |m| m.minimum_ram_size),
+            TbfHeader::TbfHeaderV2(hd) => match hd.program {
+                Some(p) => p.minimum_ram_size,
+                None => hd.main.map_or(0, |m| m.minimum_ram_size),
+            },
             _ => 0,
         }
     }
@@ -358,7 +662,11 @@ impl TbfHeader {
     pub fn get_protected_size(&self) -> u32 {
         match *self {
             TbfHeader::TbfHeaderV2(hd) => {
-                hd.main.map_or(0, |m| m.protected_size) + (hd.base.header_size as u32)
+                let protected_size = match hd.program {
+                    Some(p) => p.protected_size,
+                    None => hd.main.map_or(0, |m| m.protected_size),
+                };
+                protected_size + (hd.base.header_size as u32)
             }
             _ => 0,
         }
@@ -369,12 +677,37 @@ impl TbfHeader {
     pub fn get_init_function_offset(&self) -> u32 {
         match *self {
             TbfHeader::TbfHeaderV2(hd) => {
-                hd.main.map_or(0, |m| m.init_fn_offset) + (hd.base.header_size as u32)
+                let init_fn_offset = match hd.program {
+                    Some(p) => p.init_fn_offset,
+                    None => hd.main.map_or(0, |m| m.init_fn_offset),
+                };
+                init_fn_offset + (hd.base.header_size as u32)
             }
             _ => 0,
         }
     }
 
+    /// Get the offset from the beginning of the TBF where the application
+    /// binary ends and the footers begin. Without a program header there are no
+    /// footers, so the binary extends to the end of the TBF.
+    pub fn get_binary_end(&self) -> u32 {
+        match *self {
+            TbfHeader::TbfHeaderV2(hd) => hd
+                .program
+                .map_or(hd.base.total_size, |p| p.binary_end_offset),
+            TbfHeader::Padding(base) => base.total_size,
+        }
+    }
+
+    /// Get the version of the application from the program header, or 0 if
+    /// the header does not include one.
+    pub fn get_binary_version(&self) -> u32 {
+        match *self {
+            TbfHeader::TbfHeaderV2(hd) => hd.program.map_or(0, |p| p.version),
+            _ => 0,
+        }
+    }
+
     /// Get the name of the app.
     pub fn get_package_name(&self) -> Option<&'static str> {
         match *self {
@@ -434,4 +767,62 @@ impl TbfHeader {
             start => Some(start),
         }
     }
+
+    /// Get the persistent application identifier from the header, if the
+    /// header includes one.
+    pub fn get_app_id(&self) -> Option<u32> {
+        match self {
+            TbfHeader::TbfHeaderV2(hd) => hd.app_id.map(|a| a.app_id),
+            _ => None,
+        }
+    }
+
+    /// Get the period and the CPU time budget per period of the application,
+    /// both in microseconds, if the header includes them.
+    pub fn get_deadline(&self) -> Option<(u32, u32)> {
+        match self {
+            TbfHeader::TbfHeaderV2(hd) => hd.deadline.map(|d| (d.period_us, d.budget_us)),
+            _ => None,
+        }
+    }
+
+    /// Get the scheduling priority the application requests, if the header
+    /// includes one. Lower numbers are higher priorities.
+    pub fn get_priority(&self) -> Option<u32> {
+        match self {
+            TbfHeader::TbfHeaderV2(hd) => hd.priority.map(|p| p.priority),
+            _ => None,
+        }
+    }
+
+    /// Get the commands of driver `driver_num` the app may call, out of the
+    /// commands `offset * 64` to `offset * 64 + 63`.
+    pub fn get_command_permissions(&self, driver_num: usize, offset: usize) -> CommandPermissions {
+        let permissions = match self {
+            TbfHeader::TbfHeaderV2(hd) => match hd.permissions {
+                Some(permissions) => permissions,
+                None => return CommandPermissions::NoPermsAtAll,
+            },
+            _ => return CommandPermissions::NoPermsAtAll,
+        };
+
+        let mut driver_listed = false;
+        let mut mask = 0;
+        for perm in permissions
+            .iter()
+            .flatten()
+            .filter(|perm| perm.driver_number as usize == driver_num)
+        {
+            driver_listed = true;
+            if perm.offset as usize == offset {
+                mask |= perm.allowed_commands;
+            }
+        }
+
+        if driver_listed {
+            CommandPermissions::Mask(mask)
+        } else {
+            CommandPermissions::NoPermsThisDriver
+        }
+    }
 }
@@ -0,0 +1,419 @@
+//! Walking and editing the linked list of TBFs in an app flash image.
+//!
+//! The walk follows the list the same way the kernel does when it loads
+//! processes: it reads the lengths at the start of each entry, skips entries
+//! whose lengths are invalid, and stops at the first bytes that are not a TBF
+//! header or at the first header that does not parse.
+
+use std::convert::TryInto;
+
+use tock_tbf::builder::{update_checksum, TbfHeaderBuilder};
+use tock_tbf::parse::{parse_tbf_header, parse_tbf_header_lengths};
+use tock_tbf::types::{InitialTbfParseError, TbfHeader, TbfParseError};
+
+/// Size of a padding TBF, which has a header without TLVs and nothing else.
+const PADDING_SIZE: usize = 16;
+
+/// Offset of the flags in a version 2 header.
+const FLAGS_OFFSET: usize = 8;
+
+/// Flag bit that enables an app.
+const FLAG_ENABLED: u32 = 0x1;
+
+/// What the kernel finds in an entry of the app list.
+pub enum EntryKind {
+    /// The header parsed. This is either an app or padding.
+    Header(TbfHeader),
+    /// The header lengths are invalid. The kernel skips the entry.
+    InvalidLengths,
+    /// The header did not parse. The kernel stops loading processes at this
+    /// entry, so it is always the last entry of the walk.
+    BadHeader(TbfParseError),
+}
+
+/// An entry of the app list.
+pub struct Entry {
+    /// Offset of the entry from the start of the image.
+    pub offset: usize,
+    /// Length of the TBF header, or 0 if the header lengths are invalid.
+    pub header_length: usize,
+    /// The whole entry, as long as the total size in its header.
+    pub bytes: &'static [u8],
+    pub kind: EntryKind,
+}
+
+impl Entry {
+    /// Return whether the entry is padding between apps.
+    pub fn is_padding(&self) -> bool {
+        match self.kind {
+            EntryKind::Header(ref header) => !header.is_app(),
+            _ => false,
+        }
+    }
+}
+
+/// Why the walk stopped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ListEnd {
+    /// The image ends, or the next bytes are not a TBF header. This is how a
+    /// well formed app list ends.
+    EndOfList,
+    /// The next entry is `length` bytes long, which is longer than the rest
+    /// of the image. The kernel stops loading processes with
+    /// `NotEnoughFlash`.
+    Truncated { length: usize },
+    /// The next entry has a total size of zero, so the kernel finds the same
+    /// entry again for every process slot.
+    ZeroLength,
+    /// The last entry has a header that did not parse. The kernel does not
+    /// look at the flash after it.
+    BadHeader,
+}
+
+/// The entries of an app list, and where and why the list ends.
+pub struct Walk {
+    pub entries: Vec<Entry>,
+    /// Offset of the first byte after the list, or of the entry with the
+    /// header that did not parse.
+    pub end_offset: usize,
+    pub end: ListEnd,
+}
+
+/// Walk the app list at the start of `image`.
+pub fn walk(image: &'static [u8]) -> Walk {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    let end = loop {
+        let remaining = &image[offset..];
+        let lengths: &'static [u8; 8] = match remaining.get(0..8) {
+            Some(bytes) => bytes.try_into().unwrap(),
+            None => break ListEnd::EndOfList,
+        };
+        let (version, header_length, entry_length) = match parse_tbf_header_lengths(lengths) {
+            Ok((version, header_length, entry_length)) => {
+                (version, header_length as usize, entry_length as usize)
+            }
+            Err(InitialTbfParseError::InvalidHeader(entry_length)) => (0, 0, entry_length as usize),
+            Err(InitialTbfParseError::UnableToParse) => break ListEnd::EndOfList,
+        };
+        if entry_length == 0 {
+            break ListEnd::ZeroLength;
+        }
+        let bytes = match remaining.get(0..entry_length) {
+            Some(bytes) => bytes,
+            None => {
+                break ListEnd::Truncated {
+                    length: entry_length,
+                }
+            }
+        };
+        let kind = if header_length == 0 {
+            EntryKind::InvalidLengths
+        } else {
+            match parse_tbf_header(&bytes[0..header_length], version) {
+                Ok(header) => EntryKind::Header(header),
+                Err(error) => EntryKind::BadHeader(error),
+            }
+        };
+        let bad_header = matches!(kind, EntryKind::BadHeader(_));
+        entries.push(Entry {
+            offset,
+            header_length,
+            bytes,
+            kind,
+        });
+        if bad_header {
+            break ListEnd::BadHeader;
+        }
+        offset += entry_length;
+    };
+    Walk {
+        entries,
+        end_offset: offset,
+        end,
+    }
+}
+
+/// Alignment an MPU requires of the flash region of an app.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Alignment {
+    /// The size is a power of two and the start is a multiple of the size,
+    /// as for the Cortex-M MPU.
+    PowerOfTwo,
+    /// The start and the size are multiples of this many bytes.
+    Multiple(usize),
+}
+
+impl Alignment {
+    /// Parse `pow2` or a number of bytes.
+    pub fn parse(rule: &str) -> Option<Alignment> {
+        match rule {
+            "pow2" => Some(Alignment::PowerOfTwo),
+            _ => match parse_number(rule) {
+                Some(0) | None => None,
+                Some(bytes) => Some(Alignment::Multiple(bytes)),
+            },
+        }
+    }
+
+    /// Return why an app of `size` bytes at `address` breaks the rule, or
+    /// `None` if it does not.
+    pub fn check(&self, address: usize, size: usize) -> Option<String> {
+        match *self {
+            Alignment::PowerOfTwo if !size.is_power_of_two() => {
+                Some(format!("size {:#x} is not a power of two", size))
+            }
+            Alignment::PowerOfTwo if address % size != 0 => Some(format!(
+                "start {:#010x} is not a multiple of the size {:#x}",
+                address, size
+            )),
+            Alignment::Multiple(bytes) if address % bytes != 0 || size % bytes != 0 => {
+                Some(format!(
+                    "start {:#010x} or size {:#x} is not a multiple of {:#x}",
+                    address, size, bytes
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// Return the first address at or after `address` where an app of
+    /// `size` bytes may start.
+    fn next_start(&self, address: usize, size: usize) -> usize {
+        let step = match *self {
+            Alignment::PowerOfTwo => size.next_power_of_two(),
+            Alignment::Multiple(bytes) => bytes,
+        };
+        (address + step - 1) / step * step
+    }
+}
+
+/// Parse a decimal number, or a hexadecimal number with a `0x` prefix.
+pub fn parse_number(number: &str) -> Option<usize> {
+    match number.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => number.parse().ok(),
+    }
+}
+
+/// Return what will keep the kernel from loading `entry` as an app, when the
+/// image starts at flash address `base`.
+pub fn problems(entry: &Entry, base: usize, alignment: Option<Alignment>) -> Vec<String> {
+    let header = match entry.kind {
+        EntryKind::Header(ref header) => header,
+        EntryKind::InvalidLengths => {
+            return vec!["header lengths are invalid, the kernel skips this entry".to_string()];
+        }
+        EntryKind::BadHeader(ref error) => {
+            return vec![format!(
+                "{:?}, the kernel stops loading processes here and ignores the entries after it",
+                error
+            )];
+        }
+    };
+    let mut problems = Vec::new();
+    if !header.is_app() {
+        return problems;
+    }
+    let address = base + entry.offset;
+    if let Some(fixed_flash) = header.get_fixed_address_flash() {
+        let actual = address + header.get_protected_size() as usize;
+        if actual != fixed_flash as usize {
+            problems.push(format!(
+                "binary is at {:#010x}, but the header requires {:#010x}",
+                actual, fixed_flash
+            ));
+        }
+    }
+    if let Some(problem) =
+        alignment.and_then(|alignment| alignment.check(address, entry.bytes.len()))
+    {
+        problems.push(problem);
+    }
+    problems
+}
+
+/// Find the entry with list index or package name `app`.
+pub fn find(walk: &Walk, app: &str) -> Option<usize> {
+    if let Some(index) = parse_number(app) {
+        return Some(index).filter(|&index| index < walk.entries.len());
+    }
+    walk.entries.iter().position(|entry| match entry.kind {
+        EntryKind::Header(ref header) => header.get_package_name() == Some(app),
+        _ => false,
+    })
+}
+
+/// Return the TBFs of `entries` that are not padding, in list order.
+pub fn without_padding(entries: &[Entry]) -> Vec<&'static [u8]> {
+    entries
+        .iter()
+        .filter(|entry| !entry.is_padding())
+        .map(|entry| entry.bytes)
+        .collect()
+}
+
+/// Place `tbfs` back to back in a new image that starts at flash address
+/// `base`. If an app has to start later to satisfy `alignment`, a padding
+/// TBF fills the gap before it.
+pub fn layout(tbfs: &[&[u8]], base: usize, alignment: Option<Alignment>) -> Vec<u8> {
+    let mut image = Vec::new();
+    for tbf in tbfs {
+        let end = base + image.len();
+        let start = match alignment {
+            Some(alignment) => {
+                let mut start = alignment.next_start(end, tbf.len());
+                // A gap that is too small for a padding TBF cannot be filled.
+                while start > end && start - end < PADDING_SIZE {
+                    start = alignment.next_start(start + 1, tbf.len());
+                }
+                start
+            }
+            None => end,
+        };
+        if start > end {
+            let offset = image.len();
+            image.resize(offset + (start - end), 0);
+            TbfHeaderBuilder::new()
+                .total_size((start - end) as u32)
+                .write(&mut image[offset..])
+                .expect("padding header does not fit");
+        }
+        image.extend_from_slice(tbf);
+    }
+    image
+}
+
+/// Enable or disable the app in `entry`, which must be an entry of `image`.
+pub fn set_enabled(image: &mut [u8], entry: &Entry, enabled: bool) -> Result<(), String> {
+    match entry.kind {
+        EntryKind::Header(ref header) if header.is_app() => {}
+        _ => return Err(format!("entry at {:#x} is not an app", entry.offset)),
+    }
+    let header = &mut image[entry.offset..entry.offset + entry.header_length];
+    let flags = u32::from_le_bytes(header[FLAGS_OFFSET..FLAGS_OFFSET + 4].try_into().unwrap());
+    let flags = if enabled {
+        flags | FLAG_ENABLED
+    } else {
+        flags & !FLAG_ENABLED
+    };
+    header[FLAGS_OFFSET..FLAGS_OFFSET + 4].copy_from_slice(&flags.to_le_bytes());
+    update_checksum(header).map_err(|error| format!("{:?}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use tock_tbf::builder::TbfHeaderBuilder;
+
+    use super::{find, layout, set_enabled, walk, without_padding, Alignment, EntryKind, ListEnd};
+
+    fn leak(image: Vec<u8>) -> &'static [u8] {
+        Box::leak(image.into_boxed_slice())
+    }
+
+    fn app(name: &str, size: usize) -> Vec<u8> {
+        let mut tbf = vec![0xa5; size];
+        TbfHeaderBuilder::new()
+            .total_size(size as u32)
+            .enabled(true)
+            .main(0x41, 0, 4096)
+            .package_name(name)
+            .write(&mut tbf)
+            .unwrap();
+        tbf
+    }
+
+    fn names(image: &'static [u8]) -> Vec<Option<&'static str>> {
+        walk(image)
+            .entries
+            .iter()
+            .map(|entry| match entry.kind {
+                EntryKind::Header(ref header) => header.get_package_name(),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_walk_ends_like_the_kernel() {
+        let mut image = app("blink", 512);
+        image.extend(app("hello", 256));
+        image.extend(vec![0xff; 100]);
+        let list = walk(leak(image.clone()));
+        assert_eq!(list.entries.len(), 2);
+        assert_eq!(list.entries[1].offset, 512);
+        assert_eq!(list.end_offset, 768);
+        assert_eq!(list.end, ListEnd::EndOfList);
+
+        // The second app claims more flash than is left.
+        image[512 + 5] = 0x10;
+        let list = walk(leak(image.clone()));
+        assert_eq!(list.entries.len(), 1);
+        assert_eq!(list.end, ListEnd::Truncated { length: 0x1000 });
+
+        // A header longer than the entry is skipped, but a total size of zero
+        // ends the walk.
+        image[512 + 5] = 0x1;
+        image[512 + 3] = 0xff;
+        let list = walk(leak(image.clone()));
+        assert!(matches!(list.entries[1].kind, EntryKind::InvalidLengths));
+        image[512 + 4] = 0;
+        image[512 + 5] = 0;
+        assert_eq!(walk(leak(image)).end, ListEnd::ZeroLength);
+    }
+
+    #[test]
+    fn test_walk_stops_at_bad_checksum() {
+        let mut image = app("blink", 512);
+        image.extend(app("hello", 256));
+        image.extend(app("sensors", 256));
+        image[512 + 13] ^= 0x1;
+        let list = walk(leak(image));
+        assert_eq!(list.entries.len(), 2);
+        assert!(matches!(list.entries[1].kind, EntryKind::BadHeader(_)));
+        assert_eq!(list.end_offset, 512);
+        assert_eq!(list.end, ListEnd::BadHeader);
+    }
+
+    #[test]
+    fn test_layout_pads_to_alignment() {
+        let tbfs = [app("a", 0x300), app("b", 0x400), app("c", 0x100)];
+        let tbfs: Vec<&[u8]> = tbfs.iter().map(|tbf| tbf.as_slice()).collect();
+        let image = leak(layout(&tbfs, 0x40000, Some(Alignment::PowerOfTwo)));
+        let list = walk(image);
+        let offsets: Vec<usize> = list.entries.iter().map(|entry| entry.offset).collect();
+        assert_eq!(offsets, vec![0, 0x300, 0x400, 0x800]);
+        assert!(list.entries[1].is_padding());
+        assert_eq!(names(image), vec![Some("a"), None, Some("b"), Some("c")]);
+        assert_eq!(
+            Alignment::PowerOfTwo.check(0x40000, 0x300),
+            Some("size 0x300 is not a power of two".to_string())
+        );
+        assert_eq!(Alignment::PowerOfTwo.check(0x40400, 0x400), None);
+
+        // A 4 byte gap cannot hold a padding TBF.
+        let tbfs = [app("a", 0x104), app("b", 0x100)];
+        let tbfs: Vec<&[u8]> = tbfs.iter().map(|tbf| tbf.as_slice()).collect();
+        let image = leak(layout(&tbfs, 0, Some(Alignment::Multiple(8))));
+        let offsets: Vec<usize> = walk(image).entries.iter().map(|e| e.offset).collect();
+        assert_eq!(offsets, vec![0, 0x104, 0x118]);
+        assert_eq!(without_padding(&walk(image).entries).len(), 2);
+    }
+
+    #[test]
+    fn test_set_enabled() {
+        let mut image = app("blink", 512);
+        image.extend(app("hello", 256));
+        let list = walk(leak(image.clone()));
+        let index = find(&list, "hello").unwrap();
+        assert_eq!(find(&list, "1"), Some(1));
+        assert_eq!(find(&list, "2"), None);
+        set_enabled(&mut image, &list.entries[index], false).unwrap();
+
+        let list = walk(leak(image));
+        match list.entries[1].kind {
+            EntryKind::Header(ref header) => assert!(!header.enabled()),
+            _ => panic!("checksum not updated"),
+        }
+    }
+}
@@ -0,0 +1,341 @@
+//! Inspect and edit the list of apps in a Tock app flash image.
+//!
+//! An image is a raw dump of the app flash region, or TBF files concatenated
+//! together. `tbf-image list` walks the app list the way the kernel does at
+//! boot and prints every entry it finds, along with anything that keeps the
+//! kernel from loading an app. The other commands add, remove, enable, or
+//! disable apps and write the edited image to a new file.
+
+mod image;
+
+use std::process::exit;
+
+use tock_tbf::types::TbfHeader;
+
+use crate::image::{Alignment, EntryKind, ListEnd, Walk};
+
+/// Prints an error message and the usage string, and exits. Used to report
+/// command line argument errors.
+fn usage_error(message: &str) -> ! {
+    eprintln!(
+        "{}
+
+Usage: tbf-image <command> [options]
+
+Commands:
+  list <image>...                   Print the app list of the images, in order.
+  insert <image> <tbf> [--at <app>] Insert the TBF before <app>, or after the
+                                    last app.
+  remove <image> <app>              Remove an app.
+  enable <image> <app>              Enable an app.
+  disable <image> <app>             Disable an app, so that the kernel does not
+                                    start it.
+
+<app> is an entry number printed by `list`, or a package name.
+
+Options:
+  --base <address>  Flash address the image starts at. Defaults to 0.
+  --align <rule>    Alignment the MPU requires of the flash of each app:
+                    `pow2` for a power of two size and a start that is a
+                    multiple of the size (Cortex-M), or a number of bytes that
+                    the start and the size must be multiples of. Required by
+                    `insert` and `remove`, which move apps.
+  -o <file>         File to write the edited image to. Required by all
+                    commands except `list`.
+
+Examples:
+  tbf-image list --base 0x40000 --align pow2 flash.bin
+  tbf-image insert --align pow2 -o apps.bin apps.bin blink.tbf",
+        message
+    );
+    exit(1);
+}
+
+/// Command line arguments.
+struct Args {
+    command: String,
+    positional: Vec<String>,
+    base: usize,
+    alignment: Option<Alignment>,
+    at: Option<String>,
+    output: Option<String>,
+}
+
+fn parse_args() -> Args {
+    let mut args = std::env::args().skip(1);
+    let command = args
+        .next()
+        .unwrap_or_else(|| usage_error("Missing command"));
+    let mut parsed = Args {
+        command,
+        positional: Vec::new(),
+        base: 0,
+        alignment: None,
+        at: None,
+        output: None,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |option: &str| {
+            args.next()
+                .unwrap_or_else(|| usage_error(&format!("{} requires a value", option)))
+        };
+        match arg.as_str() {
+            "--base" => {
+                parsed.base = image::parse_number(&value("--base"))
+                    .unwrap_or_else(|| usage_error("--base must be a number"));
+            }
+            "--align" => {
+                parsed.alignment = Some(
+                    Alignment::parse(&value("--align"))
+                        .unwrap_or_else(|| usage_error("--align must be pow2 or a number")),
+                );
+            }
+            "--at" => parsed.at = Some(value("--at")),
+            "-o" => parsed.output = Some(value("-o")),
+            _ if arg.starts_with('-') => usage_error(&format!("Unknown option {}", arg)),
+            _ => parsed.positional.push(arg),
+        }
+    }
+    parsed
+}
+
+/// Read and concatenate `files`. The image lives until the tool exits, which
+/// lets `tock-tbf` parse it like flash.
+fn read_image(files: &[String]) -> &'static [u8] {
+    let mut image = Vec::new();
+    for file in files {
+        match std::fs::read(file) {
+            Ok(bytes) => image.extend(bytes),
+            Err(error) => {
+                eprintln!("Unable to read {}: {}", file, error);
+                exit(1);
+            }
+        }
+    }
+    Box::leak(image.into_boxed_slice())
+}
+
+fn print_header(header: &TbfHeader) {
+    println!(
+        "      init offset {:#x}, protected {:#x} bytes, minimum RAM {} bytes",
+        header.get_init_function_offset(),
+        header.get_protected_size(),
+        header.get_minimum_app_ram_size()
+    );
+    println!(
+        "      binary end {:#x}, binary version {}",
+        header.get_binary_end(),
+        header.get_binary_version()
+    );
+    for index in 0..header.number_writeable_flash_regions() {
+        let (offset, size) = header.get_writeable_flash_region(index);
+        println!(
+            "      writeable flash region at offset {:#x}, {:#x} bytes",
+            offset, size
+        );
+    }
+    if let (Some(flash), Some(ram)) = (
+        header.get_fixed_address_flash(),
+        header.get_fixed_address_ram(),
+    ) {
+        println!(
+            "      fixed addresses: flash {:#010x}, RAM {:#010x}",
+            flash, ram
+        );
+    }
+    if let Some(app_id) = header.get_app_id() {
+        println!("      app ID {:#x}", app_id);
+    }
+    if let Some((period, budget)) = header.get_deadline() {
+        println!("      deadline every {} us, budget {} us", period, budget);
+    }
+    if let Some(priority) = header.get_priority() {
+        println!("      priority {}", priority);
+    }
+}
+
+/// Print the entries of `walk`, and return the number of problems found.
+fn print_list(walk: &Walk, image: &[u8], base: usize, alignment: Option<Alignment>) -> usize {
+    let mut problem_count = 0;
+    for (index, entry) in walk.entries.iter().enumerate() {
+        let address = base + entry.offset;
+        let size = entry.bytes.len();
+        match entry.kind {
+            EntryKind::Header(ref header) if header.is_app() => {
+                println!(
+                    "[{}] {:#010x} app {:?}, {:#x} bytes, {}",
+                    index,
+                    address,
+                    header.get_package_name().unwrap_or(""),
+                    size,
+                    if header.enabled() {
+                        "enabled"
+                    } else {
+                        "disabled"
+                    }
+                );
+                println!("      header {} bytes, checksum valid", entry.header_length);
+                print_header(header);
+            }
+            EntryKind::Header(_) => {
+                println!("[{}] {:#010x} padding, {:#x} bytes", index, address, size)
+            }
+            EntryKind::InvalidLengths | EntryKind::BadHeader(_) => {
+                println!("[{}] {:#010x} invalid, {:#x} bytes", index, address, size)
+            }
+        }
+        for problem in image::problems(entry, base, alignment) {
+            println!("      ! {}", problem);
+            problem_count += 1;
+        }
+    }
+
+    let end_address = base + walk.end_offset;
+    match walk.end {
+        ListEnd::EndOfList => println!(
+            "{:#010x} end of apps, {:#x} bytes of flash after it",
+            end_address,
+            image.len() - walk.end_offset
+        ),
+        ListEnd::Truncated { length } => {
+            println!(
+                "{:#010x} ! entry of {:#x} bytes is longer than the {:#x} bytes left",
+                end_address,
+                length,
+                image.len() - walk.end_offset
+            );
+            problem_count += 1;
+        }
+        ListEnd::ZeroLength => {
+            println!(
+                "{:#010x} ! entry has a total size of 0, the kernel does not get past it",
+                end_address
+            );
+            problem_count += 1;
+        }
+        ListEnd::BadHeader => println!(
+            "{:#010x} end of apps at the invalid header, {:#x} bytes of flash from it",
+            end_address,
+            image.len() - walk.end_offset
+        ),
+    }
+    problem_count
+}
+
+/// Find the entry `app` of `walk`, or exit.
+fn find_entry(walk: &Walk, app: &str) -> usize {
+    image::find(walk, app).unwrap_or_else(|| {
+        eprintln!("No app {} in the image", app);
+        exit(1);
+    })
+}
+
+/// Walk the app list of `image` to move its apps, or exit if the list does not
+/// end cleanly, since the flash after the end of the list would be lost.
+fn walk_to_relayout(image: &'static [u8], args: &Args) -> Walk {
+    if args.alignment.is_none() {
+        usage_error(&format!(
+            "{} requires --align, so that the apps it moves stay aligned",
+            args.command
+        ));
+    }
+    let walk = image::walk(image);
+    if walk.end != ListEnd::EndOfList {
+        eprintln!(
+            "Unable to {}: the app list ends with a problem at {:#x}, see `tbf-image list`",
+            args.command,
+            args.base + walk.end_offset
+        );
+        exit(1);
+    }
+    walk
+}
+
+/// Lay out `tbfs` as a new image. The new image is at least as long as
+/// `image`, so that a flash dump stays the size of the app flash region. The
+/// rest is filled as erased flash, which ends the app list.
+fn relayout(image: &[u8], tbfs: &[&[u8]], args: &Args) -> Vec<u8> {
+    let mut edited = image::layout(tbfs, args.base, args.alignment);
+    if edited.len() > image.len() && !image.is_empty() {
+        eprintln!(
+            "Warning: the apps take {:#x} bytes, more than the {:#x} bytes of the image",
+            edited.len(),
+            image.len()
+        );
+    }
+    if edited.len() < image.len() {
+        edited.resize(image.len(), 0xff);
+    }
+    edited
+}
+
+fn main() {
+    let args = parse_args();
+    let expect_positional = |count: usize| {
+        if args.positional.len() != count {
+            usage_error("Incorrect number of arguments");
+        }
+    };
+
+    if args.command == "list" {
+        if args.positional.is_empty() {
+            usage_error("Missing image");
+        }
+        let image = read_image(&args.positional);
+        let problem_count = print_list(&image::walk(image), image, args.base, args.alignment);
+        if problem_count > 0 {
+            exit(2);
+        }
+        return;
+    }
+
+    let edited = match args.command.as_str() {
+        "insert" => {
+            expect_positional(2);
+            let image = read_image(&args.positional[0..1]);
+            let tbf = read_image(&args.positional[1..2]);
+            let walk = walk_to_relayout(image, &args);
+            let index = match args.at {
+                Some(ref app) => find_entry(&walk, app),
+                None => walk.entries.len(),
+            };
+            let mut tbfs = image::without_padding(&walk.entries[..index]);
+            tbfs.push(tbf);
+            tbfs.extend(image::without_padding(&walk.entries[index..]));
+            relayout(image, &tbfs, &args)
+        }
+        "remove" => {
+            expect_positional(2);
+            let image = read_image(&args.positional[0..1]);
+            let walk = walk_to_relayout(image, &args);
+            let index = find_entry(&walk, &args.positional[1]);
+            let mut tbfs = image::without_padding(&walk.entries[..index]);
+            tbfs.extend(image::without_padding(&walk.entries[index + 1..]));
+            relayout(image, &tbfs, &args)
+        }
+        "enable" | "disable" => {
+            expect_positional(2);
+            let image = read_image(&args.positional[0..1]);
+            let walk = image::walk(image);
+            let entry = &walk.entries[find_entry(&walk, &args.positional[1])];
+            let mut edited = image.to_vec();
+            if let Err(error) = image::set_enabled(&mut edited, entry, args.command == "enable") {
+                eprintln!("Unable to {}: {}", args.command, error);
+                exit(1);
+            }
+            edited
+        }
+        _ => usage_error(&format!("Unknown command {}", args.command)),
+    };
+
+    let output = args
+        .output
+        .as_ref()
+        .unwrap_or_else(|| usage_error("Missing -o <file>"));
+    if let Err(error) = std::fs::write(output, &edited) {
+        eprintln!("Unable to write {}: {}", output, error);
+        exit(1);
+    }
+    let edited: &'static [u8] = Box::leak(edited.into_boxed_slice());
+    print_list(&image::walk(edited), edited, args.base, args.alignment);
+}